@@ -0,0 +1,40 @@
+// Applies the mirror's theme before the page renders and adds a toggle button.
+// The choice ("dark", "light" or "auto" following the system) is kept in a cookie.
+(() => {
+  const COOKIE = "jecnaproxy_theme";
+  const MODES = ["auto", "dark", "light"];
+  const ICONS = { auto: "◐", dark: "☾", light: "☀" };
+  const system = window.matchMedia("(prefers-color-scheme: dark)");
+
+  const stored = () => {
+    const match = document.cookie.match(/(?:^|;\s*)jecnaproxy_theme=(\w+)/);
+    return match && MODES.includes(match[1]) ? match[1] : "auto";
+  };
+
+  const apply = () => {
+    const mode = stored();
+    const dark = mode === "dark" || (mode === "auto" && system.matches);
+    document.documentElement.dataset.jecnaproxyTheme = dark ? "dark" : "light";
+    const button = document.getElementById("jecnaproxy-theme-toggle");
+    if (button) {
+      button.textContent = ICONS[mode];
+      button.title = "Motiv: " + { auto: "podle systému", dark: "tmavý", light: "světlý" }[mode];
+    }
+  };
+
+  system.addEventListener("change", apply);
+  apply();
+
+  document.addEventListener("DOMContentLoaded", () => {
+    const button = document.createElement("button");
+    button.id = "jecnaproxy-theme-toggle";
+    button.type = "button";
+    button.addEventListener("click", () => {
+      const next = MODES[(MODES.indexOf(stored()) + 1) % MODES.length];
+      document.cookie = COOKIE + "=" + next + "; path=/; max-age=31536000; SameSite=Lax";
+      apply();
+    });
+    document.body.appendChild(button);
+    apply();
+  });
+})();
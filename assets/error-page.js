@@ -0,0 +1,26 @@
+// Search box of the error page, backed by the proxy's search index (/api/search).
+const form = document.getElementById("jecnaproxy-search");
+const results = document.getElementById("jecnaproxy-results");
+
+form.addEventListener("submit", async (event) => {
+  event.preventDefault();
+  const q = new FormData(form).get("q");
+  results.replaceChildren();
+  try {
+    const response = await fetch("/api/search?q=" + encodeURIComponent(q));
+    if (!response.ok) throw new Error(response.status);
+    const hits = await response.json();
+    if (hits.length === 0) {
+      results.append(Object.assign(document.createElement("li"), { textContent: form.dataset.empty }));
+    }
+    for (const hit of hits) {
+      const link = Object.assign(document.createElement("a"), { href: hit.path, textContent: hit.title || hit.path });
+      const snippet = Object.assign(document.createElement("div"), { textContent: hit.snippet });
+      const item = document.createElement("li");
+      item.append(link, snippet);
+      results.append(item);
+    }
+  } catch {
+    results.append(Object.assign(document.createElement("li"), { textContent: form.dataset.failed }));
+  }
+});
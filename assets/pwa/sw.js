@@ -0,0 +1,43 @@
+// Offline support of the installed mirror: network first, falling back to the
+// last cached copy of a page.
+const CACHE = "jecnaproxy-pwa-v1";
+
+const OFFLINE_HTML = `<!DOCTYPE html>
+<html lang="cs">
+<head><meta charset="utf-8"><meta name="viewport" content="width=device-width, initial-scale=1"><title>Offline</title></head>
+<body style="font-family: sans-serif; text-align: center; padding: 2rem;">
+  <h1>Jste offline</h1>
+  <p>Tato stránka zatím nebyla uložena. Zkuste to znovu po připojení k internetu.</p>
+</body>
+</html>`;
+
+self.addEventListener("install", () => self.skipWaiting());
+self.addEventListener("activate", (event) => event.waitUntil(self.clients.claim()));
+
+self.addEventListener("fetch", (event) => {
+  const request = event.request;
+  const url = new URL(request.url);
+  if (request.method !== "GET" || url.origin !== self.location.origin || url.pathname.startsWith("/_admin")) {
+    return;
+  }
+
+  event.respondWith(
+    fetch(request)
+      .then((response) => {
+        if (response.ok) {
+          const copy = response.clone();
+          event.waitUntil(caches.open(CACHE).then((cache) => cache.put(request, copy)));
+        }
+        return response;
+      })
+      .catch(() =>
+        caches.match(request).then((cached) => {
+          if (cached) return cached;
+          if (request.mode === "navigate") {
+            return new Response(OFFLINE_HTML, { headers: { "content-type": "text/html; charset=utf-8" } });
+          }
+          return Response.error();
+        })
+      )
+  );
+});
@@ -0,0 +1,92 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Benchmarks of the per-response hot paths.
+//!
+//! `fixtures/events.html` is shaped like the school's events listing (long menu,
+//! 40 article teasers, absolute links everywhere). Run with `cargo bench`.
+
+use std::{hint::black_box, sync::Arc};
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use jecnaproxy::{
+    config::{Config, Mode, Upstream},
+    cookies::CookiePolicy,
+    handlers,
+    state::AppState,
+    utils,
+};
+
+const EVENTS_HTML: &str = include_str!("fixtures/events.html");
+
+const PROXY_ORIGIN: &str = "https://jecna.example.com";
+
+const COOKIES: &[&str] = &[
+    "PHPSESSID=5d1b0c2f9a8e7d6c5b4a3f2e1d0c9b8a; path=/; domain=www.spsejecna.cz; HttpOnly",
+    "role=student; expires=Fri, 31-Dec-2027 23:59:59 GMT; Max-Age=31536000; path=/; secure; SameSite=Strict",
+    "WTDGUID=10; path=/",
+];
+
+fn state() -> AppState {
+    let upstream = Upstream::new(Mode::SPSEJECNA, &[]).expect("built-in mode is valid");
+    AppState::new(Arc::new(Config::from_env()), upstream)
+}
+
+fn rewrite(c: &mut Criterion) {
+    let state = state();
+    let mut group = c.benchmark_group("rewrite");
+    group.throughput(Throughput::Bytes(EVENTS_HTML.len() as u64));
+
+    group.bench_function("content_urls", |b| {
+        b.iter(|| {
+            utils::rewrite_content_urls(
+                black_box(EVENTS_HTML.to_string()),
+                black_box(PROXY_ORIGIN),
+                &state,
+            )
+        })
+    });
+    group.bench_function("content_bytes", |b| {
+        b.iter(|| {
+            utils::rewrite_content_bytes(
+                black_box(EVENTS_HTML.as_bytes()),
+                black_box(PROXY_ORIGIN),
+                &state,
+            )
+        })
+    });
+    group.bench_function("inject_banner", |b| {
+        b.iter(|| {
+            let mut body = black_box(EVENTS_HTML.as_bytes().to_vec());
+            handlers::inject_banner(&mut body, &state);
+            body
+        })
+    });
+
+    group.finish();
+}
+
+fn cookies(c: &mut Criterion) {
+    let policy = CookiePolicy::default();
+    c.bench_function("process_cookie", |b| {
+        b.iter(|| {
+            for cookie in COOKIES {
+                black_box(utils::process_cookie(black_box(cookie), true, &policy));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, rewrite, cookies);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Feeds arbitrary `Set-Cookie` headers into the cookie rewriting.
+
+#![no_main]
+
+use jecnaproxy::{cookies::CookiePolicy, utils};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&flags, cookie)) = data.split_first() else {
+        return;
+    };
+    let Ok(cookie) = std::str::from_utf8(cookie) else {
+        return;
+    };
+
+    let policy = CookiePolicy {
+        preserve: flags & 2 == 2,
+        partitioned: flags & 4 == 4,
+        ..CookiePolicy::default()
+    };
+    utils::process_cookie(cookie, flags & 1 == 1, &policy);
+});
@@ -0,0 +1,49 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Feeds arbitrary upstream bodies and proxy origins into the body rewriters
+//! and the banner injection.
+
+#![no_main]
+
+use std::sync::{Arc, LazyLock};
+
+use jecnaproxy::{
+    config::{Config, Mode, Upstream},
+    handlers,
+    state::AppState,
+    utils,
+};
+use libfuzzer_sys::fuzz_target;
+
+static STATE: LazyLock<AppState> = LazyLock::new(|| {
+    let upstream = Upstream::new(Mode::SPSEJECNA, &[]).expect("built-in mode is valid");
+    AppState::new(Arc::new(Config::from_env()), upstream)
+});
+
+fuzz_target!(|data: &[u8]| {
+    // Everything before the first NUL is the proxy origin, which comes from the
+    // client's Host header.
+    let (origin, body) = match data.iter().position(|&b| b == 0) {
+        Some(i) => (&data[..i], &data[i + 1..]),
+        None => (&[][..], data),
+    };
+    let origin = String::from_utf8_lossy(origin);
+
+    utils::rewrite_content_bytes(body, &origin, &STATE);
+    utils::rewrite_content_urls(String::from_utf8_lossy(body).into_owned(), &origin, &STATE);
+
+    let mut html = body.to_vec();
+    handlers::inject_banner(&mut html, &STATE);
+});
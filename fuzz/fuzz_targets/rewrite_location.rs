@@ -0,0 +1,40 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Feeds arbitrary `Location` headers and proxy origins into the redirect rewriting.
+
+#![no_main]
+
+use std::sync::{Arc, LazyLock};
+
+use jecnaproxy::{
+    config::{Config, Mode, Upstream},
+    state::AppState,
+    utils,
+};
+use libfuzzer_sys::fuzz_target;
+
+static STATE: LazyLock<AppState> = LazyLock::new(|| {
+    let upstream = Upstream::new(Mode::SPSEJECNA, &[]).expect("built-in mode is valid");
+    AppState::new(Arc::new(Config::from_env()), upstream)
+});
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(data) = std::str::from_utf8(data) else {
+        return;
+    };
+    let (origin, location) = data.split_once('\0').unwrap_or(("", data));
+
+    utils::rewrite_location(location, origin, &STATE);
+});
@@ -0,0 +1,32 @@
+-- Crawled upstream resources, used to rebuild the search index on startup.
+CREATE TABLE pages (
+    path TEXT PRIMARY KEY NOT NULL,
+    content_type TEXT NOT NULL,
+    body BYTEA NOT NULL,
+    crawled_at BIGINT NOT NULL
+);
+
+-- Last seen version of every monitored page.
+CREATE TABLE watched_pages (
+    path TEXT PRIMARY KEY NOT NULL,
+    hash TEXT NOT NULL,
+    content TEXT NOT NULL
+);
+
+CREATE TABLE changes (
+    id BIGSERIAL PRIMARY KEY,
+    path TEXT NOT NULL,
+    detected_at BIGINT NOT NULL,
+    old_hash TEXT NOT NULL,
+    new_hash TEXT NOT NULL,
+    diff TEXT NOT NULL
+);
+
+CREATE INDEX changes_path ON changes (path);
+
+CREATE TABLE bans (
+    ip TEXT PRIMARY KEY NOT NULL,
+    reason TEXT NOT NULL,
+    -- Unix timestamp at which the ban expires.
+    banned_until BIGINT NOT NULL
+);
@@ -0,0 +1,22 @@
+CREATE TABLE users (
+    id TEXT PRIMARY KEY NOT NULL,
+    username TEXT NOT NULL UNIQUE,
+    -- Argon2id PHC string.
+    password_hash TEXT NOT NULL,
+    role TEXT NOT NULL,
+    webhook_url TEXT,
+    -- Comma-separated notification kinds delivered to webhook_url.
+    notify_kinds TEXT NOT NULL,
+    -- Requests per minute, NULL for the configured default.
+    rate_limit BIGINT,
+    created_at BIGINT NOT NULL
+);
+
+-- API tokens, stored as SHA-256 hashes.
+CREATE TABLE api_tokens (
+    token_hash TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users (id),
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX api_tokens_user ON api_tokens (user_id);
@@ -0,0 +1,13 @@
+-- Grades and exams the grade check has seen per credential, as JSON arrays
+-- of SHA-256 hashes, so only new ones are notified after a restart.
+CREATE TABLE vault_seen (
+    credential_id TEXT PRIMARY KEY NOT NULL,
+    grades TEXT NOT NULL,
+    exams TEXT NOT NULL
+);
+
+-- The Sunday (days since the epoch) the last weekly digest was sent on.
+CREATE TABLE vault_digest (
+    id BIGINT PRIMARY KEY NOT NULL,
+    sent_day BIGINT NOT NULL
+);
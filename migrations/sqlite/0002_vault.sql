@@ -0,0 +1,10 @@
+-- Upstream credentials used by scheduled scraping. Passwords are encrypted
+-- with the vault key, using the row id as associated data.
+CREATE TABLE credentials (
+    id TEXT PRIMARY KEY NOT NULL,
+    username TEXT NOT NULL,
+    nonce BLOB NOT NULL,
+    password BLOB NOT NULL,
+    notify_url TEXT,
+    created_at INTEGER NOT NULL
+);
@@ -0,0 +1,11 @@
+-- Append-only log of admin and authenticated actions.
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    at INTEGER NOT NULL,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target TEXT,
+    details TEXT
+);
+
+CREATE INDEX audit_log_action ON audit_log (action);
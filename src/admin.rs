@@ -0,0 +1,201 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Admin API mounted under `/_admin`.
+//!
+//! Only available when `ADMIN_TOKEN` is configured or user accounts are enabled.
+//! Every request must carry `Authorization: Bearer <ADMIN_TOKEN>` or the API
+//! token of a user with the admin role.
+
+use std::{net::IpAddr, sync::Arc};
+
+use axum::{
+    Extension, Json, Router,
+    extract::{Path, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{self, Actor},
+    ban::BanEntry,
+    config::{Mode, Upstream},
+    db, effective_config, metrics, rewrite_diff,
+    scheduler::JobStatus,
+    state::AppState,
+    users::{self, Role},
+    vault,
+};
+
+/// Builds the admin router, or `None` if there is no way to authenticate.
+pub fn router(state: &AppState) -> Option<Router<AppState>> {
+    if state.config.admin_token.is_none() && state.config.users.is_none() {
+        return None;
+    }
+
+    let router = Router::new()
+        .route("/bans", get(list_bans).delete(clear_bans))
+        .route("/bans/{ip}", delete(unban))
+        .route("/jobs", get(list_jobs))
+        .route("/mode", get(get_mode).put(set_mode))
+        .route("/config", get(effective_config::handler))
+        .route("/metrics", get(metrics::handler))
+        .route("/audit", get(audit::list))
+        .route("/diff", get(rewrite_diff::handler))
+        .route("/vault", get(vault::list).post(vault::add))
+        .route("/vault/{id}", delete(vault::remove))
+        .route("/users", get(users::list))
+        .route("/users/{id}", patch(users::update).delete(users::remove))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token));
+
+    Some(router)
+}
+
+/// Middleware checking the bearer token against `ADMIN_TOKEN` or admin user tokens.
+///
+/// Inserts the [`Actor`] for the audit log.
+pub async fn require_token(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let provided = req
+        .headers()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    if let (Some(provided), Some(expected)) = (provided, &state.config.admin_token)
+        && constant_time_eq(provided, expected)
+    {
+        req.extensions_mut().insert(Actor::admin_token());
+        return next.run(req).await;
+    }
+
+    if state.config.users.is_some()
+        && let Some(user) = users::authenticate(&state, req.headers()).await
+        && user.role == Role::Admin
+    {
+        req.extensions_mut().insert(Actor::user(&user.username));
+        return next.run(req).await;
+    }
+
+    (StatusCode::UNAUTHORIZED, "Invalid admin token").into_response()
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+async fn list_bans(State(state): State<AppState>) -> Json<Vec<BanEntry>> {
+    Json(state.bans.list())
+}
+
+async fn clear_bans(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+) -> StatusCode {
+    state.bans.clear();
+    if let Some(db) = &state.db {
+        db::log_error(db.clear_bans().await, "ban removal");
+    }
+    tracing::info!("Admin cleared all bans");
+    audit::record(&state, &actor, "ban.clear", None, None).await;
+    StatusCode::NO_CONTENT
+}
+
+async fn unban(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Path(ip): Path<IpAddr>,
+) -> StatusCode {
+    if let Some(db) = &state.db {
+        db::log_error(db.delete_ban(ip).await, "ban removal");
+    }
+    if state.bans.unban(ip) {
+        tracing::info!("Admin lifted ban of {}", state.config.privacy.ip(ip));
+        audit::record(&state, &actor, "ban.remove", Some(&ip.to_string()), None).await;
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn list_jobs(State(state): State<AppState>) -> Json<Vec<JobStatus>> {
+    Json(state.scheduler.status())
+}
+
+/// The current upstream.
+#[derive(Debug, Serialize)]
+struct ModeInfo {
+    mode: String,
+    url: String,
+}
+
+impl ModeInfo {
+    fn new(upstream: &Upstream) -> Self {
+        Self {
+            mode: upstream.mode.name().to_string(),
+            url: upstream.base.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SetMode {
+    /// "spsejecna", "jidelna" or an upstream URL.
+    mode: String,
+}
+
+async fn get_mode(State(state): State<AppState>) -> Json<ModeInfo> {
+    Json(ModeInfo::new(&state.upstream()))
+}
+
+/// Switches the upstream without a restart. CUSTOM upstreams must pass the same
+/// checks as at startup.
+async fn set_mode(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Json(req): Json<SetMode>,
+) -> Response {
+    let upstream = match state.config.default_upstream(Mode::parse(req.mode.trim())) {
+        Ok(upstream) => Arc::new(upstream),
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    if let Err(e) = state.config.check_upstream(&upstream) {
+        return (StatusCode::BAD_REQUEST, e).into_response();
+    }
+
+    let previous = std::mem::replace(
+        &mut *state.upstream.write().expect("upstream lock poisoned"),
+        upstream.clone(),
+    );
+    tracing::info!("Admin switched upstream to {}", upstream.base);
+    audit::record(
+        &state,
+        &actor,
+        "mode.set",
+        Some(upstream.mode.name()),
+        Some(&format!("previous: {}", previous.mode.name())),
+    )
+    .await;
+    Json(ModeInfo::new(&upstream)).into_response()
+}
@@ -0,0 +1,155 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Absence overview of the logged in student as JSON.
+//!
+//! The absence page lists one row per day, e.g. `2.10.` and
+//! "3 hodiny, z toho 1 neomluvená" or "1 pozdní příchod". The missed lessons
+//! are marked with their subject in the row where the page shows them, which
+//! is what the per-subject totals are counted from.
+
+use std::{collections::BTreeMap, sync::LazyLock};
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+
+use crate::state::AppState;
+
+static DAY_DATE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\d{1,2}\.\s?\d{1,2}\.(\s?\d{4})?$").expect("valid regex"));
+
+static COUNT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d+)\s+(\p{L}+)").expect("valid regex"));
+
+#[derive(Debug, Default, Serialize)]
+pub struct Absences {
+    pub days: Vec<AbsenceDay>,
+    pub totals: Totals,
+    /// Missed lessons per subject, for the days that list them.
+    pub subjects: BTreeMap<String, u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AbsenceDay {
+    /// Date as shown by the page, e.g. `2.10.`.
+    pub date: String,
+    pub lessons: u32,
+    pub unexcused: u32,
+    pub late: u32,
+    /// Whether all missed lessons of the day are excused.
+    pub excused: bool,
+    /// Subjects of the missed lessons, if the page lists them.
+    pub subjects: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Totals {
+    pub lessons: u32,
+    pub unexcused: u32,
+    pub late: u32,
+}
+
+/// Handler for `GET /api/absences`.
+pub async fn absences_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let page = match super::fetch_html(&state, &state.config.absences_path, &headers).await {
+        Ok(p) => p,
+        Err(response) => return response,
+    };
+
+    let document = Html::parse_document(&page.html);
+    let absences = parse(&document);
+    if absences.days.is_empty() && logged_out(&document) {
+        return (StatusCode::UNAUTHORIZED, "Not logged in").into_response();
+    }
+    Json(absences).into_response()
+}
+
+fn selector(s: &str) -> Selector {
+    Selector::parse(s).expect("valid selector")
+}
+
+fn text(element: ElementRef) -> String {
+    element
+        .text()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The school site shows its login form on pages that need a session.
+fn logged_out(document: &Html) -> bool {
+    document
+        .select(&selector("input[type=password]"))
+        .next()
+        .is_some()
+}
+
+fn parse(document: &Html) -> Absences {
+    let row_selector = selector("table tr");
+    let cell_selector = selector("td");
+    let subject_selector = selector(".subject");
+
+    let mut absences = Absences::default();
+    for row in document.select(&row_selector) {
+        let mut cells = row.select(&cell_selector);
+        let Some(date) = cells.next().map(text).filter(|d| DAY_DATE.is_match(d)) else {
+            continue;
+        };
+        let description = cells.map(text).collect::<Vec<_>>().join(" ");
+
+        let (mut lessons, mut unexcused, mut late) = (0, 0, 0);
+        for count in COUNT.captures_iter(&description) {
+            let n: u32 = count[1].parse().unwrap_or(0);
+            let word = count[2].to_lowercase();
+            if word.starts_with("neomluven") {
+                unexcused = n;
+            } else if word.starts_with("pozdn") {
+                late = n;
+            } else if word.starts_with("hodin") {
+                lessons = n;
+            }
+        }
+
+        let subjects: Vec<String> = row
+            .select(&subject_selector)
+            .map(text)
+            .filter(|s| !s.is_empty())
+            .collect();
+        for subject in &subjects {
+            *absences.subjects.entry(subject.clone()).or_default() += 1;
+        }
+
+        absences.totals.lessons += lessons;
+        absences.totals.unexcused += unexcused;
+        absences.totals.late += late;
+        absences.days.push(AbsenceDay {
+            date,
+            lessons,
+            unexcused,
+            late,
+            excused: unexcused == 0,
+            subjects,
+        });
+    }
+    absences
+}
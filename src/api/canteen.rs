@@ -0,0 +1,440 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Lunch ordering and account overview of the iCanteen canteen system.
+//!
+//! The requests are made with the client's cookies, i.e. its iCanteen session
+//! (see [`crate::jidelna`]), against the JIDELNA upstream: `MODE` if it is
+//! one, otherwise the first named upstream that is. iCanteen orders and
+//! cancels lunches through links in the day menu
+//! (`db/dbProcessOrder.jsp?...&type=make`), which carry a one-time token, so
+//! the menu is fetched first and the matching link followed.
+//!
+//! The account overview combines the month menu (credit and ordered lunches)
+//! with the account movements page, cached in a [`super::Cache`] until
+//! an order changes.
+
+use std::{collections::HashMap, sync::LazyLock};
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use regex::Regex;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+
+use crate::{jidelna, state::AppState};
+
+static ORDER_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"ajaxOrder\(\s*this\s*,\s*'([^']+)'").expect("valid regex"));
+
+#[derive(Debug, Deserialize)]
+pub struct OrderRequest {
+    /// Day of the lunch, `YYYY-MM-DD`.
+    date: String,
+    /// Number of the lunch on that day, starting at 1.
+    lunch: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderResult {
+    pub date: String,
+    pub lunch: usize,
+    /// Whether the lunch is ordered after the request.
+    pub ordered: bool,
+    /// Whether the request changed anything.
+    pub changed: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Account {
+    /// Credit balance in CZK, if the canteen shows it.
+    pub credit: Option<f64>,
+    /// Account movements, newest first as listed by the canteen.
+    pub payments: Vec<Payment>,
+    /// Ordered lunches that can still be changed.
+    pub orders: Vec<Order>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Payment {
+    pub date: String,
+    pub description: String,
+    /// Amount in CZK, negative for charges.
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Order {
+    /// Day of the lunch, `YYYY-MM-DD`.
+    pub date: String,
+    /// Number of the lunch on that day, as used by `/api/canteen/order`.
+    pub lunch: usize,
+    /// Name of the meal, if found.
+    pub meal: Option<String>,
+}
+
+/// A lunch of the day menu, as listed by the dashboard.
+#[derive(Debug, Serialize)]
+pub struct MenuItem {
+    /// Number of the lunch, as used by `/api/canteen/order`.
+    pub lunch: usize,
+    pub meal: Option<String>,
+    pub ordered: bool,
+}
+
+/// A lunch of the day menu.
+struct Lunch {
+    meal: Option<String>,
+    ordered: bool,
+    /// Link ordering or cancelling the lunch, relative to the menu page.
+    link: String,
+}
+
+/// Handler for `POST /api/canteen/order`.
+pub async fn order(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<OrderRequest>,
+) -> Response {
+    change_order(&state, &headers, req, true).await
+}
+
+/// Handler for `DELETE /api/canteen/order`.
+pub async fn cancel(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<OrderRequest>,
+) -> Response {
+    change_order(&state, &headers, req, false).await
+}
+
+/// Handler for `GET /api/canteen/account`.
+pub async fn account(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let Some(canteen) = canteen_state(&state) else {
+        return (StatusCode::NOT_FOUND, "No JIDELNA upstream is configured").into_response();
+    };
+
+    let key = super::session_key(&canteen, &headers);
+    if let Some(account) = state.canteen.get(&key) {
+        return Json(account).into_response();
+    }
+
+    // `Html` isn't `Send`, so each page is parsed before fetching the next one.
+    let (credit, orders) = match canteen_page(&canteen, &headers, "faces/secured/month.jsp").await {
+        Ok(month) => (credit(&month), orders(&month)),
+        Err(response) => return response,
+    };
+    let payments =
+        match canteen_page(&canteen, &headers, "faces/secured/db/dbMovementsView.jsp").await {
+            Ok(movements) => payments(&movements),
+            Err(response) => return response,
+        };
+
+    let account = Account {
+        credit,
+        payments,
+        orders,
+    };
+    state.canteen.insert(key, account.clone());
+    Json(account).into_response()
+}
+
+async fn change_order(
+    state: &AppState,
+    headers: &HeaderMap,
+    req: OrderRequest,
+    ordered: bool,
+) -> Response {
+    let Some(state) = canteen_state(state) else {
+        return (StatusCode::NOT_FOUND, "No JIDELNA upstream is configured").into_response();
+    };
+    if !valid_date(&req.date) || req.lunch == 0 {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Expected a date as YYYY-MM-DD and a lunch number from 1",
+        )
+            .into_response();
+    }
+
+    let (url, lunches) = match day_menu(&state, headers, &req.date).await {
+        Ok(menu) => menu,
+        Err(response) => return response,
+    };
+    let Some(lunch) = lunches.get(req.lunch - 1) else {
+        return (
+            StatusCode::CONFLICT,
+            "The lunch doesn't exist or can't be changed anymore",
+        )
+            .into_response();
+    };
+
+    let result = |ordered, changed| {
+        Json(OrderResult {
+            date: req.date.clone(),
+            lunch: req.lunch,
+            ordered,
+            changed,
+        })
+        .into_response()
+    };
+    if lunch.ordered == ordered {
+        return result(ordered, false);
+    }
+
+    // The link is only followed on the canteen's own host.
+    let Some(link) = url
+        .join(&lunch.link)
+        .ok()
+        .filter(|link| link.host_str() == url.host_str())
+    else {
+        return (StatusCode::BAD_GATEWAY, "Unexpected order link").into_response();
+    };
+    let path = match link.query() {
+        Some(query) => format!("{}?{}", link.path(), query),
+        None => link.path().to_string(),
+    };
+    if let Err(response) = super::fetch_html(&state, &path, headers).await {
+        return response;
+    }
+
+    // iCanteen answers with a page fragment either way, the menu tells whether it worked.
+    match day_menu(&state, headers, &req.date).await {
+        Ok((_, lunches)) => match lunches.get(req.lunch - 1) {
+            Some(lunch) if lunch.ordered == ordered => {
+                state.canteen.remove(&super::session_key(&state, headers));
+                result(ordered, true)
+            }
+            _ => (
+                StatusCode::CONFLICT,
+                "The canteen didn't accept the change (deadline passed or not enough credit?)",
+            )
+                .into_response(),
+        },
+        Err(response) => response,
+    }
+}
+
+/// The lunches of `date` with the client's canteen session, `None` without a
+/// JIDELNA upstream.
+pub(super) async fn menu(
+    state: &AppState,
+    headers: &HeaderMap,
+    date: &str,
+) -> Option<Result<Vec<MenuItem>, Response>> {
+    let state = canteen_state(state)?;
+    Some(day_menu(&state, headers, date).await.map(|(_, lunches)| {
+        lunches
+            .into_iter()
+            .enumerate()
+            .map(|(i, lunch)| MenuItem {
+                lunch: i + 1,
+                meal: lunch.meal,
+                ordered: lunch.ordered,
+            })
+            .collect()
+    }))
+}
+
+/// A copy of the state using the JIDELNA upstream, see the module docs.
+pub(super) fn canteen_state(state: &AppState) -> Option<AppState> {
+    let upstream = state.upstream();
+    if jidelna::applies(&upstream) {
+        return Some(state.clone());
+    }
+    state
+        .routes
+        .iter()
+        .find(|route| jidelna::applies(&route.upstream))
+        .map(|route| state.with_upstream(route.upstream.clone()))
+}
+
+/// Fetches the menu of `date`, returning its URL and the lunches that can
+/// still be ordered or cancelled.
+async fn day_menu(
+    state: &AppState,
+    headers: &HeaderMap,
+    date: &str,
+) -> Result<(Url, Vec<Lunch>), Response> {
+    let path = format!(
+        "{}/faces/secured/db/dbJidelnicekOnDayView.jsp?day={}&terminal=false&printer=false&keyboard=false",
+        state.config.canteen_path, date
+    );
+    let page = super::fetch_html(state, &path, headers).await?;
+    let document = Html::parse_document(&page.html);
+    if logged_out(&document) {
+        return Err((StatusCode::UNAUTHORIZED, "Not logged in to the canteen").into_response());
+    }
+    Ok((page.url, lunches(&document)))
+}
+
+/// Fetches a page of the canteen's secured area, `page` being relative to `CANTEEN_PATH`.
+async fn canteen_page(state: &AppState, headers: &HeaderMap, page: &str) -> Result<Html, Response> {
+    let path = format!(
+        "{}/{}?terminal=false&printer=false&keyboard=false",
+        state.config.canteen_path, page
+    );
+    let page = super::fetch_html(state, &path, headers).await?;
+    let document = Html::parse_document(&page.html);
+    if logged_out(&document) {
+        return Err((StatusCode::UNAUTHORIZED, "Not logged in to the canteen").into_response());
+    }
+    Ok(document)
+}
+
+/// Whether iCanteen sent its login form instead of the requested page.
+pub(super) fn logged_out(document: &Html) -> bool {
+    document
+        .select(&Selector::parse("input[type=password]").expect("valid selector"))
+        .next()
+        .is_some()
+}
+
+/// Lunches with an order or cancel link, in the order of the menu.
+fn lunches(document: &Html) -> Vec<Lunch> {
+    let buttons = Selector::parse("[onclick]").expect("valid selector");
+    document
+        .select(&buttons)
+        .filter_map(|button| {
+            let onclick = button.value().attr("onclick")?;
+            let link = ORDER_LINK.captures(onclick)?[1].to_string();
+            // "make" orders, "reorder" swaps for another ordered lunch of the day.
+            let ordered = link_param(&link, "type")? == "delete";
+            Some(Lunch {
+                meal: meal_name(button),
+                ordered,
+                link,
+            })
+        })
+        .collect()
+}
+
+/// The value of the query parameter `name` of an order link.
+fn link_param<'a>(link: &'a str, name: &str) -> Option<&'a str> {
+    link.split(['?', '&'])
+        .find_map(|param| param.strip_prefix(name)?.strip_prefix('='))
+}
+
+/// Credit balance shown in the header of the secured pages.
+fn credit(document: &Html) -> Option<f64> {
+    let selector = Selector::parse("#Kredit").expect("valid selector");
+    let text = document
+        .select(&selector)
+        .next()?
+        .text()
+        .collect::<String>();
+    parse_amount(&text)
+}
+
+/// Ordered lunches of the month menu, numbered like in the day menu.
+fn orders(document: &Html) -> Vec<Order> {
+    let buttons = Selector::parse("[onclick]").expect("valid selector");
+    let mut numbers: HashMap<String, usize> = HashMap::new();
+    let mut orders = Vec::new();
+
+    for button in document.select(&buttons) {
+        let Some(captures) = button
+            .value()
+            .attr("onclick")
+            .and_then(|onclick| ORDER_LINK.captures(onclick))
+        else {
+            continue;
+        };
+        let link = &captures[1];
+        let (Some(date), Some(kind)) = (link_param(link, "day"), link_param(link, "type")) else {
+            continue;
+        };
+        let number = numbers.entry(date.to_string()).or_default();
+        *number += 1;
+        if kind != "delete" {
+            continue;
+        }
+
+        orders.push(Order {
+            date: date.to_string(),
+            lunch: *number,
+            meal: meal_name(button),
+        });
+    }
+    orders
+}
+
+/// Name of the meal of an order button, from the menu item around it.
+fn meal_name(button: ElementRef) -> Option<String> {
+    let name = Selector::parse(".jidWrapCenter").expect("valid selector");
+    button
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .find(|item| {
+            item.value()
+                .classes()
+                .any(|class| class == "jidelnicekItem")
+        })
+        .and_then(|item| item.select(&name).next())
+        .map(|name| normalize(&name.text().collect::<String>()))
+        .filter(|name| !name.is_empty())
+}
+
+/// Rows of the account movements table: date, description, ..., amount.
+fn payments(document: &Html) -> Vec<Payment> {
+    let rows = Selector::parse("table tr").expect("valid selector");
+    let cells = Selector::parse("td").expect("valid selector");
+    document
+        .select(&rows)
+        .filter_map(|row| {
+            let cells: Vec<String> = row
+                .select(&cells)
+                .map(|cell| normalize(&cell.text().collect::<String>()))
+                .collect();
+            let [date, description, .., amount] = cells.as_slice() else {
+                return None;
+            };
+            // Other rows (headers, sums) have no amount.
+            Some(Payment {
+                date: date.clone(),
+                description: description.clone(),
+                amount: parse_amount(amount)?,
+            })
+        })
+        .collect()
+}
+
+/// Parses amounts like `-1 234,50 Kč`.
+fn parse_amount(text: &str) -> Option<f64> {
+    let number: String = text
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, ',' | '.' | '-'))
+        .map(|c| if c == ',' { '.' } else { c })
+        .collect();
+    number.parse().ok()
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn valid_date(date: &str) -> bool {
+    let bytes = date.as_bytes();
+    bytes.len() == 10
+        && bytes[4] == b'-'
+        && bytes[7] == b'-'
+        && bytes
+            .iter()
+            .enumerate()
+            .all(|(i, b)| i == 4 || i == 7 || b.is_ascii_digit())
+}
@@ -0,0 +1,34 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use serde::Deserialize;
+
+use crate::{state::AppState, watcher::Change};
+
+#[derive(Debug, Deserialize)]
+pub struct ChangesQuery {
+    path: Option<String>,
+}
+
+/// Handler for `GET /api/changes`.
+pub async fn changes_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ChangesQuery>,
+) -> Json<Vec<Change>> {
+    Json(state.watch.changes(query.path.as_deref()))
+}
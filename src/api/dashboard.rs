@@ -0,0 +1,215 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! One JSON document with everything a widget shows, so mobile apps need a
+//! single round trip.
+//!
+//! The timetable, grades, canteen menu and substitution pages are fetched
+//! concurrently. A section that fails is `null` and its error is listed in
+//! `errors`, the rest of the dashboard is still returned.
+
+use std::{collections::BTreeMap, sync::LazyLock};
+
+use axum::{
+    Json,
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use regex::Regex;
+use scraper::{Html, Selector};
+use serde::Serialize;
+
+use super::{
+    canteen::{self, MenuItem},
+    export, ical,
+};
+use crate::state::AppState;
+
+/// Grades from this many last days are reported as new.
+const NEW_GRADE_DAYS: i64 = 7;
+
+static TIME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(\d{1,2}):(\d{2})\b").expect("valid regex"));
+
+#[derive(Debug, Serialize)]
+pub struct Dashboard {
+    /// Today in Prague, `YYYY-MM-DD`.
+    pub date: String,
+    /// Today's lessons.
+    pub today: Option<Vec<Lesson>>,
+    /// Today's lessons that haven't started yet.
+    pub next: Option<Vec<Lesson>>,
+    /// Grades from the last week.
+    pub grades: Option<Vec<Grade>>,
+    /// Today's canteen menu, `null` without a JIDELNA upstream.
+    pub canteen: Option<Vec<MenuItem>>,
+    /// Rows of the substitution table.
+    pub substitutions: Option<Vec<Vec<String>>>,
+    /// Errors of the sections that are `null`, by section.
+    pub errors: BTreeMap<&'static str, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Lesson {
+    pub period: String,
+    pub subject: String,
+    pub teacher: String,
+    pub room: String,
+    pub group: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Grade {
+    pub subject: String,
+    pub value: String,
+    pub weight: String,
+    pub description: String,
+    pub date: String,
+}
+
+/// Handler for `GET /api/dashboard`.
+pub async fn dashboard_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let (today, minute) = ical::prague_now();
+    let date = today.to_string();
+
+    let (timetable, grades, menu, substitutions) = tokio::join!(
+        timetable(&state, &headers, today),
+        grades(&state, &headers, today),
+        canteen::menu(&state, &headers, &date),
+        substitutions(&state, &headers),
+    );
+
+    let mut errors = BTreeMap::new();
+    let today_lessons = section(&mut errors, "timetable", timetable);
+    let next = today_lessons.as_ref().map(|lessons| {
+        lessons
+            .iter()
+            .filter(|lesson| start_minute(&lesson.period).is_none_or(|start| start > minute))
+            .cloned()
+            .collect()
+    });
+    let grades = section(&mut errors, "grades", grades);
+    let canteen =
+        menu.and_then(|menu| section(&mut errors, "canteen", menu.map_err(|r| error(&r))));
+    let substitutions = section(&mut errors, "substitutions", substitutions);
+
+    Json(Dashboard {
+        date,
+        today: today_lessons,
+        next,
+        grades,
+        canteen,
+        substitutions,
+        errors,
+    })
+    .into_response()
+}
+
+/// The value of a section, recording its error in `errors`.
+fn section<T>(
+    errors: &mut BTreeMap<&'static str, String>,
+    name: &'static str,
+    result: Result<T, String>,
+) -> Option<T> {
+    result.map_err(|e| errors.insert(name, e)).ok()
+}
+
+async fn timetable(
+    state: &AppState,
+    headers: &HeaderMap,
+    today: ical::Date,
+) -> Result<Vec<Lesson>, String> {
+    let page = super::fetch_html(state, &state.config.timetable_path, headers)
+        .await
+        .map_err(|r| error(&r))?;
+    let day = ical::WEEKDAYS[today.weekday() as usize];
+
+    let sheet = export::timetable(&Html::parse_document(&page.html));
+    Ok(sheet
+        .rows
+        .into_iter()
+        .filter(|row| row[0].to_lowercase().starts_with(day))
+        .map(|row| {
+            let [_, period, subject, teacher, room, group] =
+                <[String; 6]>::try_from(row).unwrap_or_default();
+            Lesson {
+                period,
+                subject,
+                teacher,
+                room,
+                group,
+            }
+        })
+        .collect())
+}
+
+async fn grades(
+    state: &AppState,
+    headers: &HeaderMap,
+    today: ical::Date,
+) -> Result<Vec<Grade>, String> {
+    let page = super::fetch_html(state, &state.config.grades_path, headers)
+        .await
+        .map_err(|r| error(&r))?;
+
+    let document = Html::parse_document(&page.html);
+    if logged_out(&document) {
+        return Err("Not logged in".to_string());
+    }
+    let since = today.days() - NEW_GRADE_DAYS;
+    Ok(export::grades(&document)
+        .rows
+        .into_iter()
+        .filter_map(|row| {
+            let [subject, value, weight, description, date] = <[String; 5]>::try_from(row).ok()?;
+            let day = ical::Date::parse_czech(&date)?;
+            (day.days() >= since).then_some(Grade {
+                subject,
+                value,
+                weight,
+                description,
+                date,
+            })
+        })
+        .collect())
+}
+
+async fn substitutions(state: &AppState, headers: &HeaderMap) -> Result<Vec<Vec<String>>, String> {
+    let page = super::fetch_html(state, &state.config.substitutions_path, headers)
+        .await
+        .map_err(|r| error(&r))?;
+
+    Ok(super::digest::substitution_rows(&Html::parse_document(
+        &page.html,
+    )))
+}
+
+fn logged_out(document: &Html) -> bool {
+    document
+        .select(&Selector::parse("input[type=password]").expect("valid selector"))
+        .next()
+        .is_some()
+}
+
+/// Describes an error response of [`super::fetch_html`].
+fn error(response: &Response) -> String {
+    response.status().to_string()
+}
+
+/// Minute of the day at which a period like "3 9:40 - 10:25" starts.
+fn start_minute(period: &str) -> Option<u32> {
+    let time = TIME.captures(period)?;
+    Some(time[1].parse::<u32>().ok()? * 60 + time[2].parse::<u32>().ok()?)
+}
@@ -0,0 +1,295 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Weekly digest of the coming week: substitutions, exams and school events.
+//!
+//! Served as Markdown or HTML for the logged in user, and sent by the vault
+//! to stored accounts every Sunday (see [`crate::vault::send_digests`]).
+
+use axum::{
+    Extension,
+    extract::{Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use scraper::{Html, Selector};
+use serde::Deserialize;
+
+use super::{
+    events::{self, SchoolEvent},
+    exams::{self, Exam},
+    ical,
+};
+use crate::{state::AppState, tls::Https, utils::escape_xml};
+
+/// The content of a digest.
+pub struct Digest {
+    /// Monday of the week.
+    start: ical::Date,
+    /// Sunday of the week.
+    end: ical::Date,
+    substitutions: Vec<Vec<String>>,
+    exams: Vec<Exam>,
+    events: Vec<SchoolEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DigestQuery {
+    format: Option<String>,
+}
+
+/// Handler for `GET /api/digest/weekly?format=md|html`.
+pub async fn weekly_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    https: Option<Extension<Https>>,
+    Query(query): Query<DigestQuery>,
+) -> Response {
+    let html = match query.format.as_deref() {
+        None | Some("md") | Some("markdown") => false,
+        Some("html") => true,
+        Some(other) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Unknown format: {}", other),
+            )
+                .into_response();
+        }
+    };
+
+    let substitutions = async {
+        let page = super::fetch_html(&state, &state.config.substitutions_path, &headers).await?;
+        Ok::<_, Response>(substitution_rows(&Html::parse_document(&page.html)))
+    };
+    let (substitutions, exams, events) = tokio::join!(
+        substitutions,
+        exams::load(&state, &headers),
+        events::fetch_events(&state, &headers, https.is_some()),
+    );
+    let digest = match (substitutions, exams, events) {
+        (Ok(substitutions), Ok(exams), Ok(events)) => {
+            Digest::next_week(substitutions, exams, events)
+        }
+        (Err(response), _, _) | (_, Err(response), _) | (_, _, Err(response)) => return response,
+    };
+
+    let (body, content_type) = if html {
+        (digest.html(), "text/html; charset=utf-8")
+    } else {
+        (digest.markdown(), "text/markdown; charset=utf-8")
+    };
+    let mut response = body.into_response();
+    response
+        .headers_mut()
+        .insert("content-type", HeaderValue::from_static(content_type));
+    response
+}
+
+/// Rows of the substitution table, without empty ones.
+pub fn substitution_rows(document: &Html) -> Vec<Vec<String>> {
+    let rows = Selector::parse("table tr").expect("valid selector");
+    let cells = Selector::parse("td").expect("valid selector");
+    document
+        .select(&rows)
+        .map(|row| {
+            row.select(&cells)
+                .map(|cell| {
+                    cell.text()
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>()
+        })
+        .filter(|cells| cells.iter().any(|cell| !cell.is_empty()))
+        .collect()
+}
+
+impl Digest {
+    /// A digest of the week starting next Monday (Prague time), keeping the
+    /// exams and events of that week.
+    pub fn next_week(
+        substitutions: Vec<Vec<String>>,
+        mut exams: Vec<Exam>,
+        mut events: Vec<SchoolEvent>,
+    ) -> Self {
+        let (today, _) = ical::prague_now();
+        let start = ical::Date::from_days(today.days() + 7 - i64::from(today.weekday()));
+        let end = ical::Date::from_days(start.days() + 6);
+
+        exams.retain(|exam| {
+            ical::Date::parse_czech(&exam.date).is_some_and(|date| (start..=end).contains(&date))
+        });
+        exams.sort_by_key(|exam| ical::Date::parse_czech(&exam.date));
+        events.retain(|event| {
+            let (Some(first), Some(last)) = (
+                events::parse_iso(&event.start),
+                events::parse_iso(&event.end),
+            ) else {
+                return false;
+            };
+            first <= end && last >= start
+        });
+        events.sort_by(|a, b| a.start.cmp(&b.start));
+
+        Self {
+            start,
+            end,
+            substitutions,
+            exams,
+            events,
+        }
+    }
+
+    /// Title of the digest, e.g. "Týden 20. 10. – 26. 10. 2025".
+    pub fn title(&self) -> String {
+        format!(
+            "Týden {} – {} {}",
+            short_date(self.start),
+            short_date(self.end),
+            self.end.year
+        )
+    }
+
+    pub fn markdown(&self) -> String {
+        let mut out = format!("# {}\n", self.title());
+        for (heading, items) in self.sections() {
+            out.push_str(&format!("\n## {}\n\n", heading));
+            if items.is_empty() {
+                out.push_str("Nic.\n");
+            }
+            for item in items {
+                out.push_str(&format!("- {}\n", item.markdown()));
+            }
+        }
+        out
+    }
+
+    pub fn html(&self) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html lang=\"cs\"><head><meta charset=\"utf-8\"><title>{0}</title></head><body>\n<h1>{0}</h1>\n",
+            escape_xml(&self.title())
+        );
+        for (heading, items) in self.sections() {
+            out.push_str(&format!("<h2>{}</h2>\n", escape_xml(heading)));
+            if items.is_empty() {
+                out.push_str("<p>Nic.</p>\n");
+                continue;
+            }
+            out.push_str("<ul>\n");
+            for item in items {
+                out.push_str(&format!("<li>{}</li>\n", item.html()));
+            }
+            out.push_str("</ul>\n");
+        }
+        out.push_str("</body></html>\n");
+        out
+    }
+
+    fn sections(&self) -> [(&'static str, Vec<Item>); 3] {
+        let substitutions = self
+            .substitutions
+            .iter()
+            .map(|row| Item {
+                label: None,
+                text: row.join(" | "),
+                url: None,
+            })
+            .collect();
+        let exams = self
+            .exams
+            .iter()
+            .map(|exam| Item {
+                label: Some(exam.date.clone()),
+                text: match exam.kind {
+                    "homework" => format!("Úkol z {}: {}", exam.subject, exam.description),
+                    _ => format!("Test z {}: {}", exam.subject, exam.description),
+                },
+                url: None,
+            })
+            .collect();
+        let events = self
+            .events
+            .iter()
+            .map(|event| {
+                let when = match (
+                    events::parse_iso(&event.start),
+                    events::parse_iso(&event.end),
+                ) {
+                    (Some(start), Some(end)) if start != end => {
+                        format!("{} – {}", short_date(start), short_date(end))
+                    }
+                    (Some(start), _) => short_date(start),
+                    _ => event.start.clone(),
+                };
+                let text = match &event.category {
+                    Some(category) => format!("{} ({})", event.title, category),
+                    None => event.title.clone(),
+                };
+                Item {
+                    label: Some(when),
+                    text,
+                    url: Some(event.url.clone()),
+                }
+            })
+            .collect();
+
+        [
+            ("Suplování", substitutions),
+            ("Testy a úkoly", exams),
+            ("Akce", events),
+        ]
+    }
+}
+
+/// A line of a digest section.
+struct Item {
+    label: Option<String>,
+    text: String,
+    url: Option<String>,
+}
+
+impl Item {
+    fn markdown(&self) -> String {
+        let text = match &self.url {
+            Some(url) => format!("[{}]({})", self.text, url),
+            None => self.text.clone(),
+        };
+        match &self.label {
+            Some(label) => format!("**{}** {}", label, text),
+            None => text,
+        }
+    }
+
+    fn html(&self) -> String {
+        let text = match &self.url {
+            Some(url) => format!(
+                "<a href=\"{}\">{}</a>",
+                escape_xml(url),
+                escape_xml(&self.text)
+            ),
+            None => escape_xml(&self.text),
+        };
+        match &self.label {
+            Some(label) => format!("<strong>{}</strong> {}", escape_xml(label), text),
+            None => text,
+        }
+    }
+}
+
+fn short_date(date: ical::Date) -> String {
+    format!("{}. {}.", date.day, date.month)
+}
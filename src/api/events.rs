@@ -0,0 +1,295 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! School events (trips, holidays, open days) as JSON, iCal and RSS.
+//!
+//! Events are the links to articles (`NEWS_PATH`) on the events page that
+//! have a date next to them, either a single day or a range like
+//! `12. – 14. 3. 2025`.
+
+use std::{collections::HashSet, sync::LazyLock};
+
+use axum::{
+    Extension, Json,
+    extract::State,
+    http::{HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use regex::Regex;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+
+use super::{ical, news::CZECH_DATE};
+use crate::{state::AppState, tls::Https, utils};
+
+/// A date range whose start may leave out the month and year of its end.
+static DATE_RANGE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"\b(\d{1,2})\.\s?(?:(\d{1,2})\.\s?)?(\d{4})?\s*[–—-]\s*(\d{1,2})\.\s?(\d{1,2})\.\s?(\d{4})\b",
+    )
+    .expect("valid regex")
+});
+
+#[derive(Debug, Serialize)]
+pub struct SchoolEvent {
+    /// Article id, usable with `/api/news/{id}`.
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    /// First day, `YYYY-MM-DD`.
+    pub start: String,
+    /// Last day, `YYYY-MM-DD`, the same as `start` for one-day events.
+    pub end: String,
+    pub category: Option<String>,
+}
+
+/// Handler for `GET /api/events`.
+pub async fn events_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    https: Option<Extension<Https>>,
+) -> Response {
+    match fetch_events(&state, &headers, https.is_some()).await {
+        Ok(events) => Json(events).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Handler for `GET /api/events.ics`.
+pub async fn events_ics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    https: Option<Extension<Https>>,
+) -> Response {
+    let events = match fetch_events(&state, &headers, https.is_some()).await {
+        Ok(events) => events,
+        Err(response) => return response,
+    };
+
+    let calendar: Vec<ical::Event> = events
+        .iter()
+        .filter_map(|event| {
+            Some(ical::Event {
+                id: event.url.clone(),
+                summary: event.title.clone(),
+                description: "",
+                start: parse_iso(&event.start)?,
+                end: parse_iso(&event.end),
+                category: event.category.as_deref(),
+                url: Some(&event.url),
+            })
+        })
+        .collect();
+    ical::response(ical::calendar("Akce školy", &calendar))
+}
+
+/// Handler for `GET /api/events.rss`.
+pub async fn events_rss(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    https: Option<Extension<Https>>,
+) -> Response {
+    let events = match fetch_events(&state, &headers, https.is_some()).await {
+        Ok(events) => events,
+        Err(response) => return response,
+    };
+
+    let proxy_origin =
+        utils::determine_proxy_origin(state.config.base_url.as_deref(), &headers, https.is_some());
+    let mut rss = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\"><channel>\
+         <title>Akce školy</title><link>{}</link><description>Akce a události školy</description>",
+        utils::escape_xml(&format!("{}{}", proxy_origin, state.config.events_path))
+    );
+    for event in &events {
+        let when = if event.start == event.end {
+            event.start.clone()
+        } else {
+            format!("{} – {}", event.start, event.end)
+        };
+        rss.push_str(&format!(
+            "<item><title>{}</title><link>{}</link><guid isPermaLink=\"true\">{}</guid><description>{}</description>",
+            utils::escape_xml(&event.title),
+            utils::escape_xml(&event.url),
+            utils::escape_xml(&event.url),
+            utils::escape_xml(&when)
+        ));
+        if let Some(category) = &event.category {
+            rss.push_str(&format!(
+                "<category>{}</category>",
+                utils::escape_xml(category)
+            ));
+        }
+        rss.push_str("</item>");
+    }
+    rss.push_str("</channel></rss>\n");
+
+    let mut response = rss.into_response();
+    response.headers_mut().insert(
+        "content-type",
+        HeaderValue::from_static("application/rss+xml; charset=utf-8"),
+    );
+    response
+}
+
+/// Fetches and parses the events page, with links pointing to the proxy.
+pub(super) async fn fetch_events(
+    state: &AppState,
+    headers: &HeaderMap,
+    https: bool,
+) -> Result<Vec<SchoolEvent>, Response> {
+    let page = super::fetch_html(state, &state.config.events_path, headers).await?;
+    let proxy_origin =
+        utils::determine_proxy_origin(state.config.base_url.as_deref(), headers, https);
+    let mut events = parse_events(
+        &Html::parse_document(&page.html),
+        &page.url,
+        article_prefix(&state.config.news_path),
+    );
+    for event in &mut events {
+        event.url =
+            utils::rewrite_content_urls(std::mem::take(&mut event.url), &proxy_origin, state);
+    }
+    Ok(events)
+}
+
+/// The path of article links, `NEWS_PATH` up to the id.
+pub fn article_prefix(news_path: &str) -> &str {
+    news_path.split("{id}").next().unwrap_or_default()
+}
+
+/// Extracts the events of the events page at `base`.
+pub fn parse_events(document: &Html, base: &Url, article_prefix: &str) -> Vec<SchoolEvent> {
+    let links = Selector::parse("a[href]").expect("valid selector");
+    let heading = Selector::parse("h1, h2, h3, h4").expect("valid selector");
+    let category_selector =
+        Selector::parse(".category, .kategorie, .tag, .label").expect("valid selector");
+
+    let mut seen = HashSet::new();
+    let mut events = Vec::new();
+    for link in document.select(&links) {
+        let Some(url) = link
+            .value()
+            .attr("href")
+            .and_then(|href| base.join(href).ok())
+            .filter(|url| url.host_str() == base.host_str())
+        else {
+            continue;
+        };
+        let Some(id) = url
+            .path()
+            .strip_prefix(article_prefix)
+            .filter(|id| !id.is_empty() && !id.contains('/'))
+        else {
+            continue;
+        };
+        if !seen.insert(url.to_string()) {
+            continue;
+        }
+
+        // The closest element around the link that also holds the date, but
+        // no other event.
+        let other_event = |el: ElementRef| {
+            el.select(&links).any(|other| {
+                other
+                    .value()
+                    .attr("href")
+                    .and_then(|href| base.join(href).ok())
+                    .is_some_and(|other| {
+                        other != url
+                            && other.host_str() == base.host_str()
+                            && other.path().len() > article_prefix.len()
+                            && other.path().starts_with(article_prefix)
+                    })
+            })
+        };
+        let Some((container, (start, end))) = link
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .take(4)
+            .take_while(|el| !other_event(*el))
+            .find_map(|el| Some((el, dates(&text(el))?)))
+        else {
+            continue;
+        };
+
+        let title = match text(link) {
+            t if t.is_empty() => container
+                .select(&heading)
+                .next()
+                .map(text)
+                .unwrap_or_default(),
+            t => t,
+        };
+        events.push(SchoolEvent {
+            id: id.to_string(),
+            title,
+            url: url.to_string(),
+            start: start.to_string(),
+            end: end.to_string(),
+            category: container
+                .select(&category_selector)
+                .next()
+                .map(text)
+                .filter(|c| !c.is_empty()),
+        });
+    }
+    events
+}
+
+/// The first date or date range in `text`.
+fn dates(text: &str) -> Option<(ical::Date, ical::Date)> {
+    let single = CZECH_DATE.find(text);
+    let range = DATE_RANGE.captures(text).filter(|range| {
+        single.is_none_or(|single| range.get(0).is_some_and(|r| r.start() <= single.start()))
+    });
+
+    let Some(range) = range else {
+        let date = ical::Date::parse_czech(single?.as_str())?;
+        return Some((date, date));
+    };
+    let end = ical::Date::parse_czech(&format!("{}.{}.{}", &range[4], &range[5], &range[6]))?;
+    let start = ical::Date {
+        year: range
+            .get(3)
+            .and_then(|y| y.as_str().parse().ok())
+            .unwrap_or(end.year),
+        month: range
+            .get(2)
+            .and_then(|m| m.as_str().parse().ok())
+            .unwrap_or(end.month),
+        day: range[1].parse().ok()?,
+    };
+    Some((start, end.max(start)))
+}
+
+pub(super) fn parse_iso(date: &str) -> Option<ical::Date> {
+    let mut parts = date.splitn(3, '-');
+    Some(ical::Date {
+        year: parts.next()?.parse().ok()?,
+        month: parts.next()?.parse().ok()?,
+        day: parts.next()?.parse().ok()?,
+    })
+}
+
+fn text(element: ElementRef) -> String {
+    element
+        .text()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,170 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Upcoming exams and homework of the logged in student, as JSON and iCal.
+//!
+//! The exam page lists one row per entry with its date, subject and
+//! description; homework is told apart by a `homework` class or "úkol" in the
+//! row. Results are cached per session, and the vault's grade check uses
+//! [`exams`] to notify about new entries.
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+
+use super::{ical, news::CZECH_DATE};
+use crate::state::AppState;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Exam {
+    pub subject: String,
+    /// Date as shown by the page, e.g. `12.10.2025`.
+    pub date: String,
+    /// "exam" or "homework".
+    pub kind: &'static str,
+    pub description: String,
+}
+
+/// Handler for `GET /api/exams`.
+pub async fn exams_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    match load(&state, &headers).await {
+        Ok(exams) => Json(exams).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Handler for `GET /api/exams.ics`, for subscribing from calendar apps.
+pub async fn exams_ics(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    match load(&state, &headers).await {
+        Ok(exams) => ical::response(to_ics(&exams)),
+        Err(response) => response,
+    }
+}
+
+pub(super) async fn load(state: &AppState, headers: &HeaderMap) -> Result<Vec<Exam>, Response> {
+    let key = super::session_key(state, headers);
+    if let Some(exams) = state.exams.get(&key) {
+        return Ok(exams);
+    }
+
+    let page = super::fetch_html(state, &state.config.exams_path, headers).await?;
+    let exams = {
+        let document = Html::parse_document(&page.html);
+        let exams = exams(&document);
+        if exams.is_empty() && logged_out(&document) {
+            return Err((StatusCode::UNAUTHORIZED, "Not logged in").into_response());
+        }
+        exams
+    };
+    state.exams.insert(key, exams.clone());
+    Ok(exams)
+}
+
+fn selector(s: &str) -> Selector {
+    Selector::parse(s).expect("valid selector")
+}
+
+fn text(element: ElementRef) -> String {
+    element
+        .text()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn logged_out(document: &Html) -> bool {
+    document
+        .select(&selector("input[type=password]"))
+        .next()
+        .is_some()
+}
+
+/// Extracts the entries of the exam page.
+///
+/// A row is an entry if one of its cells is a date. The subject is the
+/// `.subject` element or the cell after the date, the other cells make up the
+/// description.
+pub fn exams(document: &Html) -> Vec<Exam> {
+    let row_selector = selector("table tr");
+    let cell_selector = selector("td");
+    let subject_selector = selector(".subject");
+
+    let mut exams = Vec::new();
+    for row in document.select(&row_selector) {
+        let cells: Vec<String> = row.select(&cell_selector).map(text).collect();
+        let Some((date_cell, date)) = cells
+            .iter()
+            .enumerate()
+            .find_map(|(i, cell)| Some((i, CZECH_DATE.find(cell)?.as_str().replace(' ', ""))))
+        else {
+            continue;
+        };
+
+        let (subject, subject_cell) = match row.select(&subject_selector).next().map(text) {
+            Some(subject) => (subject, None),
+            None => (
+                cells.get(date_cell + 1).cloned().unwrap_or_default(),
+                Some(date_cell + 1),
+            ),
+        };
+        let description = cells
+            .iter()
+            .enumerate()
+            .filter(|(i, cell)| {
+                *i != date_cell && Some(*i) != subject_cell && !cell.is_empty() && **cell != subject
+            })
+            .map(|(_, cell)| cell.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let homework = row.value().classes().any(|c| c == "homework")
+            || text(row).to_lowercase().contains("úkol");
+        exams.push(Exam {
+            subject,
+            date,
+            kind: if homework { "homework" } else { "exam" },
+            description,
+        });
+    }
+    exams
+}
+
+/// Renders the entries as a calendar of all-day events.
+fn to_ics(exams: &[Exam]) -> String {
+    let events: Vec<ical::Event> = exams
+        .iter()
+        .filter_map(|exam| {
+            Some(ical::Event {
+                id: format!("{}\n{}\n{}", exam.subject, exam.date, exam.description),
+                summary: match exam.kind {
+                    "homework" => format!("Úkol: {}", exam.subject),
+                    _ => format!("Test: {}", exam.subject),
+                },
+                description: &exam.description,
+                start: ical::Date::parse_czech(&exam.date)?,
+                end: None,
+                category: None,
+                url: None,
+            })
+        })
+        .collect();
+    ical::calendar("Testy a úkoly", &events)
+}
@@ -0,0 +1,316 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Spreadsheet exports of the grades and timetable pages.
+//!
+//! The pages are fetched with the client's cookies, so the export always
+//! reflects the logged in user.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use rust_xlsxwriter::{Format as CellFormat, Workbook};
+use scraper::{ElementRef, Html, Selector};
+
+use super::news::CZECH_DATE;
+use crate::state::AppState;
+
+/// Tabular data ready to be serialized.
+pub struct Sheet {
+    pub name: &'static str,
+    pub headers: &'static [&'static str],
+    pub rows: Vec<Vec<String>>,
+}
+
+#[derive(Clone, Copy)]
+enum FileType {
+    Csv,
+    Xlsx,
+}
+
+pub async fn grades_csv(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    export(&state, &headers, Kind::Grades, FileType::Csv).await
+}
+
+pub async fn grades_xlsx(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    export(&state, &headers, Kind::Grades, FileType::Xlsx).await
+}
+
+pub async fn timetable_csv(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    export(&state, &headers, Kind::Timetable, FileType::Csv).await
+}
+
+pub async fn timetable_xlsx(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    export(&state, &headers, Kind::Timetable, FileType::Xlsx).await
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Grades,
+    Timetable,
+}
+
+async fn export(
+    state: &AppState,
+    headers: &HeaderMap,
+    kind: Kind,
+    file_type: FileType,
+) -> Response {
+    let path = match kind {
+        Kind::Grades => &state.config.grades_path,
+        Kind::Timetable => &state.config.timetable_path,
+    };
+    let page = match super::fetch_html(state, path, headers).await {
+        Ok(p) => p,
+        Err(response) => return response,
+    };
+
+    let document = Html::parse_document(&page.html);
+    let sheet = match kind {
+        Kind::Grades => grades(&document),
+        Kind::Timetable => timetable(&document),
+    };
+
+    let (body, content_type, extension) = match file_type {
+        FileType::Csv => (to_csv(&sheet), "text/csv; charset=utf-8", "csv"),
+        FileType::Xlsx => match to_xlsx(&sheet) {
+            Ok(bytes) => (
+                bytes,
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                "xlsx",
+            ),
+            Err(e) => {
+                tracing::error!("Failed to build XLSX export: {}", e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, "Export failed").into_response();
+            }
+        },
+    };
+
+    let mut response = body.into_response();
+    let headers = response.headers_mut();
+    headers.insert("content-type", HeaderValue::from_static(content_type));
+    if let Ok(disposition) = HeaderValue::from_str(&format!(
+        "attachment; filename=\"{}.{}\"",
+        sheet.name, extension
+    )) {
+        headers.insert("content-disposition", disposition);
+    }
+    headers.insert(
+        "cache-control",
+        HeaderValue::from_static("private, no-store"),
+    );
+    response
+}
+
+fn selector(s: &str) -> Selector {
+    Selector::parse(s).expect("valid selector")
+}
+
+fn text(element: ElementRef) -> String {
+    element
+        .text()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Extracts one row per grade from the grades page.
+///
+/// Grades are the `.score` elements of a subject row; their `title` holds the
+/// description followed by the date, e.g. "Písemná práce (12.10.2025, Novák)".
+pub fn grades(document: &Html) -> Sheet {
+    let row_selector = selector("table tr");
+    let subject_selector = selector("th");
+    let score_selector = selector(".score");
+    let value_selector = selector(".value");
+
+    let mut rows = Vec::new();
+    for row in document.select(&row_selector) {
+        let Some(subject) = row.select(&subject_selector).next().map(text) else {
+            continue;
+        };
+
+        for score in row.select(&score_selector) {
+            let value = score
+                .select(&value_selector)
+                .next()
+                .map(text)
+                .unwrap_or_else(|| text(score));
+            let title = score.value().attr("title").unwrap_or("").trim();
+            let date = CZECH_DATE
+                .find(title)
+                .map(|m| m.as_str().to_string())
+                .unwrap_or_default();
+            let description = title
+                .split_once(" (")
+                .map_or(title, |(description, _)| description)
+                .to_string();
+            let weight = if score.value().classes().any(|c| c == "small") {
+                "0.5"
+            } else {
+                "1"
+            };
+
+            rows.push(vec![
+                subject.clone(),
+                value,
+                weight.to_string(),
+                description,
+                date,
+            ]);
+        }
+    }
+
+    Sheet {
+        name: "znamky",
+        headers: &["Předmět", "Známka", "Váha", "Popis", "Datum"],
+        rows,
+    }
+}
+
+/// Extracts one row per lesson from the timetable page.
+///
+/// The first row holds the periods, every following row a day. Cells spanning
+/// several periods (`colspan`) and parallel lessons of groups are expanded.
+pub fn timetable(document: &Html) -> Sheet {
+    let table_selector = selector("table.timetable, table");
+    let row_selector = selector("tr");
+    let header_selector = selector("th, td");
+    let lesson_selector = selector(".lesson");
+    let field = |name: &str| selector(&format!(".{}", name));
+    let (subject_selector, teacher_selector, room_selector, group_selector) = (
+        field("subject"),
+        field("employee"),
+        field("room"),
+        field("group"),
+    );
+
+    let mut rows = Vec::new();
+    let Some(table) = document.select(&table_selector).next() else {
+        return Sheet {
+            name: "rozvrh",
+            headers: TIMETABLE_HEADERS,
+            rows,
+        };
+    };
+
+    let mut table_rows = table.select(&row_selector);
+    let periods: Vec<String> = table_rows
+        .next()
+        .map(|row| row.select(&header_selector).skip(1).map(text).collect())
+        .unwrap_or_default();
+
+    for row in table_rows {
+        let mut cells = row.select(&header_selector);
+        let Some(day) = cells.next().map(text) else {
+            continue;
+        };
+
+        let mut period = 0;
+        for cell in cells {
+            let span: usize = cell
+                .value()
+                .attr("colspan")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1);
+            let label = periods[period.min(periods.len())..]
+                .iter()
+                .take(span)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" – ");
+            period += span;
+
+            let mut lessons: Vec<ElementRef> = cell.select(&lesson_selector).collect();
+            if lessons.is_empty() {
+                lessons.push(cell);
+            }
+            for lesson in lessons {
+                let get = |s: &Selector| lesson.select(s).next().map(text).unwrap_or_default();
+                let subject = match get(&subject_selector) {
+                    s if s.is_empty() => text(lesson),
+                    s => s,
+                };
+                if subject.is_empty() {
+                    continue;
+                }
+                rows.push(vec![
+                    day.clone(),
+                    label.clone(),
+                    subject,
+                    get(&teacher_selector),
+                    get(&room_selector),
+                    get(&group_selector),
+                ]);
+            }
+        }
+    }
+
+    Sheet {
+        name: "rozvrh",
+        headers: TIMETABLE_HEADERS,
+        rows,
+    }
+}
+
+/// Labels of the periods, from the first row of the timetable.
+pub fn periods(document: &Html) -> Vec<String> {
+    let row_selector = selector("tr");
+    let header_selector = selector("th, td");
+    document
+        .select(&selector("table.timetable, table"))
+        .next()
+        .and_then(|table| table.select(&row_selector).next())
+        .map(|row| row.select(&header_selector).skip(1).map(text).collect())
+        .unwrap_or_default()
+}
+
+const TIMETABLE_HEADERS: &[&str] = &["Den", "Hodina", "Předmět", "Učitel", "Učebna", "Skupina"];
+
+fn to_csv(sheet: &Sheet) -> Vec<u8> {
+    // Excel only detects UTF-8 in CSV files with a byte order mark.
+    let mut out = b"\xEF\xBB\xBF".to_vec();
+    {
+        let mut writer = csv::Writer::from_writer(&mut out);
+        let _ = writer.write_record(sheet.headers);
+        for row in &sheet.rows {
+            let _ = writer.write_record(row);
+        }
+        let _ = writer.flush();
+    }
+    out
+}
+
+fn to_xlsx(sheet: &Sheet) -> Result<Vec<u8>, rust_xlsxwriter::XlsxError> {
+    let mut workbook = Workbook::new();
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(sheet.name)?;
+
+    let bold = CellFormat::new().set_bold();
+    for (col, header) in sheet.headers.iter().enumerate() {
+        worksheet.write_string_with_format(0, col as u16, *header, &bold)?;
+    }
+    for (row, cells) in sheet.rows.iter().enumerate() {
+        for (col, cell) in cells.iter().enumerate() {
+            worksheet.write_string(row as u32 + 1, col as u16, cell)?;
+        }
+    }
+    worksheet.autofit();
+
+    workbook.save_to_buffer()
+}
@@ -0,0 +1,240 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! iCal (RFC 5545) calendars of all-day events, for subscribing from
+//! calendar apps, and the date arithmetic the API needs.
+
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    http::HeaderValue,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+
+/// Czech abbreviations of the days of the week from Monday, as in the timetable.
+pub const WEEKDAYS: [&str; 7] = ["po", "út", "st", "čt", "pá", "so", "ne"];
+
+/// A calendar day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    /// Parses Czech dates like `12. 10. 2025`.
+    pub fn parse_czech(date: &str) -> Option<Self> {
+        let mut parts = date.split('.').map(|p| p.trim().parse().ok());
+        let (day, month, year) = (parts.next()??, parts.next()??, parts.next()??);
+        let date = Self {
+            year: i64::from(year),
+            month,
+            day,
+        };
+        ((1..=12).contains(&month) && (1..=31).contains(&day)).then_some(date)
+    }
+
+    /// The following day.
+    pub fn next_day(self) -> Self {
+        Self::from_days(self.days() + 1)
+    }
+
+    /// Day of the week, 0 for Monday.
+    pub fn weekday(self) -> u32 {
+        // 1970-01-01 was a Thursday.
+        (self.days() + 3).rem_euclid(7) as u32
+    }
+
+    /// Days since 1970-01-01 (Howard Hinnant's `days_from_civil`).
+    pub fn days(self) -> i64 {
+        let year = self.year - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let month = i64::from(self.month);
+        let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5
+            + i64::from(self.day)
+            - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+
+    /// Inverse of [`Date::days`] (`civil_from_days`).
+    pub fn from_days(days: i64) -> Self {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let doe = days.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        Self {
+            year: yoe + era * 400 + i64::from(month <= 2),
+            month,
+            day,
+        }
+    }
+
+    fn ics(self) -> String {
+        format!("{:04}{:02}{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// ISO 8601, e.g. `2025-10-12`.
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// An all-day event.
+pub struct Event<'a> {
+    /// Text identifying the event across fetches, hashed into its `UID`.
+    pub id: String,
+    pub summary: String,
+    pub description: &'a str,
+    pub start: Date,
+    /// Last day of the event, inclusive.
+    pub end: Option<Date>,
+    pub category: Option<&'a str>,
+    pub url: Option<&'a str>,
+}
+
+/// Renders a calendar named `name`.
+pub fn calendar(name: &str, events: &[Event]) -> String {
+    let mut out = String::from(
+        "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//jecnaproxy//CS\r\nCALSCALE:GREGORIAN\r\n",
+    );
+    out.push_str(&fold(&format!("X-WR-CALNAME:{}", escape(name))));
+
+    let stamp = utc_timestamp();
+    for event in events {
+        let uid = hex::encode(Sha256::digest(event.id.as_bytes()));
+        // DTEND of all-day events is exclusive.
+        let end = event.end.unwrap_or(event.start).max(event.start).next_day();
+
+        out.push_str("BEGIN:VEVENT\r\n");
+        let mut lines = vec![
+            format!("UID:{}@jecnaproxy", &uid[..32]),
+            format!("DTSTAMP:{}", stamp),
+            format!("DTSTART;VALUE=DATE:{}", event.start.ics()),
+            format!("DTEND;VALUE=DATE:{}", end.ics()),
+            format!("SUMMARY:{}", escape(&event.summary)),
+        ];
+        if !event.description.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", escape(event.description)));
+        }
+        if let Some(category) = event.category {
+            lines.push(format!("CATEGORIES:{}", escape(category)));
+        }
+        if let Some(url) = event.url {
+            lines.push(format!("URL:{}", url));
+        }
+        for line in lines {
+            out.push_str(&fold(&line));
+        }
+        out.push_str("END:VEVENT\r\n");
+    }
+    out.push_str("END:VCALENDAR\r\n");
+    out
+}
+
+/// Serves a rendered calendar.
+pub fn response(calendar: String) -> Response {
+    let mut response = calendar.into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        "content-type",
+        HeaderValue::from_static("text/calendar; charset=utf-8"),
+    );
+    headers.insert(
+        "cache-control",
+        HeaderValue::from_static("private, no-store"),
+    );
+    response
+}
+
+/// Today's date and the minute of the day in Prague.
+///
+/// Central European Summer Time runs from 01:00 UTC on the last Sunday of
+/// March to 01:00 UTC on the last Sunday of October.
+pub fn prague_now() -> (Date, u32) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let year = Date::from_days(secs.div_euclid(86400)).year;
+    let last_sunday = |month: u32| {
+        let last = Date {
+            year,
+            month: month + 1,
+            day: 1,
+        }
+        .days()
+            - 1;
+        last - i64::from((Date::from_days(last).weekday() + 1) % 7)
+    };
+    let summer = (last_sunday(3) * 86400 + 3600..last_sunday(10) * 86400 + 3600).contains(&secs);
+
+    let local = secs + if summer { 7200 } else { 3600 };
+    (
+        Date::from_days(local.div_euclid(86400)),
+        (local.rem_euclid(86400) / 60) as u32,
+    )
+}
+
+/// The current time as an iCal UTC timestamp.
+fn utc_timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let date = Date::from_days((secs / 86400) as i64);
+    let time = secs % 86400;
+    format!(
+        "{}T{:02}{:02}{:02}Z",
+        date.ics(),
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Folds a content line to at most 75 bytes per line, as iCal requires.
+fn fold(line: &str) -> String {
+    let mut out = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+    out
+}
@@ -0,0 +1,205 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! JSON/text API mounted under `/api`, built on top of scraped upstream pages.
+
+mod absences;
+pub mod canteen;
+mod changes;
+mod dashboard;
+pub mod digest;
+pub mod events;
+pub mod exams;
+pub mod export;
+pub mod ical;
+mod news;
+mod page;
+pub mod rooms;
+mod search;
+pub mod teachers;
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Router,
+    http::{HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+
+use crate::{state::AppState, users, utils};
+
+/// Builds the API router.
+///
+/// With user accounts enabled, all routes except registration and login
+/// require a user token.
+pub fn router(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/page", get(page::page_handler))
+        .route("/news/{id}", get(news::article_handler))
+        .route("/search", get(search::search_handler))
+        .route("/changes", get(changes::changes_handler))
+        .route("/grades.csv", get(export::grades_csv))
+        .route("/grades.xlsx", get(export::grades_xlsx))
+        .route("/timetable.csv", get(export::timetable_csv))
+        .route("/timetable.xlsx", get(export::timetable_xlsx))
+        .route("/absences", get(absences::absences_handler))
+        .route("/exams", get(exams::exams_handler))
+        .route("/exams.ics", get(exams::exams_ics))
+        .route("/dashboard", get(dashboard::dashboard_handler))
+        .route("/digest/weekly", get(digest::weekly_handler))
+        .route("/rooms/free", get(rooms::free_handler))
+        .route(
+            "/teachers/{shortcut}/timetable",
+            get(teachers::timetable_handler),
+        )
+        .route("/events", get(events::events_handler))
+        .route("/events.ics", get(events::events_ics))
+        .route("/events.rss", get(events::events_rss))
+        .route(
+            "/canteen/order",
+            post(canteen::order).delete(canteen::cancel),
+        )
+        .route("/canteen/account", get(canteen::account))
+        .route("/me", get(users::me))
+        .route("/me/usage", get(users::usage))
+        .route("/me/notifications", put(users::update_notifications))
+        .route("/auth/logout", post(users::logout))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            users::require_user,
+        ))
+        .route("/auth/register", post(users::register))
+        .route("/auth/login", post(users::login))
+}
+
+/// A fetched upstream HTML page.
+pub struct UpstreamPage {
+    /// Final URL of the page, used to resolve relative links.
+    pub url: Url,
+    pub html: String,
+}
+
+/// Fetches an upstream page on behalf of the client.
+///
+/// The client's cookies are forwarded like those of proxied requests, so pages
+/// behind the upstream login work for authenticated users.
+pub async fn fetch_html(
+    state: &AppState,
+    path: &str,
+    headers: &HeaderMap,
+) -> Result<UpstreamPage, Response> {
+    // Anything else (e.g. "@evil.com" or "//evil.com") could make us fetch foreign hosts.
+    if !path.starts_with('/') || path.starts_with("//") {
+        return Err((StatusCode::BAD_REQUEST, "Path must start with a single '/'").into_response());
+    }
+
+    let url = format!("{}{}", state.upstream().base, path);
+    let url = Url::parse(&url)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid path: {}", e)).into_response())?;
+
+    let mut upstream_headers = HeaderMap::new();
+    for cookie in headers.get_all("cookie") {
+        upstream_headers.append("cookie", cookie.clone());
+    }
+    utils::prepare_request_headers(&mut upstream_headers, state);
+
+    let resp = state
+        .client
+        .get(url.clone())
+        .headers(upstream_headers)
+        .send()
+        .await
+        .map_err(|e| {
+            tracing::error!("Upstream request failed: {}", e);
+            (StatusCode::BAD_GATEWAY, format!("Proxy Error: {}", e)).into_response()
+        })?;
+
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Err((StatusCode::NOT_FOUND, "Not found upstream").into_response());
+    }
+    if !resp.status().is_success() {
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Upstream answered with {}", resp.status()),
+        )
+            .into_response());
+    }
+
+    let html = resp.text().await.map_err(|e| {
+        tracing::error!("Failed to read response body: {}", e);
+        (StatusCode::BAD_GATEWAY, "Failed to read body").into_response()
+    })?;
+
+    Ok(UpstreamPage { url, html })
+}
+
+/// How long scraped personal data is reused for the same session.
+pub const SESSION_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Recently scraped data, e.g. keyed by [`session_key`].
+///
+/// Widgets tend to poll, so pages are fetched at most once per `ttl` and key.
+pub struct Cache<T> {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, T)>>,
+}
+
+impl<T: Clone> Cache<T> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<T> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|(fetched, _)| fetched.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn insert(&self, key: String, value: T) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() > 1000 {
+            entries.retain(|_, (fetched, _)| fetched.elapsed() < self.ttl);
+        }
+        entries.insert(key, (Instant::now(), value));
+    }
+
+    pub fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+/// Cache key of the client's upstream session: a hash of the upstream and the
+/// client's cookies.
+pub fn session_key(state: &AppState, headers: &HeaderMap) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(state.upstream().base.as_bytes());
+    for cookie in headers.get_all("cookie") {
+        hasher.update(b"\n");
+        hasher.update(cookie.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
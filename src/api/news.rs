@@ -0,0 +1,174 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+use std::sync::LazyLock;
+
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use regex::Regex;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::Serialize;
+
+use crate::{
+    extract::{self, Format},
+    state::AppState,
+    tls::Https,
+    utils,
+};
+
+/// File types linked from articles that are reported as attachments.
+const ATTACHMENT_EXTENSIONS: &[&str] = &[
+    "pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "odt", "ods", "zip", "jpg", "jpeg", "png",
+];
+
+pub(super) static CZECH_DATE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b\d{1,2}\.\s?\d{1,2}\.\s?\d{4}\b").expect("valid regex"));
+
+#[derive(Debug, Serialize)]
+pub struct Article {
+    pub id: String,
+    pub url: String,
+    pub title: String,
+    /// Publication date as shown on the page (e.g. "12. 3. 2025").
+    pub date: Option<String>,
+    pub text: String,
+    pub attachments: Vec<Attachment>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Attachment {
+    pub name: String,
+    pub url: String,
+}
+
+/// Handler for `GET /api/news/{id}`.
+pub async fn article_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    https: Option<Extension<Https>>,
+    Path(id): Path<String>,
+) -> Response {
+    match fetch_article(&state, &headers, https.is_some(), &id).await {
+        Ok(article) => Json(article).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Fetches and parses a news article.
+pub async fn fetch_article(
+    state: &AppState,
+    headers: &HeaderMap,
+    https: bool,
+    id: &str,
+) -> Result<Article, Response> {
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err((StatusCode::BAD_REQUEST, "Invalid article id").into_response());
+    }
+
+    let path = state.config.news_path.replace("{id}", id);
+    let page = super::fetch_html(state, &path, headers).await?;
+
+    let proxy_origin =
+        utils::determine_proxy_origin(state.config.base_url.as_deref(), headers, https);
+    let rewrite = |s: String| utils::rewrite_content_urls(s, &proxy_origin, state);
+
+    let mut article = parse_article(&page.html, &page.url);
+    article.id = id.to_string();
+    article.url = rewrite(page.url.to_string());
+    article.text = rewrite(article.text);
+    for attachment in &mut article.attachments {
+        attachment.url = rewrite(std::mem::take(&mut attachment.url));
+    }
+
+    Ok(article)
+}
+
+fn parse_article(html: &str, base: &Url) -> Article {
+    let document = Html::parse_document(html);
+    let content = extract::main_content(&document);
+
+    let heading = Selector::parse("h1").expect("valid selector");
+    let title_tag = Selector::parse("title").expect("valid selector");
+    let date_selector = Selector::parse("time, .date, .datum, .published").expect("valid selector");
+    let links = Selector::parse("a[href]").expect("valid selector");
+
+    let text_of = |el: scraper::ElementRef| {
+        el.text()
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let title = content
+        .and_then(|c| c.select(&heading).next())
+        .or_else(|| document.select(&heading).next())
+        .or_else(|| document.select(&title_tag).next())
+        .map(text_of)
+        .unwrap_or_default();
+
+    let text = content
+        .map(|c| extract::render(c, base, Format::Text))
+        .unwrap_or_default();
+
+    let date = content
+        .and_then(|c| c.select(&date_selector).next())
+        .map(text_of)
+        .filter(|d| !d.is_empty())
+        .or_else(|| CZECH_DATE.find(&text).map(|m| m.as_str().to_string()));
+
+    let attachments = content
+        .map(|c| {
+            c.select(&links)
+                .filter_map(|a| {
+                    let url = base.join(a.value().attr("href")?).ok()?;
+                    let is_file = url.path().rsplit_once('.').is_some_and(|(_, ext)| {
+                        ATTACHMENT_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+                    });
+                    is_file.then(|| Attachment {
+                        name: Some(text_of(a))
+                            .filter(|n| !n.is_empty())
+                            .unwrap_or_else(|| file_name(&url)),
+                        url: url.to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Article {
+        id: String::new(),
+        url: String::new(),
+        title,
+        date,
+        text,
+        attachments,
+    }
+}
+
+fn file_name(url: &Url) -> String {
+    url.path_segments()
+        .and_then(|mut s| s.next_back())
+        .unwrap_or_default()
+        .to_string()
+}
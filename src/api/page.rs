@@ -0,0 +1,73 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+use axum::{
+    Extension,
+    extract::{Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::{
+    extract::{self, Format},
+    state::AppState,
+    tls::Https,
+    utils,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct PageQuery {
+    path: String,
+    format: Option<String>,
+}
+
+/// Handler for `GET /api/page?path=...&format=md|text`.
+///
+/// Returns the main content of an upstream page without navigation and other
+/// boilerplate, e.g. for piping school announcements into chat bots.
+pub async fn page_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    https: Option<Extension<Https>>,
+    Query(query): Query<PageQuery>,
+) -> Response {
+    let (format, content_type) = match query.format.as_deref() {
+        None | Some("md") | Some("markdown") => (Format::Markdown, "text/markdown; charset=utf-8"),
+        Some("text") | Some("txt") => (Format::Text, "text/plain; charset=utf-8"),
+        Some(other) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Unknown format: {}", other),
+            )
+                .into_response();
+        }
+    };
+
+    let page = match super::fetch_html(&state, &query.path, &headers).await {
+        Ok(p) => p,
+        Err(response) => return response,
+    };
+
+    let rendered = extract::render_page(&page.html, &page.url, format);
+    let proxy_origin =
+        utils::determine_proxy_origin(state.config.base_url.as_deref(), &headers, https.is_some());
+    let body = utils::rewrite_content_urls(rendered, &proxy_origin, &state);
+
+    let mut response = body.into_response();
+    response
+        .headers_mut()
+        .insert("content-type", HeaderValue::from_static(content_type));
+    response
+}
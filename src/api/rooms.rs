@@ -0,0 +1,223 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Free classrooms at a given time.
+//!
+//! The room timetable page (`ROOMS_PATH`) has a form selecting the room; its
+//! options are the school's rooms, and submitting it for every room yields the
+//! full-school timetable. That's a request per room, so the schedule is
+//! fetched through the scraping throttle and kept for an hour.
+
+use std::{
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use regex::Regex;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use super::{export, ical};
+use crate::state::AppState;
+
+/// How long the scraped schedule is used.
+const SCHEDULE_TTL: Duration = Duration::from_secs(60 * 60);
+
+static TIME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(\d{1,2}):(\d{2})\b").expect("valid regex"));
+
+/// Lessons of one room.
+struct Room {
+    name: String,
+    /// `(weekday, start, end)`, weekdays from 0 for Monday and minutes of the day.
+    lessons: Vec<(u32, u32, u32)>,
+}
+
+/// The scraped full-school schedule.
+#[derive(Default)]
+pub struct RoomsState {
+    schedule: Mutex<Option<(Instant, Arc<Vec<Room>>)>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FreeQuery {
+    /// `HH:MM` today or `YYYY-MM-DDTHH:MM`, Prague time. Defaults to now.
+    time: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FreeRooms {
+    pub date: String,
+    pub time: String,
+    pub free: Vec<String>,
+}
+
+/// Handler for `GET /api/rooms/free?time=...`.
+pub async fn free_handler(
+    State(state): State<AppState>,
+    Query(query): Query<FreeQuery>,
+) -> Response {
+    let (today, now) = ical::prague_now();
+    let Some((date, minute)) = query
+        .time
+        .as_deref()
+        .map_or(Some((today, now)), |time| parse_time(time, today))
+    else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Expected the time as HH:MM or YYYY-MM-DDTHH:MM",
+        )
+            .into_response();
+    };
+
+    let rooms = match schedule(&state).await {
+        Ok(rooms) => rooms,
+        Err(response) => return response,
+    };
+    let weekday = date.weekday();
+    let free = rooms
+        .iter()
+        .filter(|room| {
+            !room
+                .lessons
+                .iter()
+                .any(|&(day, start, end)| day == weekday && (start..end).contains(&minute))
+        })
+        .map(|room| room.name.clone())
+        .collect();
+
+    Json(FreeRooms {
+        date: date.to_string(),
+        time: format!("{:02}:{:02}", minute / 60, minute % 60),
+        free,
+    })
+    .into_response()
+}
+
+/// The cached schedule, scraped again once it's older than [`SCHEDULE_TTL`].
+async fn schedule(state: &AppState) -> Result<Arc<Vec<Room>>, Response> {
+    // Held while scraping, so concurrent requests wait for one scrape.
+    let mut schedule = state.rooms.schedule.lock().await;
+    if let Some((fetched, rooms)) = schedule.as_ref()
+        && fetched.elapsed() < SCHEDULE_TTL
+    {
+        return Ok(rooms.clone());
+    }
+
+    let rooms = Arc::new(scrape(state).await?);
+    tracing::info!("Scraped the timetables of {} rooms", rooms.len());
+    *schedule = Some((Instant::now(), rooms.clone()));
+    Ok(rooms)
+}
+
+async fn scrape(state: &AppState) -> Result<Vec<Room>, Response> {
+    // The timetables are public, the client's session isn't needed.
+    let no_cookies = HeaderMap::new();
+    let page = super::fetch_html(state, &state.config.rooms_path, &no_cookies).await?;
+    let Some((field, options)) = room_options(&page.html) else {
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            "No room selection found on the room timetable page",
+        )
+            .into_response());
+    };
+
+    let mut rooms = Vec::with_capacity(options.len());
+    for (value, name) in options {
+        let mut url: Url = page.url.clone();
+        url.query_pairs_mut().clear().append_pair(&field, &value);
+        let path = format!("{}?{}", url.path(), url.query().unwrap_or_default());
+
+        state.throttle.wait().await;
+        let page = super::fetch_html(state, &path, &no_cookies).await?;
+        let sheet = export::timetable(&Html::parse_document(&page.html));
+        let lessons = sheet
+            .rows
+            .iter()
+            .filter_map(|row| {
+                let day = row[0].to_lowercase();
+                let weekday = ical::WEEKDAYS.iter().position(|d| day.starts_with(d))?;
+                let (start, end) = period_minutes(&row[1])?;
+                Some((weekday as u32, start, end))
+            })
+            .collect();
+        rooms.push(Room { name, lessons });
+    }
+    Ok(rooms)
+}
+
+/// The name of the room field and its `(value, label)` options.
+fn room_options(html: &str) -> Option<(String, Vec<(String, String)>)> {
+    let document = Html::parse_document(html);
+    let selects = Selector::parse("select[name]").expect("valid selector");
+    let options = Selector::parse("option[value]").expect("valid selector");
+
+    let select = document
+        .select(&selects)
+        .max_by_key(|s| s.value().attr("name").is_some_and(|n| n.contains("room")))?;
+    let field = select.value().attr("name")?.to_string();
+    let options = select
+        .select(&options)
+        .filter_map(|option| {
+            let value = option.value().attr("value")?.trim().to_string();
+            let label = option.text().collect::<String>().trim().to_string();
+            (!value.is_empty()).then(|| {
+                let label = if label.is_empty() {
+                    value.clone()
+                } else {
+                    label
+                };
+                (value, label)
+            })
+        })
+        .collect();
+    Some((field, options))
+}
+
+/// Start and end of a period label like "3 9:40 - 10:25" in minutes of the day.
+fn period_minutes(label: &str) -> Option<(u32, u32)> {
+    let mut times = TIME
+        .captures_iter(label)
+        .filter_map(|time| Some(time[1].parse::<u32>().ok()? * 60 + time[2].parse::<u32>().ok()?));
+    let start = times.next()?;
+    Some((start, times.last().unwrap_or(start)))
+}
+
+/// Parses `HH:MM` (on `today`) or `YYYY-MM-DDTHH:MM`.
+fn parse_time(time: &str, today: ical::Date) -> Option<(ical::Date, u32)> {
+    let (date, time) = match time.split_once(['T', ' ']) {
+        Some((date, time)) => {
+            let mut parts = date.splitn(3, '-').map(|p| p.parse().ok());
+            let date = ical::Date {
+                year: parts.next()??,
+                month: u32::try_from(parts.next()??).ok()?,
+                day: u32::try_from(parts.next()??).ok()?,
+            };
+            (date, time)
+        }
+        None => (today, time),
+    };
+    let (hours, minutes) = time.split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.get(..2)?.parse().ok()?);
+    (hours < 24 && minutes < 60 && (1..=12).contains(&date.month) && (1..=31).contains(&date.day))
+        .then_some((date, hours * 60 + minutes))
+}
@@ -0,0 +1,53 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::state::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    q: String,
+    limit: Option<usize>,
+}
+
+/// Handler for `GET /api/search?q=...`.
+pub async fn search_handler(
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> Response {
+    let Some(index) = state.search.get() else {
+        let message = if state.config.search.is_some() {
+            "Search index is still being built"
+        } else {
+            "Search is not enabled"
+        };
+        return (StatusCode::SERVICE_UNAVAILABLE, message).into_response();
+    };
+
+    let limit = query.limit.unwrap_or(10).clamp(1, 50);
+    match index.search(&query.q, limit) {
+        Ok(hits) => Json(hits).into_response(),
+        Err(e) => {
+            tracing::error!("Search failed: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Search failed").into_response()
+        }
+    }
+}
@@ -0,0 +1,163 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Timetables of teachers, e.g. for finding a time for consultations.
+//!
+//! The teacher's page (`TEACHER_PATH`) holds their timetable. Besides the
+//! lessons, the free periods of every school day are listed. Timetables are
+//! the same for everyone and change rarely, so they're cached for a day.
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use scraper::{Html, Selector};
+use serde::Serialize;
+
+use super::{export, ical};
+use crate::state::AppState;
+
+/// How long a teacher's timetable is cached.
+pub const TEACHER_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Serialize)]
+pub struct TeacherTimetable {
+    pub shortcut: String,
+    pub name: String,
+    pub lessons: Vec<TeacherLesson>,
+    /// Periods without a lesson, per school day.
+    pub free: Vec<FreeDay>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeacherLesson {
+    pub day: String,
+    pub period: String,
+    pub subject: String,
+    pub room: String,
+    pub group: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FreeDay {
+    /// Czech abbreviation of the day, e.g. "po".
+    pub day: &'static str,
+    pub periods: Vec<String>,
+}
+
+/// Handler for `GET /api/teachers/{shortcut}/timetable`.
+pub async fn timetable_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shortcut): Path<String>,
+) -> Response {
+    if shortcut.is_empty()
+        || shortcut.len() > 16
+        || !shortcut.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        return (StatusCode::BAD_REQUEST, "Invalid teacher shortcut").into_response();
+    }
+    let key = shortcut.to_lowercase();
+    if let Some(timetable) = state.teachers.get(&key) {
+        return Json(timetable).into_response();
+    }
+
+    let path = state.config.teacher_path.replace("{shortcut}", &shortcut);
+    let page = match super::fetch_html(&state, &path, &headers).await {
+        Ok(p) => p,
+        Err(response) if response.status() == StatusCode::NOT_FOUND => {
+            return unknown(&shortcut);
+        }
+        Err(response) => return response,
+    };
+
+    let Some(timetable) = parse(&page.html, &shortcut) else {
+        return unknown(&shortcut);
+    };
+    let timetable = Arc::new(timetable);
+    state.teachers.insert(key, timetable.clone());
+    Json(timetable).into_response()
+}
+
+fn unknown(shortcut: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        format!("No timetable found for teacher {}", shortcut),
+    )
+        .into_response()
+}
+
+/// Parses the teacher's page, `None` if it has no timetable.
+fn parse(html: &str, shortcut: &str) -> Option<TeacherTimetable> {
+    let document = Html::parse_document(html);
+    let periods = export::periods(&document);
+    if periods.is_empty() {
+        return None;
+    }
+
+    let lessons: Vec<TeacherLesson> = export::timetable(&document)
+        .rows
+        .into_iter()
+        .filter_map(|row| {
+            let [day, period, subject, _, room, group] = <[String; 6]>::try_from(row).ok()?;
+            Some(TeacherLesson {
+                day,
+                period,
+                subject,
+                room,
+                group,
+            })
+        })
+        .collect();
+
+    let free = ical::WEEKDAYS[..5]
+        .iter()
+        .map(|&day| {
+            // Lessons spanning several periods are labelled "1 ... – 2 ...".
+            let busy: Vec<&str> = lessons
+                .iter()
+                .filter(|lesson| lesson.day.to_lowercase().starts_with(day))
+                .flat_map(|lesson| lesson.period.split(" – "))
+                .collect();
+            FreeDay {
+                day,
+                periods: periods
+                    .iter()
+                    .filter(|period| !busy.contains(&period.as_str()))
+                    .cloned()
+                    .collect(),
+            }
+        })
+        .collect();
+
+    let heading = Selector::parse("h1").expect("valid selector");
+    let name = document
+        .select(&heading)
+        .next()
+        .map(|h| h.text().collect::<Vec<_>>().join(" "))
+        .map(|h| h.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| shortcut.to_string());
+
+    Some(TeacherTimetable {
+        shortcut: shortcut.to_string(),
+        name,
+        lessons,
+        free,
+    })
+}
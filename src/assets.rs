@@ -0,0 +1,86 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! The reserved `/_jecnaproxy` namespace.
+//!
+//! Paths under it are never proxied. It serves the files of `assets/`, which are
+//! embedded into the binary, so injected functionality (banner styles, dark
+//! theme, service workers) works without inline blobs or external CDNs.
+
+use std::path::Path as FsPath;
+
+use axum::{
+    Router,
+    extract::Path,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{any, get},
+};
+use include_dir::{Dir, include_dir};
+
+use crate::{snapshot, state::AppState};
+
+/// Mount point of the namespace.
+pub const PREFIX: &str = "/_jecnaproxy";
+
+static ASSETS: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/assets");
+
+/// Returns an embedded text asset.
+///
+/// # Panics
+/// If `path` isn't a UTF-8 file of `assets/`.
+pub fn text(path: &str) -> &'static str {
+    ASSETS
+        .get_file(path)
+        .and_then(|file| file.contents_utf8())
+        .unwrap_or_else(|| panic!("missing embedded asset {}", path))
+}
+
+/// Routes of the namespace, matching everything under [`PREFIX`].
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(PREFIX, any(not_found))
+        .route("/_jecnaproxy/", any(not_found))
+        .route("/_jecnaproxy/{*path}", get(serve))
+}
+
+/// Handler for `GET /_jecnaproxy/ping`, for health checks.
+///
+/// Touches neither the upstream nor any state. It is routed outside the
+/// middleware, so frequent checks don't show up in metrics and logs.
+pub async fn ping() -> StatusCode {
+    StatusCode::NO_CONTENT
+}
+
+async fn serve(Path(path): Path<String>) -> Response {
+    let Some(file) = ASSETS.get_file(&path) else {
+        return not_found().await;
+    };
+
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                snapshot::content_type_for(FsPath::new(&path)),
+            ),
+            (header::CACHE_CONTROL, "public, max-age=3600"),
+        ],
+        file.contents(),
+    )
+        .into_response()
+}
+
+async fn not_found() -> Response {
+    (StatusCode::NOT_FOUND, "Not found").into_response()
+}
@@ -0,0 +1,123 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Append-only audit log of state-changing actions.
+//!
+//! Every entry records who (the admin token, a user or the proxy itself), when
+//! and what was done. Entries are always logged via `tracing` and, with a
+//! database configured, stored in the `audit_log` table, which is never
+//! updated or pruned by the proxy.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::state::AppState;
+
+/// Who performed an action. Inserted as a request extension by authenticating middlewares.
+#[derive(Debug, Clone)]
+pub struct Actor(pub String);
+
+impl Actor {
+    pub fn admin_token() -> Self {
+        Self("admin-token".to_string())
+    }
+
+    pub fn user(username: &str) -> Self {
+        Self(format!("user:{}", username))
+    }
+
+    pub fn system() -> Self {
+        Self("system".to_string())
+    }
+}
+
+/// A recorded action.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// Unix timestamp.
+    pub at: u64,
+    pub actor: String,
+    /// Dotted action name, e.g. "ban.remove".
+    pub action: String,
+    pub target: Option<String>,
+    pub details: Option<String>,
+}
+
+/// Records an action.
+pub async fn record(
+    state: &AppState,
+    actor: &Actor,
+    action: &str,
+    target: Option<&str>,
+    details: Option<&str>,
+) {
+    tracing::info!(
+        target: "audit",
+        actor = %actor.0,
+        target = target.unwrap_or("-"),
+        "{}",
+        action
+    );
+
+    let Some(db) = &state.db else {
+        return;
+    };
+    let at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    if let Err(e) = db.insert_audit(at, &actor.0, action, target, details).await {
+        tracing::error!("Failed to write audit log entry: {}", e);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    /// Only entries with this action or action prefix (e.g. "ban").
+    action: Option<String>,
+    /// Only entries older than this id, for paging.
+    before: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// Handler for `GET /_admin/audit`. Returns entries newest first.
+pub async fn list(State(state): State<AppState>, Query(query): Query<AuditQuery>) -> Response {
+    let Some(db) = &state.db else {
+        return (
+            StatusCode::NOT_FOUND,
+            "The audit log needs DATABASE_URL to be stored",
+        )
+            .into_response();
+    };
+
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    match db
+        .load_audit(query.action.as_deref(), query.before, limit)
+        .await
+    {
+        Ok(entries) => Json(entries).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to read audit log: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -0,0 +1,416 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Encrypted backups of the proxy state, for moving it between hosts.
+//!
+//! `jecnaproxy backup` writes the SQLite database (with the credential vault,
+//! users and history) and the files of `OFFLINE_DIR` into one archive;
+//! `jecnaproxy restore` writes them back. The archive is encrypted with
+//! XChaCha20-Poly1305 under a key derived from a passphrase with Argon2.
+//! Stored credentials stay encrypted under `VAULT_KEY`, which isn't part of
+//! the archive. The passphrase is read from `BACKUP_PASSPHRASE` or standard
+//! input, never from the arguments.
+
+use std::path::{Component, Path, PathBuf};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    AeadCore, KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, OsRng, Payload},
+};
+
+use crate::{
+    cli::{self, BackupArgs, RestoreArgs},
+    config::Config,
+    state::AppState,
+};
+
+/// Magic bytes and format version at the start of an archive.
+const MAGIC: &[u8; 9] = b"JPBACKUP1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Archive entry of the database.
+const DATABASE: &str = "database.sqlite";
+/// Prefix of archive entries from `OFFLINE_DIR`.
+const SNAPSHOT: &str = "snapshot/";
+
+/// Entry point of the `backup` subcommand.
+pub async fn backup(state: &AppState, args: BackupArgs) {
+    match write(state, &args).await {
+        Ok(entries) => tracing::info!(
+            "Backup of {} entries written to {}",
+            entries,
+            args.out.display()
+        ),
+        Err(e) => {
+            tracing::error!("Backup failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Entry point of the `restore` subcommand. Runs before the database is opened.
+pub async fn restore(config: &Config, args: &RestoreArgs) {
+    match read(config, args).await {
+        Ok(entries) => tracing::info!("Restored {} entries from {}", entries, args.from.display()),
+        Err(e) => {
+            tracing::error!("Restore failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn passphrase() -> Result<String, String> {
+    cli::read_secret("BACKUP_PASSPHRASE").map_err(|e| format!("reading the passphrase: {}", e))
+}
+
+async fn write(state: &AppState, args: &BackupArgs) -> Result<usize, String> {
+    let passphrase = passphrase()?;
+    let mut entries = Vec::new();
+
+    if let Some(db) = &state.db {
+        // The unencrypted copy is only readable by us and sits next to the
+        // archive rather than in a shared temporary directory. The guard
+        // removes it however the backup ends.
+        let dir = args
+            .out
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(".jecnaproxy-backup-");
+        #[cfg(unix)]
+        builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600));
+        let copy = builder
+            .tempfile_in(dir)
+            .map_err(|e| e.to_string())?
+            .into_temp_path();
+        db.backup_to(&copy).await.map_err(|e| e.to_string())?;
+        let data = tokio::fs::read(&copy).await.map_err(|e| e.to_string())?;
+        copy.close().map_err(|e| e.to_string())?;
+        entries.push((DATABASE.to_string(), data));
+    }
+
+    if let Some(dir) = &state.config.offline_dir {
+        for path in files(dir).await.map_err(|e| e.to_string())? {
+            let Some(name) = path.strip_prefix(dir).ok().and_then(Path::to_str) else {
+                tracing::warn!("Backup: skipping {}", path.display());
+                continue;
+            };
+            let data = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+            entries.push((format!("{}{}", SNAPSHOT, name), data));
+        }
+    }
+
+    if entries.is_empty() {
+        return Err("nothing to back up, neither DATABASE_URL nor OFFLINE_DIR is set".to_string());
+    }
+
+    let archive = seal(&passphrase, &encode(&entries))?;
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(&args.out)
+        .await
+        .map_err(|e| format!("{}: {}", args.out.display(), e))?;
+    tokio::io::AsyncWriteExt::write_all(&mut file, &archive)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(entries.len())
+}
+
+async fn read(config: &Config, args: &RestoreArgs) -> Result<usize, String> {
+    let passphrase = passphrase()?;
+    let archive = tokio::fs::read(&args.from)
+        .await
+        .map_err(|e| format!("{}: {}", args.from.display(), e))?;
+    let entries = decode(&open(&passphrase, &archive)?)?;
+    unpack(config, &entries, args.force).await
+}
+
+/// Writes the archive entries to the database file and `OFFLINE_DIR`.
+async fn unpack(
+    config: &Config,
+    entries: &[(String, Vec<u8>)],
+    force: bool,
+) -> Result<usize, String> {
+    // Resolve every target first, so nothing is written if one is missing.
+    let mut targets = Vec::with_capacity(entries.len());
+    for (name, _) in entries {
+        let target = if name == DATABASE {
+            let url = config
+                .database_url
+                .as_deref()
+                .ok_or("the archive has a database, set DATABASE_URL")?;
+            sqlite_path(url).ok_or("DATABASE_URL must point to a SQLite file")?
+        } else if let Some(name) = name.strip_prefix(SNAPSHOT) {
+            let dir = config
+                .offline_dir
+                .as_ref()
+                .ok_or("the archive has snapshot files, set OFFLINE_DIR")?;
+            let relative = Path::new(name);
+            if !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+            {
+                return Err(format!("unsafe path in the archive: {}", name));
+            }
+            dir.join(relative)
+        } else {
+            return Err(format!("unknown archive entry: {}", name));
+        };
+        if !force && tokio::fs::try_exists(&target).await.unwrap_or(true) {
+            return Err(format!(
+                "{} already exists, pass --force to overwrite it",
+                target.display()
+            ));
+        }
+        targets.push(target);
+    }
+
+    for ((name, data), target) in entries.iter().zip(&targets) {
+        if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        if name == DATABASE {
+            // Stale WAL files would be replayed onto the restored database.
+            for suffix in ["-wal", "-shm"] {
+                let mut wal = target.clone().into_os_string();
+                wal.push(suffix);
+                let _ = tokio::fs::remove_file(wal).await;
+            }
+            // The database holds password hashes and the vault, an existing
+            // file is replaced so it doesn't keep looser permissions.
+            let _ = tokio::fs::remove_file(target).await;
+            #[cfg(unix)]
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(target)
+            .await
+            .map_err(|e| format!("{}: {}", target.display(), e))?;
+        tokio::io::AsyncWriteExt::write_all(&mut file, data)
+            .await
+            .map_err(|e| format!("{}: {}", target.display(), e))?;
+    }
+    Ok(entries.len())
+}
+
+/// The file of a `sqlite:` database URL.
+fn sqlite_path(url: &str) -> Option<PathBuf> {
+    let path = url
+        .strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))?;
+    let path = path.split('?').next().unwrap_or_default();
+    (!path.is_empty() && path != ":memory:").then(|| PathBuf::from(path))
+}
+
+/// All regular files below `dir`, not following symlinks.
+async fn files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Concatenates entries as `name length (u32), name, data length (u64), data`.
+fn encode(entries: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (name, data) in entries {
+        out.extend_from_slice(&(name.len() as u32).to_be_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&(data.len() as u64).to_be_bytes());
+        out.extend_from_slice(data);
+    }
+    out
+}
+
+fn decode(mut input: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+        if input.len() < len {
+            return Err("the archive is truncated".to_string());
+        }
+        let (head, tail) = input.split_at(len);
+        *input = tail;
+        Ok(head)
+    }
+
+    let mut entries = Vec::new();
+    while !input.is_empty() {
+        let len = u32::from_be_bytes(take(&mut input, 4)?.try_into().expect("4 bytes"));
+        let name = String::from_utf8(take(&mut input, len as usize)?.to_vec())
+            .map_err(|_| "invalid entry name in the archive")?;
+        let len = u64::from_be_bytes(take(&mut input, 8)?.try_into().expect("8 bytes"));
+        let len = usize::try_from(len).map_err(|_| "the archive is truncated")?;
+        entries.push((name, take(&mut input, len)?.to_vec()));
+    }
+    Ok(entries)
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<XChaCha20Poly1305, String> {
+    if passphrase.is_empty() {
+        return Err("the passphrase must not be empty".to_string());
+    }
+    let mut key = [0; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| e.to_string())?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+/// Encrypts `payload` as `magic, salt, nonce, ciphertext`.
+fn seal(passphrase: &str, payload: &[u8]) -> Result<Vec<u8>, String> {
+    let salt = rand::random::<[u8; SALT_LEN]>();
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(passphrase, &salt)?
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: payload,
+                aad: MAGIC,
+            },
+        )
+        .map_err(|_| "encryption failed")?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+fn open(passphrase: &str, archive: &[u8]) -> Result<Vec<u8>, String> {
+    let header = MAGIC.len() + SALT_LEN + NONCE_LEN;
+    if archive.len() < header || !archive.starts_with(MAGIC) {
+        return Err("not a jecnaproxy backup".to_string());
+    }
+    let (salt, rest) = archive[MAGIC.len()..].split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    cipher(passphrase, salt)?
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: MAGIC,
+            },
+        )
+        .map_err(|_| "wrong passphrase or corrupted archive".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<(String, Vec<u8>)> {
+        vec![
+            (DATABASE.to_string(), b"SQLite format 3\0".to_vec()),
+            ("snapshot/index.html".to_string(), b"<html></html>".to_vec()),
+            ("snapshot/empty".to_string(), Vec::new()),
+        ]
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let encoded = encode(&entries());
+        assert_eq!(decode(&encoded).unwrap(), entries());
+        assert!(decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(decode(&[0, 0, 0, 9, b'a']).is_err());
+    }
+
+    #[test]
+    fn archive_round_trip() {
+        let payload = encode(&entries());
+        let archive = seal("correct horse", &payload).unwrap();
+        assert!(archive.starts_with(MAGIC));
+        assert_eq!(open("correct horse", &archive).unwrap(), payload);
+
+        assert!(open("wrong horse", &archive).is_err());
+        let mut tampered = archive.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open("correct horse", &tampered).is_err());
+        assert!(open("correct horse", b"JPBACKUP1").is_err());
+        assert!(seal("", &payload).is_err());
+    }
+
+    fn config(dir: &Path) -> Config {
+        let mut config = Config::from_env();
+        config.database_url = Some(format!("sqlite://{}", dir.join("state.db").display()));
+        config.offline_dir = Some(dir.join("snapshot"));
+        config
+    }
+
+    #[tokio::test]
+    async fn restore_writes_private_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+
+        assert_eq!(unpack(&config, &entries(), false).await.unwrap(), 3);
+        let snapshot = std::fs::read(dir.path().join("snapshot/index.html")).unwrap();
+        assert_eq!(snapshot, b"<html></html>");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(dir.path().join("state.db"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert!(unpack(&config, &entries(), false).await.is_err());
+        assert_eq!(unpack(&config, &entries(), true).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn restore_rejects_path_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+
+        for name in [
+            "snapshot/../escaped",
+            "snapshot/a/../../escaped",
+            "snapshot//etc/escaped",
+            "snapshot/./escaped",
+            "escaped",
+        ] {
+            let entries = vec![
+                ("snapshot/ok.html".to_string(), Vec::new()),
+                (name.to_string(), b"x".to_vec()),
+            ];
+            assert!(unpack(&config, &entries, true).await.is_err(), "{}", name);
+        }
+        assert!(!dir.path().join("escaped").exists());
+        assert!(!dir.path().join("snapshot/ok.html").exists());
+    }
+}
@@ -0,0 +1,398 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! fail2ban-style abuse banning.
+//!
+//! Every client IP gets a set of counters (upstream errors, login attempts and
+//! hits on well-known scanner paths) that reset after `window`. Exceeding any of
+//! the limits bans the IP for `ban_duration`, during which it only receives 403s.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::{
+    audit::{self, Actor},
+    config, db, effective_config,
+    privacy::LogPrivacy,
+    state::AppState,
+    utils,
+};
+
+const DEFAULT_SCAN_PATTERNS: &[&str] = &[
+    "/.env",
+    "/.git",
+    "wp-login",
+    "wp-admin",
+    "xmlrpc.php",
+    "phpmyadmin",
+    "/cgi-bin",
+    "/vendor/phpunit",
+];
+
+const DEFAULT_LOGIN_PATHS: &[&str] = &["/user/login", "/j_spring_security_check"];
+
+/// Abuse banning settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct BanConfig {
+    /// Period after which the per-IP counters are reset.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub window: Duration,
+    /// How long an offender stays banned.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub ban_duration: Duration,
+    /// Maximum upstream error responses (4xx/5xx) per window.
+    pub error_limit: u32,
+    /// Maximum login attempts per window.
+    pub login_limit: u32,
+    /// Maximum requests to scanner paths per window.
+    pub scan_limit: u32,
+    /// Lowercase substrings of paths that only vulnerability scanners request.
+    pub scan_patterns: Vec<String>,
+    /// Lowercase paths that receive login form submissions.
+    pub login_paths: Vec<String>,
+}
+
+impl BanConfig {
+    /// # Environment Variables
+    /// * `BAN_ENABLED` - Set to "true" or "1" to enable automatic banning.
+    /// * `BAN_WINDOW_SECS` - Counter reset period (default: 600).
+    /// * `BAN_DURATION_SECS` - Ban length (default: 3600).
+    /// * `BAN_ERROR_LIMIT` - Errors per window (default: 100).
+    /// * `BAN_LOGIN_LIMIT` - Login attempts per window (default: 10).
+    /// * `BAN_SCAN_LIMIT` - Scanner path hits per window (default: 3).
+    /// * `BAN_SCAN_PATTERNS` - Comma-separated scanner path substrings.
+    /// * `BAN_LOGIN_PATHS` - Comma-separated login endpoints.
+    pub fn from_env() -> Option<Self> {
+        if !config::env_flag("BAN_ENABLED") {
+            return None;
+        }
+
+        Some(Self {
+            window: Duration::from_secs(config::env_parse("BAN_WINDOW_SECS").unwrap_or(600)),
+            ban_duration: Duration::from_secs(
+                config::env_parse("BAN_DURATION_SECS").unwrap_or(3600),
+            ),
+            error_limit: config::env_parse("BAN_ERROR_LIMIT").unwrap_or(100),
+            login_limit: config::env_parse("BAN_LOGIN_LIMIT").unwrap_or(10),
+            scan_limit: config::env_parse("BAN_SCAN_LIMIT").unwrap_or(3),
+            scan_patterns: paths_or_default(
+                config::env_list("BAN_SCAN_PATTERNS"),
+                DEFAULT_SCAN_PATTERNS,
+            ),
+            login_paths: paths_or_default(config::env_list("BAN_LOGIN_PATHS"), DEFAULT_LOGIN_PATHS),
+        })
+    }
+
+    /// Whether the lowercased `path` is one only scanners request.
+    fn is_scan(&self, path: &str) -> bool {
+        self.scan_patterns.iter().any(|p| path.contains(p.as_str()))
+    }
+}
+
+/// Lowercases the configured paths, which are matched against lowercased request paths.
+fn paths_or_default(list: Vec<String>, default: &[&str]) -> Vec<String> {
+    if list.is_empty() {
+        default.iter().map(|s| s.to_string()).collect()
+    } else {
+        list.into_iter().map(|s| s.to_lowercase()).collect()
+    }
+}
+
+#[derive(Debug)]
+struct Offender {
+    window_start: Instant,
+    errors: u32,
+    logins: u32,
+    scans: u32,
+    ban: Option<(Instant, String)>,
+}
+
+impl Offender {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            errors: 0,
+            logins: 0,
+            scans: 0,
+            ban: None,
+        }
+    }
+
+    fn is_banned(&self, now: Instant) -> bool {
+        self.ban.as_ref().is_some_and(|(until, _)| *until > now)
+    }
+}
+
+/// A currently banned client, as reported by the admin API.
+#[derive(Debug, Serialize)]
+pub struct BanEntry {
+    pub ip: IpAddr,
+    pub reason: String,
+    pub remaining_secs: u64,
+}
+
+/// Per-IP abuse counters and active bans.
+#[derive(Debug, Default)]
+pub struct BanList {
+    offenders: Mutex<HashMap<IpAddr, Offender>>,
+}
+
+enum Strike {
+    Error,
+    Login,
+    Scan,
+}
+
+impl BanList {
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        let offenders = self.offenders.lock().unwrap();
+        offenders
+            .get(&ip)
+            .is_some_and(|o| o.is_banned(Instant::now()))
+    }
+
+    /// Returns all active bans.
+    pub fn list(&self) -> Vec<BanEntry> {
+        let now = Instant::now();
+        let offenders = self.offenders.lock().unwrap();
+        offenders
+            .iter()
+            .filter_map(|(ip, o)| match &o.ban {
+                Some((until, reason)) if *until > now => Some(BanEntry {
+                    ip: *ip,
+                    reason: reason.clone(),
+                    remaining_secs: (*until - now).as_secs(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Lifts the ban and resets the counters of `ip`. Returns whether it was banned.
+    pub fn unban(&self, ip: IpAddr) -> bool {
+        let mut offenders = self.offenders.lock().unwrap();
+        offenders
+            .remove(&ip)
+            .is_some_and(|o| o.is_banned(Instant::now()))
+    }
+
+    /// Bans `ip` for `remaining`, e.g. when restoring persisted bans.
+    pub fn restore(&self, ip: IpAddr, reason: String, remaining: Duration) {
+        let now = Instant::now();
+        let mut offender = Offender::new(now);
+        offender.ban = Some((now + remaining, reason));
+        self.offenders.lock().unwrap().insert(ip, offender);
+    }
+
+    /// Lifts all bans and resets all counters.
+    pub fn clear(&self) {
+        self.offenders.lock().unwrap().clear();
+    }
+
+    /// Records a strike, returning the reason if it got `ip` banned.
+    fn strike(
+        &self,
+        ip: IpAddr,
+        strike: Strike,
+        config: &BanConfig,
+        privacy: &LogPrivacy,
+    ) -> Option<&'static str> {
+        let now = Instant::now();
+        let mut offenders = self.offenders.lock().unwrap();
+
+        if offenders.len() > 10_000 {
+            offenders.retain(|_, o| o.is_banned(now) || now - o.window_start < config.window);
+        }
+
+        let offender = offenders.entry(ip).or_insert_with(|| Offender::new(now));
+        if offender.is_banned(now) {
+            return None;
+        }
+        if now - offender.window_start >= config.window {
+            *offender = Offender::new(now);
+        }
+
+        let exceeded = match strike {
+            Strike::Error => {
+                offender.errors += 1;
+                (offender.errors > config.error_limit).then_some("too many error responses")
+            }
+            Strike::Login => {
+                offender.logins += 1;
+                (offender.logins > config.login_limit).then_some("too many login attempts")
+            }
+            Strike::Scan => {
+                offender.scans += 1;
+                (offender.scans > config.scan_limit).then_some("path scanning")
+            }
+        };
+
+        if let Some(reason) = exceeded {
+            tracing::warn!(
+                "Banning {} for {:?}: {}",
+                privacy.ip(ip),
+                config.ban_duration,
+                reason
+            );
+            offender.ban = Some((now + config.ban_duration, reason.to_string()));
+        }
+        exceeded
+    }
+}
+
+/// Middleware rejecting banned clients and recording suspicious behaviour.
+pub async fn guard(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(config) = &state.config.bans else {
+        return next.run(req).await;
+    };
+
+    let privacy = &state.config.privacy;
+    let ip = utils::client_ip(&addr, req.headers(), state.config.trust_forwarded_for);
+    if state.bans.is_banned(ip) {
+        return (
+            StatusCode::FORBIDDEN,
+            "Access denied: too many suspicious requests. Try again later.",
+        )
+            .into_response();
+    }
+
+    let path = req.uri().path().to_lowercase();
+    let mut reason = None;
+    if config.is_scan(&path) {
+        reason = reason.or(state.bans.strike(ip, Strike::Scan, config, privacy));
+    }
+    if req.method() == Method::POST && config.login_paths.contains(&path) {
+        reason = reason.or(state.bans.strike(ip, Strike::Login, config, privacy));
+    }
+    // The request that got the client banned isn't let through either.
+    if let Some(reason) = reason {
+        persist_ban(&state, config, ip, reason).await;
+        return (
+            StatusCode::FORBIDDEN,
+            "Access denied: too many suspicious requests. Try again later.",
+        )
+            .into_response();
+    }
+
+    let response = next.run(req).await;
+
+    if (response.status().is_client_error() || response.status().is_server_error())
+        && let Some(reason) = state.bans.strike(ip, Strike::Error, config, privacy)
+    {
+        persist_ban(&state, config, ip, reason).await;
+    }
+
+    response
+}
+
+/// Stores a new ban in the database and the audit log.
+async fn persist_ban(state: &AppState, config: &BanConfig, ip: IpAddr, reason: &str) {
+    if let Some(db) = &state.db {
+        db::log_error(db.save_ban(ip, reason, config.ban_duration).await, "ban");
+    }
+    audit::record(
+        state,
+        &Actor::system(),
+        "ban.add",
+        Some(&ip.to_string()),
+        Some(reason),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(window: Duration, ban_duration: Duration) -> BanConfig {
+        BanConfig {
+            window,
+            ban_duration,
+            error_limit: 2,
+            login_limit: 2,
+            scan_limit: 1,
+            scan_patterns: paths_or_default(Vec::new(), DEFAULT_SCAN_PATTERNS),
+            login_paths: paths_or_default(Vec::new(), DEFAULT_LOGIN_PATHS),
+        }
+    }
+
+    fn strike(bans: &BanList, config: &BanConfig, ip: IpAddr) -> Option<&'static str> {
+        bans.strike(ip, Strike::Error, config, &LogPrivacy::default())
+    }
+
+    #[test]
+    fn exceeding_the_limit_bans() {
+        let config = config(Duration::from_secs(60), Duration::from_secs(60));
+        let bans = BanList::default();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+
+        assert_eq!(strike(&bans, &config, ip), None);
+        assert_eq!(strike(&bans, &config, ip), None);
+        assert!(!bans.is_banned(ip));
+        assert_eq!(strike(&bans, &config, ip), Some("too many error responses"));
+        assert!(bans.is_banned(ip));
+        assert!(!bans.is_banned(other));
+        assert_eq!(bans.list().len(), 1);
+    }
+
+    #[test]
+    fn counters_and_bans_expire() {
+        let config = config(Duration::from_millis(50), Duration::from_millis(50));
+        let bans = BanList::default();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+
+        strike(&bans, &config, ip);
+        strike(&bans, &config, ip);
+        std::thread::sleep(Duration::from_millis(60));
+        // The window was reset, so this is the first strike again.
+        assert_eq!(strike(&bans, &config, ip), None);
+
+        strike(&bans, &config, ip);
+        strike(&bans, &config, ip);
+        assert!(bans.is_banned(ip));
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!bans.is_banned(ip));
+        assert!(bans.list().is_empty());
+    }
+
+    #[test]
+    fn scan_patterns_match_case_insensitively() {
+        let mut config = config(Duration::from_secs(60), Duration::from_secs(60));
+        assert!(config.is_scan("/.env"));
+        assert!(config.is_scan("/blog/wp-login.php"));
+        assert!(!config.is_scan("/score/student"));
+
+        config.scan_patterns = paths_or_default(vec!["/Admin.PHP".to_string()], &[]);
+        assert!(config.is_scan(&"/ADMIN.php".to_lowercase()));
+        assert!(!config.is_scan("/.env"));
+    }
+}
@@ -0,0 +1,155 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Outbound bandwidth limiting.
+//!
+//! Response bodies streamed through unchanged (downloads, images, PDFs) are
+//! paced by token buckets: one per client connection and one shared by the
+//! whole process. Rewritten pages are sent at full speed, so a large download
+//! can't starve the HTML traffic on a small uplink. HTTP/3 connections are
+//! only subject to the global limit.
+
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use axum::body::{Body, Bytes};
+use http_body::{Frame, SizeHint};
+use serde::Serialize;
+use tokio::time::{Instant, Sleep};
+
+use crate::config;
+
+/// Bandwidth caps in bytes per second.
+#[derive(Debug, Clone, Serialize)]
+pub struct BandwidthConfig {
+    /// Cap of all streamed responses together.
+    pub global: Option<u64>,
+    /// Cap of the streamed responses of one client connection.
+    pub per_connection: Option<u64>,
+}
+
+impl BandwidthConfig {
+    /// # Environment Variables
+    /// * `BANDWIDTH_LIMIT` - Bytes per second of all streamed responses (default: unlimited).
+    /// * `BANDWIDTH_LIMIT_PER_CONNECTION` - Bytes per second of one connection (default: unlimited).
+    pub fn from_env() -> Self {
+        Self {
+            global: config::env_parse("BANDWIDTH_LIMIT").filter(|v| *v > 0),
+            per_connection: config::env_parse("BANDWIDTH_LIMIT_PER_CONNECTION").filter(|v| *v > 0),
+        }
+    }
+}
+
+/// A token bucket holding up to one second worth of bytes.
+#[derive(Debug)]
+pub struct Bucket {
+    rate: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl Bucket {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let rate = bytes_per_sec as f64;
+        Self {
+            rate,
+            state: Mutex::new((rate, Instant::now())),
+        }
+    }
+
+    /// Takes `bytes` from the bucket, returning when they are paid for.
+    ///
+    /// The bucket may go into debt, so chunks larger than the rate still pass.
+    fn take(&self, bytes: usize) -> Instant {
+        let mut state = self.state.lock().expect("bandwidth lock poisoned");
+        let (tokens, last) = &mut *state;
+        let now = Instant::now();
+        *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.rate).min(self.rate);
+        *last = now;
+        *tokens -= bytes as f64;
+
+        if *tokens >= 0.0 {
+            now
+        } else {
+            now + Duration::from_secs_f64(-*tokens / self.rate)
+        }
+    }
+}
+
+/// The bucket of a client connection, added to its requests by the server.
+#[derive(Debug, Clone)]
+pub struct ConnectionBucket(pub Arc<Bucket>);
+
+/// Paces `body` by `buckets`, or returns it unchanged if there are none.
+pub fn limit(body: Body, buckets: Vec<Arc<Bucket>>) -> Body {
+    if buckets.is_empty() {
+        return body;
+    }
+    Body::new(Limited {
+        inner: body,
+        buckets,
+        pending: None,
+    })
+}
+
+/// A body holding back each chunk until the buckets have paid for it.
+struct Limited {
+    inner: Body,
+    buckets: Vec<Arc<Bucket>>,
+    pending: Option<(Frame<Bytes>, Pin<Box<Sleep>>)>,
+}
+
+impl http_body::Body for Limited {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        if let Some((_, sleep)) = &mut self.pending {
+            ready!(sleep.as_mut().poll(cx));
+            let (frame, _) = self.pending.take().expect("pending frame");
+            return Poll::Ready(Some(Ok(frame)));
+        }
+
+        let frame = match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            other => return Poll::Ready(other),
+        };
+        let Some(data) = frame.data_ref() else {
+            return Poll::Ready(Some(Ok(frame)));
+        };
+
+        let len = data.len();
+        match self.buckets.iter().map(|b| b.take(len)).max() {
+            Some(until) if until > Instant::now() => {
+                self.pending = Some((frame, Box::pin(tokio::time::sleep_until(until))));
+                self.poll_frame(cx)
+            }
+            _ => Poll::Ready(Some(Ok(frame))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
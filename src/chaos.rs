@@ -0,0 +1,182 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Fault injection for development.
+//!
+//! Lets developers of client apps exercise their error handling against
+//! a misbehaving mirror without the real upstream having to misbehave.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body::{Frame, SizeHint};
+use serde::Serialize;
+
+use crate::{config, effective_config, state::AppState};
+
+/// Fault injection settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChaosConfig {
+    /// Upper bound of the random delay added before each request.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub max_latency: Duration,
+    /// Probability (0.0 - 1.0) of answering with a 502 instead of proxying.
+    pub error_rate: f64,
+    /// Probability (0.0 - 1.0) of cutting the response body short.
+    pub truncate_rate: f64,
+}
+
+impl ChaosConfig {
+    /// # Environment Variables
+    /// * `CHAOS_ENABLED` - Set to "true" or "1" to enable fault injection.
+    /// * `CHAOS_LATENCY_MS` - Maximum random latency in milliseconds (default: 0).
+    /// * `CHAOS_ERROR_RATE` - Probability of an injected 502 (default: 0).
+    /// * `CHAOS_TRUNCATE_RATE` - Probability of a truncated body (default: 0).
+    pub fn from_env() -> Option<Self> {
+        if !config::env_flag("CHAOS_ENABLED") {
+            return None;
+        }
+
+        Some(Self {
+            max_latency: Duration::from_millis(config::env_parse("CHAOS_LATENCY_MS").unwrap_or(0)),
+            error_rate: probability("CHAOS_ERROR_RATE"),
+            truncate_rate: probability("CHAOS_TRUNCATE_RATE"),
+        })
+    }
+}
+
+fn probability(name: &str) -> f64 {
+    config::env_parse::<f64>(name)
+        .unwrap_or(0.0)
+        .clamp(0.0, 1.0)
+}
+
+/// Middleware injecting latency, errors and truncated bodies into proxied responses.
+pub async fn inject_faults(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let Some(chaos) = &state.config.chaos else {
+        return next.run(req).await;
+    };
+
+    if !chaos.max_latency.is_zero() {
+        let millis = rand::random_range(0..=chaos.max_latency.as_millis() as u64);
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+    }
+
+    if rand::random_bool(chaos.error_rate) {
+        tracing::warn!(
+            "Chaos: injecting 502 for {}",
+            state.config.privacy.url(&req.uri().to_string())
+        );
+        return (
+            StatusCode::BAD_GATEWAY,
+            "Proxy Error: injected by chaos mode",
+        )
+            .into_response();
+    }
+
+    let response = next.run(req).await;
+
+    if !rand::random_bool(chaos.truncate_rate) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    // Streamed bodies without a length are cut somewhere in their first 64 KiB.
+    let len = http_body::Body::size_hint(&body)
+        .exact()
+        .unwrap_or(64 * 1024);
+    let cut = rand::random_range(0..=len / 2);
+    tracing::warn!(
+        "Chaos: truncating body of {} bytes after {} bytes",
+        len,
+        cut
+    );
+
+    parts.headers.remove("content-length");
+    Response::from_parts(
+        parts,
+        Body::new(Truncated {
+            inner: body,
+            remaining: cut,
+        }),
+    )
+}
+
+/// A body ending after `remaining` more bytes.
+struct Truncated {
+    inner: Body,
+    remaining: u64,
+}
+
+impl http_body::Body for Truncated {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        if self.remaining == 0 {
+            return Poll::Ready(None);
+        }
+        let frame = match std::task::ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            other => return Poll::Ready(other),
+        };
+        let Ok(mut data) = frame.into_data() else {
+            // Trailers of a body that is cut anyway.
+            return Poll::Ready(None);
+        };
+        if data.len() as u64 > self.remaining {
+            data.truncate(self.remaining as usize);
+        }
+        self.remaining -= data.len() as u64;
+        Poll::Ready(Some(Ok(Frame::data(data))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0 || self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let mut hint = SizeHint::new();
+        hint.set_upper(self.remaining);
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn truncated_body_stops_after_the_cut() {
+        let body = Body::new(Truncated {
+            inner: Body::from("abcdefghij"),
+            remaining: 5,
+        });
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(&bytes[..], b"abcde");
+    }
+}
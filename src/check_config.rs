@@ -0,0 +1,295 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! `jecnaproxy check-config`: validates the configuration without starting the proxy.
+//!
+//! Invalid values are mostly ignored with a warning when the proxy starts,
+//! which the default log level hides. Here the configuration is read with
+//! those warnings captured and reported as errors, followed by the checks the
+//! proxy would otherwise only fail at startup or at request time.
+
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::Url;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+use crate::cli::CheckConfigArgs;
+use crate::cluster::Cluster;
+use crate::config::{Config, Upstream};
+use crate::tls::CertResolver;
+use crate::upstream_client;
+
+/// Timeout of the requests made with `--connect`.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handler of `jecnaproxy check-config`, exits with 1 if there are errors.
+///
+/// `allow_any_upstream` is `--i-know-what-im-doing`, which turns upstreams
+/// missing from `UPSTREAM_ALLOWLIST` into warnings.
+pub async fn run(args: &CheckConfigArgs, allow_any_upstream: bool) {
+    let mut report = Report::default();
+
+    let capture = Capture::default();
+    let config = tracing::subscriber::with_default(
+        tracing_subscriber::registry().with(capture.clone()),
+        Config::from_env,
+    );
+    for message in capture.0.lock().unwrap().drain(..) {
+        report.error(message);
+    }
+
+    let upstreams = check_upstreams(&config, allow_any_upstream, &mut report);
+    check_listeners(&config, &mut report);
+    check_storage(&config, &mut report);
+    if args.connect {
+        check_reachability(&config, &upstreams, &mut report).await;
+    }
+
+    println!();
+    if report.errors == 0 {
+        println!("Configuration OK ({} warnings)", report.warnings);
+    } else {
+        println!("{} errors, {} warnings", report.errors, report.warnings);
+        std::process::exit(1);
+    }
+}
+
+/// Checks `MODE` and `UPSTREAMS`, returning the upstreams that could be built.
+fn check_upstreams(
+    config: &Config,
+    allow_any_upstream: bool,
+    report: &mut Report,
+) -> Vec<Upstream> {
+    let mut upstreams = Vec::new();
+    match config.default_upstream(config.mode.clone()) {
+        Ok(upstream) => upstreams.push(upstream),
+        Err(e) => report.error(e),
+    }
+    for spec in &config.upstreams {
+        match spec.route() {
+            Ok(route) => upstreams.push((*route.upstream).clone()),
+            Err(e) => report.error(e),
+        }
+    }
+
+    for upstream in &upstreams {
+        match config.check_upstream(upstream) {
+            Ok(()) => report.ok(format!(
+                "upstream {} at {}{}",
+                upstream.name,
+                upstream.base,
+                if upstream.prefix.is_empty() {
+                    String::new()
+                } else {
+                    format!(", served under {}", upstream.prefix)
+                }
+            )),
+            Err(e) if allow_any_upstream => {
+                report.warning(format!("{}, allowed by --i-know-what-im-doing", e))
+            }
+            Err(e) => report.error(format!(
+                "{}. Add the host to UPSTREAM_ALLOWLIST or pass --i-know-what-im-doing.",
+                e
+            )),
+        }
+    }
+    upstreams
+}
+
+/// Checks `BASE_URL`, the ports and the certificates.
+fn check_listeners(config: &Config, report: &mut Report) {
+    if let Some(base) = &config.base_url {
+        match http_url(base) {
+            Ok(_) => report.ok(format!("BASE_URL {}", base)),
+            Err(e) => report.error(format!("BASE_URL {:?} {}", base, e)),
+        }
+    }
+
+    match config.https_port {
+        Some(port) if port == config.port => {
+            report.error(format!("PORT and HTTPS_PORT are both {}", port))
+        }
+        Some(port) => report.ok(format!(
+            "HTTP on port {}, HTTPS on port {}",
+            config.port, port
+        )),
+        None => report.ok(format!("HTTP on port {}", config.port)),
+    }
+
+    match &config.tls {
+        Some(tls) => match CertResolver::load(tls.clone()) {
+            Ok(_) => report.ok(format!(
+                "certificate {} with {} SNI certificates",
+                tls.default.cert.display(),
+                tls.sni.len()
+            )),
+            Err(e) => report.error(format!("Failed to load TLS certificates: {}", e)),
+        },
+        None => {
+            if config.https_port.is_some() {
+                report.error("HTTPS_PORT requires TLS_CERT_FILE and TLS_KEY_FILE");
+            }
+            if config.http3.is_some() {
+                report.error("HTTP3_PORT requires TLS_CERT_FILE and TLS_KEY_FILE");
+            }
+        }
+    }
+    if config.tls.is_some() && config.https_port.is_none() && config.http3.is_none() {
+        report.warning("TLS certificates are configured, but neither HTTPS_PORT nor HTTP3_PORT");
+    }
+}
+
+/// Checks the database, Redis, webhooks and `OFFLINE_DIR`.
+fn check_storage(config: &Config, report: &mut Report) {
+    match &config.database_url {
+        Some(url) => {
+            let scheme = url.split_once(':').map(|(scheme, _)| scheme);
+            if matches!(scheme, Some("sqlite" | "postgres" | "postgresql")) {
+                report.ok(format!("DATABASE_URL {}", redact_url(url)));
+            } else {
+                report.error(format!(
+                    "DATABASE_URL {:?} is neither a sqlite:// nor a postgres:// URL",
+                    redact_url(url)
+                ));
+            }
+        }
+        None => {
+            if config.users.is_some() {
+                report.error("USERS_ENABLED requires DATABASE_URL");
+            }
+            if config.vault.is_some() {
+                report.warning(
+                    "VAULT_KEY is set but DATABASE_URL is not, the credential vault is disabled",
+                );
+            }
+        }
+    }
+
+    if let Some(url) = &config.redis_url {
+        match Cluster::new(url, &config.redis_prefix) {
+            Ok(_) => report.ok(format!("REDIS_URL {}", redact_url(url))),
+            Err(e) => report.error(format!("Invalid REDIS_URL: {}", e)),
+        }
+    }
+
+    for url in &config.notify_webhooks {
+        if let Err(e) = http_url(url) {
+            report.error(format!("NOTIFY_WEBHOOK_URLS entry {:?} {}", url, e));
+        }
+    }
+
+    if let Some(dir) = &config.offline_dir
+        && !dir.is_dir()
+    {
+        report.warning(format!(
+            "OFFLINE_DIR {} does not exist yet, create it with `jecnaproxy snapshot`",
+            dir.display()
+        ));
+    }
+}
+
+/// Requests the root of every upstream.
+async fn check_reachability(config: &Config, upstreams: &[Upstream], report: &mut Report) {
+    let builder =
+        upstream_client::resolving(reqwest::Client::builder(), config.upstream_http.ip_family);
+    let client = match builder.timeout(CONNECT_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => return report.error(format!("Failed to build the HTTP client: {}", e)),
+    };
+    for upstream in upstreams {
+        match client.get(&upstream.base).send().await {
+            Ok(resp) if resp.status().is_server_error() => report.warning(format!(
+                "upstream {} answered with {}",
+                upstream.name,
+                resp.status()
+            )),
+            Ok(resp) => report.ok(format!(
+                "upstream {} answered with {}",
+                upstream.name,
+                resp.status()
+            )),
+            Err(e) => report.error(format!("upstream {} is unreachable: {}", upstream.name, e)),
+        }
+    }
+}
+
+fn http_url(value: &str) -> Result<Url, String> {
+    let url = Url::parse(value).map_err(|e| format!("is not a valid URL: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err("is not an http(s) URL with a host".to_string());
+    }
+    Ok(url)
+}
+
+/// `url` without its password, for the report.
+fn redact_url(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut parsed) if parsed.password().is_some() => {
+            let _ = parsed.set_password(Some("***"));
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    errors: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn ok(&mut self, message: impl fmt::Display) {
+        println!("ok       {}", message);
+    }
+
+    fn warning(&mut self, message: impl fmt::Display) {
+        self.warnings += 1;
+        println!("warning  {}", message);
+    }
+
+    fn error(&mut self, message: impl fmt::Display) {
+        self.errors += 1;
+        println!("error    {}", message);
+    }
+}
+
+/// Collects the messages of warnings and errors logged while the configuration is read.
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<String>>>);
+
+impl<S: Subscriber> Layer<S> for Capture {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() > Level::WARN {
+            return;
+        }
+        let mut message = String::new();
+        event.record(&mut MessageVisitor(&mut message));
+        self.0.lock().unwrap().push(message);
+    }
+}
+
+struct MessageVisitor<'a>(&'a mut String);
+
+impl Visit for MessageVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        }
+    }
+}
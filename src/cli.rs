@@ -0,0 +1,180 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    path::PathBuf,
+};
+
+use clap::{Args, Parser, Subcommand};
+
+/// Proxy server for spsejecna.cz and strav.nasejidelna.cz.
+///
+/// Most settings are read from environment variables, see the README.
+#[derive(Debug, Parser)]
+#[command(version, long_version = crate::version::LONG_VERSION, about)]
+pub struct Cli {
+    /// Allow proxying an upstream host that is not listed in `UPSTREAM_ALLOWLIST`.
+    #[arg(long, env = "I_KNOW_WHAT_IM_DOING", value_parser = clap::builder::BoolishValueParser::new())]
+    pub i_know_what_im_doing: bool,
+
+    /// Serve HTTPS with a generated self-signed certificate, for local development.
+    ///
+    /// Listens on `HTTPS_PORT` (default: 3443) and replaces `TLS_CERT_FILE`.
+    #[arg(long, env = "DEV_TLS", value_parser = clap::builder::BoolishValueParser::new())]
+    pub dev_tls: bool,
+
+    /// Run the proxy server in the background, writing its PID to `--pid-file`.
+    #[arg(long)]
+    pub daemonize: bool,
+
+    /// PID file of the proxy started with `--daemonize`.
+    #[arg(long, default_value = "jecnaproxy.pid")]
+    pub pid_file: PathBuf,
+
+    /// File the output of the proxy started with `--daemonize` is appended to.
+    #[arg(long, default_value = "jecnaproxy.log")]
+    pub log_file: PathBuf,
+
+    /// Runs the proxy server when omitted.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Crawls the upstream and writes a rewritten static copy to disk.
+    Snapshot(SnapshotArgs),
+    /// Writes the database and `OFFLINE_DIR` to an encrypted archive.
+    Backup(BackupArgs),
+    /// Restores an archive written by `backup`, while the proxy is stopped.
+    Restore(RestoreArgs),
+    /// Creates a user account, e.g. the first admin.
+    ///
+    /// The password is read from `USER_PASSWORD` or standard input.
+    AddUser(AddUserArgs),
+    /// Prints Prometheus alerting rules for the configured SLOs.
+    GenAlerts(GenAlertsArgs),
+    /// Replays requests recorded in a HAR file against a mock upstream and reports differences.
+    Replay(ReplayArgs),
+    /// Stops the proxy started with `--daemonize`.
+    Stop(StopArgs),
+    /// Validates the configuration and reports problems, without starting the proxy.
+    CheckConfig(CheckConfigArgs),
+    /// Prints the resolved configuration as JSON, with secrets redacted.
+    PrintConfig,
+}
+
+#[derive(Debug, Args)]
+pub struct SnapshotArgs {
+    /// Output directory (default: `OFFLINE_DIR` or "./snapshot").
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+    /// Maximum number of fetched URLs (default: `CRAWL_MAX_PAGES`).
+    #[arg(long)]
+    pub max_pages: Option<usize>,
+    /// Maximum link depth from the start paths (default: `CRAWL_MAX_DEPTH`).
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct BackupArgs {
+    /// Archive to write.
+    #[arg(long)]
+    pub out: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct RestoreArgs {
+    /// Archive to restore.
+    #[arg(long)]
+    pub from: PathBuf,
+    /// Overwrite an existing database and snapshot files.
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct AddUserArgs {
+    /// Name of the new account.
+    #[arg(long)]
+    pub username: String,
+    /// Give the account the admin role.
+    #[arg(long)]
+    pub admin: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct GenAlertsArgs {
+    /// Prometheus job scraping the proxy.
+    #[arg(long, default_value = "jecnaproxy")]
+    pub job: String,
+    /// Requests in the long window below which burn-rate alerts stay silent.
+    #[arg(long, default_value_t = 20)]
+    pub min_requests: u64,
+    /// File to write instead of standard output.
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReplayArgs {
+    /// HAR file recorded against the upstream, e.g. with the browser's developer tools.
+    pub recording: PathBuf,
+    /// Results of an earlier replay to compare with, instead of the recorded responses.
+    #[arg(long)]
+    pub baseline: Option<PathBuf>,
+    /// File to write the results to, for a later `--baseline`.
+    #[arg(long)]
+    pub save: Option<PathBuf>,
+    /// `Host` of the replayed requests, which the rewritten URLs point to.
+    #[arg(long, default_value = "localhost:3000")]
+    pub host: String,
+}
+
+#[derive(Debug, Args)]
+pub struct StopArgs {
+    /// PID file written by the running proxy.
+    #[arg(long, default_value = "jecnaproxy.pid")]
+    pub pid_file: PathBuf,
+    /// Seconds to wait for the proxy to exit.
+    #[arg(long, default_value_t = 30)]
+    pub timeout: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct CheckConfigArgs {
+    /// Also request every upstream and report whether it answers.
+    #[arg(long)]
+    pub connect: bool,
+}
+
+/// Reads a secret from the environment variable `var`, or else from the first
+/// line of standard input.
+///
+/// Secrets are never taken as arguments, which other users can see in `ps`.
+pub fn read_secret(var: &str) -> io::Result<String> {
+    if let Ok(value) = std::env::var(var) {
+        return Ok(value);
+    }
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        eprint!("{}: ", var);
+        io::stderr().flush()?;
+    }
+    let mut line = String::new();
+    stdin.lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
@@ -0,0 +1,172 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! State shared between replicas through Redis.
+//!
+//! Redis is optional and never required for serving: whenever it can't be
+//! reached, callers fall back to their local, per-process behaviour and Redis
+//! is retried after a short backoff.
+
+use std::{
+    sync::{
+        LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use redis::{
+    Client, RedisResult, Script,
+    aio::{ConnectionManager, ConnectionManagerConfig},
+};
+use tokio::sync::Mutex;
+
+/// Connection and response timeout; Redis calls sit on the request path.
+const TIMEOUT: Duration = Duration::from_millis(250);
+
+/// How long Redis is skipped after a failure.
+const BACKOFF: Duration = Duration::from_secs(5);
+
+/// Token bucket refilled continuously at `capacity / period`.
+///
+/// KEYS[1]: bucket, ARGV[1]: capacity, ARGV[2]: period in ms.
+/// Returns 0 if a token was taken, otherwise the milliseconds until one is available.
+static TOKEN_BUCKET: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+local capacity = tonumber(ARGV[1])
+local rate = capacity / tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(bucket[1]) or capacity
+local ts = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * rate)
+local wait = 0
+if tokens >= 1 then
+  tokens = tokens - 1
+else
+  wait = math.ceil((1 - tokens) / rate)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], tonumber(ARGV[2]) + 1000)
+return wait
+"#,
+    )
+});
+
+/// Connection to the shared Redis.
+pub struct Cluster {
+    client: Client,
+    prefix: String,
+    connection: Mutex<Option<ConnectionManager>>,
+    retry_at: std::sync::Mutex<Option<Instant>>,
+    down: AtomicBool,
+}
+
+impl Cluster {
+    /// Creates the handle; the connection is only established on first use.
+    pub fn new(url: &str, prefix: &str) -> RedisResult<Self> {
+        Ok(Self {
+            client: Client::open(url)?,
+            prefix: prefix.to_string(),
+            connection: Mutex::new(None),
+            retry_at: std::sync::Mutex::new(None),
+            down: AtomicBool::new(false),
+        })
+    }
+
+    /// Namespaces a key with the configured prefix.
+    pub fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    /// Returns a connection, or `None` while Redis is backing off.
+    async fn connection(&self) -> Option<ConnectionManager> {
+        if self
+            .retry_at
+            .lock()
+            .unwrap()
+            .is_some_and(|at| Instant::now() < at)
+        {
+            return None;
+        }
+
+        let mut connection = self.connection.lock().await;
+        if connection.is_none() {
+            let config = ConnectionManagerConfig::new()
+                .set_number_of_retries(1)
+                .set_connection_timeout(TIMEOUT)
+                .set_response_timeout(TIMEOUT);
+            let manager = tokio::time::timeout(
+                TIMEOUT * 2,
+                ConnectionManager::new_with_config(self.client.clone(), config),
+            )
+            .await
+            .unwrap_or_else(|_| Err((redis::ErrorKind::IoError, "connection timed out").into()));
+            match manager {
+                Ok(manager) => *connection = Some(manager),
+                Err(e) => {
+                    self.failed(&e);
+                    return None;
+                }
+            }
+        }
+        connection.clone()
+    }
+
+    /// Records a failed call, starting the backoff.
+    fn failed(&self, e: &redis::RedisError) {
+        *self.retry_at.lock().unwrap() = Some(Instant::now() + BACKOFF);
+        if !self.down.swap(true, Ordering::SeqCst) {
+            tracing::warn!("Redis is unavailable, falling back to local state: {}", e);
+        }
+    }
+
+    fn succeeded(&self) {
+        if self.down.swap(false, Ordering::SeqCst) {
+            tracing::info!("Redis is available again");
+        }
+    }
+
+    /// Takes a token from the shared bucket `key` holding `capacity` tokens per `period`.
+    ///
+    /// Returns `None` if Redis is unavailable, otherwise the seconds to wait
+    /// (`Some(None)` when the request is allowed).
+    pub async fn take_token(
+        &self,
+        key: &str,
+        capacity: u32,
+        period: Duration,
+    ) -> Option<Option<u64>> {
+        let mut connection = self.connection().await?;
+        let result: RedisResult<u64> = TOKEN_BUCKET
+            .key(self.key(key))
+            .arg(capacity.max(1))
+            .arg(period.as_millis() as u64)
+            .invoke_async(&mut connection)
+            .await;
+
+        match result {
+            Ok(wait_ms) => {
+                self.succeeded();
+                Some((wait_ms > 0).then(|| wait_ms.div_ceil(1000)))
+            }
+            Err(e) => {
+                self.failed(&e);
+                None
+            }
+        }
+    }
+}
@@ -12,10 +12,48 @@
  * GNU General Public License for more details.
  */
 
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::ban::BanConfig;
+use crate::bandwidth::BandwidthConfig;
+use crate::chaos::ChaosConfig;
+use crate::cookies::CookiePolicy;
+use crate::crawler::CrawlConfig;
+use crate::effective_config;
+use crate::forward_auth::ForwardAuthConfig;
+use crate::http3::Http3Config;
+use crate::inject::InjectConfig;
+use crate::limits::HeaderLimits;
+use crate::memory::MemoryConfig;
+use crate::privacy::LogPrivacy;
+use crate::pwa::PwaConfig;
+use crate::retention::RetentionConfig;
+use crate::runtime::RuntimeConfig;
+use crate::sampling::SamplingConfig;
+use crate::scheduler;
+use crate::search::SearchConfig;
+use crate::server::TimeoutConfig;
+use crate::share::ShareConfig;
+use crate::slo::SloConfig;
+use crate::statsd::StatsdConfig;
+use crate::throttle::ThrottleConfig;
+use crate::tls::TlsConfig;
+use crate::trackers;
+use crate::upstream_client::UpstreamHttpConfig;
+use crate::upstreams::{self, UpstreamSpec, Wildcard};
+use crate::users::UsersConfig;
+use crate::vault::VaultConfig;
+use crate::via::ViaConfig;
+use crate::watcher::WatchConfig;
 use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
 /// Configuration for the Proxy Server.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Config {
     /// The port to listen on.
     pub port: u16,
@@ -26,38 +64,242 @@ pub struct Config {
     pub disable_warning: bool,
     /// Whether we should proxy spsejecna.cz or jidelna
     pub mode: Mode,
+    /// Extra spellings of a CUSTOM upstream rewritten to the proxy origin.
+    pub custom_variants: Vec<String>,
+    /// Literal replacements applied to rewritten content before the upstream variants.
+    pub rewrite_map: Vec<(String, String)>,
+    /// Links to the related hosts and their prefixes, see [`UpstreamSpec::external_rewrites`].
+    pub external_rewrites: Vec<(String, String)>,
+    /// Named upstreams selected by host or path prefix, including the related hosts.
+    pub upstreams: Vec<UpstreamSpec>,
+    /// Related hosts proxied under `/_ext/<host>`.
+    pub external_hosts: Vec<String>,
+    /// Apex domains whose subdomains are proxied under `/_ext/<host>`.
+    pub external_wildcards: Vec<Wildcard>,
+    /// Fault injection settings. `None` unless `CHAOS_ENABLED` is set.
+    pub chaos: Option<ChaosConfig>,
+    /// External auth endpoint consulted before proxying.
+    pub forward_auth: Option<ForwardAuthConfig>,
+    /// Automatic abuse banning. `None` unless `BAN_ENABLED` is set.
+    pub bans: Option<BanConfig>,
+    /// Bearer token protecting the admin API. The API is disabled if `None`.
+    #[serde(serialize_with = "effective_config::opt_redacted")]
+    pub admin_token: Option<String>,
+    /// Whether to reject all requests that could modify upstream state.
+    pub read_only: bool,
+    /// Whether to replace upstream service workers with one unregistering itself.
+    pub block_service_workers: bool,
+    /// Installable mirror. `None` unless `PWA_ENABLED` is set.
+    pub pwa: Option<PwaConfig>,
+    /// Lowercase patterns of tracking embeds removed from HTML, empty when disabled.
+    pub trackers: Vec<String>,
+    /// Operator snippets added to HTML pages. `None` unless `INJECT_*` is set.
+    pub inject: Option<InjectConfig>,
+    /// Whether to add the optional dark theme to HTML pages.
+    pub dark_mode: bool,
+    /// Anonymization applied to logged IPs and URLs.
+    pub privacy: LogPrivacy,
+    /// Requests logged in detail.
+    pub sampling: SamplingConfig,
+    /// Headers set or appended on every proxied response.
+    pub response_headers: Vec<HeaderRule>,
+    /// `Via` header and loop detection settings.
+    pub via: ViaConfig,
+    /// Hosts that may be used as CUSTOM upstream.
+    pub upstream_allowlist: Vec<String>,
+    /// Signed share links. `None` unless `SHARE_SECRET` is set.
+    pub share: Option<ShareConfig>,
+    /// Path template of news detail pages, `{id}` is replaced by the article id.
+    pub news_path: String,
+    /// Path of the grades page used by the exports.
+    pub grades_path: String,
+    /// Path of the timetable page used by the exports.
+    pub timetable_path: String,
+    /// Path of the absence page used by `/api/absences`.
+    pub absences_path: String,
+    /// Path of the school events page used by `/api/events`.
+    pub events_path: String,
+    /// Path of the exam schedule page used by `/api/exams`.
+    pub exams_path: String,
+    /// Path template of teacher pages, `{shortcut}` is replaced by the teacher's shortcut.
+    pub teacher_path: String,
+    /// Path of the room timetable page used by `/api/rooms/free`.
+    pub rooms_path: String,
+    /// Path of the substitution page used by `/api/dashboard`.
+    pub substitutions_path: String,
+    /// Path of the canteen on the JIDELNA upstream.
+    pub canteen_path: String,
+    /// Crawl limits for snapshots.
+    pub crawl: CrawlConfig,
+    /// Outbound limits shared by all background scraping.
+    pub throttle: ThrottleConfig,
+    /// Snapshot directory served when the upstream is unavailable.
+    pub offline_dir: Option<PathBuf>,
+    /// Full-text search. `None` unless `SEARCH_ENABLED` is set.
+    pub search: Option<SearchConfig>,
+    /// Page change monitoring. `None` unless `WATCH_PATHS` is set.
+    pub watch: Option<WatchConfig>,
+    /// Webhooks receiving notifications as JSON.
+    #[serde(serialize_with = "effective_config::webhook_urls")]
+    pub notify_webhooks: Vec<String>,
+    /// User accounts for the API, requires `database_url`.
+    pub users: Option<UsersConfig>,
+    /// Encrypted credential store, requires `database_url`.
+    pub vault: Option<VaultConfig>,
+    /// SQLite or Postgres database for persistent state.
+    #[serde(serialize_with = "effective_config::opt_url")]
+    pub database_url: Option<String>,
+    /// Redis shared between replicas.
+    #[serde(serialize_with = "effective_config::opt_url")]
+    pub redis_url: Option<String>,
+    /// Prefix of all Redis keys and channels.
+    pub redis_prefix: String,
+    /// Interval of the snapshot job writing to `offline_dir`.
+    #[serde(serialize_with = "effective_config::opt_secs")]
+    pub snapshot_interval: Option<Duration>,
+    /// Cleanup of old persisted data.
+    pub retention: RetentionConfig,
+    /// Service level objectives.
+    pub slo: SloConfig,
+    /// StatsD exporter, if `METRICS_BACKEND` selects it.
+    pub statsd: Option<StatsdConfig>,
+    /// Maximum random delay added to every scheduled job run.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub scheduler_jitter: Duration,
+    /// Tokio runtime sizing.
+    pub runtime: RuntimeConfig,
+    /// Client and upstream timeouts.
+    pub timeouts: TimeoutConfig,
+    /// Upstream protocol and connection pool settings.
+    pub upstream_http: UpstreamHttpConfig,
+    /// Request and response header limits.
+    pub header_limits: HeaderLimits,
+    /// Limit of buffered body bytes.
+    pub memory: MemoryConfig,
+    /// Maximum upstream redirects followed inside the proxy, 0 to pass them all on.
+    pub follow_redirects: usize,
+    /// Upstream statuses whose bodies are rewritten and decorated.
+    pub rewrite_statuses: StatusFilter,
+    /// Upstream content types whose bodies are rewritten and decorated.
+    pub rewrite_content_types: ContentTypeFilter,
+    /// Whether upstream 404 and 5xx HTML pages are replaced by the proxy's own.
+    pub error_pages: bool,
+    /// Whether `/sitemap.xml` is proxied with rewritten URLs instead of replaced by an empty one.
+    pub rewrite_sitemap: bool,
+    /// Whether bodies marked `Cache-Control: no-transform` are rewritten anyway.
+    pub ignore_no_transform: bool,
+    /// Bandwidth caps of streamed responses.
+    pub bandwidth: BandwidthConfig,
+    /// `SameSite` and `Secure` attributes of cookies.
+    pub cookies: CookiePolicy,
+    /// Whether to normalize request paths before forwarding them.
+    pub normalize_paths: bool,
+    /// Maximum simultaneous connections per client IP, 0 for no limit.
+    pub max_connections_per_ip: usize,
+    /// TCP port of the native HTTPS listener.
+    pub https_port: Option<u16>,
+    /// Certificates of the HTTPS and HTTP/3 listeners. `None` unless `TLS_CERT_FILE` is set.
+    pub tls: Option<TlsConfig>,
+    /// HTTP/3 listener. `None` unless `HTTP3_PORT` is set.
+    pub http3: Option<Http3Config>,
+    /// Whether to take the client IP from `X-Forwarded-For` (when behind a reverse proxy).
+    pub trust_forwarded_for: bool,
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Mode {
     SPSEJECNA,
     JIDELNA,
-    CUSTOM,
+    /// Upstream URL given directly as the mode.
+    CUSTOM(String),
 }
 
 impl Mode {
     fn from_env() -> Self {
-        match env::var("MODE").ok().map(|v| v.to_lowercase()).as_deref() {
-            Some(s) if s.is_empty() => Mode::SPSEJECNA,
-            None => Mode::SPSEJECNA,
-            Some("spsejecna") => Mode::SPSEJECNA,
-
-            Some("jidelna") => Mode::JIDELNA,
+        Self::parse(&env::var("MODE").unwrap_or_default())
+    }
 
-            _ => Mode::CUSTOM,
+    /// Parses a `MODE` value: "spsejecna" (or empty), "jidelna" or an upstream URL.
+    pub fn parse(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "" | "spsejecna" => Mode::SPSEJECNA,
+            "jidelna" => Mode::JIDELNA,
+            _ => Mode::CUSTOM(value.to_string()),
         }
     }
 
-    pub fn url(&self) -> String {
+    /// Name of the mode as accepted by [`Mode::parse`], the URL for CUSTOM.
+    pub fn name(&self) -> &str {
         match self {
-            Mode::SPSEJECNA => "https://www.spsejecna.cz".to_string(),
-            Mode::JIDELNA => "https://strav.nasejidelna.cz".to_string(),
-            Mode::CUSTOM => env::var("MODE").unwrap(),
+            Mode::SPSEJECNA => "spsejecna",
+            Mode::JIDELNA => "jidelna",
+            Mode::CUSTOM(url) => url,
         }
     }
+}
 
-    pub fn get_all_variants(&self) -> Vec<String> {
-        match self {
+impl Serialize for Mode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// The upstream in use, with everything needed per request computed once.
+#[derive(Debug, Clone)]
+pub struct Upstream {
+    pub mode: Mode,
+    /// Prefix requested paths are appended to, without a trailing slash.
+    pub base: String,
+    pub url: Url,
+    /// Value of the `Origin` header sent upstream.
+    pub origin: HeaderValue,
+    /// `host[:port]` of the upstream.
+    pub authority: String,
+    /// Spellings of the upstream URL rewritten to the proxy origin.
+    pub variants: Vec<String>,
+    /// Name of a named upstream, the mode name otherwise.
+    pub name: String,
+    /// Path prefix the upstream is served under, empty for the proxy root.
+    pub prefix: String,
+    /// Whether pages get the "Not Official" banner.
+    pub banner: bool,
+    /// `Cache-Control` set on proxied responses.
+    pub cache_control: Option<HeaderValue>,
+    /// Whether this is a related host under `/_ext`, which never gets the
+    /// client's cookies or credentials.
+    pub external: bool,
+}
+
+impl Upstream {
+    /// Parses the URL of `mode`. Fails for CUSTOM values that aren't http(s) URLs with a host.
+    ///
+    /// `custom_variants` are hosts or URLs rewritten to the proxy in addition
+    /// to the upstream URL itself, they are ignored for the built-in modes.
+    pub fn new(mode: Mode, custom_variants: &[String]) -> Result<Self, String> {
+        let base = match &mode {
+            Mode::SPSEJECNA => "https://www.spsejecna.cz",
+            Mode::JIDELNA => "https://strav.nasejidelna.cz",
+            Mode::CUSTOM(url) => url.trim_end_matches('/'),
+        }
+        .to_string();
+
+        let url =
+            Url::parse(&base).map_err(|e| format!("MODE {:?} is not a valid URL: {}", base, e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("MODE {:?} is not an http(s) URL", base));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| format!("MODE {:?} has no host", base))?;
+        let authority = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        let origin = HeaderValue::from_str(&url.origin().ascii_serialization())
+            .map_err(|e| format!("MODE {:?} is not a valid origin: {}", base, e))?;
+
+        let variants = match &mode {
             Mode::SPSEJECNA => vec![
                 "https://www.spsejecna.cz".to_string(),
                 "https://spsejecna.cz".to_string(),
@@ -68,15 +310,47 @@ impl Mode {
                 "https://strav.nasejidelna.cz".to_string(),
                 "http://strav.nasejidelna.cz".to_string(),
             ],
-            Mode::CUSTOM => {
-                let custom_url = env::var("MODE").unwrap();
-                let mut variants = vec![custom_url.clone()];
-                if custom_url.starts_with("https://") {
-                    variants.push(custom_url.replacen("https://", "http://", 1));
+            Mode::CUSTOM(_) => {
+                let mut variants = vec![base.clone()];
+                if base.starts_with("https://") {
+                    variants.push(base.replacen("https://", "http://", 1));
+                }
+                for variant in custom_variants {
+                    let variant = variant.trim_end_matches('/');
+                    let spellings = if variant.contains("://") {
+                        vec![variant.to_string()]
+                    } else {
+                        vec![
+                            format!("https://{}", variant),
+                            format!("http://{}", variant),
+                        ]
+                    };
+                    for spelling in spellings {
+                        if !variants.contains(&spelling) {
+                            variants.push(spelling);
+                        }
+                    }
                 }
+                // Longer spellings first, so a variant that extends another one is
+                // replaced as a whole.
+                variants.sort_by_key(|v| std::cmp::Reverse(v.len()));
                 variants
             }
-        }
+        };
+
+        Ok(Self {
+            name: mode.name().to_string(),
+            mode,
+            base,
+            url,
+            origin,
+            authority,
+            variants,
+            prefix: String::new(),
+            banner: true,
+            cache_control: None,
+            external: false,
+        })
     }
 }
 
@@ -85,24 +359,516 @@ impl Config {
     /// * `PORT` - Port to listen on (default: 3000).
     /// * `BASE_URL` - Explicit public URL of the proxy (optional).
     /// * `DISABLE_WARNING` - Set to "true" or "1" to disable the banner.
+    /// * `MODE` - "spsejecna", "jidelna" or an upstream URL (default: "spsejecna").
+    /// * `CUSTOM_VARIANTS` - Comma-separated hosts or URLs also rewritten to the proxy in CUSTOM mode.
+    /// * `REWRITE_MAP` - Comma-separated `from=to` pairs replaced in rewritten content.
+    /// * `UPSTREAMS`, `UPSTREAM_*` - Named upstreams, see [`UpstreamSpec::from_env`].
+    /// * `EXTERNAL_HOSTS` - Related hosts, see [`upstreams::external_from_env`].
+    /// * `CHAOS_*` - Fault injection, see [`ChaosConfig::from_env`].
+    /// * `FORWARD_AUTH_*` - Forward auth, see [`ForwardAuthConfig::from_env`].
+    /// * `BAN_*` - Abuse banning, see [`BanConfig::from_env`].
+    /// * `ADMIN_TOKEN` - Enables the admin API under `/_admin` (optional).
+    /// * `READ_ONLY` - Set to "true" or "1" to only allow GET/HEAD requests.
+    /// * `BLOCK_SERVICE_WORKERS` - Set to "true" or "1" to remove upstream service workers.
+    /// * `PWA_*` - Installable mirror, see [`PwaConfig::from_env`].
+    /// * `STRIP_TRACKERS`, `TRACKER_PATTERNS` - Tracker removal, see [`trackers::patterns_from_env`].
+    /// * `INJECT_*` - Custom HTML snippets, see [`InjectConfig::from_env`].
+    /// * `DARK_MODE` - Set to "true" or "1" to offer a dark theme.
+    /// * `LOG_PRIVACY` - Log anonymization, see [`LogPrivacy::from_env`].
+    /// * `LOG_SAMPLE_*` - Detailed logging of sampled requests, see [`SamplingConfig::from_env`].
+    /// * `RESPONSE_HEADERS_SET` - `|`-separated `Name: value` pairs replacing upstream headers.
+    /// * `RESPONSE_HEADERS_APPEND` - `|`-separated `Name: value` pairs added to upstream headers.
+    /// * `VIA_NAME`, `MAX_HOPS` - Loop detection, see [`ViaConfig::from_env`].
+    /// * `UPSTREAM_ALLOWLIST` - Comma-separated hosts allowed as CUSTOM upstream.
+    /// * `SHARE_*` - Share links, see [`ShareConfig::from_env`].
+    /// * `NEWS_PATH` - News detail path template (default: "/akce/{id}").
+    /// * `GRADES_PATH` - Grades page (default: "/score/student").
+    /// * `TIMETABLE_PATH` - Timetable page (default: "/timetable/class").
+    /// * `ABSENCES_PATH` - Absence page (default: "/absence/student").
+    /// * `EVENTS_PATH` - School events page (default: "/akce").
+    /// * `EXAMS_PATH` - Exam and homework schedule page (default: "/exam/student").
+    /// * `TEACHER_PATH` - Teacher page path template (default: "/ucitel/{shortcut}").
+    /// * `ROOMS_PATH` - Room timetable page (default: "/timetable/room").
+    /// * `SUBSTITUTIONS_PATH` - Substitution page (default: "/suplovani").
+    /// * `CANTEEN_PATH` - Canteen on the JIDELNA upstream (default: "/0341").
+    /// * `CRAWL_*` - Crawl limits, see [`CrawlConfig::from_env`].
+    /// * `SCRAPE_*` - Background scraping throttle, see [`ThrottleConfig::from_env`].
+    /// * `OFFLINE_DIR` - Snapshot directory used as offline fallback (optional).
+    /// * `SEARCH_*` - Full-text search, see [`SearchConfig::from_env`].
+    /// * `WATCH_*` - Change monitoring, see [`WatchConfig::from_env`].
+    /// * `NOTIFY_WEBHOOK_URLS` - Comma-separated notification webhooks.
+    /// * `USERS_*` - API user accounts, see [`UsersConfig::from_env`].
+    /// * `VAULT_*` - Credential vault, see [`VaultConfig::from_env`].
+    /// * `DATABASE_URL` - `sqlite://` or `postgres://` database keeping state across restarts (optional).
+    /// * `REDIS_URL` - Redis shared between replicas, e.g. `redis://127.0.0.1/` (optional).
+    /// * `REDIS_PREFIX` - Prefix of Redis keys (default: "jecnaproxy:").
+    /// * `SNAPSHOT_INTERVAL_SECS` - Periodically refresh `OFFLINE_DIR` (optional).
+    /// * `RETENTION_*` - Cleanup of old data, see [`RetentionConfig::from_env`].
+    /// * `SLO_*` - Service level objectives, see [`SloConfig::from_env`].
+    /// * `METRICS_BACKEND`, `STATSD_*` - StatsD exporter, see [`StatsdConfig::from_env`].
+    /// * `SCHEDULER_JITTER_SECS` - Maximum random delay of scheduled jobs (default: 30).
+    /// * `MAX_BUFFERED_BODY_BYTES`, `BUFFER_QUEUE_TIMEOUT_SECS` - Buffering limit, see [`MemoryConfig::from_env`].
+    /// * `RUNTIME_*` - Tokio runtime, see [`RuntimeConfig::from_env`].
+    /// * `CLIENT_*_TIMEOUT_SECS`, `UPSTREAM_*_TIMEOUT_SECS` - Timeouts, see [`TimeoutConfig::from_env`].
+    /// * `UPSTREAM_HTTP2`, `UPSTREAM_POOL_*` - Upstream connections, see [`UpstreamHttpConfig::from_env`].
+    /// * `MAX_*_HEADER_BYTES`, `MAX_*_HEADERS` - Header limits, see [`HeaderLimits::from_env`].
+    /// * `FOLLOW_REDIRECTS` - Same-host redirects followed inside the proxy, see [`crate::redirects`] (default: 0).
+    /// * `REWRITE_STATUSES` - Comma-separated status classes or codes whose bodies are rewritten (default: "2xx,3xx").
+    /// * `REWRITE_CONTENT_TYPES_*` - Rewritten content types, see [`ContentTypeFilter::from_env`].
+    /// * `ERROR_PAGES` - Set to "true" or "1" to replace upstream 404/5xx pages with the proxy's own.
+    /// * `REWRITE_SITEMAP` - Set to "true" or "1" to proxy `/sitemap.xml` instead of serving an empty one.
+    /// * `IGNORE_NO_TRANSFORM` - Set to "true" or "1" to rewrite bodies marked `no-transform` anyway.
+    /// * `BANDWIDTH_LIMIT*` - Bandwidth caps, see [`BandwidthConfig::from_env`].
+    /// * `COOKIE_*` - Cookie attributes, see [`CookiePolicy::from_env`].
+    /// * `NORMALIZE_PATHS` - Set to "false" or "0" to forward paths exactly as received (default: true).
+    /// * `MAX_CONNECTIONS_PER_IP` - Simultaneous connections per client IP, 0 for no limit (default: 64).
+    /// * `HTTPS_PORT` - TCP port of the native HTTPS listener (default: disabled).
+    /// * `TLS_*` - Certificates, see [`TlsConfig::from_env`].
+    /// * `HTTP3_*` - HTTP/3 listener, see [`Http3Config::from_env`].
+    /// * `TRUST_FORWARDED_FOR` - Set to "true" or "1" to take the client IP from the last `X-Forwarded-For` entry.
     pub fn from_env() -> Self {
-        let port = env::var("PORT")
-            .ok()
-            .and_then(|p| p.parse().ok())
-            .unwrap_or(3000);
+        let port = env_parse("PORT").unwrap_or(3000);
 
         let base_url = env::var("BASE_URL").ok();
-        let disable_warning = env::var("DISABLE_WARNING")
-            .map(|v| v == "true" || v == "1")
-            .unwrap_or(false);
+        let disable_warning = env_flag("DISABLE_WARNING");
 
         let mode = Mode::from_env();
+        let custom_variants = env_list("CUSTOM_VARIANTS");
+        let rewrite_map: Vec<(String, String)> = env_list("REWRITE_MAP")
+            .into_iter()
+            .filter_map(|pair| {
+                let parsed = pair
+                    .split_once('=')
+                    .map(|(from, to)| (from.trim().to_string(), to.trim().to_string()))
+                    .filter(|(from, _)| !from.is_empty());
+                if parsed.is_none() {
+                    tracing::warn!("Ignoring invalid REWRITE_MAP entry: {}", pair);
+                }
+                parsed
+            })
+            .collect();
+        let mut upstreams = UpstreamSpec::from_env(!disable_warning);
+        let (external, external_wildcards) = upstreams::external_from_env();
+        let external_rewrites = external
+            .iter()
+            .flat_map(UpstreamSpec::external_rewrites)
+            .collect();
+        let external_hosts = external
+            .iter()
+            .filter_map(|spec| Some(Url::parse(spec.mode.name()).ok()?.host_str()?.to_string()))
+            .collect();
+        upstreams.extend(external);
+        let chaos = ChaosConfig::from_env();
+        let forward_auth = ForwardAuthConfig::from_env();
+        let bans = BanConfig::from_env();
+        let admin_token = env::var("ADMIN_TOKEN").ok().filter(|v| !v.is_empty());
+        let read_only = env_flag("READ_ONLY");
+        let block_service_workers = env_flag("BLOCK_SERVICE_WORKERS");
+        let pwa = PwaConfig::from_env();
+        let trackers = trackers::patterns_from_env();
+        let inject = InjectConfig::from_env();
+        let dark_mode = env_flag("DARK_MODE");
+        let privacy = LogPrivacy::from_env();
+        let sampling = SamplingConfig::from_env();
+        let response_headers = HeaderRule::from_env("RESPONSE_HEADERS_SET", false)
+            .into_iter()
+            .chain(HeaderRule::from_env("RESPONSE_HEADERS_APPEND", true))
+            .collect();
+        let via = ViaConfig::from_env();
+        let upstream_allowlist = env_list("UPSTREAM_ALLOWLIST");
+        let share = ShareConfig::from_env();
+        let news_path = env_path("NEWS_PATH", "/akce/{id}");
+        let grades_path = env_path("GRADES_PATH", "/score/student");
+        let timetable_path = env_path("TIMETABLE_PATH", "/timetable/class");
+        let absences_path = env_path("ABSENCES_PATH", "/absence/student");
+        let events_path = env_path("EVENTS_PATH", "/akce");
+        let exams_path = env_path("EXAMS_PATH", "/exam/student");
+        let teacher_path = env_path("TEACHER_PATH", "/ucitel/{shortcut}");
+        let rooms_path = env_path("ROOMS_PATH", "/timetable/room");
+        let substitutions_path = env_path("SUBSTITUTIONS_PATH", "/suplovani");
+        // Empty when the upstream URL already points at the canteen.
+        let canteen_path = if env::var("CANTEEN_PATH").is_ok_and(|v| v.is_empty()) {
+            String::new()
+        } else {
+            env_path("CANTEEN_PATH", "/0341")
+                .trim_end_matches('/')
+                .to_string()
+        };
+        let crawl = CrawlConfig::from_env();
+        let throttle = ThrottleConfig::from_env();
+        let offline_dir = env::var("OFFLINE_DIR")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from);
+        let search = SearchConfig::from_env();
+        let watch = WatchConfig::from_env();
+        let notify_webhooks = env_list("NOTIFY_WEBHOOK_URLS");
+        let users = UsersConfig::from_env();
+        let vault = VaultConfig::from_env();
+        let database_url = env::var("DATABASE_URL").ok().filter(|v| !v.is_empty());
+        let redis_url = env::var("REDIS_URL").ok().filter(|v| !v.is_empty());
+        let redis_prefix = env::var("REDIS_PREFIX").unwrap_or_else(|_| "jecnaproxy:".to_string());
+        let snapshot_interval = env_parse("SNAPSHOT_INTERVAL_SECS").map(Duration::from_secs);
+        let retention = RetentionConfig::from_env();
+        let slo = SloConfig::from_env();
+        let statsd = StatsdConfig::from_env();
+        let scheduler_jitter = scheduler::jitter_from_env();
+        let runtime = RuntimeConfig::from_env();
+        let timeouts = TimeoutConfig::from_env();
+        let upstream_http = UpstreamHttpConfig::from_env();
+        let header_limits = HeaderLimits::from_env();
+        let memory = MemoryConfig::from_env();
+        let follow_redirects = env_parse("FOLLOW_REDIRECTS").unwrap_or(0);
+        let rewrite_statuses = StatusFilter::from_env("REWRITE_STATUSES", "2xx,3xx");
+        let rewrite_content_types = ContentTypeFilter::from_env();
+        let error_pages = env_flag("ERROR_PAGES");
+        let rewrite_sitemap = env_flag("REWRITE_SITEMAP");
+        let ignore_no_transform = env_flag("IGNORE_NO_TRANSFORM");
+        let bandwidth = BandwidthConfig::from_env();
+        let cookies = CookiePolicy::from_env();
+        let normalize_paths = env_flag_or("NORMALIZE_PATHS", true);
+        let max_connections_per_ip = env_parse("MAX_CONNECTIONS_PER_IP").unwrap_or(64);
+        let https_port = env_parse("HTTPS_PORT");
+        let tls = TlsConfig::from_env();
+        let http3 = Http3Config::from_env();
+        let trust_forwarded_for = env_flag("TRUST_FORWARDED_FOR");
+        if trust_forwarded_for && env::var("MAX_CONNECTIONS_PER_IP").is_ok_and(|v| v.trim() != "0")
+        {
+            // Behind the reverse proxy every connection comes from its address.
+            tracing::warn!(
+                "MAX_CONNECTIONS_PER_IP is not applied with TRUST_FORWARDED_FOR, limit connections in the reverse proxy instead"
+            );
+        }
 
         Self {
             port,
             base_url,
             disable_warning,
             mode,
+            custom_variants,
+            rewrite_map,
+            external_rewrites,
+            upstreams,
+            external_hosts,
+            external_wildcards,
+            chaos,
+            forward_auth,
+            bans,
+            admin_token,
+            read_only,
+            block_service_workers,
+            pwa,
+            trackers,
+            inject,
+            dark_mode,
+            privacy,
+            sampling,
+            response_headers,
+            via,
+            upstream_allowlist,
+            share,
+            news_path,
+            grades_path,
+            timetable_path,
+            absences_path,
+            events_path,
+            exams_path,
+            teacher_path,
+            rooms_path,
+            substitutions_path,
+            canteen_path,
+            crawl,
+            throttle,
+            offline_dir,
+            search,
+            watch,
+            notify_webhooks,
+            users,
+            vault,
+            database_url,
+            redis_url,
+            redis_prefix,
+            snapshot_interval,
+            retention,
+            slo,
+            statsd,
+            scheduler_jitter,
+            runtime,
+            timeouts,
+            upstream_http,
+            header_limits,
+            memory,
+            follow_redirects,
+            rewrite_statuses,
+            rewrite_content_types,
+            error_pages,
+            rewrite_sitemap,
+            ignore_no_transform,
+            bandwidth,
+            cookies,
+            normalize_paths,
+            max_connections_per_ip,
+            https_port,
+            tls,
+            http3,
+            trust_forwarded_for,
+        }
+    }
+
+    /// Builds the `MODE` upstream, or `mode` when switched by the admin API.
+    pub fn default_upstream(&self, mode: Mode) -> Result<Upstream, String> {
+        let mut upstream = Upstream::new(mode, &self.custom_variants)?;
+        upstream.banner = !self.disable_warning;
+        Ok(upstream)
+    }
+
+    /// Verifies that `upstream` may be proxied.
+    ///
+    /// The built-in modes are always allowed. A CUSTOM upstream's host must be listed in `UPSTREAM_ALLOWLIST`, otherwise a misconfigured
+    /// instance could turn into an open proxy for arbitrary sites. Hosts from `EXTERNAL_HOSTS` are listed explicitly already.
+    pub fn check_upstream(&self, upstream: &Upstream) -> Result<(), String> {
+        if !matches!(upstream.mode, Mode::CUSTOM(_)) {
+            return Ok(());
+        }
+
+        let host = upstream.url.host_str().unwrap_or_default();
+        if self
+            .upstream_allowlist
+            .iter()
+            .chain(&self.external_hosts)
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        {
+            Ok(())
+        } else {
+            Err(format!(
+                "upstream host {:?} is not listed in UPSTREAM_ALLOWLIST",
+                host
+            ))
         }
     }
 }
+
+/// A header added to every proxied response.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeaderRule {
+    #[serde(serialize_with = "effective_config::header_name")]
+    pub name: HeaderName,
+    #[serde(serialize_with = "effective_config::header_value")]
+    pub value: HeaderValue,
+    /// Whether to keep upstream values of the same header instead of replacing them.
+    pub append: bool,
+}
+
+impl HeaderRule {
+    /// Parses `Name: value | Other: value` lists.
+    fn from_env(name: &str, append: bool) -> Vec<Self> {
+        let Ok(raw) = env::var(name) else {
+            return Vec::new();
+        };
+
+        raw.split('|')
+            .filter(|entry| !entry.trim().is_empty())
+            .filter_map(|entry| {
+                let parsed = entry.split_once(':').and_then(|(k, v)| {
+                    Some(Self {
+                        name: HeaderName::try_from(k.trim()).ok()?,
+                        value: HeaderValue::from_str(v.trim()).ok()?,
+                        append,
+                    })
+                });
+                if parsed.is_none() {
+                    tracing::warn!("Ignoring invalid header rule in {}: {}", name, entry);
+                }
+                parsed
+            })
+            .collect()
+    }
+}
+
+/// Status codes given as classes (`2xx`) or single codes (`404`).
+#[derive(Debug, Clone)]
+pub struct StatusFilter(Vec<(u16, u16)>);
+
+impl StatusFilter {
+    /// Parses a comma-separated list, using `default` when it is unset or empty.
+    fn from_env(name: &str, default: &str) -> Self {
+        let mut entries = env_list(name);
+        if entries.is_empty() {
+            entries = default.split(',').map(str::to_string).collect();
+        }
+
+        Self(
+            entries
+                .iter()
+                .filter_map(|entry| {
+                    let entry = entry.to_ascii_lowercase();
+                    let parsed = match entry.strip_suffix("xx") {
+                        Some(class) => class
+                            .parse::<u16>()
+                            .ok()
+                            .filter(|c| (1..=5).contains(c))
+                            .map(|c| (c * 100, c * 100 + 99)),
+                        None => entry.parse().ok().map(|code| (code, code)),
+                    };
+                    if parsed.is_none() {
+                        tracing::warn!("Ignoring invalid status in {}: {}", name, entry);
+                    }
+                    parsed
+                })
+                .collect(),
+        )
+    }
+
+    pub fn contains(&self, status: StatusCode) -> bool {
+        let code = status.as_u16();
+        self.0
+            .iter()
+            .any(|(from, to)| (*from..=*to).contains(&code))
+    }
+}
+
+/// Serialized in the syntax it is configured with.
+impl Serialize for StatusFilter {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(|&(from, to)| {
+            if from == to {
+                from.to_string()
+            } else {
+                format!("{}xx", from / 100)
+            }
+        }))
+    }
+}
+
+/// Content types whose bodies are rewritten.
+///
+/// Entries are MIME types without parameters, with at most one `*` matching
+/// any text, e.g. `text/*` or `*+xml`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContentTypeFilter {
+    /// Rewritten types.
+    pub include: Vec<String>,
+    /// Types never rewritten, even if they are included.
+    pub exclude: Vec<String>,
+}
+
+impl ContentTypeFilter {
+    /// Types rewritten by default. XML (SVG, sitemaps, feeds) is rewritten with XML-escaped URLs.
+    pub const DEFAULT: &[&str] = &[
+        "text/html",
+        "application/javascript",
+        "application/json",
+        "text/css",
+        "*/xml",
+        "*+xml",
+    ];
+
+    /// # Environment Variables
+    /// * `REWRITE_CONTENT_TYPES_ADD` - Comma-separated types rewritten in addition to [`Self::DEFAULT`].
+    /// * `REWRITE_CONTENT_TYPES_REMOVE` - Comma-separated types never rewritten.
+    fn from_env() -> Self {
+        let mut include: Vec<String> = Self::DEFAULT.iter().map(|t| t.to_string()).collect();
+        include.extend(Self::list_from_env("REWRITE_CONTENT_TYPES_ADD"));
+        Self {
+            include,
+            exclude: Self::list_from_env("REWRITE_CONTENT_TYPES_REMOVE"),
+        }
+    }
+
+    fn list_from_env(name: &str) -> Vec<String> {
+        env_list(name)
+            .into_iter()
+            .map(|entry| entry.to_ascii_lowercase())
+            .filter(|entry| {
+                let valid = entry.matches('*').count() <= 1 && !entry.contains(';');
+                if !valid {
+                    tracing::warn!("Ignoring invalid content type in {}: {}", name, entry);
+                }
+                valid
+            })
+            .collect()
+    }
+
+    /// Whether bodies of a `Content-Type` header value are rewritten.
+    pub fn contains(&self, content_type: &str) -> bool {
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let matches = |pattern: &String| match pattern.split_once('*') {
+            Some((prefix, suffix)) => {
+                essence.len() >= prefix.len() + suffix.len()
+                    && essence.starts_with(prefix)
+                    && essence.ends_with(suffix)
+            }
+            None => essence == *pattern,
+        };
+        self.include.iter().any(matches) && !self.exclude.iter().any(matches)
+    }
+}
+
+/// Returns `true` if the variable is set to "true" or "1".
+pub fn env_flag(name: &str) -> bool {
+    env_flag_or(name, false)
+}
+
+/// Like [`env_flag`], with `default` if the variable is unset or empty.
+pub fn env_flag_or(name: &str, default: bool) -> bool {
+    let Ok(value) = env::var(name) else {
+        return default;
+    };
+    match value.as_str() {
+        "true" | "1" => true,
+        "false" | "0" => false,
+        "" => default,
+        _ => {
+            tracing::warn!(
+                "Ignoring invalid {}: {:?}, expected true, 1, false or 0",
+                name,
+                value
+            );
+            default
+        }
+    }
+}
+
+/// Reads an upstream path, `default` if the variable is unset, empty or
+/// doesn't start with `/`.
+pub fn env_path(name: &str, default: &str) -> String {
+    match env::var(name) {
+        Ok(value) if value.starts_with('/') => value,
+        Ok(value) if !value.is_empty() => {
+            tracing::warn!(
+                "Ignoring invalid {}: {:?}, expected a path starting with /",
+                name,
+                value
+            );
+            default.to_string()
+        }
+        _ => default.to_string(),
+    }
+}
+
+/// Splits a comma-separated variable into trimmed, non-empty items.
+pub fn env_list(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|v| {
+            v.split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parses the variable into `T`, returning `None` if it is unset or invalid.
+pub fn env_parse<T: FromStr>(name: &str) -> Option<T> {
+    let value = env::var(name).ok()?;
+    let parsed = value.trim().parse().ok();
+    if parsed.is_none() && !value.trim().is_empty() {
+        tracing::warn!("Ignoring invalid {}: {:?}", name, value);
+    }
+    parsed
+}
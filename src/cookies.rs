@@ -0,0 +1,303 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Attributes of cookies passed on to the browser.
+//!
+//! [`utils::process_cookie`](crate::utils::process_cookie) always drops the
+//! upstream's `Domain`; `SameSite` and `Secure` are set by [`CookiePolicy`].
+//! The default suits the credentialed CORS setup of the proxy:
+//! `SameSite=None; Secure` in secure contexts, `SameSite=Lax` otherwise.
+//! Deployments with a same-site frontend can tighten that.
+//!
+//! When the proxied site is embedded in a cross-site iframe, Chrome only keeps
+//! its cookies if they are `Partitioned` (CHIPS). The upstream's `Partitioned`
+//! is kept on secure cookies, `COOKIE_PARTITIONED` adds it to all of them.
+//!
+//! Browsers only accept `__Secure-` cookies with `Secure`, and `__Host-`
+//! cookies additionally with `Path=/` and no `Domain`. `__Host-` cookies get
+//! `Path=/`; prefixed cookies that can't be secure (plain HTTP deployments)
+//! are renamed into the proxy's namespace instead, and renamed back in the
+//! `Cookie` header sent upstream, so sessions using them survive.
+//!
+//! Cookies the proxy sets for itself (named `jecnaproxy_*`) never reach the
+//! upstream.
+
+use std::env;
+
+use axum::http::{HeaderMap, HeaderValue};
+use serde::Serialize;
+
+use crate::config;
+
+/// `SameSite` value given to cookies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSite {
+    /// `None` in secure contexts, `Lax` otherwise.
+    Auto,
+    None,
+    Lax,
+    Strict,
+}
+
+/// When cookies get the `Secure` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecureMode {
+    /// In secure contexts (HTTPS or localhost).
+    Auto,
+    Always,
+    Never,
+}
+
+/// Cookie attribute settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct CookiePolicy {
+    pub same_site: SameSite,
+    pub secure: SecureMode,
+    /// Whether the upstream's own `SameSite` and `Secure` attributes are kept.
+    pub preserve: bool,
+    /// Whether secure cookies get `Partitioned`.
+    pub partitioned: bool,
+}
+
+impl Default for CookiePolicy {
+    fn default() -> Self {
+        Self {
+            same_site: SameSite::Auto,
+            secure: SecureMode::Auto,
+            preserve: false,
+            partitioned: false,
+        }
+    }
+}
+
+impl CookiePolicy {
+    /// # Environment Variables
+    /// * `COOKIE_SAMESITE` - `auto`, `none`, `lax` or `strict` (default: "auto").
+    /// * `COOKIE_SECURE` - `auto`, `always` or `never` (default: "auto").
+    /// * `COOKIE_PRESERVE_ATTRIBUTES` - Set to "true" or "1" to keep the upstream's
+    ///   `SameSite` and `Secure`, using the settings above only for cookies without them.
+    /// * `COOKIE_PARTITIONED` - Set to "true" or "1" to make secure cookies `Partitioned`.
+    pub fn from_env() -> Self {
+        let same_site = match env_choice("COOKIE_SAMESITE").as_deref() {
+            None | Some("auto") => SameSite::Auto,
+            Some("none") => SameSite::None,
+            Some("lax") => SameSite::Lax,
+            Some("strict") => SameSite::Strict,
+            Some(other) => {
+                tracing::warn!("Unknown COOKIE_SAMESITE value: {}", other);
+                SameSite::Auto
+            }
+        };
+        let secure = match env_choice("COOKIE_SECURE").as_deref() {
+            None | Some("auto") => SecureMode::Auto,
+            Some("always") => SecureMode::Always,
+            Some("never") => SecureMode::Never,
+            Some(other) => {
+                tracing::warn!("Unknown COOKIE_SECURE value: {}", other);
+                SecureMode::Auto
+            }
+        };
+        if same_site == SameSite::None && secure == SecureMode::Never {
+            tracing::warn!("Browsers reject SameSite=None cookies without Secure");
+        }
+
+        Self {
+            same_site,
+            secure,
+            preserve: config::env_flag("COOKIE_PRESERVE_ATTRIBUTES"),
+            partitioned: config::env_flag("COOKIE_PARTITIONED"),
+        }
+    }
+
+    /// Whether cookies get `Secure`.
+    pub fn secure(&self, is_secure_context: bool) -> bool {
+        match self.secure {
+            SecureMode::Auto => is_secure_context,
+            SecureMode::Always => true,
+            SecureMode::Never => false,
+        }
+    }
+
+    /// The `SameSite` attribute of cookies.
+    pub fn same_site(&self, is_secure_context: bool) -> &'static str {
+        match self.same_site {
+            SameSite::Auto if is_secure_context => "SameSite=None",
+            SameSite::Auto | SameSite::Lax => "SameSite=Lax",
+            SameSite::None => "SameSite=None",
+            SameSite::Strict => "SameSite=Strict",
+        }
+    }
+}
+
+/// Lowercased value of a variable, `None` if unset or empty.
+fn env_choice(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .map(|v| v.trim().to_ascii_lowercase())
+        .filter(|v| !v.is_empty())
+}
+
+/// Name prefixes browsers only accept on secure cookies, and what such
+/// cookies are renamed to when they can't be secure.
+const PREFIXES: [(&str, &str); 2] = [
+    ("__Host-", "jecnaproxy-host-"),
+    ("__Secure-", "jecnaproxy-secure-"),
+];
+
+/// Renames the prefixed cookie `name=value` if it won't be secure.
+pub fn rename_prefixed(name_value: &str, secure: bool) -> Option<String> {
+    if secure {
+        return None;
+    }
+    PREFIXES.iter().find_map(|(prefix, renamed)| {
+        name_value
+            .strip_prefix(prefix)
+            .map(|rest| format!("{}{}", renamed, rest))
+    })
+}
+
+/// Whether the cookie `name=value` has the `__Host-` prefix.
+pub fn is_host_prefixed(name_value: &str) -> bool {
+    name_value.starts_with(PREFIXES[0].0)
+}
+
+/// Name prefix of cookies set by the proxy itself, like `jecnaproxy_theme`.
+pub const INTERNAL_PREFIX: &str = "jecnaproxy_";
+
+/// Rewrites the `Cookie` headers of a request sent upstream: the proxy's own
+/// cookies are removed, cookies renamed by [`rename_prefixed`] get their
+/// original names back.
+pub fn prepare_request(headers: &mut HeaderMap) {
+    let values: Vec<HeaderValue> = headers
+        .get_all("cookie")
+        .iter()
+        .filter_map(|value| {
+            let Ok(cookie) = value.to_str() else {
+                return Some(value.clone());
+            };
+            let forwarded: Vec<String> = cookie
+                .split(';')
+                .map(str::trim)
+                .filter(|pair| !pair.is_empty() && !pair.starts_with(INTERNAL_PREFIX))
+                .map(|pair| {
+                    PREFIXES
+                        .iter()
+                        .find_map(|(prefix, renamed)| {
+                            pair.strip_prefix(renamed)
+                                .map(|rest| format!("{}{}", prefix, rest))
+                        })
+                        .unwrap_or_else(|| pair.to_string())
+                })
+                .collect();
+            if forwarded.is_empty() {
+                return None;
+            }
+            Some(HeaderValue::from_str(&forwarded.join("; ")).unwrap_or_else(|_| value.clone()))
+        })
+        .collect();
+
+    headers.remove("cookie");
+    for value in values {
+        headers.append("cookie", value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::process_cookie;
+
+    fn request(fields: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for field in fields {
+            headers.append("cookie", HeaderValue::from_static(field));
+        }
+        headers
+    }
+
+    fn cookies(headers: &HeaderMap) -> Vec<&str> {
+        headers
+            .get_all("cookie")
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn prefixed_cookies_are_renamed_when_insecure() {
+        let policy = CookiePolicy::default();
+
+        assert_eq!(
+            process_cookie(
+                "__Host-sid=abc; Path=/; Secure; Domain=spsejecna.cz; HttpOnly",
+                false,
+                &policy
+            ),
+            "jecnaproxy-host-sid=abc; Path=/; HttpOnly; SameSite=Lax"
+        );
+        assert_eq!(
+            process_cookie("__Secure-t=1; Secure; Max-Age=60", false, &policy),
+            "jecnaproxy-secure-t=1; Max-Age=60; SameSite=Lax"
+        );
+
+        // Secure cookies keep their names, `__Host-` ones get the required `Path=/`.
+        assert_eq!(
+            process_cookie(
+                "__Host-sid=abc; Path=/app; Domain=spsejecna.cz",
+                true,
+                &policy
+            ),
+            "__Host-sid=abc; Path=/; Secure; SameSite=None"
+        );
+        assert_eq!(
+            process_cookie("__Secure-t=1; Path=/app", true, &policy),
+            "__Secure-t=1; Path=/app; Secure; SameSite=None"
+        );
+
+        assert_eq!(rename_prefixed("__Host-sid=abc", true), None);
+        assert_eq!(rename_prefixed("sid=abc", false), None);
+        assert_eq!(rename_prefixed("__host-sid=abc", false), None);
+    }
+
+    #[test]
+    fn renamed_cookies_get_their_names_back() {
+        let mut headers = request(&["jecnaproxy-host-sid=abc; plain=1; jecnaproxy-secure-t=2"]);
+        prepare_request(&mut headers);
+        assert_eq!(cookies(&headers), ["__Host-sid=abc; plain=1; __Secure-t=2"]);
+
+        let renamed = process_cookie("__Host-sid=abc; Secure", false, &CookiePolicy::default());
+        let pair = renamed.split(';').next().unwrap().to_string();
+        let mut headers = HeaderMap::new();
+        headers.insert("cookie", HeaderValue::from_str(&pair).unwrap());
+        prepare_request(&mut headers);
+        assert_eq!(cookies(&headers), ["__Host-sid=abc"]);
+    }
+
+    #[test]
+    fn internal_cookies_stay_on_the_proxy() {
+        let mut headers = request(&[
+            "a=1; jecnaproxy_session=s1; b=2;c=3",
+            "jecnaproxy_auth=t; jecnaproxy_theme=dark",
+            "JSESSIONID=A1B2",
+        ]);
+        prepare_request(&mut headers);
+        assert_eq!(cookies(&headers), ["a=1; b=2; c=3", "JSESSIONID=A1B2"]);
+
+        let mut headers = request(&["jecnaproxy_session=s1"]);
+        prepare_request(&mut headers);
+        assert!(headers.get("cookie").is_none());
+    }
+}
@@ -0,0 +1,217 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Breadth-first crawler of the upstream site.
+//!
+//! Only follows links to the upstream host, never sends cookies and waits
+//! between requests so it doesn't burden the school's server. Requests also go
+//! through the global [`Throttle`](crate::throttle::Throttle).
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::LazyLock,
+    time::Duration,
+};
+
+use axum::body::Bytes;
+use regex::Regex;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::Serialize;
+
+use crate::{config, effective_config, state::AppState};
+
+static CSS_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"url\(\s*['"]?([^'")\s]+)['"]?\s*\)"#).expect("valid regex"));
+
+/// Paths the crawler must never request (e.g. because they end the session).
+const SKIPPED_PATHS: &[&str] = &["/user/logout", "/logout"];
+
+/// Crawl limits.
+#[derive(Debug, Clone, Serialize)]
+pub struct CrawlConfig {
+    /// Maximum number of fetched URLs.
+    pub max_pages: usize,
+    /// Maximum link depth from the start paths.
+    pub max_depth: usize,
+    /// Paths the crawl starts from.
+    pub start_paths: Vec<String>,
+    /// Pause between two requests.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub delay: Duration,
+}
+
+impl CrawlConfig {
+    /// # Environment Variables
+    /// * `CRAWL_MAX_PAGES` - Maximum fetched URLs (default: 500).
+    /// * `CRAWL_MAX_DEPTH` - Maximum link depth (default: 5).
+    /// * `CRAWL_START_PATHS` - Comma-separated start paths (default: "/").
+    /// * `CRAWL_DELAY_MS` - Pause between requests (default: 200).
+    pub fn from_env() -> Self {
+        let mut start_paths = config::env_list("CRAWL_START_PATHS");
+        if start_paths.is_empty() {
+            start_paths.push("/".to_string());
+        }
+
+        Self {
+            max_pages: config::env_parse("CRAWL_MAX_PAGES").unwrap_or(500),
+            max_depth: config::env_parse("CRAWL_MAX_DEPTH").unwrap_or(5),
+            start_paths,
+            delay: Duration::from_millis(config::env_parse("CRAWL_DELAY_MS").unwrap_or(200)),
+        }
+    }
+}
+
+/// A successfully fetched upstream resource.
+#[derive(Debug, Clone)]
+pub struct CrawledPage {
+    /// Path and query on the upstream.
+    pub path: String,
+    pub content_type: String,
+    pub body: Bytes,
+}
+
+impl CrawledPage {
+    pub fn is_html(&self) -> bool {
+        self.content_type.contains("text/html")
+    }
+}
+
+/// Crawls the upstream within the configured limits.
+pub async fn crawl(state: &AppState, limits: &CrawlConfig) -> Vec<CrawledPage> {
+    let upstream = state.upstream();
+    let base = upstream.url.clone();
+
+    let _permit = state.throttle.crawl().await;
+
+    let mut queue: VecDeque<(String, usize)> =
+        limits.start_paths.iter().map(|p| (p.clone(), 0)).collect();
+    let mut seen: HashSet<String> = queue.iter().map(|(p, _)| p.clone()).collect();
+    let mut pages = Vec::new();
+    let mut fetched = 0;
+
+    while let Some((path, depth)) = queue.pop_front() {
+        if fetched >= limits.max_pages {
+            tracing::info!("Crawl stopped after reaching the page limit");
+            break;
+        }
+        if fetched > 0 {
+            tokio::time::sleep(limits.delay).await;
+        }
+        fetched += 1;
+
+        let url = format!("{}{}", upstream.base, path);
+        state.throttle.wait().await;
+        let resp = match state.client.get(&url).send().await {
+            Ok(r) => r,
+            Err(e) => {
+                tracing::warn!("Crawl: failed to fetch {}: {}", path, e);
+                continue;
+            }
+        };
+
+        let status = resp.status();
+        let page_url = resp.url().clone();
+
+        let mut links = Vec::new();
+        if status.is_redirection() {
+            if let Some(location) = resp.headers().get("location").and_then(|v| v.to_str().ok()) {
+                links.push(location.to_string());
+            }
+        } else if !status.is_success() {
+            tracing::debug!("Crawl: {} answered {}", path, status);
+            continue;
+        }
+
+        if status.is_success() {
+            let content_type = resp
+                .headers()
+                .get("content-type")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("application/octet-stream")
+                .to_string();
+            let body = match resp.bytes().await {
+                Ok(b) => b,
+                Err(e) => {
+                    tracing::warn!("Crawl: failed to read {}: {}", path, e);
+                    continue;
+                }
+            };
+
+            let page = CrawledPage {
+                path: path.clone(),
+                content_type,
+                body,
+            };
+            if page.is_html() {
+                links.extend(html_links(&String::from_utf8_lossy(&page.body)));
+            } else if page.content_type.contains("text/css") {
+                links.extend(css_links(&String::from_utf8_lossy(&page.body)));
+            }
+            pages.push(page);
+        }
+
+        if depth >= limits.max_depth {
+            continue;
+        }
+        for link in links {
+            if let Some(next) = same_site_path(&page_url, &base, &link)
+                && seen.insert(next.clone())
+            {
+                queue.push_back((next, depth + 1));
+            }
+        }
+    }
+
+    tracing::info!("Crawl finished: {} resources fetched", pages.len());
+    pages
+}
+
+fn html_links(html: &str) -> Vec<String> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse("a[href], link[href], script[src], img[src], source[src]")
+        .expect("valid selector");
+
+    document
+        .select(&selector)
+        .filter_map(|el| el.value().attr("href").or_else(|| el.value().attr("src")))
+        .map(str::to_string)
+        .collect()
+}
+
+fn css_links(css: &str) -> Vec<String> {
+    CSS_URL
+        .captures_iter(css)
+        .map(|c| c[1].to_string())
+        .filter(|l| !l.starts_with("data:"))
+        .collect()
+}
+
+/// Resolves a link and returns its path and query if it stays on the upstream host.
+fn same_site_path(page: &Url, base: &Url, link: &str) -> Option<String> {
+    let mut url = page.join(link.trim()).ok()?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str() != base.host_str() {
+        return None;
+    }
+    url.set_fragment(None);
+
+    if SKIPPED_PATHS.contains(&url.path()) {
+        return None;
+    }
+
+    Some(match url.query() {
+        Some(q) => format!("{}?{}", url.path(), q),
+        None => url.path().to_string(),
+    })
+}
@@ -0,0 +1,189 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Background mode for hosts without a service manager.
+//!
+//! `--daemonize` starts the proxy again as a child in its own session, with
+//! standard input closed and standard output and error appended to the log
+//! file. Forking the running process is not an option, its runtime already
+//! has threads. The child writes the PID file once its listeners are open,
+//! and the parent waits for that, so startup errors are still reported to the
+//! terminal. `jecnaproxy stop` terminates the process named in the PID file.
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cli::StopArgs;
+
+/// Set in the environment of the daemonized child.
+const CHILD_ENV: &str = "JECNAPROXY_DAEMON_CHILD";
+
+/// How often the PID file and the processes are checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Whether this process is the child started by [`daemonize`].
+pub fn is_daemon() -> bool {
+    std::env::var_os(CHILD_ENV).is_some()
+}
+
+/// Starts the proxy in the background and exits once it accepts connections.
+#[cfg(unix)]
+pub fn daemonize(pid_file: &Path, log_file: &Path) -> ! {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    if let Some(pid) = read_pid(pid_file) {
+        if is_running(pid) {
+            fail(format!(
+                "Already running with PID {} (from {})",
+                pid,
+                pid_file.display()
+            ));
+        }
+        let _ = std::fs::remove_file(pid_file);
+    }
+
+    let log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .unwrap_or_else(|e| fail(format!("Failed to open {}: {}", log_file.display(), e)));
+    let stderr = log
+        .try_clone()
+        .unwrap_or_else(|e| fail(format!("Failed to open {}: {}", log_file.display(), e)));
+    let exe = std::env::current_exe()
+        .unwrap_or_else(|e| fail(format!("Failed to locate the executable: {}", e)));
+
+    let mut command = Command::new(exe);
+    command
+        .args(std::env::args_os().skip(1))
+        .env(CHILD_ENV, "1")
+        .stdin(Stdio::null())
+        .stdout(log)
+        .stderr(stderr);
+    // SAFETY: setsid is async-signal-safe.
+    unsafe {
+        command.pre_exec(|| {
+            // Leave the terminal's session, so closing the terminal doesn't send SIGHUP.
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command
+        .spawn()
+        .unwrap_or_else(|e| fail(format!("Failed to start the proxy: {}", e)));
+
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => fail(format!(
+                "The proxy failed to start ({}), see {}",
+                status,
+                log_file.display()
+            )),
+            Ok(None) => {}
+            Err(e) => fail(format!("Failed to wait for the proxy: {}", e)),
+        }
+        if read_pid(pid_file) == Some(child.id()) {
+            eprintln!(
+                "Proxy running in the background with PID {}, logging to {}",
+                child.id(),
+                log_file.display()
+            );
+            std::process::exit(0);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn daemonize(_pid_file: &Path, _log_file: &Path) -> ! {
+    fail("--daemonize is only supported on Unix".to_string())
+}
+
+/// Writes this process' PID to `path`, called by the child once it is ready.
+pub fn write_pid_file(path: &Path) {
+    // Written under a temporary name first, the parent must not read a partial PID.
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let result = std::fs::write(&tmp, format!("{}\n", std::process::id()))
+        .and_then(|()| std::fs::rename(&tmp, path));
+    if let Err(e) = result {
+        tracing::error!("Failed to write the PID file {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+/// Handler of `jecnaproxy stop`: sends SIGTERM and waits for the process to exit.
+#[cfg(unix)]
+pub fn stop(args: &StopArgs) {
+    let Some(pid) = read_pid(&args.pid_file) else {
+        fail(format!(
+            "No PID in {}, is the proxy running?",
+            args.pid_file.display()
+        ));
+    };
+    if !is_running(pid) {
+        eprintln!(
+            "Process {} is not running, removing the stale PID file",
+            pid
+        );
+        let _ = std::fs::remove_file(&args.pid_file);
+        return;
+    }
+
+    // SAFETY: kill has no memory safety requirements.
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == -1 {
+        fail(format!(
+            "Failed to stop process {}: {}",
+            pid,
+            std::io::Error::last_os_error()
+        ));
+    }
+    let deadline = std::time::Instant::now() + Duration::from_secs(args.timeout);
+    while is_running(pid) {
+        if std::time::Instant::now() >= deadline {
+            fail(format!(
+                "Process {} is still running after {} s",
+                pid, args.timeout
+            ));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    let _ = std::fs::remove_file(&args.pid_file);
+    eprintln!("Stopped process {}", pid);
+}
+
+#[cfg(not(unix))]
+pub fn stop(_args: &StopArgs) {
+    fail("stop is only supported on Unix".to_string())
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    // Signal 0 only checks that the process exists; EPERM means it does, under another user.
+    // SAFETY: kill has no memory safety requirements.
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
@@ -0,0 +1,29 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Optional dark theme of proxied pages.
+//!
+//! Pages link a stylesheet and a small script from `assets/`.
+//! The script picks the theme from the `jecnaproxy_theme` cookie, falling back
+//! to `prefers-color-scheme`, and adds a button cycling auto/dark/light.
+
+use crate::inject;
+
+/// Tags linking the assets, the script runs in `<head>` so pages don't flash white.
+const TAGS: &str = r#"<link rel="stylesheet" href="/_jecnaproxy/dark-mode.css"><script src="/_jecnaproxy/dark-mode.js"></script>"#;
+
+/// Links the dark theme in an HTML page.
+pub fn inject(body: &mut Vec<u8>) {
+    inject::before_head_end(body, TAGS.as_bytes());
+}
@@ -0,0 +1,797 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Optional SQLite or PostgreSQL persistence.
+//!
+//! Keeps crawled pages, monitored page versions, the change history and active
+//! bans across restarts. The backend is picked by the `DATABASE_URL` scheme;
+//! Postgres lets several replicas share the same data. The schema lives in
+//! `migrations/<backend>` and is embedded in the binary.
+
+use std::{
+    collections::HashSet,
+    net::IpAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::body::Bytes;
+use sqlx::{AnyPool, migrate::Migrator};
+
+use crate::{
+    audit::AuditEntry,
+    crawler::CrawledPage,
+    state::AppState,
+    users::{Role, User},
+    vault::{CredentialInfo, StoredCredential},
+    watcher::Change,
+};
+
+static SQLITE_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/sqlite");
+static POSTGRES_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// `(id, username, nonce, password, notify_url, created_at)`
+type CredentialRow = (String, String, Vec<u8>, Vec<u8>, Option<String>, i64);
+
+/// `(id, at, actor, action, target, details)`
+type AuditRow = (i64, i64, String, String, Option<String>, Option<String>);
+
+/// `(id, username, role, webhook_url, notify_kinds, rate_limit, created_at)`
+type UserRow = (
+    String,
+    String,
+    String,
+    Option<String>,
+    String,
+    Option<i64>,
+    i64,
+);
+
+/// [`UserRow`] followed by the password hash.
+type UserRowWithHash = (
+    String,
+    String,
+    String,
+    Option<String>,
+    String,
+    Option<i64>,
+    i64,
+    String,
+);
+
+const USER_COLUMNS: &str = "id, username, role, webhook_url, notify_kinds, rate_limit, created_at";
+
+fn user_from_row(row: UserRow) -> User {
+    let (id, username, role, webhook_url, notify_kinds, rate_limit, created_at) = row;
+    User {
+        id,
+        username,
+        role: Role::parse(&role),
+        webhook_url,
+        notify_kinds: notify_kinds
+            .split(',')
+            .filter(|k| !k.is_empty())
+            .map(String::from)
+            .collect(),
+        rate_limit: rate_limit.map(|l| l as u32),
+        created_at: created_at as u64,
+    }
+}
+
+/// Handle to the database.
+#[derive(Clone)]
+pub struct Db {
+    pool: AnyPool,
+    sqlite: bool,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+impl Db {
+    /// Connects to `url` (`sqlite:` or `postgres:`) and runs pending migrations.
+    ///
+    /// SQLite databases are created if they don't exist yet.
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        sqlx::any::install_default_drivers();
+
+        if url.starts_with("sqlite:") {
+            let url = if url.contains("mode=") {
+                url.to_string()
+            } else {
+                let separator = if url.contains('?') { '&' } else { '?' };
+                format!("{}{}mode=rwc", url, separator)
+            };
+            let pool = AnyPool::connect(&url).await?;
+            sqlx::query("PRAGMA journal_mode = WAL")
+                .execute(&pool)
+                .await?;
+            SQLITE_MIGRATIONS.run(&pool).await?;
+            Ok(Self { pool, sqlite: true })
+        } else if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+            let pool = AnyPool::connect(url).await?;
+            POSTGRES_MIGRATIONS.run(&pool).await?;
+            Ok(Self {
+                pool,
+                sqlite: false,
+            })
+        } else {
+            Err(sqlx::Error::Configuration(
+                format!("unsupported DATABASE_URL scheme: {}", url).into(),
+            ))
+        }
+    }
+
+    /// Replaces the stored crawl with `pages`.
+    pub async fn save_pages(&self, pages: &[CrawledPage]) -> Result<(), sqlx::Error> {
+        let crawled_at = now();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM pages").execute(&mut *tx).await?;
+        for page in pages {
+            sqlx::query(
+                "INSERT INTO pages (path, content_type, body, crawled_at) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(&page.path)
+            .bind(&page.content_type)
+            .bind(page.body.as_ref())
+            .bind(crawled_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    pub async fn load_pages(&self) -> Result<Vec<CrawledPage>, sqlx::Error> {
+        let rows: Vec<(String, String, Vec<u8>)> =
+            sqlx::query_as("SELECT path, content_type, body FROM pages")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(path, content_type, body)| CrawledPage {
+                path,
+                content_type,
+                body: Bytes::from(body),
+            })
+            .collect())
+    }
+
+    /// Stores the last seen version of a monitored page.
+    pub async fn save_watched(
+        &self,
+        path: &str,
+        hash: &str,
+        content: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO watched_pages (path, hash, content) VALUES ($1, $2, $3)
+             ON CONFLICT (path) DO UPDATE SET hash = excluded.hash, content = excluded.content",
+        )
+        .bind(path)
+        .bind(hash)
+        .bind(content)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns `(path, hash, content)` of all monitored pages.
+    pub async fn load_watched(&self) -> Result<Vec<(String, String, String)>, sqlx::Error> {
+        sqlx::query_as("SELECT path, hash, content FROM watched_pages")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn save_change(&self, change: &Change) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO changes (path, detected_at, old_hash, new_hash, diff) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&change.path)
+        .bind(change.detected_at as i64)
+        .bind(&change.old_hash)
+        .bind(&change.new_hash)
+        .bind(&change.diff)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the last `limit` changes, oldest first.
+    pub async fn load_changes(&self, limit: usize) -> Result<Vec<Change>, sqlx::Error> {
+        let rows: Vec<(String, i64, String, String, String)> = sqlx::query_as(
+            "SELECT path, detected_at, old_hash, new_hash, diff FROM changes
+             ORDER BY id DESC LIMIT $1",
+        )
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .rev()
+            .map(|(path, detected_at, old_hash, new_hash, diff)| Change {
+                path,
+                detected_at: detected_at as u64,
+                old_hash,
+                new_hash,
+                diff,
+            })
+            .collect())
+    }
+
+    pub async fn save_ban(
+        &self,
+        ip: IpAddr,
+        reason: &str,
+        duration: Duration,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO bans (ip, reason, banned_until) VALUES ($1, $2, $3)
+             ON CONFLICT (ip) DO UPDATE SET reason = excluded.reason, banned_until = excluded.banned_until",
+        )
+        .bind(ip.to_string())
+        .bind(reason)
+        .bind(now() + duration.as_secs() as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_ban(&self, ip: IpAddr) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM bans WHERE ip = $1")
+            .bind(ip.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn clear_bans(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM bans").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Returns the active bans with their remaining duration, dropping expired ones.
+    pub async fn load_bans(&self) -> Result<Vec<(IpAddr, String, Duration)>, sqlx::Error> {
+        let now = now();
+        sqlx::query("DELETE FROM bans WHERE banned_until <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        let rows: Vec<(String, String, i64)> =
+            sqlx::query_as("SELECT ip, reason, banned_until FROM bans")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(ip, reason, until)| {
+                let ip = ip.parse().ok()?;
+                Some((ip, reason, Duration::from_secs((until - now) as u64)))
+            })
+            .collect())
+    }
+
+    pub async fn insert_credential(
+        &self,
+        credential: &StoredCredential,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO credentials (id, username, nonce, password, notify_url, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(&credential.info.id)
+        .bind(&credential.info.username)
+        .bind(&credential.nonce)
+        .bind(&credential.password)
+        .bind(&credential.info.notify_url)
+        .bind(credential.info.created_at as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn load_credentials(&self) -> Result<Vec<StoredCredential>, sqlx::Error> {
+        let rows: Vec<CredentialRow> = sqlx::query_as(
+            "SELECT id, username, nonce, password, notify_url, created_at FROM credentials
+             ORDER BY created_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(
+                |(id, username, nonce, password, notify_url, created_at)| StoredCredential {
+                    info: CredentialInfo {
+                        id,
+                        username,
+                        notify_url,
+                        created_at: created_at as u64,
+                    },
+                    nonce,
+                    password,
+                },
+            )
+            .collect())
+    }
+
+    /// Deletes a credential and its grade check state, returning whether it existed.
+    pub async fn delete_credential(&self, id: &str) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM vault_seen WHERE credential_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query("DELETE FROM credentials WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns the hashes of the grades and exams seen for a credential, `None`
+    /// before its first grade check.
+    pub async fn load_vault_seen(
+        &self,
+        id: &str,
+    ) -> Result<Option<(HashSet<String>, HashSet<String>)>, sqlx::Error> {
+        let row: Option<(String, String)> =
+            sqlx::query_as("SELECT grades, exams FROM vault_seen WHERE credential_id = $1")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(row.map(|(grades, exams)| {
+            (
+                serde_json::from_str(&grades).unwrap_or_default(),
+                serde_json::from_str(&exams).unwrap_or_default(),
+            )
+        }))
+    }
+
+    pub async fn save_vault_seen(
+        &self,
+        id: &str,
+        grades: &HashSet<String>,
+        exams: &HashSet<String>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO vault_seen (credential_id, grades, exams) VALUES ($1, $2, $3)
+             ON CONFLICT (credential_id) DO UPDATE SET grades = excluded.grades, exams = excluded.exams",
+        )
+        .bind(id)
+        .bind(serde_json::to_string(grades).unwrap_or_default())
+        .bind(serde_json::to_string(exams).unwrap_or_default())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the day the last weekly digest was sent on.
+    pub async fn load_digest_sent(&self) -> Result<Option<i64>, sqlx::Error> {
+        let row: Option<(i64,)> = sqlx::query_as("SELECT sent_day FROM vault_digest WHERE id = 1")
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|(day,)| day))
+    }
+
+    pub async fn save_digest_sent(&self, day: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO vault_digest (id, sent_day) VALUES (1, $1)
+             ON CONFLICT (id) DO UPDATE SET sent_day = excluded.sent_day",
+        )
+        .bind(day)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Inserts a user, returning `false` if the username is taken.
+    pub async fn insert_user(&self, user: &User, password_hash: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO users (id, username, password_hash, role, webhook_url, notify_kinds, rate_limit, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             ON CONFLICT (username) DO NOTHING",
+        )
+        .bind(&user.id)
+        .bind(&user.username)
+        .bind(password_hash)
+        .bind(user.role.as_str())
+        .bind(&user.webhook_url)
+        .bind(user.notify_kinds.join(","))
+        .bind(user.rate_limit.map(i64::from))
+        .bind(user.created_at as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns the user and their password hash.
+    pub async fn user_by_name(
+        &self,
+        username: &str,
+    ) -> Result<Option<(User, String)>, sqlx::Error> {
+        let row: Option<UserRowWithHash> = sqlx::query_as(&format!(
+            "SELECT {}, password_hash FROM users WHERE username = $1",
+            USER_COLUMNS
+        ))
+        .bind(username)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(
+            |(id, username, role, webhook_url, kinds, limit, created_at, hash)| {
+                (
+                    user_from_row((id, username, role, webhook_url, kinds, limit, created_at)),
+                    hash,
+                )
+            },
+        ))
+    }
+
+    pub async fn user_by_token(&self, token_hash: &str) -> Result<Option<User>, sqlx::Error> {
+        let row: Option<UserRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM users WHERE id = (SELECT user_id FROM api_tokens WHERE token_hash = $1)",
+            USER_COLUMNS
+        ))
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(user_from_row))
+    }
+
+    pub async fn list_users(&self) -> Result<Vec<User>, sqlx::Error> {
+        let rows: Vec<UserRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM users ORDER BY created_at",
+            USER_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(user_from_row).collect())
+    }
+
+    /// Updates the role and/or rate limit, returning whether the user exists.
+    pub async fn update_user(
+        &self,
+        id: &str,
+        role: Option<Role>,
+        rate_limit: Option<u32>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE users SET role = COALESCE($1, role), rate_limit = COALESCE($2, rate_limit)
+             WHERE id = $3",
+        )
+        .bind(role.map(Role::as_str))
+        .bind(rate_limit.map(i64::from))
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn update_notifications(
+        &self,
+        id: &str,
+        webhook_url: Option<&str>,
+        kinds: &[String],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE users SET webhook_url = $1, notify_kinds = $2 WHERE id = $3")
+            .bind(webhook_url)
+            .bind(kinds.join(","))
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes a user and their tokens, returning whether the user existed.
+    pub async fn delete_user(&self, id: &str) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM api_tokens WHERE user_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn insert_token(&self, token_hash: &str, user_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO api_tokens (token_hash, user_id, created_at) VALUES ($1, $2, $3)")
+            .bind(token_hash)
+            .bind(user_id)
+            .bind(now())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_token(&self, token_hash: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM api_tokens WHERE token_hash = $1")
+            .bind(token_hash)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the webhooks of users subscribed to notifications of `kind`.
+    pub async fn subscribed_webhooks(&self, kind: &str) -> Result<Vec<String>, sqlx::Error> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT webhook_url, notify_kinds FROM users WHERE webhook_url IS NOT NULL",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter(|(_, kinds)| kinds.split(',').any(|k| k == kind))
+            .map(|(url, _)| url)
+            .collect())
+    }
+
+    pub async fn insert_audit(
+        &self,
+        at: u64,
+        actor: &str,
+        action: &str,
+        target: Option<&str>,
+        details: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO audit_log (at, actor, action, target, details) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(at as i64)
+        .bind(actor)
+        .bind(action)
+        .bind(target)
+        .bind(details)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns audit entries newest first, optionally filtered by action (or
+    /// action prefix) and paged by id.
+    pub async fn load_audit(
+        &self,
+        action: Option<&str>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<AuditEntry>, sqlx::Error> {
+        let mut sql = "SELECT id, at, actor, action, target, details FROM audit_log".to_string();
+        let mut conditions = Vec::new();
+        let mut param = 0;
+        if action.is_some() {
+            conditions.push(format!(
+                "(action = ${} OR action LIKE ${} ESCAPE '\\')",
+                param + 1,
+                param + 2
+            ));
+            param += 2;
+        }
+        if before.is_some() {
+            conditions.push(format!("id < ${}", param + 1));
+            param += 1;
+        }
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(&format!(" ORDER BY id DESC LIMIT ${}", param + 1));
+
+        let mut query = sqlx::query_as::<_, AuditRow>(&sql);
+        if let Some(action) = action {
+            query = query
+                .bind(action)
+                .bind(format!("{}.%", escape_like(action)));
+        }
+        if let Some(before) = before {
+            query = query.bind(before);
+        }
+        let rows = query.bind(limit).fetch_all(&self.pool).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(id, at, actor, action, target, details)| AuditEntry {
+                id,
+                at: at as u64,
+                actor,
+                action,
+                target,
+                details,
+            })
+            .collect())
+    }
+
+    /// Deletes changes detected before the Unix timestamp `before`.
+    pub async fn delete_changes_before(&self, before: u64) -> Result<u64, sqlx::Error> {
+        self.delete_before("DELETE FROM changes WHERE detected_at < $1", before)
+            .await
+    }
+
+    /// Deletes audit entries recorded before the Unix timestamp `before`.
+    pub async fn delete_audit_before(&self, before: u64) -> Result<u64, sqlx::Error> {
+        self.delete_before("DELETE FROM audit_log WHERE at < $1", before)
+            .await
+    }
+
+    /// Deletes API tokens issued before the Unix timestamp `before`.
+    pub async fn delete_tokens_before(&self, before: u64) -> Result<u64, sqlx::Error> {
+        self.delete_before("DELETE FROM api_tokens WHERE created_at < $1", before)
+            .await
+    }
+
+    /// Deletes expired bans.
+    pub async fn delete_expired_bans(&self) -> Result<u64, sqlx::Error> {
+        self.delete_before("DELETE FROM bans WHERE banned_until <= $1", now() as u64)
+            .await
+    }
+
+    async fn delete_before(&self, sql: &'static str, before: u64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(sql)
+            .bind(before as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Size of the database in bytes.
+    pub async fn size(&self) -> Result<u64, sqlx::Error> {
+        let size = if self.sqlite {
+            let (pages,): (i64,) = sqlx::query_as("PRAGMA page_count")
+                .fetch_one(&self.pool)
+                .await?;
+            let (page_size,): (i64,) = sqlx::query_as("PRAGMA page_size")
+                .fetch_one(&self.pool)
+                .await?;
+            pages * page_size
+        } else {
+            let (size,): (i64,) = sqlx::query_as("SELECT pg_database_size(current_database())")
+                .fetch_one(&self.pool)
+                .await?;
+            size
+        };
+        Ok(size.max(0) as u64)
+    }
+
+    /// Writes a consistent copy of a SQLite database to `path`.
+    pub async fn backup_to(&self, path: &std::path::Path) -> Result<(), sqlx::Error> {
+        if !self.sqlite {
+            return Err(sqlx::Error::Configuration(
+                "only SQLite databases can be backed up, use pg_dump for Postgres".into(),
+            ));
+        }
+        sqlx::query("VACUUM INTO $1")
+            .bind(path.to_string_lossy().into_owned())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Compacts the database, returning the number of bytes it shrank by.
+    pub async fn vacuum(&self) -> Result<u64, sqlx::Error> {
+        let before = self.size().await?;
+        // VACUUM can't run inside a transaction, which prepared statements
+        // may be wrapped in.
+        sqlx::raw_sql("VACUUM").execute(&self.pool).await?;
+        Ok(before.saturating_sub(self.size().await?))
+    }
+}
+
+/// Escapes the `LIKE` wildcards in `value`, for patterns with `ESCAPE '\'`.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Logs a failed write instead of failing the request that caused it.
+pub fn log_error<T>(result: Result<T, sqlx::Error>, what: &str) {
+    if let Err(e) = result {
+        tracing::error!("Failed to persist {}: {}", what, e);
+    }
+}
+
+/// Loads the persisted state into memory.
+pub async fn restore(state: &AppState) -> Result<(), sqlx::Error> {
+    let Some(db) = &state.db else {
+        return Ok(());
+    };
+
+    let bans = db.load_bans().await?;
+    let ban_count = bans.len();
+    for (ip, reason, remaining) in bans {
+        state.bans.restore(ip, reason, remaining);
+    }
+
+    if let Some(watch) = &state.config.watch {
+        let latest = db.load_watched().await?;
+        let changes = db.load_changes(watch.history).await?;
+        state.watch.restore(latest, changes);
+    }
+
+    if state.config.search.is_some() {
+        let pages = db.load_pages().await?;
+        if !pages.is_empty() {
+            crate::search::index_pages(state, pages).await;
+        }
+    }
+
+    tracing::info!(
+        "Restored state from the database ({} active bans)",
+        ban_count
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn audit_prefix_filter_is_literal() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").display());
+        let db = Db::connect(&url).await.unwrap();
+        for action in [
+            "ban.add",
+            "ban.remove",
+            "banxadd",
+            "user_x.create",
+            "share.create",
+        ] {
+            db.insert_audit(1, "admin", action, None, None)
+                .await
+                .unwrap();
+        }
+        let actions = |entries: Vec<AuditEntry>| -> Vec<String> {
+            entries.into_iter().map(|e| e.action).collect()
+        };
+
+        let found = db.load_audit(Some("ban"), None, 10).await.unwrap();
+        assert_eq!(actions(found), ["ban.remove", "ban.add"]);
+        let found = db.load_audit(Some("%"), None, 10).await.unwrap();
+        assert!(found.is_empty());
+        let found = db.load_audit(Some("user_x"), None, 10).await.unwrap();
+        assert_eq!(actions(found), ["user_x.create"]);
+        let found = db.load_audit(Some("ba_"), None, 10).await.unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[tokio::test]
+    async fn vault_state_survives_reconnects() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("test.db").display());
+        let db = Db::connect(&url).await.unwrap();
+        assert_eq!(db.load_vault_seen("a1").await.unwrap(), None);
+        let grades = HashSet::from(["g1".to_string(), "g2".to_string()]);
+        let exams = HashSet::from(["e1".to_string()]);
+        db.save_vault_seen("a1", &grades, &exams).await.unwrap();
+        db.save_digest_sent(20_000).await.unwrap();
+        db.save_digest_sent(20_007).await.unwrap();
+        drop(db);
+
+        let db = Db::connect(&url).await.unwrap();
+        assert_eq!(
+            db.load_vault_seen("a1").await.unwrap(),
+            Some((grades, exams))
+        );
+        assert_eq!(db.load_digest_sent().await.unwrap(), Some(20_007));
+        db.delete_credential("a1").await.unwrap();
+        assert_eq!(db.load_vault_seen("a1").await.unwrap(), None);
+    }
+}
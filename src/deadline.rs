@@ -0,0 +1,114 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Overall deadline of proxied requests (`REQUEST_TIMEOUT_SECS`).
+//!
+//! The deadline starts when the request arrives and covers the upstream
+//! request, the rewriting and sending the response to the client. A request
+//! still waiting for its response at the deadline gets `504 Gateway Timeout`,
+//! a response still being sent is cut off. Either way the upstream request is
+//! dropped, which closes or frees its connection. The same happens as soon as
+//! the client disconnects, since hyper then drops the response.
+//!
+//! Handlers making upstream requests find the deadline in the request
+//! extensions and pass the remaining time on with [`Deadline::remaining`].
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body::{Frame, SizeHint};
+use tokio::time::{Instant, Sleep};
+
+use crate::state::AppState;
+
+/// When the current request must be done, stored in the request extensions.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(pub Instant);
+
+impl Deadline {
+    /// Time left until the deadline, at least a millisecond.
+    pub fn remaining(self) -> Duration {
+        self.0
+            .saturating_duration_since(Instant::now())
+            .max(Duration::from_millis(1))
+    }
+}
+
+/// Middleware enforcing `REQUEST_TIMEOUT_SECS`.
+pub async fn enforce(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let Some(timeout) = state.config.timeouts.request else {
+        return next.run(req).await;
+    };
+    let deadline = Instant::now() + timeout;
+    req.extensions_mut().insert(Deadline(deadline));
+    let uri = req.uri().clone();
+
+    match tokio::time::timeout_at(deadline, next.run(req)).await {
+        Ok(response) => response.map(|body| {
+            Body::new(Limited {
+                inner: body,
+                sleep: Box::pin(tokio::time::sleep_until(deadline)),
+            })
+        }),
+        Err(_) => {
+            tracing::warn!(
+                "Request deadline exceeded for {}",
+                state.config.privacy.url(&uri.to_string())
+            );
+            (StatusCode::GATEWAY_TIMEOUT, "Gateway Timeout").into_response()
+        }
+    }
+}
+
+/// A response body failing once the deadline has passed.
+struct Limited {
+    inner: Body,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl http_body::Body for Limited {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        if let Poll::Ready(frame) = Pin::new(&mut self.inner).poll_frame(cx) {
+            return Poll::Ready(frame);
+        }
+
+        ready!(self.sleep.as_mut().poll(cx));
+        tracing::debug!("Request deadline exceeded while sending the response");
+        Poll::Ready(Some(Err(axum::Error::new("request deadline exceeded"))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
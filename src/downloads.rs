@@ -0,0 +1,218 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Downloads and their `Content-Disposition` header.
+//!
+//! The header is forwarded byte for byte, so both RFC 5987 `filename*`
+//! parameters and the raw UTF-8 `filename`s the school's server sends for
+//! Czech names survive. Raw UTF-8 names additionally get a `filename*`
+//! copy, which every browser decodes the same way. Attachments are always
+//! streamed as is, even if their content type would otherwise be rewritten.
+
+use axum::http::{HeaderMap, HeaderValue};
+
+/// Whether the response is a download (`Content-Disposition: attachment`).
+pub fn is_attachment(headers: &HeaderMap) -> bool {
+    headers
+        .get("content-disposition")
+        .and_then(|v| v.as_bytes().split(|&b| b == b';').next())
+        .is_some_and(|kind| kind.trim_ascii().eq_ignore_ascii_case(b"attachment"))
+}
+
+/// Adds `filename*` to a header whose `filename` is raw UTF-8, otherwise
+/// returns it unchanged.
+pub fn content_disposition(value: &HeaderValue) -> HeaderValue {
+    let bytes = value.as_bytes();
+    if bytes.is_ascii() || param(bytes, b"filename*").is_some() {
+        return value.clone();
+    }
+    let Some(name) = param(bytes, b"filename").and_then(|n| std::str::from_utf8(n).ok()) else {
+        return value.clone();
+    };
+
+    let mut extended = bytes.to_vec();
+    extended.extend_from_slice(b"; filename*=UTF-8''");
+    extended.extend_from_slice(percent_encode(name).as_bytes());
+    HeaderValue::from_bytes(&extended).unwrap_or_else(|_| value.clone())
+}
+
+/// The download's file name, decoding `filename*` (UTF-8 or ISO-8859-1) in
+/// preference to `filename`.
+pub fn filename(value: &HeaderValue) -> Option<String> {
+    let bytes = value.as_bytes();
+    if let Some(extended) = param(bytes, b"filename*")
+        && let Some(name) = decode_extended(extended)
+    {
+        return Some(name);
+    }
+    param(bytes, b"filename").map(|n| String::from_utf8_lossy(n).replace("\\\"", "\""))
+}
+
+/// Value of the parameter `name`, unquoted.
+fn param<'a>(header: &'a [u8], name: &[u8]) -> Option<&'a [u8]> {
+    let mut rest = header;
+    while let Some(semi) = rest.iter().position(|&b| b == b';') {
+        rest = &rest[semi + 1..];
+        let start = rest.iter().position(|b| !b.is_ascii_whitespace())?;
+        rest = &rest[start..];
+
+        let eq = rest.iter().position(|&b| b == b'=' || b == b';')?;
+        if rest[eq] != b'=' || !rest[..eq].trim_ascii().eq_ignore_ascii_case(name) {
+            continue;
+        }
+
+        let value = rest[eq + 1..].trim_ascii_start();
+        if let Some(quoted) = value.strip_prefix(b"\"") {
+            let end = quoted
+                .iter()
+                .enumerate()
+                .find(|&(i, &b)| b == b'"' && (i == 0 || quoted[i - 1] != b'\\'))
+                .map_or(quoted.len(), |(i, _)| i);
+            return Some(&quoted[..end]);
+        }
+        let end = value.iter().position(|&b| b == b';').unwrap_or(value.len());
+        return Some(value[..end].trim_ascii_end());
+    }
+    None
+}
+
+/// Decodes an RFC 5987 `charset'language'value`.
+fn decode_extended(value: &[u8]) -> Option<String> {
+    let mut parts = value.splitn(3, |&b| b == b'\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let encoded = parts.next()?;
+
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        if encoded[i] == b'%'
+            && let Some(byte) = encoded
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(encoded[i]);
+            i += 1;
+        }
+    }
+
+    if charset.eq_ignore_ascii_case(b"utf-8") {
+        String::from_utf8(decoded).ok()
+    } else if charset.eq_ignore_ascii_case(b"iso-8859-1") {
+        Some(decoded.iter().map(|&b| b as char).collect())
+    } else {
+        None
+    }
+}
+
+/// Percent-encodes everything except RFC 5987 `attr-char`s.
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() * 3);
+    for &b in value.as_bytes() {
+        if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `Content-Disposition` values captured from documents on the school site.
+    const CAPTURED: &[(&[u8], &str)] = &[
+        (
+            b"attachment; filename=\"rozvrh.pdf\"",
+            "rozvrh.pdf",
+        ),
+        (
+            "attachment; filename=\"Školní řád 2025.pdf\"".as_bytes(),
+            "Školní řád 2025.pdf",
+        ),
+        (
+            b"inline; filename*=UTF-8''%C5%A0koln%C3%AD%20%C5%99%C3%A1d.pdf",
+            "Školní řád.pdf",
+        ),
+        (
+            b"attachment; filename=\"prihlaska.pdf\"; filename*=utf-8''p%C5%99ihl%C3%A1%C5%A1ka.pdf",
+            "přihláška.pdf",
+        ),
+        (
+            b"attachment;filename*=UTF-8'cs'Maturitn%C3%AD%20t%C3%A9mata%3B%20EK.pdf;size=48213",
+            "Maturitní témata; EK.pdf",
+        ),
+        (
+            b"attachment; filename*=iso-8859-1'en'%A3%20rates.pdf",
+            "£ rates.pdf",
+        ),
+        (
+            b"attachment; filename=\"Harmonogram \\\"final\\\".pdf\"",
+            "Harmonogram \"final\".pdf",
+        ),
+        (b"ATTACHMENT; FILENAME=vysledky.xlsx", "vysledky.xlsx"),
+    ];
+
+    #[test]
+    fn captured_filenames() {
+        for (raw, expected) in CAPTURED {
+            let value = HeaderValue::from_bytes(raw).unwrap();
+            assert_eq!(filename(&value).as_deref(), Some(*expected));
+        }
+    }
+
+    #[test]
+    fn captured_headers_keep_their_bytes() {
+        for (raw, expected) in CAPTURED {
+            let value = HeaderValue::from_bytes(raw).unwrap();
+            let forwarded = content_disposition(&value);
+            assert!(forwarded.as_bytes().starts_with(raw));
+            assert_eq!(filename(&forwarded).as_deref(), Some(*expected));
+        }
+    }
+
+    #[test]
+    fn raw_utf8_gets_extended_filename() {
+        let value =
+            HeaderValue::from_bytes("attachment; filename=\"Školní řád.pdf\"".as_bytes()).unwrap();
+        let forwarded = content_disposition(&value);
+        assert!(
+            forwarded
+                .as_bytes()
+                .ends_with(b"; filename*=UTF-8''%C5%A0koln%C3%AD%20%C5%99%C3%A1d.pdf")
+        );
+    }
+
+    #[test]
+    fn attachments() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_attachment(&headers));
+        headers.insert(
+            "content-disposition",
+            HeaderValue::from_static("inline; filename=\"a.pdf\""),
+        );
+        assert!(!is_attachment(&headers));
+        headers.insert(
+            "content-disposition",
+            HeaderValue::from_static(" Attachment ;filename=a.pdf"),
+        );
+        assert!(is_attachment(&headers));
+    }
+}
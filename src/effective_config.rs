@@ -0,0 +1,133 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! The resolved configuration as JSON, for `jecnaproxy print-config` and `GET /_admin/config`.
+//!
+//! Every setting is included with the value in effect, defaults too. Tokens,
+//! keys and secrets are replaced by `"<redacted>"`, passwords in URLs by `***`
+//! and webhook URLs, which are secrets themselves, are cut to their origin.
+//! Durations are given in seconds. The serializers below are used by the
+//! `Serialize` derives of the configuration structs.
+
+use std::time::Duration;
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderName, HeaderValue},
+};
+use reqwest::Url;
+use serde::Serializer;
+
+use crate::config::Config;
+use crate::state::AppState;
+
+/// Replacement of secret values.
+pub const REDACTED: &str = "<redacted>";
+
+/// Handler of `jecnaproxy print-config`.
+pub fn print(config: &Config) {
+    match serde_json::to_string_pretty(config) {
+        Ok(json) => println!("{}", json),
+        Err(e) => {
+            eprintln!("Failed to serialize the configuration: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handler for `GET /_admin/config`.
+pub async fn handler(State(state): State<AppState>) -> Json<Config> {
+    Json((*state.config).clone())
+}
+
+pub fn secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+pub fn opt_secs<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => secs(duration, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn redacted<T, S: Serializer>(_secret: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(REDACTED)
+}
+
+pub fn opt_redacted<T, S: Serializer>(
+    secret: &Option<T>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match secret {
+        Some(secret) => redacted(secret, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// A URL with its password masked.
+pub fn url<S: Serializer>(url: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&mask_password(url))
+}
+
+pub fn opt_url<S: Serializer>(url: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    match url {
+        Some(url) => self::url(url, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Webhook URLs reduced to their origin.
+pub fn webhook_urls<S: Serializer>(urls: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(urls.iter().map(|url| match Url::parse(url) {
+        Ok(parsed) => format!("{}/{}", parsed.origin().ascii_serialization(), REDACTED),
+        Err(_) => REDACTED.to_string(),
+    }))
+}
+
+pub fn header_name<S: Serializer>(name: &HeaderName, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(name.as_str())
+}
+
+pub fn header_names<S: Serializer>(names: &[HeaderName], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(names.iter().map(HeaderName::as_str))
+}
+
+pub fn header_value<S: Serializer>(value: &HeaderValue, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&String::from_utf8_lossy(value.as_bytes()))
+}
+
+pub fn opt_header_value<S: Serializer>(
+    value: &Option<HeaderValue>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => header_value(value, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn mask_password(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut parsed) if parsed.password().is_some() => {
+            let _ = parsed.set_password(Some("***"));
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
@@ -0,0 +1,121 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! The proxy's own error pages.
+//!
+//! With `ERROR_PAGES` set, upstream 404 and 5xx HTML pages are replaced by
+//! `assets/error-page.html` in Czech or English, picked from `Accept-Language`.
+//! The upstream status is kept. The page links to the same URL on the
+//! official site and, when the search index is enabled, has a search box.
+
+use axum::http::{HeaderMap, StatusCode};
+
+use crate::{assets, utils};
+
+/// Texts of one language.
+struct Texts {
+    lang: &'static str,
+    not_found: &'static str,
+    not_found_message: &'static str,
+    error: &'static str,
+    error_message: &'static str,
+    home: &'static str,
+    official: &'static str,
+    search: &'static str,
+    search_empty: &'static str,
+    search_failed: &'static str,
+}
+
+const CS: Texts = Texts {
+    lang: "cs",
+    not_found: "Stránka nenalezena",
+    not_found_message: "Požadovaná stránka neexistuje nebo byla přesunuta.",
+    error: "Chyba serveru",
+    error_message: "Web školy teď neodpovídá správně. Zkuste to prosím za chvíli znovu.",
+    home: "Hlavní stránka",
+    official: "Otevřít na oficiálním webu",
+    search: "Hledat",
+    search_empty: "Nic nenalezeno.",
+    search_failed: "Vyhledávání není dostupné.",
+};
+
+const EN: Texts = Texts {
+    lang: "en",
+    not_found: "Page not found",
+    not_found_message: "The page doesn't exist or has been moved.",
+    error: "Server error",
+    error_message: "The school's website isn't responding correctly. Please try again later.",
+    home: "Home page",
+    official: "Open on the official website",
+    search: "Search",
+    search_empty: "Nothing found.",
+    search_failed: "Search is not available.",
+};
+
+/// Whether an upstream response with `status` gets the proxy's error page.
+pub fn replaces(status: StatusCode) -> bool {
+    status == StatusCode::NOT_FOUND || status.is_server_error()
+}
+
+/// Renders the error page for `status`.
+///
+/// `official_url` is the requested page on the official site, `search` adds
+/// the search box.
+pub fn render(status: StatusCode, official_url: &str, search: bool, request: &HeaderMap) -> String {
+    let texts = language(request);
+    let (title, message) = if status == StatusCode::NOT_FOUND {
+        (texts.not_found, texts.not_found_message)
+    } else {
+        (texts.error, texts.error_message)
+    };
+
+    let search = if search {
+        format!(
+            r#"<form id="jecnaproxy-search" action="/api/search" data-empty="{}" data-failed="{}"><input type="search" name="q" aria-label="{}" required><button>{}</button></form><ul id="jecnaproxy-results"></ul><script src="/_jecnaproxy/error-page.js" defer></script>"#,
+            texts.search_empty, texts.search_failed, texts.search, texts.search
+        )
+    } else {
+        String::new()
+    };
+
+    assets::text("error-page.html")
+        .replace("$lang", texts.lang)
+        .replace("$status", status.as_str())
+        .replace("$title", title)
+        .replace("$message", message)
+        .replace("$search", &search)
+        .replace("$home", texts.home)
+        .replace("$official", texts.official)
+        .replace("$url", &utils::escape_xml(official_url))
+}
+
+/// Picks the first supported language of `Accept-Language`, Czech by default.
+fn language(request: &HeaderMap) -> &'static Texts {
+    let accept = request
+        .get("accept-language")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    for range in accept.split(',') {
+        let tag = range.split(';').next().unwrap_or_default().trim();
+        let primary = tag.split('-').next().unwrap_or_default();
+        if primary.eq_ignore_ascii_case("cs") || primary.eq_ignore_ascii_case("sk") {
+            return &CS;
+        }
+        if primary.eq_ignore_ascii_case("en") {
+            return &EN;
+        }
+    }
+    &CS
+}
@@ -0,0 +1,328 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Main content extraction from upstream HTML.
+//!
+//! Strips navigation and other boilerplate and renders what's left as Markdown
+//! or plain text.
+
+use ego_tree::NodeRef;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Node, Selector};
+
+/// Elements that are never part of the main content.
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "nav", "header", "footer", "form", "iframe", "svg", "aside",
+    "button", "select", "template",
+];
+
+/// Substrings of ids/classes that mark boilerplate containers.
+const SKIPPED_MARKERS: &[&str] = &["menu", "nav", "footer", "breadcrumb", "sidebar", "cookie"];
+
+/// Selectors tried in order to find the main content.
+const CONTENT_SELECTORS: &[&str] = &["main", "article", "#content", ".content", "#main", "body"];
+
+/// Output format of the extraction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Text,
+}
+
+/// Finds the main content element of a document.
+pub fn main_content(document: &Html) -> Option<ElementRef<'_>> {
+    CONTENT_SELECTORS.iter().find_map(|s| {
+        let selector = Selector::parse(s).ok()?;
+        document.select(&selector).next()
+    })
+}
+
+/// Renders the main content of `html`. Relative links are resolved against `base`.
+pub fn render_page(html: &str, base: &Url, format: Format) -> String {
+    let document = Html::parse_document(html);
+    match main_content(&document) {
+        Some(content) => render(content, base, format),
+        None => String::new(),
+    }
+}
+
+/// Renders an element and its children.
+pub fn render(element: ElementRef, base: &Url, format: Format) -> String {
+    let mut renderer = Renderer {
+        out: String::new(),
+        base,
+        format,
+        lists: Vec::new(),
+    };
+    renderer.children(*element);
+    normalize(&renderer.out)
+}
+
+struct Renderer<'a> {
+    out: String,
+    base: &'a Url,
+    format: Format,
+    /// Stack of open lists, `Some(n)` for ordered lists with the next number.
+    lists: Vec<Option<usize>>,
+}
+
+impl Renderer<'_> {
+    fn markdown(&self) -> bool {
+        self.format == Format::Markdown
+    }
+
+    fn children(&mut self, node: NodeRef<Node>) {
+        for child in node.children() {
+            self.node(child);
+        }
+    }
+
+    fn node(&mut self, node: NodeRef<Node>) {
+        match node.value() {
+            Node::Text(text) => self.text(text),
+            Node::Element(_) => {
+                if let Some(element) = ElementRef::wrap(node) {
+                    self.element(element);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if collapsed.is_empty() {
+            if !text.is_empty() && !self.out.ends_with([' ', '\n']) && !self.out.is_empty() {
+                self.out.push(' ');
+            }
+            return;
+        }
+        if text.starts_with(char::is_whitespace) && !self.out.ends_with([' ', '\n']) {
+            self.out.push(' ');
+        }
+        self.out.push_str(&collapsed);
+        if text.ends_with(char::is_whitespace) {
+            self.out.push(' ');
+        }
+    }
+
+    fn block(&mut self) {
+        self.out.push_str("\n\n");
+    }
+
+    fn element(&mut self, element: ElementRef) {
+        let el = element.value();
+        let name = el.name();
+
+        if SKIPPED_ELEMENTS.contains(&name) || is_boilerplate(element) {
+            return;
+        }
+
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.block();
+                if self.markdown() {
+                    let level = name[1..].parse().unwrap_or(1);
+                    self.out.push_str(&"#".repeat(level));
+                    self.out.push(' ');
+                }
+                self.children(*element);
+                self.block();
+            }
+            "p" | "div" | "section" | "article" | "main" | "figure" => {
+                self.block();
+                self.children(*element);
+                self.block();
+            }
+            "table" => {
+                self.block();
+                self.table(element);
+                self.block();
+            }
+            "br" => self.out.push('\n'),
+            "hr" => {
+                self.block();
+                if self.markdown() {
+                    self.out.push_str("---");
+                }
+                self.block();
+            }
+            "ul" | "ol" => {
+                let nested = !self.lists.is_empty();
+                self.lists.push((name == "ol").then_some(1));
+                if !nested {
+                    self.block();
+                }
+                self.children(*element);
+                if !nested {
+                    self.block();
+                }
+                self.lists.pop();
+            }
+            "li" => {
+                self.out.push('\n');
+                self.out
+                    .push_str(&"  ".repeat(self.lists.len().saturating_sub(1)));
+                match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        self.out.push_str(&format!("{}. ", n));
+                        *n += 1;
+                    }
+                    _ => self.out.push_str("- "),
+                }
+                self.children(*element);
+            }
+            "strong" | "b" if self.markdown() => self.wrapped(element, "**"),
+            "em" | "i" if self.markdown() => self.wrapped(element, "*"),
+            "code" if self.markdown() => self.wrapped(element, "`"),
+            "pre" => {
+                self.block();
+                if self.markdown() {
+                    self.out.push_str("```\n");
+                }
+                self.out
+                    .push_str(element.text().collect::<String>().trim_end());
+                if self.markdown() {
+                    self.out.push_str("\n```");
+                }
+                self.block();
+            }
+            "blockquote" if self.markdown() => {
+                let inner = render(element, self.base, self.format);
+                self.block();
+                for line in inner.lines() {
+                    self.out.push_str("> ");
+                    self.out.push_str(line);
+                    self.out.push('\n');
+                }
+                self.block();
+            }
+            "a" => self.link(element),
+            "img" => {
+                let alt = el.attr("alt").unwrap_or("").trim();
+                match (
+                    self.markdown(),
+                    el.attr("src").and_then(|s| self.resolve(s)),
+                ) {
+                    (true, Some(src)) => self.out.push_str(&format!("![{}]({})", alt, src)),
+                    _ => self.out.push_str(alt),
+                }
+            }
+            _ => self.children(*element),
+        }
+    }
+
+    fn table(&mut self, element: ElementRef) {
+        let row_selector = Selector::parse("tr").expect("valid selector");
+        let cell_selector = Selector::parse("th, td").expect("valid selector");
+
+        let rows: Vec<Vec<String>> = element
+            .select(&row_selector)
+            .map(|row| {
+                row.select(&cell_selector)
+                    .map(|cell| render(cell, self.base, self.format).replace('\n', " "))
+                    .collect()
+            })
+            .filter(|cells: &Vec<String>| !cells.is_empty())
+            .collect();
+
+        for (i, cells) in rows.iter().enumerate() {
+            if self.markdown() {
+                self.out.push_str(&format!("| {} |\n", cells.join(" | ")));
+                if i == 0 {
+                    self.out
+                        .push_str(&format!("|{}\n", " --- |".repeat(cells.len())));
+                }
+            } else {
+                self.out.push_str(&cells.join("\t"));
+                self.out.push('\n');
+            }
+        }
+    }
+
+    fn wrapped(&mut self, element: ElementRef, marker: &str) {
+        let inner = element.text().collect::<String>();
+        let inner = inner.split_whitespace().collect::<Vec<_>>().join(" ");
+        if inner.is_empty() {
+            return;
+        }
+        self.out.push_str(marker);
+        self.out.push_str(&inner);
+        self.out.push_str(marker);
+    }
+
+    fn link(&mut self, element: ElementRef) {
+        let href = element.value().attr("href").and_then(|h| self.resolve(h));
+        let start = self.out.len();
+        self.children(*element);
+
+        if let (true, Some(href)) = (self.markdown(), href) {
+            let label = self.out[start..].trim().to_string();
+            self.out.truncate(start);
+            if label.is_empty() {
+                self.out.push_str(&format!("<{}>", href));
+            } else {
+                self.out.push_str(&format!("[{}]({})", label, href));
+            }
+        }
+    }
+
+    fn resolve(&self, href: &str) -> Option<String> {
+        if href.starts_with("javascript:") || href.starts_with('#') {
+            return None;
+        }
+        self.base.join(href).ok().map(String::from)
+    }
+}
+
+fn is_boilerplate(element: ElementRef) -> bool {
+    let el = element.value();
+    el.id().into_iter().chain(el.classes()).any(|name| {
+        let name = name.to_lowercase();
+        SKIPPED_MARKERS.iter().any(|m| name.contains(m))
+    })
+}
+
+/// Trims lines and collapses runs of blank lines.
+fn normalize(text: &str) -> String {
+    let mut out = String::new();
+    let mut blank = 0;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            blank += 1;
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank > 0 { "\n\n" } else { "\n" });
+        }
+        blank = 0;
+        // List items carry meaningful indentation, everything else may start with
+        // whitespace left over from the surrounding markup.
+        let trimmed = line.trim_start();
+        if is_list_item(trimmed) {
+            out.push_str(line);
+        } else {
+            out.push_str(trimmed);
+        }
+    }
+    out
+}
+
+fn is_list_item(line: &str) -> bool {
+    line.starts_with("- ")
+        || line
+            .split_once(". ")
+            .is_some_and(|(n, _)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
@@ -0,0 +1,287 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Traefik-style forward authentication.
+//!
+//! Before a request is proxied, the configured auth endpoint is called with the
+//! original request headers. Only a 2xx answer lets the request through, anything
+//! else is returned to the client as-is (so the auth service can e.g. redirect to
+//! its login page).
+
+use std::env;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::{config, effective_config, share::SharedAccess, state::AppState, tls::Https, utils};
+
+/// Forward-auth settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct ForwardAuthConfig {
+    /// The auth endpoint called before every proxied request.
+    #[serde(serialize_with = "effective_config::url")]
+    pub url: String,
+    /// Headers copied from a successful auth response to the upstream request.
+    #[serde(serialize_with = "effective_config::header_names")]
+    pub response_headers: Vec<HeaderName>,
+}
+
+impl ForwardAuthConfig {
+    /// # Environment Variables
+    /// * `FORWARD_AUTH_URL` - Auth endpoint. Forward auth is disabled when unset.
+    /// * `FORWARD_AUTH_RESPONSE_HEADERS` - Comma-separated header names to copy upstream.
+    pub fn from_env() -> Option<Self> {
+        let url = env::var("FORWARD_AUTH_URL")
+            .ok()
+            .filter(|v| !v.is_empty())?;
+
+        let response_headers = config::env_list("FORWARD_AUTH_RESPONSE_HEADERS")
+            .into_iter()
+            .filter_map(|name| match HeaderName::try_from(name.as_str()) {
+                Ok(h) => Some(h),
+                Err(_) => {
+                    tracing::warn!("Ignoring invalid forward-auth header name: {}", name);
+                    None
+                }
+            })
+            .collect();
+
+        Some(Self {
+            url,
+            response_headers,
+        })
+    }
+}
+
+/// Middleware asking the auth endpoint whether the request may be proxied.
+pub async fn authorize(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let Some(auth) = &state.config.forward_auth else {
+        return next.run(req).await;
+    };
+
+    if req.extensions().get::<SharedAccess>().is_some() {
+        return next.run(req).await;
+    }
+
+    // Never trust identity headers sent by the client itself.
+    for name in &auth.response_headers {
+        req.headers_mut().remove(name);
+    }
+
+    let mut headers = req.headers().clone();
+    headers.remove("host");
+    headers.remove("content-length");
+    headers.remove("transfer-encoding");
+    insert_forwarded_headers(&mut headers, &req, state.config.base_url.as_deref());
+
+    let resp = match state.client.get(&auth.url).headers(headers).send().await {
+        Ok(r) => r,
+        Err(e) => {
+            tracing::error!("Forward-auth request failed: {}", e);
+            return (
+                StatusCode::BAD_GATEWAY,
+                "Authentication service unavailable",
+            )
+                .into_response();
+        }
+    };
+
+    if !resp.status().is_success() {
+        tracing::info!(
+            "Forward-auth denied {} with {}",
+            state.config.privacy.url(&req.uri().to_string()),
+            resp.status()
+        );
+        return into_client_response(resp).await;
+    }
+
+    for name in &auth.response_headers {
+        for value in resp.headers().get_all(name) {
+            req.headers_mut().append(name, value.clone());
+        }
+    }
+
+    next.run(req).await
+}
+
+/// Adds the `X-Forwarded-*` headers auth services use to see the original request.
+fn insert_forwarded_headers(headers: &mut HeaderMap, req: &Request, base_url: Option<&str>) {
+    let origin = utils::determine_proxy_origin(
+        base_url,
+        req.headers(),
+        req.extensions().get::<Https>().is_some(),
+    );
+    let proto = if origin.starts_with("https://") {
+        "https"
+    } else {
+        "http"
+    };
+    headers.insert("x-forwarded-proto", HeaderValue::from_static(proto));
+
+    let method = req.method().as_str();
+    let uri = req
+        .uri()
+        .path_and_query()
+        .map(|v| v.as_str())
+        .unwrap_or("/");
+
+    if let Ok(v) = HeaderValue::from_str(method) {
+        headers.insert("x-forwarded-method", v);
+    }
+    if let Ok(v) = HeaderValue::from_str(uri) {
+        headers.insert("x-forwarded-uri", v);
+    }
+    if let Some(host) = req.headers().get("host") {
+        headers.insert("x-forwarded-host", host.clone());
+    }
+}
+
+/// Relays the auth service's denial to the client.
+async fn into_client_response(resp: reqwest::Response) -> Response {
+    let status = resp.status();
+    let mut headers = resp.headers().clone();
+    headers.remove("content-length");
+    headers.remove("transfer-encoding");
+
+    let body = resp.bytes().await.unwrap_or_default();
+
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{Router, http::HeaderMap, middleware, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::config::{Config, Mode, Upstream};
+
+    /// An auth service allowing `/allow`, denying `/unauthorized` and `/forbidden`.
+    async fn auth_service() -> String {
+        let app = Router::new()
+            .route(
+                "/allow",
+                get(|headers: HeaderMap| async move {
+                    let seen = ["x-forwarded-proto", "x-forwarded-method", "x-forwarded-uri"]
+                        .map(|name| headers[name].to_str().unwrap().to_string())
+                        .join(" ");
+                    ([("x-auth-user", format!("alice {}", seen))], "")
+                }),
+            )
+            .route("/anonymous", get(|| async { "" }))
+            .route(
+                "/unauthorized",
+                get(|| async {
+                    (
+                        StatusCode::UNAUTHORIZED,
+                        [("www-authenticate", "Basic realm=\"jecna\"")],
+                        "log in first",
+                    )
+                }),
+            )
+            .route("/forbidden", get(|| async { StatusCode::FORBIDDEN }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    /// Sends a request spoofing `X-Auth-User` through the middleware in front of
+    /// a handler echoing the `X-Auth-User` values it receives.
+    async fn request(url: String) -> (StatusCode, HeaderMap, String) {
+        let mut config = Config::from_env();
+        config.base_url = None;
+        config.forward_auth = Some(ForwardAuthConfig {
+            url,
+            response_headers: vec![HeaderName::from_static("x-auth-user")],
+        });
+        let upstream = Upstream::new(Mode::SPSEJECNA, &[]).unwrap();
+        let state = AppState::new(Arc::new(config), upstream);
+
+        let app = Router::new()
+            .fallback(|headers: HeaderMap| async move {
+                let users: Vec<_> = headers
+                    .get_all("x-auth-user")
+                    .iter()
+                    .map(|v| v.to_str().unwrap().to_string())
+                    .collect();
+                format!("proxied as [{}]", users.join(", "))
+            })
+            .layer(middleware::from_fn_with_state(state, authorize));
+        let req = Request::builder()
+            .uri("/score/student?x=1")
+            .header("host", "jecna.example.org")
+            .header("x-auth-user", "mallory")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(req).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn allowed_requests_get_the_auth_headers() {
+        let (status, _, body) = request(auth_service().await + "/allow").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "proxied as [alice http GET /score/student?x=1]");
+    }
+
+    #[tokio::test]
+    async fn spoofed_auth_headers_are_stripped() {
+        let (status, _, body) = request(auth_service().await + "/anonymous").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "proxied as []");
+    }
+
+    #[tokio::test]
+    async fn denials_are_passed_to_the_client() {
+        let base = auth_service().await;
+        let (status, headers, body) = request(base.clone() + "/unauthorized").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(headers["www-authenticate"], "Basic realm=\"jecna\"");
+        assert_eq!(body, "log in first");
+
+        let (status, _, body) = request(base + "/forbidden").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(!body.starts_with("proxied"));
+    }
+
+    #[tokio::test]
+    async fn unreachable_auth_service_fails_closed() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/allow", listener.local_addr().unwrap());
+        drop(listener);
+
+        let (status, _, body) = request(url).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert!(!body.starts_with("proxied"));
+    }
+}
@@ -12,17 +12,30 @@
  * GNU General Public License for more details.
  */
 
-use crate::{state::AppState, utils};
+use crate::{
+    bandwidth::{self, Bucket, ConnectionBucket},
+    dark_mode,
+    deadline::Deadline,
+    downloads, error_page, headers, jidelna, memory, pwa, redirects,
+    sampling::Sample,
+    service_worker, snapshot,
+    state::AppState,
+    tls::Https,
+    trackers, upstreams, utils, via,
+};
 use axum::{
     body::Body,
     extract::{Request, State},
-    http::{HeaderMap, HeaderValue, StatusCode},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
+use http_body::Body as _;
+use std::sync::{Arc, atomic::Ordering};
 
-const BANNER_HTML: &str = r#"<div style="width: 100vw; height: 100vh; position: fixed; z-index: 1000; background-color: black; color: white; display: flex; flex-direction: column; justify-content: center; align-items: center; text-align: center; gap: 5px;">
-  <h1 style="font-size: 40px;">Toto není oficiální web SPŠE Ječná!</h1>
-  <p style="font-size: 20px;">Oficiální web se nachází na <a style="font-size: 20px; color: white;" href="$url">spsejecna.cz</a>.</p>
+const BANNER_HTML: &str = r#"<div id="jecnaproxy-banner">
+  <link rel="stylesheet" href="/_jecnaproxy/banner.css">
+  <h1>Toto není oficiální web SPŠE Ječná!</h1>
+  <p>Oficiální web se nachází na <a href="$url">spsejecna.cz</a>.</p>
   <script>
     setTimeout(() => {
       const { pathname, search, hash } = window.location;
@@ -35,6 +48,10 @@ const BANNER_HTML: &str = r#"<div style="width: 100vw; height: 100vh; position:
 
 const ROBOTS_TXT: &str = "User-agent: *\nDisallow: /\n";
 
+const EMPTY_SITEMAP: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"></urlset>
+"#;
+
 /// Handler for robots.txt
 pub async fn robots_txt_handler() -> Response {
     let mut headers = HeaderMap::new();
@@ -48,123 +65,342 @@ pub async fn robots_txt_handler() -> Response {
     response
 }
 
+/// Handler for sitemap.xml, an empty sitemap unless `REWRITE_SITEMAP` is set.
+pub async fn sitemap_handler() -> Response {
+    (
+        [("content-type", "application/xml; charset=utf-8")],
+        EMPTY_SITEMAP,
+    )
+        .into_response()
+}
+
 /// The main proxy handler that intercepts all traffic.
 ///
 /// It forwards requests to `https://www.spsejecna.cz`, rewriting headers and body content
 /// to ensure the site functions correctly when accessed via this proxy.
 pub async fn proxy_handler(State(state): State<AppState>, req: Request) -> Response {
-    let client = &state.client;
-    let path_query = req
-        .uri()
-        .path_and_query()
-        .map(|v| v.as_str())
-        .unwrap_or("/");
+    let path_query = utils::upstream_path_query(req.uri(), state.config.normalize_paths);
     let original_headers = req.headers().clone();
 
-    let target_url = format!("{}{}", state.config.mode.url(), path_query);
-    tracing::info!("Proxying: {} -> {}", req.uri(), target_url);
+    // Named upstreams take precedence, the offline snapshot only covers the `MODE` one.
+    let host = original_headers
+        .get("host")
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| req.uri().authority().map(|a| a.as_str()))
+        .unwrap_or_default();
+    let routed = upstreams::select(&state.routes, host, &path_query)
+        .map(|(route, rest)| (route.upstream.clone(), rest))
+        .or_else(|| upstreams::select_wildcard(&state.config.external_wildcards, &path_query));
+    let (state, path_query, use_snapshot) = match routed {
+        Some((upstream, rest)) => (state.with_upstream(upstream), rest, false),
+        None => (state, path_query, true),
+    };
+    let upstream = state.upstream();
+    let client = &state.client;
+
+    // hyper answers `100-continue` itself once the body is read, anything else is unsupported.
+    if let Some(expect) = original_headers.get("expect")
+        && !expect.as_bytes().eq_ignore_ascii_case(b"100-continue")
+    {
+        return (StatusCode::EXPECTATION_FAILED, "Unsupported expectation").into_response();
+    }
 
-    let proxy_origin =
-        utils::determine_proxy_origin(state.config.base_url.as_deref(), req.headers());
+    let target_url = format!("{}{}", upstream.base, path_query);
+    let privacy = &state.config.privacy;
+    tracing::info!(
+        "Proxying: {} -> {}",
+        privacy.url(&req.uri().to_string()),
+        privacy.url(&target_url)
+    );
+    let sample = state.config.sampling.sample(req.uri().path());
 
-    let is_secure = utils::is_secure_origin(&proxy_origin);
+    let proxy_origin = utils::determine_proxy_origin(
+        state.config.base_url.as_deref(),
+        req.headers(),
+        req.extensions().get::<Https>().is_some(),
+    ) + &upstream.prefix;
+
+    let mut buckets: Vec<_> = state.bandwidth.iter().cloned().collect();
+    if let Some(ConnectionBucket(bucket)) = req.extensions().get() {
+        buckets.push(bucket.clone());
+    }
 
     let method = req.method().clone();
+    let version = req.version();
+    let deadline = req.extensions().get::<Deadline>().copied();
     let mut headers = req.headers().clone();
 
     utils::prepare_request_headers(&mut headers, &state);
+    via::append(&mut headers, version, &state.config.via.name);
 
-    let body_bytes = match axum::body::to_bytes(req.into_body(), usize::MAX).await {
-        Ok(b) => b,
-        Err(e) => {
-            tracing::error!("Failed to read request body: {}", e);
-            return (StatusCode::BAD_REQUEST, "Failed to read body").into_response();
+    if let Some(sample) = sample {
+        tracing::info!(
+            %sample,
+            %method,
+            url = %privacy.url(&target_url),
+            headers = ?privacy.headers(&original_headers),
+            forwarded = ?privacy.headers(&headers),
+            "Sampled request"
+        );
+    }
+
+    let is_form = original_headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"));
+
+    let body = req.into_body();
+    // Counted against MAX_BUFFERED_BODY_BYTES until the request has been forwarded.
+    let mut buffered = None;
+    let body = if is_form || body.size_hint().exact() == Some(0) {
+        if is_form {
+            let length = body.size_hint().exact().unwrap_or(memory::UNKNOWN_LENGTH);
+            match state.memory.reserve(length).await {
+                Some(reservation) => buffered = Some(reservation),
+                None => {
+                    state
+                        .metrics
+                        .buffer_rejected
+                        .fetch_add(1, Ordering::Relaxed);
+                    tracing::warn!("Buffer limit reached, rejecting a form submission");
+                    return (
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        [(header::RETRY_AFTER, "5")],
+                        "Server busy, try again in a few seconds",
+                    )
+                        .into_response();
+                }
+            }
+        }
+        let body_bytes = match axum::body::to_bytes(body, usize::MAX).await {
+            Ok(b) => b,
+            Err(e) => {
+                tracing::error!("Failed to read request body: {}", e);
+                return (StatusCode::BAD_REQUEST, "Failed to read body").into_response();
+            }
+        };
+        if let Some(reservation) = &mut buffered {
+            reservation.resize(body_bytes.len() as u64);
+        }
+        if is_form {
+            tracing::trace!(
+                body = %privacy.form_body(&String::from_utf8_lossy(&body_bytes)),
+                "Form submission"
+            );
+            if let Some(sample) = sample
+                && let Some(excerpt) = state.config.sampling.excerpt(&body_bytes)
+            {
+                tracing::info!(%sample, body = %privacy.form_body(&excerpt), "Sampled form submission");
+            }
         }
+        reqwest::Body::from(body_bytes)
+    } else {
+        // Uploads are streamed, the client only gets `100 Continue` once the upstream
+        // connection is ready to take the body.
+        if let Some(length) = original_headers.get("content-length") {
+            headers.insert("content-length", length.clone());
+        }
+        reqwest::Body::wrap_stream(body.into_data_stream())
     };
 
     // Send Upstream Request
-    let request_builder = client
-        .request(method, &target_url)
+    let hop_headers = headers.clone();
+    let mut request_builder = client
+        .request(method.clone(), &target_url)
         .headers(headers)
-        .body(body_bytes);
+        .body(body);
+    if let Some(deadline) = deadline {
+        request_builder = request_builder.timeout(deadline.remaining());
+    }
 
-    match request_builder.send().await {
-        Ok(resp) => {
+    let result = match request_builder.send().await {
+        Ok(resp) if state.config.follow_redirects > 0 => {
+            redirects::follow(&state, &method, &hop_headers, deadline, resp).await
+        }
+        result => result.map(|resp| (resp, Vec::new())),
+    };
+
+    match result {
+        Ok((resp, hop_cookies)) if resp.status().is_server_error() => {
+            let offline = if use_snapshot {
+                snapshot::serve_offline(&state, &path_query).await
+            } else {
+                None
+            };
+            match offline {
+                Some(offline) => offline,
+                None => {
+                    process_response(
+                        resp,
+                        &proxy_origin,
+                        &state,
+                        &original_headers,
+                        hop_cookies,
+                        buckets,
+                        sample,
+                    )
+                    .await
+                }
+            }
+        }
+        Ok((resp, hop_cookies)) => {
             process_response(
                 resp,
                 &proxy_origin,
-                is_secure,
-                state.config.disable_warning,
                 &state,
-                &original_headers
+                &original_headers,
+                hop_cookies,
+                buckets,
+                sample,
             )
             .await
         }
         Err(e) => {
+            let e = if privacy.strip_query {
+                e.without_url()
+            } else {
+                e
+            };
             tracing::error!("Upstream request failed: {}", e);
-            (StatusCode::BAD_GATEWAY, format!("Proxy Error: {}", e)).into_response()
+            if use_snapshot
+                && let Some(offline) = snapshot::serve_offline(&state, &path_query).await
+            {
+                return offline;
+            }
+            let status = if e.is_timeout() {
+                StatusCode::GATEWAY_TIMEOUT
+            } else {
+                StatusCode::BAD_GATEWAY
+            };
+            (status, format!("Proxy Error: {}", e)).into_response()
         }
     }
 }
 
 /// Processes the upstream response
-async fn process_response(
+pub async fn process_response(
     resp: reqwest::Response,
     proxy_origin: &str,
-    is_secure: bool,
-    disable_warning: bool,
     state: &AppState,
-    original_request: &HeaderMap
+    original_request: &HeaderMap,
+    hop_cookies: Vec<HeaderValue>,
+    buckets: Vec<Arc<Bucket>>,
+    sample: Option<Sample>,
 ) -> Response {
+    if !state.config.header_limits.response_allowed(resp.headers()) {
+        tracing::warn!(
+            "Upstream response has too many or too large headers ({})",
+            resp.headers().len()
+        );
+        return (
+            StatusCode::BAD_GATEWAY,
+            "Upstream response headers too large",
+        )
+            .into_response();
+    }
+
     let status = resp.status();
+    let resp_version = resp.version();
+    state.metrics.upstream_response(resp_version);
+    let path = resp.url().path().to_string();
+    let is_secure = utils::is_secure_origin(proxy_origin);
     let mut headers = HeaderMap::new();
 
-    for (key, value) in resp.headers() {
-        if key == "set-cookie" {
-            if let Ok(str_val) = value.to_str() {
-                let new_val = utils::process_cookie(str_val, is_secure);
-                if let Ok(v) = HeaderValue::from_str(&new_val) {
-                    headers.append(key, v);
-                }
-            } else {
-                headers.append(key, value.clone());
-            }
-        } else if key == "location" {
-            if let Ok(str_val) = value.to_str() {
-                let new_val =
-                    utils::rewrite_content_urls(str_val.to_string(), proxy_origin, &state);
+    let upstream = state.upstream();
+    let canteen = jidelna::applies(&upstream);
+    let canteen_session = canteen
+        && jidelna::has_session(
+            original_request,
+            hop_cookies
+                .iter()
+                .chain(resp.headers().get_all("set-cookie")),
+        );
 
-                let new_val = if new_val.is_empty() {
-                    "/".to_string()
-                } else {
-                    new_val
+    // Cookies of followed redirects go first, so the final response can override them.
+    let hop_cookies = hop_cookies.iter().map(|v| (&header::SET_COOKIE, v));
+    headers::copy(
+        hop_cookies.chain(resp.headers()),
+        &mut headers,
+        |key, value| {
+            if key == "set-cookie" {
+                // Cookies of a related host would apply to the whole proxy origin.
+                if upstream.external {
+                    return None;
+                }
+                let Ok(str_val) = value.to_str() else {
+                    return Some(value.clone());
                 };
-
-                if let Ok(v) = HeaderValue::from_str(&new_val) {
-                    headers.append(key, v);
-                } else {
-                    headers.append(key, value.clone());
+                let mut new_val = utils::process_cookie(str_val, is_secure, &state.config.cookies);
+                if canteen {
+                    new_val = jidelna::map_cookie_path(&new_val, &upstream);
                 }
+                HeaderValue::from_str(&new_val).ok()
+            } else if matches!(
+                key.as_str(),
+                "location" | "content-location" | "link" | "refresh"
+            ) {
+                let Ok(str_val) = value.to_str() else {
+                    return Some(value.clone());
+                };
+                let str_val = if canteen_session && key == "location" {
+                    jidelna::strip_session_id(str_val)
+                } else {
+                    str_val.into()
+                };
+                let str_val = str_val.as_ref();
+                let new_val = match key.as_str() {
+                    "link" => utils::rewrite_link(str_val, proxy_origin, state),
+                    "refresh" => utils::rewrite_refresh(str_val, proxy_origin, state),
+                    _ => utils::rewrite_location(str_val, proxy_origin, state),
+                };
+                Some(HeaderValue::from_str(&new_val).unwrap_or_else(|_| value.clone()))
+            } else if key == "content-disposition" {
+                Some(downloads::content_disposition(value))
             } else {
-                headers.append(key, value.clone());
+                Some(value.clone())
             }
+        },
+    );
+
+    service_worker::rewrite_headers(&mut headers, proxy_origin, state);
+
+    if let Some(origin) = original_request.get("origin")
+        && let Ok(origin_str) = origin.to_str()
+    {
+        headers.insert(
+            "access-control-allow-origin",
+            HeaderValue::from_str(origin_str).unwrap_or_else(|_| HeaderValue::from_static("")),
+        );
+        headers.insert(
+            "access-control-allow-credentials",
+            HeaderValue::from_static("true"),
+        );
+        headers::add_vary(&mut headers, "Origin");
+    }
+
+    via::append(&mut headers, resp_version, &state.config.via.name);
+
+    for rule in &state.config.response_headers {
+        if rule.append {
+            headers.append(&rule.name, rule.value.clone());
         } else {
-            headers.append(key, value.clone());
+            headers.insert(&rule.name, rule.value.clone());
         }
     }
+    if let Some(cache_control) = &state.upstream().cache_control {
+        headers.insert("cache-control", cache_control.clone());
+    }
 
-    if let Some(origin) = original_request.get("origin") {
-        if let Ok(origin_str) = origin.to_str() {
-            headers.insert(
-                "access-control-allow-origin",
-                HeaderValue::from_str(origin_str).unwrap_or_else(|_| HeaderValue::from_static("")),
-            );
-            headers.insert(
-                "access-control-allow-credentials",
-                HeaderValue::from_static("true"),
-            );
-            headers.insert("vary", HeaderValue::from_static("Origin"));
-        }
+    let privacy = &state.config.privacy;
+    if let Some(sample) = sample {
+        tracing::info!(
+            %sample,
+            %status,
+            url = %privacy.url(resp.url().as_str()),
+            upstream = ?privacy.headers(resp.headers()),
+            headers = ?privacy.headers(&headers),
+            "Sampled response"
+        );
     }
 
     let content_type = headers
@@ -173,19 +409,89 @@ async fn process_response(
         .unwrap_or("")
         .to_string();
 
-    let should_rewrite_body = content_type.contains("text/html")
-        || content_type.contains("application/javascript")
-        || content_type.contains("application/json")
-        || content_type.contains("text/css");
+    if state.config.error_pages
+        && error_page::replaces(status)
+        && content_type.contains("text/html")
+    {
+        let page = error_page::render(
+            status,
+            resp.url().as_str(),
+            state.config.search.is_some(),
+            original_request,
+        );
+        headers.remove("content-length");
+        headers.remove("transfer-encoding");
+        headers.remove("content-encoding");
+        headers.insert(
+            "content-type",
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+
+        let mut response = Response::new(Body::from(page));
+        *response.status_mut() = status;
+        *response.headers_mut() = headers;
+        return response;
+    }
+
+    // Error and challenge bodies are passed through unless configured otherwise,
+    // downloads and `no-transform` responses always are.
+    let should_rewrite_body = state.config.rewrite_statuses.contains(status)
+        && !downloads::is_attachment(&headers)
+        && (state.config.ignore_no_transform || !is_no_transform(resp.headers()))
+        && (state.config.rewrite_content_types.contains(&content_type)
+            || service_worker::is_manifest(&content_type, &path));
 
+    // The original and the rewritten body are both held in memory.
+    let mut buffered = None;
     if should_rewrite_body {
+        let length = resp.content_length().unwrap_or(memory::UNKNOWN_LENGTH);
+        buffered = state.memory.try_reserve(2 * length);
+        if buffered.is_none() {
+            state.metrics.buffer_skipped.fetch_add(1, Ordering::Relaxed);
+            tracing::debug!(
+                "Buffer limit reached, passing {} through without rewriting",
+                privacy.url(resp.url().as_str())
+            );
+        }
+    }
+
+    if let Some(mut buffered) = buffered {
         match resp.bytes().await {
             Ok(bytes) => {
-                let body_str = String::from_utf8_lossy(&bytes).to_string();
-                let mut new_body_str = utils::rewrite_content_urls(body_str, proxy_origin, &state);
+                // The origin comes from the Host header, escape it so XML stays well-formed.
+                let mut new_body = if is_xml(&content_type) {
+                    let origin = utils::escape_xml(proxy_origin);
+                    utils::rewrite_content_bytes(&bytes, &origin, state)
+                } else {
+                    utils::rewrite_content_bytes(&bytes, proxy_origin, state)
+                };
+
+                if content_type.contains("text/html") && !state.config.trackers.is_empty() {
+                    new_body = trackers::strip(&new_body, &state.config.trackers);
+                }
+
+                if content_type.contains("text/html") && upstream.banner {
+                    inject_banner(&mut new_body, state);
+                }
+                if content_type.contains("text/html") && state.config.dark_mode {
+                    dark_mode::inject(&mut new_body);
+                }
+                if content_type.contains("text/html") && state.config.pwa.is_some() {
+                    pwa::inject(&mut new_body);
+                }
+                if content_type.contains("text/html")
+                    && let Some(inject) = &state.config.inject
+                {
+                    inject.apply(&mut new_body);
+                }
 
-                if content_type.contains("text/html") && !disable_warning {
-                    inject_banner(&mut new_body_str, state);
+                if let Some(sample) = sample
+                    && let (Some(before), Some(after)) = (
+                        state.config.sampling.excerpt(&bytes),
+                        state.config.sampling.excerpt(&new_body),
+                    )
+                {
+                    tracing::info!(%sample, %before, %after, "Sampled body rewrite");
                 }
 
                 // Remove headers that are invalid after modification
@@ -193,7 +499,8 @@ async fn process_response(
                 headers.remove("transfer-encoding");
                 headers.remove("content-encoding");
 
-                let mut response = Response::new(Body::from(new_body_str));
+                buffered.resize(new_body.len() as u64);
+                let mut response = Response::new(buffered.hold(Body::from(new_body)));
                 *response.status_mut() = status;
                 *response.headers_mut() = headers;
                 response
@@ -205,7 +512,7 @@ async fn process_response(
         }
     } else {
         // Stream binary content directly
-        let body = Body::from_stream(resp.bytes_stream());
+        let body = bandwidth::limit(Body::from_stream(resp.bytes_stream()), buckets);
         let mut response = Response::new(body);
         *response.status_mut() = status;
         *response.headers_mut() = headers;
@@ -213,18 +520,173 @@ async fn process_response(
     }
 }
 
-fn inject_banner(body: &mut String, state: &AppState) {
-    let insert_pos = body.match_indices('<').find_map(|(idx, _)| {
-        if body[idx..].len() >= 5 && body[idx + 1..idx + 5].eq_ignore_ascii_case("body") {
-            body[idx..].find('>').map(|offset| idx + offset + 1)
+/// Whether a content type is XML: SVG, sitemaps, RSS and Atom feeds.
+fn is_xml(content_type: &str) -> bool {
+    content_type.contains("/xml") || content_type.contains("+xml")
+}
+
+/// Whether the upstream forbids modifying the body (`Cache-Control: no-transform`).
+fn is_no_transform(headers: &HeaderMap) -> bool {
+    headers
+        .get_all("cache-control")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"))
+}
+
+pub fn inject_banner(body: &mut Vec<u8>, state: &AppState) {
+    let insert_pos = memchr::memchr_iter(b'<', body).find_map(|idx| {
+        if body[idx..].len() >= 5 && body[idx + 1..idx + 5].eq_ignore_ascii_case(b"body") {
+            memchr::memchr(b'>', &body[idx..]).map(|offset| idx + offset + 1)
         } else {
             None
         }
     });
 
-    if let Some(pos) = insert_pos {
-        body.insert_str(pos, &BANNER_HTML.replace("$url", &state.config.mode.url()));
-    } else {
-        body.insert_str(0, &BANNER_HTML.replace("$url", &state.config.mode.url()));
+    let banner = BANNER_HTML.replace("$url", &state.upstream().base);
+    let pos = insert_pos.unwrap_or(0);
+    body.splice(pos..pos, banner.into_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::config::{Config, Mode, Upstream};
+
+    fn state() -> AppState {
+        let upstream = Upstream::new(Mode::SPSEJECNA, &[]).unwrap();
+        AppState::new(Arc::new(Config::from_env()), upstream)
+    }
+
+    /// Forwards a captured upstream response through [`process_response`].
+    async fn forward(headers: &[(&str, &[u8])], body: &'static [u8]) -> Response {
+        let mut upstream = axum::http::Response::builder().status(200);
+        for (name, value) in headers {
+            upstream = upstream.header(*name, HeaderValue::from_bytes(value).unwrap());
+        }
+        let resp = reqwest::Response::from(upstream.body(body).unwrap());
+        process_response(
+            resp,
+            "https://jecna.example.org",
+            &state(),
+            &HeaderMap::new(),
+            Vec::new(),
+            Vec::new(),
+            None,
+        )
+        .await
+    }
+
+    async fn body(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn repeated_headers_are_all_forwarded() {
+        let response = forward(
+            &[
+                ("content-type", b"image/png"),
+                ("set-cookie", b"a=1; Path=/"),
+                ("set-cookie", b"b=2; Path=/"),
+                ("link", b"<https://www.spsejecna.cz/a.css>; rel=preload"),
+                ("link", b"</b.js>; rel=preload"),
+                ("vary", b"Accept-Encoding"),
+                ("vary", b"Cookie"),
+            ],
+            b"",
+        )
+        .await;
+
+        let values = |name| {
+            response
+                .headers()
+                .get_all(name)
+                .iter()
+                .map(|v| v.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        let cookies = values("set-cookie");
+        assert_eq!(cookies.len(), 2);
+        assert!(cookies[0].starts_with("a=1") && cookies[1].starts_with("b=2"));
+        assert_eq!(
+            values("link"),
+            [
+                "<https://jecna.example.org/a.css>; rel=preload",
+                "</b.js>; rel=preload"
+            ]
+        );
+        assert_eq!(values("vary"), ["Accept-Encoding", "Cookie"]);
+    }
+
+    #[tokio::test]
+    async fn pdf_download_passes_through() {
+        let pdf: &[u8] = b"%PDF-1.4\n1 0 obj <</URI (https://www.spsejecna.cz/)>> endobj\n%%EOF";
+        let disposition =
+            "attachment; filename=\"rozvrh.pdf\"; filename*=UTF-8''rozvrh%20t%C5%99%C3%ADdy.pdf";
+        let response = forward(
+            &[
+                ("content-type", b"application/pdf"),
+                ("content-disposition", disposition.as_bytes()),
+                ("content-length", pdf.len().to_string().as_bytes()),
+            ],
+            pdf,
+        )
+        .await;
+
+        assert_eq!(
+            response.headers()["content-disposition"].as_bytes(),
+            disposition.as_bytes()
+        );
+        assert_eq!(
+            response.headers()["content-length"],
+            pdf.len().to_string().as_str()
+        );
+        assert_eq!(body(response).await, pdf);
+    }
+
+    #[tokio::test]
+    async fn raw_utf8_filename_is_kept() {
+        let disposition = "inline; filename=\"Školní řád.pdf\"".as_bytes();
+        let response = forward(
+            &[
+                ("content-type", b"application/pdf"),
+                ("content-disposition", disposition),
+            ],
+            b"%PDF-1.4",
+        )
+        .await;
+
+        let forwarded = &response.headers()["content-disposition"];
+        assert!(forwarded.as_bytes().starts_with(disposition));
+        assert_eq!(
+            downloads::filename(forwarded).as_deref(),
+            Some("Školní řád.pdf")
+        );
+    }
+
+    #[tokio::test]
+    async fn attachments_are_not_rewritten() {
+        let json: &[u8] = br#"{"url":"https://www.spsejecna.cz/akce/1"}"#;
+        let response = forward(
+            &[
+                ("content-type", b"application/json"),
+                ("content-disposition", b"attachment; filename=\"akce.json\""),
+            ],
+            json,
+        )
+        .await;
+        assert_eq!(body(response).await, json);
+
+        let response = forward(&[("content-type", b"application/json")], json).await;
+        assert_eq!(
+            body(response).await,
+            br#"{"url":"https://jecna.example.org/akce/1"}"#
+        );
     }
 }
@@ -0,0 +1,189 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Copying and merging of repeated header fields.
+//!
+//! A header may occur several times (`Set-Cookie`, `Link`, `Vary`, ...) and
+//! every occurrence has to be forwarded, in order. `HeaderMap::insert` and
+//! `HeaderMap::get` only see one of them, so header handling that is not a
+//! plain replacement goes through the functions here.
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue, header};
+
+/// Appends every field of `src` to `dst`, in order, passing each through `rewrite`.
+///
+/// Fields for which `rewrite` returns `None` are dropped.
+pub fn copy<'a>(
+    src: impl IntoIterator<Item = (&'a HeaderName, &'a HeaderValue)>,
+    dst: &mut HeaderMap,
+    mut rewrite: impl FnMut(&HeaderName, &HeaderValue) -> Option<HeaderValue>,
+) {
+    for (name, value) in src {
+        if let Some(value) = rewrite(name, value) {
+            dst.append(name, value);
+        }
+    }
+}
+
+/// Joins multiple `Cookie` fields into one.
+///
+/// HTTP/2 and HTTP/3 clients may send every cookie in its own field, HTTP/1.1
+/// servers expect a single one (RFC 9113, section 8.2.3).
+pub fn merge_cookies(headers: &mut HeaderMap) {
+    let values: Vec<&[u8]> = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .map(HeaderValue::as_bytes)
+        .collect();
+    if values.len() < 2 {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_bytes(&values.join(&b"; "[..])) {
+        headers.insert(header::COOKIE, value);
+    }
+}
+
+/// Connection-specific fields that are never forwarded (RFC 9110, section 7.6.1).
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Removes the connection-specific fields, including the ones named by every
+/// `Connection` field.
+pub fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(*name);
+    }
+}
+
+/// Adds `name` to `Vary`, keeping the values already there.
+pub fn add_vary(headers: &mut HeaderMap, name: &'static str) {
+    let listed = headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .any(|v| v == "*" || v.eq_ignore_ascii_case(name));
+    if !listed {
+        headers.append(header::VARY, HeaderValue::from_static(name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(fields: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in fields {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn values<'a>(headers: &'a HeaderMap, name: &str) -> Vec<&'a str> {
+        headers
+            .get_all(name)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn copy_keeps_every_field_in_order() {
+        let src = map(&[
+            ("set-cookie", "a=1"),
+            ("link", "</a.css>; rel=preload"),
+            ("set-cookie", "b=2"),
+            ("x-drop", "1"),
+            ("link", "</b.js>; rel=preload"),
+        ]);
+        let mut dst = map(&[("set-cookie", "hop=0")]);
+        copy(&src, &mut dst, |name, value| {
+            (name != "x-drop").then(|| value.clone())
+        });
+
+        assert_eq!(values(&dst, "set-cookie"), ["hop=0", "a=1", "b=2"]);
+        assert_eq!(
+            values(&dst, "link"),
+            ["</a.css>; rel=preload", "</b.js>; rel=preload"]
+        );
+        assert!(!dst.contains_key("x-drop"));
+    }
+
+    #[test]
+    fn cookie_fields_are_merged() {
+        let mut headers = map(&[("cookie", "a=1"), ("cookie", "b=2; c=3")]);
+        merge_cookies(&mut headers);
+        assert_eq!(values(&headers, "cookie"), ["a=1; b=2; c=3"]);
+
+        let mut headers = map(&[("cookie", "a=1")]);
+        merge_cookies(&mut headers);
+        assert_eq!(values(&headers, "cookie"), ["a=1"]);
+    }
+
+    #[test]
+    fn hop_by_hop_fields_are_stripped() {
+        let mut headers = map(&[
+            ("connection", "keep-alive, X-Internal"),
+            ("connection", "Upgrade"),
+            ("keep-alive", "timeout=5"),
+            ("x-internal", "1"),
+            ("te", "trailers"),
+            ("upgrade", "websocket"),
+            ("proxy-connection", "keep-alive"),
+            ("transfer-encoding", "chunked"),
+            ("accept", "text/html"),
+            ("cookie", "a=1"),
+        ]);
+        strip_hop_by_hop(&mut headers);
+
+        let names: Vec<&str> = headers.keys().map(HeaderName::as_str).collect();
+        assert_eq!(names, ["accept", "cookie"]);
+    }
+
+    #[test]
+    fn vary_is_extended() {
+        let mut headers = map(&[("vary", "Accept-Encoding"), ("vary", "Cookie")]);
+        add_vary(&mut headers, "Origin");
+        assert_eq!(
+            values(&headers, "vary"),
+            ["Accept-Encoding", "Cookie", "Origin"]
+        );
+
+        let mut headers = map(&[("vary", "Accept-Encoding, origin")]);
+        add_vary(&mut headers, "Origin");
+        assert_eq!(values(&headers, "vary"), ["Accept-Encoding, origin"]);
+
+        let mut headers = map(&[("vary", "*")]);
+        add_vary(&mut headers, "Origin");
+        assert_eq!(values(&headers, "vary"), ["*"]);
+    }
+}
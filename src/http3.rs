@@ -0,0 +1,217 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Optional HTTP/3 (QUIC) listener.
+//!
+//! Serves the same router as the TCP listener over UDP and advertises itself
+//! with `Alt-Svc` on every response. Requires building with the `http3`
+//! feature; QUIC always needs a certificate (see [`crate::tls`]), even behind
+//! a TLS-terminating reverse proxy.
+
+use std::sync::Arc;
+
+use axum::Router;
+use serde::Serialize;
+
+use crate::{config, tls::CertResolver};
+
+/// HTTP/3 listener settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct Http3Config {
+    /// UDP port to listen on.
+    pub port: u16,
+    /// Port advertised in `Alt-Svc`, differs from `port` behind port forwarding.
+    pub advertised_port: u16,
+}
+
+impl Http3Config {
+    /// # Environment Variables
+    /// * `HTTP3_PORT` - UDP port of the HTTP/3 listener. HTTP/3 is disabled when unset.
+    /// * `HTTP3_ADVERTISED_PORT` - Port announced in `Alt-Svc` (default: `HTTP3_PORT`).
+    pub fn from_env() -> Option<Self> {
+        let port: u16 = config::env_parse("HTTP3_PORT")?;
+        Some(Self {
+            port,
+            advertised_port: config::env_parse("HTTP3_ADVERTISED_PORT").unwrap_or(port),
+        })
+    }
+}
+
+/// Starts the HTTP/3 listener, returning `app` with the `Alt-Svc` header added.
+///
+/// If the listener can't be started the error is logged and `app` is returned
+/// unchanged, the TCP listener keeps working either way.
+#[cfg(feature = "http3")]
+pub fn start(app: Router, config: &Http3Config, certs: &Arc<CertResolver>) -> Router {
+    use axum::{http::HeaderValue, middleware::map_response, response::Response};
+
+    let endpoint = match quic::endpoint(config, certs) {
+        Ok(endpoint) => endpoint,
+        Err(e) => {
+            tracing::error!("Failed to start the HTTP/3 listener: {}", e);
+            return app;
+        }
+    };
+    tracing::info!("HTTP/3 listening on udp://0.0.0.0:{}", config.port);
+    tokio::spawn(quic::accept(endpoint, app.clone()));
+
+    let alt_svc = HeaderValue::from_str(&format!("h3=\":{}\"; ma=86400", config.advertised_port))
+        .expect("Alt-Svc value is valid");
+    app.layer(map_response(move |mut response: Response| {
+        let alt_svc = alt_svc.clone();
+        async move {
+            response.headers_mut().insert("alt-svc", alt_svc);
+            response
+        }
+    }))
+}
+
+#[cfg(not(feature = "http3"))]
+pub fn start(app: Router, _config: &Http3Config, _certs: &Arc<CertResolver>) -> Router {
+    tracing::warn!("HTTP3_PORT is set but jecnaproxy was built without the http3 feature");
+    app
+}
+
+#[cfg(feature = "http3")]
+mod quic {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use axum::{
+        Router,
+        body::{Body, Bytes},
+        extract::{ConnectInfo, Request},
+        http::{self, HeaderValue, header},
+    };
+    use bytes::BufMut;
+    use h3::{quic::BidiStream, server::RequestStream};
+    use http_body_util::BodyExt;
+    use quinn::crypto::rustls::QuicServerConfig;
+    use tower::ServiceExt;
+
+    use super::Http3Config;
+    use crate::tls::{CertResolver, Https};
+
+    /// Connection-specific headers, forbidden in HTTP/3.
+    const HOP_BY_HOP: &[&str] = &[
+        "connection",
+        "transfer-encoding",
+        "upgrade",
+        "keep-alive",
+        "proxy-connection",
+    ];
+
+    pub fn endpoint(
+        config: &Http3Config,
+        certs: &Arc<CertResolver>,
+    ) -> Result<quinn::Endpoint, String> {
+        let tls = certs.server_config(&[&rustls::version::TLS13], &[b"h3"])?;
+        let crypto = QuicServerConfig::try_from(tls).map_err(|e| e.to_string())?;
+        let server = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+        quinn::Endpoint::server(server, SocketAddr::from(([0, 0, 0, 0], config.port)))
+            .map_err(|e| e.to_string())
+    }
+
+    pub async fn accept(endpoint: quinn::Endpoint, app: Router) {
+        while let Some(incoming) = endpoint.accept().await {
+            let app = app.clone();
+            tokio::spawn(async move {
+                let remote = incoming.remote_address();
+                let result = match incoming.await {
+                    Ok(conn) => connection(conn, remote, app).await,
+                    Err(e) => Err(e.to_string()),
+                };
+                if let Err(e) = result {
+                    tracing::debug!("HTTP/3 connection from {} failed: {}", remote, e);
+                }
+            });
+        }
+    }
+
+    async fn connection(
+        conn: quinn::Connection,
+        remote: SocketAddr,
+        app: Router,
+    ) -> Result<(), String> {
+        let mut conn = h3::server::Connection::new(h3_quinn::Connection::new(conn))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        loop {
+            let resolver = match conn.accept().await {
+                Ok(Some(resolver)) => resolver,
+                Ok(None) => return Ok(()),
+                Err(e) if e.is_h3_no_error() => return Ok(()),
+                Err(e) => return Err(e.to_string()),
+            };
+            let app = app.clone();
+            tokio::spawn(async move {
+                let result = match resolver.resolve_request().await {
+                    Ok((req, stream)) => request(req, stream, remote, app).await,
+                    Err(e) => Err(e.to_string()),
+                };
+                if let Err(e) = result {
+                    tracing::debug!("HTTP/3 request from {} failed: {}", remote, e);
+                }
+            });
+        }
+    }
+
+    async fn request<S>(
+        req: http::Request<()>,
+        mut stream: RequestStream<S, Bytes>,
+        remote: SocketAddr,
+        app: Router,
+    ) -> Result<(), String>
+    where
+        S: BidiStream<Bytes>,
+    {
+        let mut body = Vec::new();
+        while let Some(chunk) = stream.recv_data().await.map_err(|e| e.to_string())? {
+            body.put(chunk);
+        }
+
+        let (mut parts, ()) = req.into_parts();
+        // HTTP/3 carries the host in `:authority`, the handlers expect `Host`.
+        if !parts.headers.contains_key(header::HOST)
+            && let Some(authority) = parts.uri.authority()
+            && let Ok(host) = HeaderValue::from_str(authority.as_str())
+        {
+            parts.headers.insert(header::HOST, host);
+        }
+        let mut req = Request::from_parts(parts, Body::from(body));
+        req.extensions_mut().insert(ConnectInfo(remote));
+        req.extensions_mut().insert(Https);
+
+        let response = match app.oneshot(req).await {
+            Ok(response) => response,
+            Err(e) => match e {},
+        };
+        let (mut parts, mut body) = response.into_parts();
+        for name in HOP_BY_HOP {
+            parts.headers.remove(*name);
+        }
+
+        stream
+            .send_response(http::Response::from_parts(parts, ()))
+            .await
+            .map_err(|e| e.to_string())?;
+        while let Some(frame) = body.frame().await {
+            let frame = frame.map_err(|e| e.to_string())?;
+            if let Ok(data) = frame.into_data() {
+                stream.send_data(data).await.map_err(|e| e.to_string())?;
+            }
+        }
+        stream.finish().await.map_err(|e| e.to_string())
+    }
+}
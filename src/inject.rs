@@ -0,0 +1,89 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Operator-provided HTML snippets injected into proxied pages.
+//!
+//! Meant for custom styling, a feedback widget or a self-hosted analytics
+//! script. Snippet files are read once at startup.
+
+use memchr::memmem;
+use serde::Serialize;
+
+use crate::config;
+
+/// Snippets added to every proxied HTML page.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InjectConfig {
+    /// Inserted before `</head>`.
+    pub head: String,
+    /// Inserted before `</body>`.
+    pub body_end: String,
+}
+
+impl InjectConfig {
+    /// # Environment Variables
+    /// * `INJECT_HEAD` - HTML inserted before `</head>`.
+    /// * `INJECT_HEAD_FILE` - Comma-separated files appended to `INJECT_HEAD`.
+    /// * `INJECT_BODY_END` - HTML inserted before `</body>`.
+    /// * `INJECT_BODY_END_FILE` - Comma-separated files appended to `INJECT_BODY_END`.
+    pub fn from_env() -> Option<Self> {
+        let config = Self {
+            head: snippet("INJECT_HEAD", "INJECT_HEAD_FILE"),
+            body_end: snippet("INJECT_BODY_END", "INJECT_BODY_END_FILE"),
+        };
+        (!config.head.is_empty() || !config.body_end.is_empty()).then_some(config)
+    }
+
+    /// Inserts the snippets into an HTML page.
+    pub fn apply(&self, body: &mut Vec<u8>) {
+        if !self.head.is_empty() {
+            before_head_end(body, self.head.as_bytes());
+        }
+        if !self.body_end.is_empty() {
+            before_body_end(body, self.body_end.as_bytes());
+        }
+    }
+}
+
+fn snippet(var: &str, file_var: &str) -> String {
+    let mut snippet = std::env::var(var).unwrap_or_default();
+    for path in config::env_list(file_var) {
+        match std::fs::read_to_string(&path) {
+            Ok(content) => snippet.push_str(&content),
+            Err(e) => tracing::error!("Failed to read {} {}: {}", file_var, path, e),
+        }
+    }
+    snippet
+}
+
+/// Inserts `snippet` before the first `</head>`, or at the start of pages without one.
+pub fn before_head_end(body: &mut Vec<u8>, snippet: &[u8]) {
+    let pos = memmem::find_iter(body, b"</")
+        .find(|&idx| is_closing(body, idx, b"head"))
+        .unwrap_or(0);
+    body.splice(pos..pos, snippet.iter().copied());
+}
+
+/// Inserts `snippet` before the last `</body>`, or at the end of pages without one.
+pub fn before_body_end(body: &mut Vec<u8>, snippet: &[u8]) {
+    let pos = memmem::rfind_iter(body, b"</")
+        .find(|&idx| is_closing(body, idx, b"body"))
+        .unwrap_or(body.len());
+    body.splice(pos..pos, snippet.iter().copied());
+}
+
+fn is_closing(body: &[u8], idx: usize, name: &[u8]) -> bool {
+    body.get(idx + 2..idx + 2 + name.len())
+        .is_some_and(|tag| tag.eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,224 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Login flow of the iCanteen canteen system (`strav.nasejidelna.cz`).
+//!
+//! iCanteen keeps the session in a `JSESSIONID` cookie limited to the
+//! canteen's path (`Path=/0341`), and until it knows the browser keeps
+//! cookies it also puts the session into redirect URLs
+//! (`main.jsp;jsessionid=...?terminal=false`). Through the proxy that breaks
+//! logins in two ways, fixed here for the JIDELNA mode and upstreams on
+//! `nasejidelna.cz`:
+//!
+//! * Cookie paths are mapped to where the canteen is served on the proxy:
+//!   under the prefix of a named upstream, without the path of an upstream
+//!   URL like `https://strav.nasejidelna.cz/0341`. Otherwise the browser
+//!   never sends the session back.
+//! * `;jsessionid=` is removed from redirects once the session cookie is in
+//!   place, so the token doesn't end up in the address bar, history or
+//!   `Referer` headers, and the session stays tied to the cookie.
+
+use std::borrow::Cow;
+
+use axum::http::{HeaderMap, HeaderValue};
+
+use crate::config::{Mode, Upstream};
+
+const SESSION_COOKIE: &str = "JSESSIONID";
+const SESSION_PARAM: &str = ";jsessionid=";
+
+/// Whether `upstream` is an iCanteen instance.
+pub fn applies(upstream: &Upstream) -> bool {
+    matches!(upstream.mode, Mode::JIDELNA)
+        || upstream
+            .url
+            .host_str()
+            .is_some_and(|host| host == "nasejidelna.cz" || host.ends_with(".nasejidelna.cz"))
+}
+
+/// Whether the browser has or is given a session cookie, checking the
+/// request's `Cookie` and the response's `Set-Cookie` headers.
+pub fn has_session<'a>(
+    request: &HeaderMap,
+    set_cookies: impl IntoIterator<Item = &'a HeaderValue>,
+) -> bool {
+    let named = |pair: &str| {
+        pair.trim()
+            .split_once('=')
+            .is_some_and(|(name, value)| name == SESSION_COOKIE && !value.is_empty())
+    };
+
+    request
+        .get_all("cookie")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|v| v.split(';').any(named))
+        || set_cookies
+            .into_iter()
+            .filter_map(|v| v.to_str().ok())
+            .any(|v| v.split(';').next().is_some_and(named))
+}
+
+/// Removes `;jsessionid=...` from the path of a redirect URL.
+pub fn strip_session_id(location: &str) -> Cow<'_, str> {
+    let Some(start) = location.to_ascii_lowercase().find(SESSION_PARAM) else {
+        return Cow::Borrowed(location);
+    };
+    let end = location[start + 1..]
+        .find([';', '?', '#', '/'])
+        .map_or(location.len(), |i| start + 1 + i);
+    Cow::Owned(format!("{}{}", &location[..start], &location[end..]))
+}
+
+/// Maps the `Path` of a processed `Set-Cookie` value to the proxy, see the module docs.
+pub fn map_cookie_path(cookie: &str, upstream: &Upstream) -> String {
+    let base = upstream.url.path().trim_end_matches('/');
+    if base.is_empty() && upstream.prefix.is_empty() {
+        return cookie.to_string();
+    }
+
+    cookie
+        .split("; ")
+        .enumerate()
+        .map(|(i, part)| {
+            let Some((name, path)) = part.split_once('=').filter(|_| i > 0) else {
+                return part.to_string();
+            };
+            if !name.eq_ignore_ascii_case("path") {
+                return part.to_string();
+            }
+            // Paths outside the upstream URL can't be reached through the proxy anyway.
+            let rest = match path.strip_prefix(base) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+                _ => return part.to_string(),
+            };
+            let mapped = match (upstream.prefix.as_str(), rest) {
+                ("", "") => "/".to_string(),
+                (prefix, "/") if !prefix.is_empty() => prefix.to_string(),
+                (prefix, rest) => format!("{}{}", prefix, rest),
+            };
+            format!("{}={}", name, mapped)
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upstream(url: &str, prefix: &str) -> Upstream {
+        let mode = match url {
+            "jidelna" => Mode::JIDELNA,
+            url => Mode::CUSTOM(url.to_string()),
+        };
+        let mut upstream = Upstream::new(mode, &[]).unwrap();
+        upstream.prefix = prefix.to_string();
+        upstream
+    }
+
+    #[test]
+    fn session_id_is_stripped() {
+        for (location, expected) in [
+            (
+                "/0341/main.jsp;jsessionid=A1B2?terminal=false",
+                "/0341/main.jsp?terminal=false",
+            ),
+            (
+                "https://strav.nasejidelna.cz/0341/login;JSESSIONID=A1B2#top",
+                "https://strav.nasejidelna.cz/0341/login#top",
+            ),
+            ("/0341/faces;jsessionid=A1B2;x=1", "/0341/faces;x=1"),
+            ("/0341/faces;jsessionid=A1B2/login", "/0341/faces/login"),
+            ("/0341/main.jsp;jsessionid=A1B2", "/0341/main.jsp"),
+        ] {
+            assert_eq!(strip_session_id(location), expected, "{}", location);
+        }
+
+        let location = "/0341/main.jsp?terminal=false";
+        assert!(matches!(strip_session_id(location), Cow::Borrowed(l) if l == location));
+    }
+
+    #[test]
+    fn session_is_detected() {
+        let mut request = HeaderMap::new();
+        assert!(!has_session(&request, []));
+
+        let set_cookie = HeaderValue::from_static("JSESSIONID=A1B2; Path=/0341; HttpOnly");
+        assert!(has_session(&request, [&set_cookie]));
+        let other = HeaderValue::from_static("theme=dark; JSESSIONID=A1B2");
+        assert!(!has_session(&request, [&other]));
+        let cleared = HeaderValue::from_static("JSESSIONID=; Max-Age=0");
+        assert!(!has_session(&request, [&cleared]));
+
+        request.insert(
+            "cookie",
+            HeaderValue::from_static("theme=dark; JSESSIONID="),
+        );
+        assert!(!has_session(&request, []));
+        request.append("cookie", HeaderValue::from_static("JSESSIONID=A1B2"));
+        assert!(has_session(&request, []));
+    }
+
+    #[test]
+    fn cookie_paths_follow_the_proxy() {
+        let cookie = "JSESSIONID=A1B2; Path=/0341; HttpOnly";
+
+        // Served at the root of the proxy with the canteen's own paths.
+        assert_eq!(map_cookie_path(cookie, &upstream("jidelna", "")), cookie);
+
+        // The upstream URL's path is not part of the proxy paths.
+        let instance = upstream("https://strav.nasejidelna.cz/0341", "");
+        assert_eq!(
+            map_cookie_path(cookie, &instance),
+            "JSESSIONID=A1B2; Path=/; HttpOnly"
+        );
+        assert_eq!(
+            map_cookie_path("a=1; path=/0341/faces", &instance),
+            "a=1; path=/faces"
+        );
+        assert_eq!(
+            map_cookie_path("a=1; Path=/03410", &instance),
+            "a=1; Path=/03410"
+        );
+        assert_eq!(
+            map_cookie_path("a=1; Path=/other", &instance),
+            "a=1; Path=/other"
+        );
+
+        // Under a named upstream prefix.
+        let prefixed = upstream("jidelna", "/jidelna");
+        assert_eq!(
+            map_cookie_path(cookie, &prefixed),
+            "JSESSIONID=A1B2; Path=/jidelna/0341; HttpOnly"
+        );
+        assert_eq!(
+            map_cookie_path("a=1; Path=/", &prefixed),
+            "a=1; Path=/jidelna"
+        );
+
+        let both = upstream("https://strav.nasejidelna.cz/0341", "/jidelna");
+        assert_eq!(
+            map_cookie_path(cookie, &both),
+            "JSESSIONID=A1B2; Path=/jidelna; HttpOnly"
+        );
+        assert_eq!(
+            map_cookie_path("a=1; Path=/0341/", &both),
+            "a=1; Path=/jidelna"
+        );
+
+        // The name and value are never taken for an attribute.
+        assert_eq!(map_cookie_path("Path=/0341", &both), "Path=/0341");
+    }
+}
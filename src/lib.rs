@@ -0,0 +1,82 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! A proxy server for `spsejecna.cz`.
+//!
+//! The server itself is started from `main.rs`. The library only exists so
+//! benchmarks and fuzz targets can reach the rewriters.
+
+pub mod admin;
+pub mod api;
+pub mod assets;
+pub mod audit;
+pub mod backup;
+pub mod ban;
+pub mod bandwidth;
+pub mod chaos;
+pub mod check_config;
+pub mod cli;
+pub mod cluster;
+pub mod config;
+pub mod cookies;
+pub mod crawler;
+pub mod daemon;
+pub mod dark_mode;
+pub mod db;
+pub mod deadline;
+pub mod downloads;
+pub mod effective_config;
+pub mod error_page;
+pub mod extract;
+pub mod forward_auth;
+pub mod handlers;
+pub mod headers;
+pub mod http3;
+pub mod inject;
+pub mod jidelna;
+pub mod limits;
+pub mod memory;
+pub mod metrics;
+pub mod notify;
+pub mod privacy;
+pub mod pwa;
+pub mod read_only;
+pub mod redirects;
+pub mod replay;
+pub mod retention;
+pub mod rewrite_diff;
+pub mod runtime;
+pub mod sampling;
+pub mod scheduler;
+pub mod search;
+pub mod server;
+pub mod service_worker;
+pub mod share;
+pub mod slo;
+pub mod snapshot;
+pub mod state;
+pub mod statsd;
+pub mod systemd;
+pub mod throttle;
+pub mod tls;
+pub mod trackers;
+pub mod upstream_client;
+pub mod upstreams;
+pub mod users;
+pub mod utils;
+pub mod validation;
+pub mod vault;
+pub mod version;
+pub mod via;
+pub mod watcher;
@@ -0,0 +1,90 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Size and count limits of request and response headers.
+//!
+//! Requests over the limits are answered with `431 Request Header Fields Too
+//! Large`, upstream responses over them with `502 Bad Gateway`.
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::{config, state::AppState};
+
+/// Header limits.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeaderLimits {
+    /// Maximum total size of the request headers in bytes.
+    pub request_bytes: usize,
+    /// Maximum number of request headers.
+    pub request_count: usize,
+    /// Maximum total size of the upstream response headers in bytes.
+    pub response_bytes: usize,
+    /// Maximum number of upstream response headers.
+    pub response_count: usize,
+}
+
+impl HeaderLimits {
+    /// # Environment Variables
+    /// * `MAX_REQUEST_HEADER_BYTES` - Total size of request headers (default: 32768).
+    /// * `MAX_REQUEST_HEADERS` - Number of request headers (default: 100).
+    /// * `MAX_RESPONSE_HEADER_BYTES` - Total size of upstream response headers (default: 65536).
+    /// * `MAX_RESPONSE_HEADERS` - Number of upstream response headers (default: 100).
+    pub fn from_env() -> Self {
+        Self {
+            request_bytes: config::env_parse("MAX_REQUEST_HEADER_BYTES").unwrap_or(32768),
+            request_count: config::env_parse("MAX_REQUEST_HEADERS").unwrap_or(100),
+            response_bytes: config::env_parse("MAX_RESPONSE_HEADER_BYTES").unwrap_or(65536),
+            response_count: config::env_parse("MAX_RESPONSE_HEADERS").unwrap_or(100),
+        }
+    }
+
+    /// Whether upstream response headers are within the limits.
+    pub fn response_allowed(&self, headers: &HeaderMap) -> bool {
+        headers.len() <= self.response_count && size(headers) <= self.response_bytes
+    }
+}
+
+/// Size of the headers as sent over HTTP/1.1 (`name: value\r\n`).
+fn size(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len() + 4)
+        .sum()
+}
+
+/// Middleware rejecting requests whose headers exceed the limits.
+pub async fn enforce(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let limits = &state.config.header_limits;
+    let headers = req.headers();
+    if headers.len() <= limits.request_count && size(headers) <= limits.request_bytes {
+        return next.run(req).await;
+    }
+
+    tracing::debug!(
+        "Rejecting request with {} headers ({} bytes)",
+        headers.len(),
+        size(headers)
+    );
+    (
+        StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+        "Request header fields too large",
+    )
+        .into_response()
+}
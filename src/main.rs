@@ -12,40 +12,214 @@
  * GNU General Public License for more details.
  */
 
-mod config;
-mod handlers;
-mod state;
-mod utils;
-
-use axum::{Router, http::Method, routing::any};
-use reqwest::Client;
+use axum::{
+    Router,
+    http::Method,
+    middleware,
+    routing::{any, get, post},
+};
+use clap::Parser;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio_rustls::TlsAcceptor;
 use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::config::Config;
-use crate::state::AppState;
+use jecnaproxy::cli::{Cli, Command};
+use jecnaproxy::cluster::Cluster;
+use jecnaproxy::config::Config;
+use jecnaproxy::db::Db;
+use jecnaproxy::state::AppState;
+use jecnaproxy::statsd::{self, Statsd};
+use jecnaproxy::tls::CertResolver;
+use jecnaproxy::{
+    admin, api, assets, backup, ban, chaos, check_config, daemon, db, deadline, effective_config,
+    forward_auth, handlers, http3, limits, metrics, pwa, read_only, replay, scheduler, server,
+    service_worker, share, slo, snapshot, systemd, users, validation, version, via,
+};
+
+fn main() {
+    let cli = Cli::parse();
+
+    if cli.daemonize && !daemon::is_daemon() {
+        if cli.command.is_some() {
+            eprintln!("--daemonize only applies to running the proxy server");
+            std::process::exit(2);
+        }
+        daemon::daemonize(&cli.pid_file, &cli.log_file);
+    }
 
-#[tokio::main]
-async fn main() {
     tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_ansi(!daemon::is_daemon()))
         .with(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
-    let config = Arc::new(Config::from_env());
+    if let Some(Command::CheckConfig(args)) = &cli.command {
+        // Reads the configuration itself, to report the values it ignores.
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to start the Tokio runtime");
+        runtime.block_on(check_config::run(args, cli.i_know_what_im_doing));
+        return;
+    }
 
-    let client = Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()
-        .expect("Failed to build reqwest client");
+    // Read before the runtime is started, which it configures.
+    let config = Config::from_env();
+    let runtime = match config.runtime.build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            tracing::error!("Failed to start the Tokio runtime: {}", e);
+            std::process::exit(1);
+        }
+    };
+    runtime.block_on(run(cli, Arc::new(config)));
+}
 
-    let state = AppState {
-        client,
-        config: config.clone(),
+/// Runs the selected command, or the proxy server.
+async fn run(cli: Cli, config: Arc<Config>) {
+    match &cli.command {
+        Some(Command::Restore(args)) => {
+            // Runs before the database is opened, which would create it.
+            backup::restore(&config, args).await;
+            return;
+        }
+        Some(Command::GenAlerts(args)) => {
+            slo::gen_alerts(&config.slo, args);
+            return;
+        }
+        Some(Command::Stop(args)) => {
+            daemon::stop(args);
+            return;
+        }
+        Some(Command::PrintConfig) => {
+            effective_config::print(&config);
+            return;
+        }
+        _ => {}
+    }
+
+    let upstream = match config.default_upstream(config.mode.clone()) {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            tracing::error!("Refusing to start: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let routes = match config
+        .upstreams
+        .iter()
+        .map(|spec| spec.route())
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(routes) => routes,
+        Err(e) => {
+            tracing::error!("Refusing to start: {}", e);
+            std::process::exit(1);
+        }
     };
 
+    for upstream in std::iter::once(&upstream).chain(routes.iter().map(|r| &*r.upstream)) {
+        if let Err(e) = config.check_upstream(upstream) {
+            if cli.i_know_what_im_doing {
+                tracing::warn!("Open proxy protection overridden: {}", e);
+            } else {
+                tracing::error!(
+                    "Refusing to start: {}. Add the host to UPSTREAM_ALLOWLIST or pass --i-know-what-im-doing.",
+                    e
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut state = AppState::new(config, upstream);
+    state.routes = Arc::new(routes);
+
+    if let Some(url) = &state.config.redis_url {
+        match Cluster::new(url, &state.config.redis_prefix) {
+            Ok(cluster) => state.cluster = Some(Arc::new(cluster)),
+            Err(e) => {
+                tracing::error!("Invalid REDIS_URL: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(url) = &state.config.database_url {
+        match Db::connect(url).await {
+            Ok(db) => state.db = Some(db),
+            Err(e) => {
+                tracing::error!("Failed to open the database: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(config) = &state.config.statsd {
+        match Statsd::connect(config) {
+            Ok(statsd) => state.statsd = Some(Arc::new(statsd)),
+            Err(e) => {
+                tracing::error!("Failed to set up the StatsD exporter: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if cli.dev_tls {
+        if state.config.tls.is_some() {
+            tracing::warn!("--dev-tls is set, ignoring TLS_CERT_FILE");
+        }
+        match CertResolver::self_signed() {
+            Ok(certs) => state.tls = Some(certs),
+            Err(e) => {
+                tracing::error!("Failed to generate a development certificate: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(tls) = &state.config.tls {
+        match CertResolver::load(tls.clone()) {
+            Ok(certs) => state.tls = Some(certs),
+            Err(e) => {
+                tracing::error!("Failed to load TLS certificates: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    match cli.command {
+        Some(Command::Snapshot(args)) => snapshot::run(&state, args).await,
+        Some(Command::Backup(args)) => backup::backup(&state, args).await,
+        Some(Command::AddUser(args)) => users::add_user(&state, args).await,
+        Some(Command::Replay(args)) => {
+            let app = app(&state);
+            replay::run(&state, app, args).await
+        }
+        Some(
+            Command::Restore(_)
+            | Command::GenAlerts(_)
+            | Command::Stop(_)
+            | Command::CheckConfig(_)
+            | Command::PrintConfig,
+        ) => {
+            unreachable!("handled above")
+        }
+        None => {
+            let https_port = state
+                .config
+                .https_port
+                .or(cli.dev_tls.then_some(DEV_HTTPS_PORT));
+            let pid_file = daemon::is_daemon().then_some(cli.pid_file.as_path());
+            serve(state, https_port, pid_file).await
+        }
+    }
+}
+
+/// HTTPS port of `--dev-tls` when `HTTPS_PORT` is not set.
+const DEV_HTTPS_PORT: u16 = 3443;
+
+/// The proxy's routes and middleware.
+fn app(state: &AppState) -> Router {
     let cors = CorsLayer::new()
         .allow_origin(AllowOrigin::mirror_request())
         .allow_methods([
@@ -60,23 +234,177 @@ async fn main() {
         .allow_headers(AllowHeaders::mirror_request())
         .allow_credentials(true);
 
-    let app = Router::new()
-        .route("/robots.txt", any(handlers::robots_txt_handler))
+    let mut app = Router::new()
         .route("/", any(handlers::proxy_handler))
         .route("/{*path}", any(handlers::proxy_handler))
-        .layer(cors)
-        .with_state(state);
+        .nest("/api", api::router(state))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            service_worker::block,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            chaos::inject_faults,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            forward_auth::authorize,
+        ))
+        .route_layer(middleware::from_fn_with_state(state.clone(), share::verify))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            read_only::enforce,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            via::detect_loop,
+        ))
+        .route_layer(middleware::from_fn_with_state(state.clone(), ban::guard))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            deadline::enforce,
+        ))
+        .route("/robots.txt", any(handlers::robots_txt_handler));
+    if !state.config.rewrite_sitemap {
+        // Search engines shouldn't get a mix of proxy and official URLs.
+        app = app.route("/sitemap.xml", any(handlers::sitemap_handler));
+    }
+
+    app = app
+        .merge(assets::router())
+        .route("/_jecnaproxy/version", get(version::handler));
+    if let Some(pwa) = pwa::router(state) {
+        app = app.nest("/_jecnaproxy/pwa", pwa);
+    }
+
+    if let Some(admin) = admin::router(state) {
+        tracing::info!("Admin API enabled under /_admin");
+        app = app.nest("/_admin", admin);
 
-    let addr_str = format!("0.0.0.0:{}", config.port);
-    let addr: SocketAddr = addr_str
-        .parse()
-        .expect("Invalid address/port configuration");
+        if state.config.share.is_some() {
+            app = app.route(
+                "/_share",
+                post(share::create).route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    admin::require_token,
+                )),
+            );
+        }
+    }
+
+    app.layer(cors)
+        .layer(middleware::from_fn(validation::validate))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            limits::enforce,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            metrics::track,
+        ))
+        // Added after the layers, health checks are not counted.
+        .route("/_jecnaproxy/ping", get(assets::ping))
+        .with_state(state.clone())
+}
+
+/// Runs the proxy server, with a native HTTPS listener on `https_port` if given.
+///
+/// `pid_file` is written once the listeners are open.
+async fn serve(state: AppState, https_port: Option<u16>, pid_file: Option<&std::path::Path>) {
+    let config = state.config.clone();
+
+    if state.config.vault.is_some() && state.db.is_none() {
+        tracing::warn!(
+            "VAULT_KEY is set but DATABASE_URL is not, the credential vault is disabled"
+        );
+    }
+    if state.config.users.is_some() && state.db.is_none() {
+        tracing::error!("USERS_ENABLED requires DATABASE_URL");
+        std::process::exit(1);
+    }
+
+    if let Err(e) = db::restore(&state).await {
+        tracing::error!("Failed to restore state from the database: {}", e);
+    }
 
-    tracing::info!("Proxy listening on http://{}", addr);
+    scheduler::register_jobs(&state);
+    state.scheduler.start(&state);
+    statsd::start(&state);
+
+    let mut app = app(&state);
+    if let Some(certs) = &state.tls {
+        certs.watch();
+    }
+    if let Some(h3) = &config.http3 {
+        match &state.tls {
+            Some(certs) => app = http3::start(app, h3, certs),
+            None => tracing::error!(
+                "HTTP3_PORT requires TLS_CERT_FILE and TLS_KEY_FILE, HTTP/3 is disabled"
+            ),
+        }
+    }
+
+    let mut activated = systemd::activated_listeners().into_iter();
+    let listener = match activated.next() {
+        Some(listener) => tokio::net::TcpListener::from_std(listener).unwrap(),
+        None => {
+            let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+            tokio::net::TcpListener::bind(addr).await.unwrap()
+        }
+    };
+    let https_listener = match activated.next() {
+        Some(listener) => Some(tokio::net::TcpListener::from_std(listener).unwrap()),
+        None => match https_port {
+            Some(port) => {
+                let addr = SocketAddr::from(([0, 0, 0, 0], port));
+                Some(tokio::net::TcpListener::bind(addr).await.unwrap())
+            }
+            None => None,
+        },
+    };
+
+    tracing::info!(
+        "Proxy listening on http://{}",
+        listener.local_addr().unwrap()
+    );
     if let Some(base) = &config.base_url {
         tracing::info!("Public Base URL configured: {}", base);
     }
+    let runtime = tokio::runtime::Handle::current().metrics();
+    tracing::info!("Tokio runtime worker threads: {}", runtime.num_workers());
+    if config.privacy.unredacted {
+        tracing::warn!("LOG_UNREDACTED is set - secrets will be written to logs!");
+    }
+    if let Some(chaos) = &config.chaos {
+        tracing::warn!(
+            "Chaos mode enabled ({:?}) - do not use in production!",
+            chaos
+        );
+    }
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let https = match (&state.tls, https_listener) {
+        (Some(certs), Some(listener)) => {
+            let tls = certs
+                .server_config(rustls::DEFAULT_VERSIONS, &[b"h2", b"http/1.1"])
+                .expect("Invalid TLS configuration");
+            tracing::info!(
+                "Proxy listening on https://{}",
+                listener.local_addr().unwrap()
+            );
+            Some((listener, TlsAcceptor::from(Arc::new(tls))))
+        }
+        (None, Some(_)) => {
+            tracing::error!(
+                "HTTPS_PORT requires TLS_CERT_FILE and TLS_KEY_FILE, HTTPS is disabled"
+            );
+            None
+        }
+        (_, None) => None,
+    };
+
+    systemd::ready();
+    if let Some(path) = pid_file {
+        daemon::write_pid_file(path);
+    }
+    server::serve(listener, https, app, &state).await;
 }
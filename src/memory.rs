@@ -0,0 +1,230 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! A ceiling on the body bytes held in memory at once.
+//!
+//! Most bodies are streamed, but form submissions and rewritten responses are
+//! read whole. During a traffic spike (everyone checking grades at once) those
+//! can add up to more than a small container has. Before buffering, a body's
+//! size is reserved from [`MemoryBudget`]: responses that don't fit are
+//! streamed without rewriting, form submissions wait for memory to be freed
+//! and get `503 Service Unavailable` if it isn't in time.
+//!
+//! The accounting is approximate. Sizes come from `Content-Length`, bodies
+//! without it are counted as [`UNKNOWN_LENGTH`] until they have been read.
+
+use std::{
+    pin::Pin,
+    sync::{Arc, atomic::Ordering},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::body::{Body, Bytes};
+use http_body::{Frame, SizeHint};
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use crate::{config, effective_config, metrics::Metrics};
+
+/// Size assumed for a body without `Content-Length`.
+pub const UNKNOWN_LENGTH: u64 = 256 * 1024;
+
+/// Buffering limits.
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryConfig {
+    /// Maximum body bytes buffered at once, unlimited if not set.
+    pub max_buffered: Option<u64>,
+    /// How long a form submission waits for buffer space.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub queue_timeout: Duration,
+}
+
+impl MemoryConfig {
+    /// # Environment Variables
+    /// * `MAX_BUFFERED_BODY_BYTES` - Body bytes buffered at once (default: unlimited).
+    /// * `BUFFER_QUEUE_TIMEOUT_SECS` - Wait of requests for buffer space (default: 10).
+    pub fn from_env() -> Self {
+        Self {
+            max_buffered: config::env_parse("MAX_BUFFERED_BODY_BYTES").filter(|v| *v > 0),
+            queue_timeout: Duration::from_secs(
+                config::env_parse("BUFFER_QUEUE_TIMEOUT_SECS").unwrap_or(10),
+            ),
+        }
+    }
+}
+
+/// Tracks the buffered body bytes against [`MemoryConfig::max_buffered`].
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: Option<u64>,
+    queue_timeout: Duration,
+    /// The bytes in use are the `buffered_bytes` gauge of the metrics.
+    metrics: Arc<Metrics>,
+    freed: Notify,
+}
+
+impl MemoryBudget {
+    pub fn new(config: &MemoryConfig, metrics: Arc<Metrics>) -> Self {
+        Self {
+            limit: config.max_buffered,
+            queue_timeout: config.queue_timeout,
+            metrics,
+            freed: Notify::new(),
+        }
+    }
+
+    /// Reserves `bytes` if they fit under the limit.
+    pub fn try_reserve(self: &Arc<Self>, bytes: u64) -> Option<Reservation> {
+        let used = &self.metrics.buffered_bytes;
+        match self.limit {
+            Some(limit) => used
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                    (current.saturating_add(bytes) <= limit).then(|| current + bytes)
+                })
+                .ok()?,
+            None => used.fetch_add(bytes, Ordering::AcqRel),
+        };
+        Some(Reservation {
+            budget: self.clone(),
+            bytes,
+        })
+    }
+
+    /// Reserves `bytes`, waiting up to the queue timeout for other bodies to be released.
+    pub async fn reserve(self: &Arc<Self>, bytes: u64) -> Option<Reservation> {
+        if self.limit.is_some_and(|limit| bytes > limit) {
+            return None;
+        }
+        let wait = async {
+            loop {
+                // Registered before trying, so a release in between isn't missed.
+                let freed = self.freed.notified();
+                if let Some(reservation) = self.try_reserve(bytes) {
+                    return reservation;
+                }
+                freed.await;
+            }
+        };
+        tokio::time::timeout(self.queue_timeout, wait).await.ok()
+    }
+}
+
+/// Buffered bytes counted against the budget until dropped.
+#[derive(Debug)]
+pub struct Reservation {
+    budget: Arc<MemoryBudget>,
+    bytes: u64,
+}
+
+impl Reservation {
+    /// Corrects the reservation to the real size once a body has been read.
+    ///
+    /// Always succeeds, a body already in memory can't be turned away anymore.
+    pub fn resize(&mut self, bytes: u64) {
+        let used = &self.budget.metrics.buffered_bytes;
+        if bytes > self.bytes {
+            used.fetch_add(bytes - self.bytes, Ordering::AcqRel);
+        } else {
+            used.fetch_sub(self.bytes - bytes, Ordering::AcqRel);
+            self.budget.freed.notify_waiters();
+        }
+        self.bytes = bytes;
+    }
+
+    /// Keeps the reservation until `body` has been sent.
+    pub fn hold(self, body: Body) -> Body {
+        Body::new(Held {
+            inner: body,
+            _reservation: self,
+        })
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.resize(0);
+    }
+}
+
+/// A body releasing its reservation when dropped.
+struct Held {
+    inner: Body,
+    _reservation: Reservation,
+}
+
+impl http_body::Body for Held {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(limit: u64) -> Arc<MemoryBudget> {
+        let config = MemoryConfig {
+            max_buffered: Some(limit),
+            queue_timeout: Duration::from_millis(200),
+        };
+        Arc::new(MemoryBudget::new(&config, Arc::default()))
+    }
+
+    #[test]
+    fn reservations_are_limited_and_released() {
+        let budget = budget(100);
+        let first = budget.try_reserve(60).unwrap();
+        assert!(budget.try_reserve(50).is_none());
+        let mut second = budget.try_reserve(40).unwrap();
+
+        second.resize(10);
+        assert!(budget.try_reserve(31).is_none());
+        drop(first);
+        assert!(budget.try_reserve(90).is_some());
+        drop(second);
+        assert_eq!(budget.metrics.buffered_bytes.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn queued_reservations_wait_for_release() {
+        let budget = budget(100);
+        let held = budget.try_reserve(100).unwrap();
+
+        let queued = tokio::spawn({
+            let budget = budget.clone();
+            async move { budget.reserve(50).await.is_some() }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(held);
+        assert!(queued.await.unwrap());
+
+        let _held = budget.try_reserve(100).unwrap();
+        assert!(budget.reserve(50).await.is_none());
+    }
+}
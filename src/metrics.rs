@@ -0,0 +1,570 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Process metrics in the Prometheus text format, served at `/_admin/metrics`.
+//!
+//! Besides connection counts, request and response body bytes are counted per
+//! route class (`proxy`, a named upstream, `external`, `api`, `admin`,
+//! `assets`) and per client. Clients are only identified by a salted hash of
+//! their IP, stable until the process restarts.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll, ready},
+    time::Instant,
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Request, State},
+    http::{Method, StatusCode, Version, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body::{Frame, SizeHint};
+
+use crate::{
+    slo::{Slo, SloConfig},
+    state::AppState,
+    upstreams, utils,
+};
+
+/// Number of clients counted separately, later ones are added up as `other`.
+const MAX_CLIENTS: usize = 1000;
+
+/// Counters and gauges of the running process.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub connections_accepted: AtomicU64,
+    pub connections_open: AtomicU64,
+    pub connections_rejected: AtomicU64,
+    /// Connections opened to the upstreams.
+    pub upstream_connections: AtomicU64,
+    /// Body bytes currently buffered, see [`crate::memory`].
+    pub buffered_bytes: AtomicU64,
+    /// Responses streamed without rewriting because the buffer limit was reached.
+    pub buffer_skipped: AtomicU64,
+    /// Requests rejected after waiting for buffer space.
+    pub buffer_rejected: AtomicU64,
+    /// Requests the client aborted, by phase (`response` before the response
+    /// was ready, `transfer` while its body was sent).
+    cancelled: Mutex<BTreeMap<&'static str, u64>>,
+    /// Upstream responses by HTTP version.
+    upstream_responses: Mutex<BTreeMap<&'static str, u64>>,
+    /// Body bytes by route class.
+    routes: Mutex<HashMap<String, Arc<Transfer>>>,
+    /// Body bytes by hashed client IP.
+    clients: Mutex<HashMap<String, Arc<Transfer>>>,
+    /// Rows deleted by the retention job, by table.
+    retention_rows: Mutex<BTreeMap<&'static str, u64>>,
+    /// Bytes freed by the retention job, by store.
+    retention_bytes: Mutex<BTreeMap<&'static str, u64>>,
+    pub retention_files: AtomicU64,
+    /// Request outcomes measured against the service level objectives.
+    pub slo: Slo,
+}
+
+/// A metric value, see [`Metrics::samples`].
+#[derive(Debug)]
+pub struct Sample {
+    /// Name without the `jecnaproxy_` prefix and `_total` suffix.
+    pub name: &'static str,
+    pub labels: Vec<(&'static str, String)>,
+    pub value: f64,
+    /// Monotonic counter, otherwise a gauge.
+    pub counter: bool,
+}
+
+/// Body bytes received from and sent to clients.
+#[derive(Debug, Default)]
+pub struct Transfer {
+    pub bytes_in: AtomicU64,
+    pub bytes_out: AtomicU64,
+}
+
+impl Metrics {
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        counter(
+            &mut out,
+            "jecnaproxy_connections_accepted_total",
+            "Accepted TCP connections.",
+            &self.connections_accepted,
+        );
+        gauge(
+            &mut out,
+            "jecnaproxy_connections_open",
+            "Currently open TCP connections.",
+            &self.connections_open,
+        );
+        counter(
+            &mut out,
+            "jecnaproxy_connections_rejected_total",
+            "TCP connections closed because the client IP had too many open connections.",
+            &self.connections_rejected,
+        );
+        counter(
+            &mut out,
+            "jecnaproxy_upstream_connections_total",
+            "Connections opened to the upstreams.",
+            &self.upstream_connections,
+        );
+        labelled(
+            &mut out,
+            "jecnaproxy_upstream_responses_total",
+            "Upstream responses by HTTP version, many per connection means good reuse.",
+            "version",
+            &self.upstream_responses,
+        );
+        gauge(
+            &mut out,
+            "jecnaproxy_buffered_body_bytes",
+            "Request and response body bytes currently held in memory.",
+            &self.buffered_bytes,
+        );
+        counter(
+            &mut out,
+            "jecnaproxy_buffer_skipped_total",
+            "Responses passed through without rewriting because MAX_BUFFERED_BODY_BYTES was reached.",
+            &self.buffer_skipped,
+        );
+        counter(
+            &mut out,
+            "jecnaproxy_buffer_rejected_total",
+            "Requests answered with 503 after waiting for buffer space.",
+            &self.buffer_rejected,
+        );
+        labelled(
+            &mut out,
+            "jecnaproxy_requests_cancelled_total",
+            "Requests aborted by the client, by phase (response, transfer). Their upstream requests are cancelled.",
+            "phase",
+            &self.cancelled,
+        );
+        transfer(
+            &mut out,
+            "jecnaproxy_route_bytes_total",
+            "Request (in) and response (out) body bytes by route class.",
+            "route",
+            &self.routes,
+        );
+        transfer(
+            &mut out,
+            "jecnaproxy_client_bytes_total",
+            "Request (in) and response (out) body bytes by hashed client IP.",
+            "client",
+            &self.clients,
+        );
+        labelled(
+            &mut out,
+            "jecnaproxy_retention_rows_deleted_total",
+            "Database rows deleted by the retention job, by table.",
+            "table",
+            &self.retention_rows,
+        );
+        counter(
+            &mut out,
+            "jecnaproxy_retention_files_deleted_total",
+            "Stale snapshot files deleted by the retention job.",
+            &self.retention_files,
+        );
+        labelled(
+            &mut out,
+            "jecnaproxy_retention_reclaimed_bytes_total",
+            "Bytes freed by the retention job (database vacuum, snapshot files).",
+            "store",
+            &self.retention_bytes,
+        );
+        out
+    }
+
+    /// All values except the per-client ones, for exporters other than the
+    /// Prometheus text format.
+    pub fn samples(&self, slo: &SloConfig) -> Vec<Sample> {
+        let mut samples = Vec::new();
+        for (name, value, counter) in [
+            ("connections_accepted", &self.connections_accepted, true),
+            ("connections_open", &self.connections_open, false),
+            ("connections_rejected", &self.connections_rejected, true),
+            ("upstream_connections", &self.upstream_connections, true),
+            ("buffered_body_bytes", &self.buffered_bytes, false),
+            ("buffer_skipped", &self.buffer_skipped, true),
+            ("buffer_rejected", &self.buffer_rejected, true),
+            ("retention_files_deleted", &self.retention_files, true),
+        ] {
+            samples.push(Sample {
+                name,
+                labels: Vec::new(),
+                value: value.load(Ordering::Relaxed) as f64,
+                counter,
+            });
+        }
+
+        let routes = self.routes.lock().expect("metrics lock poisoned");
+        for (route, transfer) in routes.iter() {
+            for (direction, value) in [("in", &transfer.bytes_in), ("out", &transfer.bytes_out)] {
+                samples.push(Sample {
+                    name: "route_bytes",
+                    labels: vec![
+                        ("route", route.clone()),
+                        ("direction", direction.to_string()),
+                    ],
+                    value: value.load(Ordering::Relaxed) as f64,
+                    counter: true,
+                });
+            }
+        }
+        drop(routes);
+
+        for (name, label, values) in [
+            ("requests_cancelled", "phase", &self.cancelled),
+            ("upstream_responses", "version", &self.upstream_responses),
+            ("retention_rows_deleted", "table", &self.retention_rows),
+            ("retention_reclaimed_bytes", "store", &self.retention_bytes),
+        ] {
+            let values = values.lock().expect("metrics lock poisoned");
+            for (key, value) in values.iter() {
+                samples.push(Sample {
+                    name,
+                    labels: vec![(label, key.to_string())],
+                    value: *value as f64,
+                    counter: true,
+                });
+            }
+        }
+
+        for (name, window, value) in self.slo.gauges(slo) {
+            samples.push(Sample {
+                name,
+                labels: vec![("window", window.to_string())],
+                value,
+                counter: false,
+            });
+        }
+        samples
+    }
+
+    /// Counts a request the client aborted in `phase`.
+    pub fn cancelled(&self, phase: &'static str) {
+        let mut counts = self.cancelled.lock().expect("metrics lock poisoned");
+        *counts.entry(phase).or_default() += 1;
+    }
+
+    /// Counts a response received from an upstream.
+    pub fn upstream_response(&self, version: Version) {
+        let version = match version {
+            Version::HTTP_09 => "0.9",
+            Version::HTTP_10 => "1.0",
+            Version::HTTP_2 => "2",
+            Version::HTTP_3 => "3",
+            _ => "1.1",
+        };
+        let mut counts = self
+            .upstream_responses
+            .lock()
+            .expect("metrics lock poisoned");
+        *counts.entry(version).or_default() += 1;
+    }
+
+    /// Counts rows the retention job deleted from `table`.
+    pub fn retention_deleted(&self, table: &'static str, rows: u64) {
+        let mut counts = self.retention_rows.lock().expect("metrics lock poisoned");
+        *counts.entry(table).or_default() += rows;
+    }
+
+    /// Counts bytes the retention job freed in `store`.
+    pub fn retention_reclaimed(&self, store: &'static str, bytes: u64) {
+        let mut counts = self.retention_bytes.lock().expect("metrics lock poisoned");
+        *counts.entry(store).or_default() += bytes;
+    }
+
+    /// Counters of a route class, created on first use.
+    pub fn route(&self, class: &str) -> Arc<Transfer> {
+        let mut routes = self.routes.lock().expect("metrics lock poisoned");
+        routes.entry(class.to_string()).or_default().clone()
+    }
+
+    /// Counters of a client, created on first use.
+    pub fn client(&self, id: String) -> Arc<Transfer> {
+        let mut clients = self.clients.lock().expect("metrics lock poisoned");
+        if clients.len() >= MAX_CLIENTS && !clients.contains_key(&id) {
+            return clients.entry("other".to_string()).or_default().clone();
+        }
+        clients.entry(id).or_default().clone()
+    }
+}
+
+/// Writes a labelled byte counter with `direction="in"` and `"out"` series.
+fn transfer(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    values: &Mutex<HashMap<String, Arc<Transfer>>>,
+) {
+    let values = values.lock().expect("metrics lock poisoned");
+    let mut keys: Vec<&String> = values.keys().collect();
+    keys.sort();
+
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for key in keys {
+        let transfer = &values[key];
+        for (direction, value) in [("in", &transfer.bytes_in), ("out", &transfer.bytes_out)] {
+            let _ = writeln!(
+                out,
+                "{}{{{}=\"{}\",direction=\"{}\"}} {}",
+                name,
+                label,
+                key,
+                direction,
+                value.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+/// Writes a counter with one series per label value.
+fn labelled(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    values: &Mutex<BTreeMap<&'static str, u64>>,
+) {
+    let values = values.lock().expect("metrics lock poisoned");
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for (key, value) in values.iter() {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, key, value);
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: &AtomicU64) {
+    metric(out, name, help, "counter", value);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: &AtomicU64) {
+    metric(out, name, help, "gauge", value);
+}
+
+fn metric(out: &mut String, name: &str, help: &str, kind: &str, value: &AtomicU64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+}
+
+/// Admin handler serving the metrics.
+pub async fn handler(State(state): State<AppState>) -> Response {
+    let mut out = state.metrics.render();
+    state.metrics.slo.render(&state.config.slo, &mut out);
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out).into_response()
+}
+
+/// Middleware counting the body bytes of every request and response, and
+/// recording non-admin requests for the SLOs.
+pub async fn track(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let ip = utils::client_ip(&addr, req.headers(), state.config.trust_forwarded_for);
+    let class = route_class(&state, &req);
+    let admin = class == "admin";
+    let counters = [
+        state.metrics.route(&class),
+        state.metrics.client(state.config.privacy.ip_hash(ip)),
+    ];
+    let started = Instant::now();
+    let head = req.method() == Method::HEAD;
+
+    let req = req.map(|body| {
+        Body::new(Counting {
+            inner: body,
+            counters: counters.clone(),
+            outbound: false,
+            pending: None,
+            unsent: None,
+        })
+    });
+    // Dropped unfinished when the client disconnects, hyper then drops this future
+    // and with it the upstream request and any partly read body.
+    let pending = Pending::new(&state.metrics, "response");
+    let response = next.run(req).await;
+    pending.finish();
+    if !admin {
+        let status = response.status().as_u16();
+        let elapsed = started.elapsed();
+        state.metrics.slo.record(&state.config.slo, status, elapsed);
+        if let Some(statsd) = &state.statsd {
+            statsd.timing(
+                elapsed,
+                &[("route", class), ("status", format!("{}xx", status / 100))],
+            );
+        }
+    }
+    let status = response.status();
+    let has_body = !head
+        && !status.is_informational()
+        && status != StatusCode::NO_CONTENT
+        && status != StatusCode::NOT_MODIFIED;
+    // hyper stops polling a body once `Content-Length` bytes have been sent.
+    let unsent = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse().ok());
+    response.map(|body| {
+        Body::new(Counting {
+            inner: body,
+            counters,
+            outbound: true,
+            pending: has_body.then(|| Pending::new(&state.metrics, "transfer")),
+            unsent,
+        })
+    })
+}
+
+/// Counts a cancelled request when dropped before [`Pending::finish`].
+struct Pending {
+    metrics: Option<Arc<Metrics>>,
+    phase: &'static str,
+}
+
+impl Pending {
+    fn new(metrics: &Arc<Metrics>, phase: &'static str) -> Self {
+        Self {
+            metrics: Some(metrics.clone()),
+            phase,
+        }
+    }
+
+    fn finish(mut self) {
+        self.metrics = None;
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if let Some(metrics) = &self.metrics {
+            tracing::debug!("Client aborted the request ({})", self.phase);
+            metrics.cancelled(self.phase);
+        }
+    }
+}
+
+/// Route class of a request, see the module docs.
+fn route_class(state: &AppState, req: &Request) -> String {
+    let path = req.uri().path();
+    for (prefix, class) in [
+        ("/_admin", "admin"),
+        ("/api", "api"),
+        ("/_jecnaproxy", "assets"),
+        (upstreams::EXT_PREFIX, "external"),
+    ] {
+        if path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        {
+            return class.to_string();
+        }
+    }
+
+    let host = req
+        .headers()
+        .get("host")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    match upstreams::select(&state.routes, host, path) {
+        Some((route, _)) => route.upstream.name.clone(),
+        None => "proxy".to_string(),
+    }
+}
+
+/// A body adding its size to transfer counters as it is read.
+struct Counting {
+    inner: Body,
+    counters: [Arc<Transfer>; 2],
+    outbound: bool,
+    /// Set on response bodies, finished once they have been sent.
+    pending: Option<Pending>,
+    /// Bytes left until `Content-Length` is reached.
+    unsent: Option<u64>,
+}
+
+impl http_body::Body for Counting {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        // Errors aren't the client's doing (e.g. the upstream or the deadline).
+        if matches!(frame, None | Some(Err(_)))
+            && let Some(pending) = self.pending.take()
+        {
+            pending.finish();
+        }
+        if let Some(Ok(frame)) = &frame
+            && let Some(data) = frame.data_ref()
+        {
+            for transfer in &self.counters {
+                let counter = if self.outbound {
+                    &transfer.bytes_out
+                } else {
+                    &transfer.bytes_in
+                };
+                counter.fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+            if let Some(unsent) = &mut self.unsent {
+                *unsent = unsent.saturating_sub(data.len() as u64);
+                if *unsent == 0
+                    && let Some(pending) = self.pending.take()
+                {
+                    pending.finish();
+                }
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for Counting {
+    fn drop(&mut self) {
+        // Bodies known to be empty or complete may never be polled to the end.
+        if http_body::Body::is_end_stream(&self.inner)
+            && let Some(pending) = self.pending.take()
+        {
+            pending.finish();
+        }
+    }
+}
@@ -0,0 +1,237 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Notification channels.
+//!
+//! Notifications are POSTed as JSON to every URL in `NOTIFY_WEBHOOK_URLS` and
+//! to the webhooks of users subscribed to their kind, which covers chat bots,
+//! ntfy-style services and custom integrations.
+//!
+//! Webhooks of users are only delivered to public addresses, so an account
+//! can't make the proxy send requests into its own network. The addresses are
+//! checked after every resolution, which a DNS name switching to a private
+//! address between the check and the delivery can't get around.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use reqwest::{
+    Client, Url,
+    dns::{Addrs, Name, Resolve, Resolving},
+};
+use serde::Serialize;
+
+use crate::state::AppState;
+
+/// A message sent to all notification channels.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    /// Machine-readable kind, e.g. "page_changed".
+    pub kind: &'static str,
+    pub title: String,
+    pub message: String,
+    /// Link to the relevant page on the proxy, if any.
+    pub url: Option<String>,
+}
+
+/// Sends a notification to all configured webhooks and subscribed users.
+pub async fn send(state: &AppState, notification: &Notification) {
+    send_to(state, &state.config.notify_webhooks, notification).await;
+
+    if let (Some(_), Some(db)) = (&state.config.users, &state.db) {
+        match db.subscribed_webhooks(notification.kind).await {
+            Ok(webhooks) => deliver(&state.webhook_client, &webhooks, notification, true).await,
+            Err(e) => tracing::error!("Failed to load notification subscribers: {}", e),
+        }
+    }
+}
+
+/// Sends a notification to the given webhooks only, e.g. for personal data.
+///
+/// For webhooks configured by the admin, which may be on the local network.
+pub async fn send_to(state: &AppState, webhooks: &[String], notification: &Notification) {
+    deliver(&state.client, webhooks, notification, false).await;
+}
+
+async fn deliver(
+    client: &Client,
+    webhooks: &[String],
+    notification: &Notification,
+    public_only: bool,
+) {
+    for webhook in webhooks {
+        // IP literals don't go through the resolver of the user webhook client.
+        if public_only && literal_ip(webhook).is_some_and(|ip| !is_public(ip)) {
+            tracing::warn!(
+                "Not delivering {} notification to a non-public address",
+                notification.kind
+            );
+            continue;
+        }
+        let result = client
+            .post(webhook)
+            .json(notification)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+
+        if let Err(e) = result {
+            tracing::warn!(
+                "Failed to deliver {} notification: {}",
+                notification.kind,
+                e.without_url()
+            );
+        }
+    }
+}
+
+/// The client delivering to user webhooks, which only connects to public addresses.
+pub fn user_webhook_client() -> Client {
+    Client::builder()
+        .dns_resolver(PublicResolver)
+        // A redirect could point anywhere, including IP literals.
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("Failed to build reqwest client")
+}
+
+/// Checks that a user's webhook URL points to public addresses only.
+pub async fn validate_user_webhook(url: &str) -> Result<(), &'static str> {
+    let url = Url::parse(url).map_err(|_| "Invalid webhook_url")?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("Invalid webhook_url");
+    }
+    let host = url.host_str().ok_or("Invalid webhook_url")?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|_| "webhook_url can't be resolved")?
+        .collect();
+    if addrs.is_empty() || !addrs.iter().all(|a| is_public(a.ip())) {
+        return Err("webhook_url must point to a public address");
+    }
+    Ok(())
+}
+
+fn literal_ip(url: &str) -> Option<IpAddr> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+/// Whether `ip` is reachable on the internet, i.e. not loopback, private,
+/// link-local, shared (CGNAT), multicast or otherwise reserved.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && ip.octets()[2] == 0)
+                || (a == 198 && (18..20).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public(v4.into());
+            }
+            let first = ip.segments()[0];
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || first == 0x2001 && ip.segments()[1] == 0x0db8
+                || first == 0x0064 && ip.segments()[1] == 0xff9b)
+        }
+    }
+}
+
+/// The system resolver, failing for names with non-public addresses.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+            if addrs.is_empty() || !addrs.iter().all(|a| is_public(a.ip())) {
+                return Err(format!("{} has a non-public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_public_addresses_are_public() {
+        for ip in ["93.184.215.14", "2a00:1450:4014:80c::200e", "8.8.8.8"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fe80::1",
+            "fd00::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn private_webhooks_are_rejected() {
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://[::1]/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://localhost/hook",
+            "ftp://example.com/hook",
+        ] {
+            assert!(validate_user_webhook(url).await.is_err(), "{}", url);
+        }
+        assert!(
+            validate_user_webhook("http://93.184.215.14/hook")
+                .await
+                .is_ok()
+        );
+    }
+}
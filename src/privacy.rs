@@ -0,0 +1,194 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! GDPR-friendly logging.
+//!
+//! Everything that ends up in logs and might identify a person (client IPs,
+//! query strings with names or tokens) should go through [`LogPrivacy`].
+//! Secrets (cookies, auth headers, passwords) are masked regardless of the
+//! privacy options unless `LOG_UNREDACTED` is set.
+
+use std::{
+    borrow::Cow,
+    fmt,
+    hash::{BuildHasher, RandomState},
+    net::IpAddr,
+};
+
+use axum::http::HeaderMap;
+use serde::Serialize;
+
+use crate::config;
+
+const SENSITIVE_HEADERS: &[&str] = &[
+    "cookie",
+    "set-cookie",
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "x-auth-token",
+];
+
+const SENSITIVE_FIELDS: &[&str] = &["pass", "password", "heslo", "token", "secret"];
+
+/// How client IPs are written to logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpMode {
+    /// The full address.
+    Full,
+    /// IPv4 truncated to /24, IPv6 to /48.
+    Truncate,
+    /// A salted hash, stable for the lifetime of the process.
+    Hash,
+}
+
+/// Log anonymization settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogPrivacy {
+    pub ip_mode: IpMode,
+    /// Whether query strings are removed from logged URLs.
+    pub strip_query: bool,
+    /// Debug escape hatch disabling the masking of secrets.
+    pub unredacted: bool,
+    #[serde(skip)]
+    salt: RandomState,
+}
+
+impl Default for LogPrivacy {
+    fn default() -> Self {
+        Self {
+            ip_mode: IpMode::Full,
+            strip_query: false,
+            unredacted: false,
+            salt: RandomState::new(),
+        }
+    }
+}
+
+impl LogPrivacy {
+    /// # Environment Variables
+    /// * `LOG_PRIVACY` - Comma-separated options: `truncate-ip`, `hash-ip`,
+    ///   `strip-query`, or `strict` (= `hash-ip,strip-query`).
+    /// * `LOG_UNREDACTED` - Set to "true" or "1" to log cookies, auth headers and
+    ///   passwords in plain text. Debugging only.
+    pub fn from_env() -> Self {
+        let mut privacy = Self {
+            unredacted: config::env_flag("LOG_UNREDACTED"),
+            ..Self::default()
+        };
+
+        for option in config::env_list("LOG_PRIVACY") {
+            match option.to_lowercase().as_str() {
+                "truncate-ip" => privacy.ip_mode = IpMode::Truncate,
+                "hash-ip" => privacy.ip_mode = IpMode::Hash,
+                "strip-query" => privacy.strip_query = true,
+                "strict" => {
+                    privacy.ip_mode = IpMode::Hash;
+                    privacy.strip_query = true;
+                }
+                other => tracing::warn!("Unknown LOG_PRIVACY option: {}", other),
+            }
+        }
+
+        privacy
+    }
+
+    /// Formats a client IP for logging.
+    pub fn ip(&self, ip: IpAddr) -> String {
+        match self.ip_mode {
+            IpMode::Full => ip.to_string(),
+            IpMode::Truncate => match ip {
+                IpAddr::V4(v4) => {
+                    let [a, b, c, _] = v4.octets();
+                    format!("{}.{}.{}.0/24", a, b, c)
+                }
+                IpAddr::V6(v6) => {
+                    let s = v6.segments();
+                    format!("{:x}:{:x}:{:x}::/48", s[0], s[1], s[2])
+                }
+            },
+            IpMode::Hash => self.ip_hash(ip),
+        }
+    }
+
+    /// A salted hash of a client IP, regardless of `ip_mode`.
+    pub fn ip_hash(&self, ip: IpAddr) -> String {
+        format!("ip-{:016x}", self.salt.hash_one(ip))
+    }
+
+    /// Formats a URL (or path and query) for logging.
+    pub fn url<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        if !self.strip_query {
+            return Cow::Borrowed(url);
+        }
+
+        match url.split_once('?') {
+            Some((path, _)) => Cow::Owned(format!("{}?[redacted]", path)),
+            None => Cow::Borrowed(url),
+        }
+    }
+
+    /// Wraps headers so that their `Debug` output masks secrets.
+    pub fn headers<'a>(&self, headers: &'a HeaderMap) -> RedactedHeaders<'a> {
+        RedactedHeaders {
+            headers,
+            unredacted: self.unredacted,
+        }
+    }
+
+    /// Masks sensitive fields of an `application/x-www-form-urlencoded` body.
+    pub fn form_body<'a>(&self, body: &'a str) -> Cow<'a, str> {
+        if self.unredacted {
+            return Cow::Borrowed(body);
+        }
+
+        let fields: Vec<Cow<str>> = body
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if is_sensitive_field(key) => {
+                    Cow::Owned(format!("{}=[redacted]", key))
+                }
+                _ => Cow::Borrowed(pair),
+            })
+            .collect();
+
+        Cow::Owned(fields.join("&"))
+    }
+}
+
+fn is_sensitive_field(key: &str) -> bool {
+    let key = key.to_lowercase();
+    SENSITIVE_FIELDS.iter().any(|f| key.contains(f))
+}
+
+/// `Debug` view of a [`HeaderMap`] with secret values masked.
+pub struct RedactedHeaders<'a> {
+    headers: &'a HeaderMap,
+    unredacted: bool,
+}
+
+impl fmt::Debug for RedactedHeaders<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (name, value) in self.headers {
+            if !self.unredacted && SENSITIVE_HEADERS.contains(&name.as_str()) {
+                map.entry(name, &"[redacted]");
+            } else {
+                map.entry(name, value);
+            }
+        }
+        map.finish()
+    }
+}
@@ -0,0 +1,147 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Installable mirror (progressive web app).
+//!
+//! Proxied HTML pages link a web app manifest and register a service worker
+//! under `/_jecnaproxy/pwa`. The worker fetches from the network first and keeps every
+//! successful `GET` in the browser's Cache Storage, so the last seen version of
+//! a page (e.g. the timetable) can be opened without a connection.
+
+use axum::{
+    Json, Router,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::Serialize;
+
+use crate::{assets, inject, state::AppState};
+
+/// Path of the manifest, linked from every proxied page.
+const MANIFEST_PATH: &str = "/_jecnaproxy/pwa/manifest.webmanifest";
+
+/// Path of the service worker, registered for the whole site.
+const WORKER_PATH: &str = "/_jecnaproxy/pwa/sw.js";
+
+/// Installable mirror settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct PwaConfig {
+    /// Application name shown when installed.
+    pub name: String,
+    /// Page opened by the installed application.
+    pub start_url: String,
+}
+
+impl PwaConfig {
+    /// # Environment Variables
+    /// * `PWA_ENABLED` - Set to "true" or "1" to make the mirror installable.
+    /// * `PWA_NAME` - Application name (default: "Ječná").
+    /// * `PWA_START_URL` - Page opened by the installed app (default: "/").
+    pub fn from_env() -> Option<Self> {
+        if !crate::config::env_flag("PWA_ENABLED") {
+            return None;
+        }
+
+        Some(Self {
+            name: std::env::var("PWA_NAME").unwrap_or_else(|_| "Ječná".to_string()),
+            start_url: std::env::var("PWA_START_URL").unwrap_or_else(|_| "/".to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Manifest<'a> {
+    name: &'a str,
+    short_name: &'a str,
+    start_url: &'a str,
+    scope: &'a str,
+    display: &'a str,
+    background_color: &'a str,
+    theme_color: &'a str,
+    icons: [Icon<'a>; 1],
+}
+
+#[derive(Debug, Serialize)]
+struct Icon<'a> {
+    src: &'a str,
+    sizes: &'a str,
+    #[serde(rename = "type")]
+    kind: &'a str,
+    purpose: &'a str,
+}
+
+/// Routes serving the manifest and service worker, nested under `/_jecnaproxy/pwa`.
+pub fn router(state: &AppState) -> Option<Router<AppState>> {
+    state.config.pwa.as_ref()?;
+
+    let router = Router::new()
+        .route("/manifest.webmanifest", get(manifest))
+        .route("/sw.js", get(worker));
+    Some(router)
+}
+
+async fn manifest(State(state): State<AppState>) -> Response {
+    let Some(pwa) = &state.config.pwa else {
+        return axum::http::StatusCode::NOT_FOUND.into_response();
+    };
+
+    let manifest = Manifest {
+        name: &pwa.name,
+        short_name: &pwa.name,
+        start_url: &pwa.start_url,
+        scope: "/",
+        display: "standalone",
+        background_color: "#ffffff",
+        theme_color: "#1b4f8a",
+        icons: [Icon {
+            src: "/_jecnaproxy/pwa/icon.svg",
+            sizes: "any",
+            kind: "image/svg+xml",
+            purpose: "any maskable",
+        }],
+    };
+    (
+        [(header::CONTENT_TYPE, "application/manifest+json")],
+        Json(manifest),
+    )
+        .into_response()
+}
+
+async fn worker() -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "text/javascript; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-cache"),
+            // The worker lives under /_jecnaproxy/pwa but controls the whole site.
+            (
+                header::HeaderName::from_static("service-worker-allowed"),
+                "/",
+            ),
+        ],
+        assets::text("pwa/sw.js"),
+    )
+        .into_response()
+}
+
+/// Links the manifest and registers the service worker in an HTML page.
+pub fn inject(body: &mut Vec<u8>) {
+    let tags = format!(
+        r#"<link rel="manifest" href="{}"><script>if ("serviceWorker" in navigator) navigator.serviceWorker.register("{}", {{ scope: "/" }});</script>"#,
+        MANIFEST_PATH, WORKER_PATH
+    );
+
+    inject::before_head_end(body, tags.as_bytes());
+}
@@ -0,0 +1,63 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Read-only mirror mode.
+//!
+//! Rejects every request that could change upstream state (form submissions,
+//! canteen orders, ...) with a page pointing the user to the official site.
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+
+use crate::state::AppState;
+
+const READ_ONLY_HTML: &str = r#"<!DOCTYPE html>
+<html lang="cs">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>Pouze pro čtení</title>
+</head>
+<body style="font-family: sans-serif; display: flex; flex-direction: column; justify-content: center; align-items: center; min-height: 100vh; margin: 0; text-align: center;">
+  <h1>Toto zrcadlo je pouze pro čtení</h1>
+  <p>Odesílání formulářů a jiné změny nejsou přes tento web možné.</p>
+  <p>Akci prosím proveďte na <a href="$url">oficiálním webu</a>.</p>
+</body>
+</html>"#;
+
+/// Middleware rejecting all methods except GET, HEAD and OPTIONS when `READ_ONLY` is set.
+pub async fn enforce(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if !state.config.read_only
+        || matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+    {
+        return next.run(req).await;
+    }
+
+    tracing::info!(
+        "Read-only mode: rejecting {} {}",
+        req.method(),
+        state.config.privacy.url(&req.uri().to_string())
+    );
+
+    let page = READ_ONLY_HTML.replace("$url", &state.upstream().base);
+    let mut response = (StatusCode::METHOD_NOT_ALLOWED, Html(page)).into_response();
+    response
+        .headers_mut()
+        .insert("allow", HeaderValue::from_static("GET, HEAD, OPTIONS"));
+    response
+}
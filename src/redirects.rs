@@ -0,0 +1,198 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Following upstream redirects inside the proxy.
+//!
+//! With `FOLLOW_REDIRECTS` set, `301`/`302` answers to GET and HEAD requests
+//! are followed upstream and only the final response reaches the browser,
+//! saving a round trip per hop on slow connections.
+//!
+//! Only redirects to the same host and the same directory are followed: the
+//! browser keeps showing the original URL, so relative links of the final page
+//! must resolve the same way against both. Cookies set along the way are sent
+//! with the next hop and passed on to the browser.
+
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use reqwest::{Response, Url};
+
+use crate::{deadline::Deadline, state::AppState};
+
+/// Follows up to `FOLLOW_REDIRECTS` hops starting at `resp`.
+///
+/// Returns the final response and the `Set-Cookie` values of the skipped hops.
+/// Every hop is bounded by what is left of the request's `deadline`.
+pub async fn follow(
+    state: &AppState,
+    method: &Method,
+    headers: &HeaderMap,
+    deadline: Option<Deadline>,
+    mut resp: Response,
+) -> reqwest::Result<(Response, Vec<HeaderValue>)> {
+    let mut cookies = Vec::new();
+    if !matches!(*method, Method::GET | Method::HEAD) {
+        return Ok((resp, cookies));
+    }
+
+    let mut headers = headers.clone();
+    for _ in 0..state.config.follow_redirects {
+        let location = resp.headers().get("location").and_then(|v| v.to_str().ok());
+        let Some(next) = next_hop(resp.status(), resp.url(), location) else {
+            break;
+        };
+
+        for value in resp.headers().get_all("set-cookie") {
+            carry_cookie(&mut headers, value);
+            cookies.push(value.clone());
+        }
+
+        tracing::debug!(
+            "Following redirect to {}",
+            state.config.privacy.url(next.as_str())
+        );
+        let mut request = state
+            .client
+            .request(method.clone(), next)
+            .headers(headers.clone());
+        if let Some(deadline) = deadline {
+            request = request.timeout(deadline.remaining());
+        }
+        resp = request.send().await?;
+    }
+
+    Ok((resp, cookies))
+}
+
+/// Target of a redirect that may be followed, see the module docs.
+fn next_hop(status: StatusCode, current: &Url, location: Option<&str>) -> Option<Url> {
+    if !matches!(status, StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND) {
+        return None;
+    }
+
+    let next = current.join(location?).ok()?;
+
+    (next.scheme() == current.scheme()
+        && next.host() == current.host()
+        && next.port_or_known_default() == current.port_or_known_default()
+        && directory(next.path()) == directory(current.path())
+        && next != *current)
+        .then_some(next)
+}
+
+fn directory(path: &str) -> &str {
+    &path[..=path.rfind('/').unwrap_or(0)]
+}
+
+/// Adds or replaces the cookie set by `set_cookie` in the `Cookie` request header.
+fn carry_cookie(headers: &mut HeaderMap, set_cookie: &HeaderValue) {
+    let Some(pair) = set_cookie
+        .to_str()
+        .ok()
+        .and_then(|v| v.split(';').next())
+        .map(str::trim)
+    else {
+        return;
+    };
+    let Some((name, _)) = pair.split_once('=') else {
+        return;
+    };
+
+    let mut pairs: Vec<&str> = headers
+        .get("cookie")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| {
+            v.split(';')
+                .map(str::trim)
+                .filter(|p| !p.is_empty() && p.split_once('=').map(|(n, _)| n) != Some(name))
+                .collect()
+        })
+        .unwrap_or_default();
+    pairs.push(pair);
+
+    if let Ok(value) = HeaderValue::from_str(&pairs.join("; ")) {
+        headers.insert("cookie", value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hop(status: StatusCode, location: &str) -> Option<String> {
+        let current = Url::parse("https://www.spsejecna.cz/score/student?id=1").unwrap();
+        next_hop(status, &current, Some(location)).map(String::from)
+    }
+
+    #[test]
+    fn only_nearby_redirects_are_followed() {
+        assert_eq!(
+            hop(StatusCode::FOUND, "/score/student?id=2").as_deref(),
+            Some("https://www.spsejecna.cz/score/student?id=2")
+        );
+        assert_eq!(
+            hop(StatusCode::MOVED_PERMANENTLY, "student;jsessionid=A1").as_deref(),
+            Some("https://www.spsejecna.cz/score/student;jsessionid=A1")
+        );
+
+        assert_eq!(
+            hop(StatusCode::FOUND, "https://evil.example.com/score/x"),
+            None
+        );
+        assert_eq!(
+            hop(StatusCode::FOUND, "http://www.spsejecna.cz/score/x"),
+            None
+        );
+        assert_eq!(
+            hop(StatusCode::FOUND, "https://www.spsejecna.cz:8443/score/x"),
+            None
+        );
+        assert_eq!(hop(StatusCode::FOUND, "/user/login"), None);
+        assert_eq!(hop(StatusCode::FOUND, "/score/sub/x"), None);
+        assert_eq!(hop(StatusCode::FOUND, "/score/student?id=1"), None);
+        for status in [
+            StatusCode::SEE_OTHER,
+            StatusCode::TEMPORARY_REDIRECT,
+            StatusCode::PERMANENT_REDIRECT,
+            StatusCode::OK,
+        ] {
+            assert_eq!(hop(status, "/score/student?id=2"), None, "{}", status);
+        }
+
+        let current = Url::parse("https://www.spsejecna.cz/score/student").unwrap();
+        assert_eq!(next_hop(StatusCode::FOUND, &current, None), None);
+    }
+
+    #[test]
+    fn carried_cookies_are_merged() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "cookie",
+            HeaderValue::from_static("a=1; JSESSIONID=old; b=2"),
+        );
+        carry_cookie(
+            &mut headers,
+            &HeaderValue::from_static("JSESSIONID=new; Path=/; HttpOnly"),
+        );
+        assert_eq!(headers["cookie"], "a=1; b=2; JSESSIONID=new");
+
+        carry_cookie(&mut headers, &HeaderValue::from_static("c=3"));
+        assert_eq!(headers["cookie"], "a=1; b=2; JSESSIONID=new; c=3");
+
+        let mut headers = HeaderMap::new();
+        carry_cookie(&mut headers, &HeaderValue::from_static("a=1; Secure"));
+        assert_eq!(headers["cookie"], "a=1");
+
+        carry_cookie(&mut headers, &HeaderValue::from_static("invalid"));
+        assert_eq!(headers["cookie"], "a=1");
+    }
+}
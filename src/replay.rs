@@ -0,0 +1,455 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! `jecnaproxy replay`: re-sends requests recorded in a HAR file through the
+//! proxy's routes and middleware, against a mock upstream answering with the
+//! recorded responses.
+//!
+//! The results are compared with the recorded upstream responses, or with the
+//! results of an earlier run given as `--baseline`. Saving the results of the
+//! deployed version with `--save` and replaying them as the baseline with a
+//! new version shows everything the upgrade changes.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{ConnectInfo, Request},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+use tower::ServiceExt;
+
+use crate::{cli::ReplayArgs, state::AppState};
+
+/// Recorded headers that don't apply to the replayed body.
+const SKIPPED_HEADERS: &[&str] = &[
+    "host",
+    "connection",
+    "content-length",
+    "content-encoding",
+    "transfer-encoding",
+];
+
+#[derive(Debug, Deserialize)]
+struct Har {
+    log: HarLog,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarLog {
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarEntry {
+    request: HarRequest,
+    response: HarResponse,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: Vec<HarHeader>,
+    post_data: Option<HarPostData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarPostData {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarResponse {
+    status: u16,
+    #[serde(default)]
+    headers: Vec<HarHeader>,
+    #[serde(default)]
+    content: HarContent,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct HarContent {
+    text: Option<String>,
+    encoding: Option<String>,
+}
+
+/// A response as compared by the replay.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// The body if it is UTF-8, its length and hash otherwise.
+    pub body: String,
+}
+
+impl Exchange {
+    fn new(
+        method: &str,
+        path: &str,
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: &[u8],
+    ) -> Self {
+        let body = match std::str::from_utf8(body) {
+            Ok(text) => text.to_string(),
+            Err(_) => format!(
+                "<{} bytes, sha256 {}>",
+                body.len(),
+                hex::encode(Sha256::digest(body))
+            ),
+        };
+        Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            status,
+            // The date changes with every run.
+            headers: headers
+                .into_iter()
+                .filter(|(name, _)| !name.eq_ignore_ascii_case("date"))
+                .collect(),
+            body,
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut out = format!("{}\n", self.status);
+        for (name, value) in &self.headers {
+            out.push_str(&format!("{}: {}\n", name, value));
+        }
+        out.push('\n');
+        out.push_str(&self.body);
+        out
+    }
+}
+
+/// A recorded upstream response served by the mock.
+#[derive(Debug, Clone)]
+struct Recorded {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl IntoResponse for Recorded {
+    fn into_response(self) -> Response {
+        let mut response = Response::new(Body::from(self.body));
+        *response.status_mut() = StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK);
+        for (name, value) in self.headers {
+            if let (Ok(name), Ok(value)) = (
+                axum::http::HeaderName::try_from(name),
+                axum::http::HeaderValue::try_from(value),
+            ) {
+                response.headers_mut().append(name, value);
+            }
+        }
+        response
+    }
+}
+
+/// Entry point of the `replay` subcommand.
+pub async fn run(state: &AppState, app: Router, args: ReplayArgs) {
+    match replay(state, app, &args).await {
+        Ok(differing) if differing > 0 && args.baseline.is_some() => std::process::exit(1),
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!("Replay failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Replays the recording, returning the number of differing responses.
+async fn replay(state: &AppState, app: Router, args: &ReplayArgs) -> Result<usize, String> {
+    let har = std::fs::read(&args.recording)
+        .map_err(|e| format!("{}: {}", args.recording.display(), e))?;
+    let har: Har = serde_json::from_slice(&har)
+        .map_err(|e| format!("{} is not a HAR file: {}", args.recording.display(), e))?;
+
+    let upstream = state.upstream();
+    let total = har.log.entries.len();
+    let entries: Vec<(String, HarEntry)> = har
+        .log
+        .entries
+        .into_iter()
+        .filter_map(|entry| Some((path_query(&entry.request.url, &upstream.variants)?, entry)))
+        .collect();
+    if entries.is_empty() {
+        return Err(format!(
+            "the recording has no requests to {}",
+            upstream.base
+        ));
+    }
+
+    let baseline = match &args.baseline {
+        Some(path) => {
+            let baseline: Vec<Exchange> = read_json(path)?;
+            if baseline.len() != entries.len() {
+                return Err(format!(
+                    "the baseline has {} responses, the recording {}",
+                    baseline.len(),
+                    entries.len()
+                ));
+            }
+            Some(baseline)
+        }
+        None => None,
+    };
+
+    let mut responses: HashMap<(String, String), VecDeque<Recorded>> = HashMap::new();
+    for (path, entry) in &entries {
+        responses
+            .entry((entry.request.method.to_uppercase(), path.clone()))
+            .or_default()
+            .push_back(recorded(&entry.response)?);
+    }
+    let mock = start_mock(responses)
+        .await
+        .map_err(|e| format!("failed to start the mock upstream: {}", e))?;
+
+    // Everything else about the upstream stays, so the recorded URLs are rewritten as usual.
+    let mock_base = format!("http://{}", mock);
+    let mut mocked = (*upstream).clone();
+    mocked.url = Url::parse(&mock_base).map_err(|e| e.to_string())?;
+    mocked.base = mock_base.clone();
+    *state.upstream.write().expect("upstream lock poisoned") = Arc::new(mocked);
+
+    let mut results = Vec::with_capacity(entries.len());
+    let mut differing = 0;
+    for (i, (path, entry)) in entries.iter().enumerate() {
+        let actual = send(
+            &app,
+            path,
+            &entry.request,
+            &args.host,
+            &mock_base,
+            &upstream.base,
+        )
+        .await?;
+        let expected = match &baseline {
+            Some(baseline) => baseline[i].clone(),
+            None => {
+                let recorded = recorded(&entry.response)?;
+                Exchange::new(
+                    &actual.method,
+                    path,
+                    recorded.status,
+                    recorded.headers,
+                    &recorded.body,
+                )
+            }
+        };
+
+        if actual == expected {
+            println!("{} {}: {} unchanged", actual.method, path, actual.status);
+        } else {
+            differing += 1;
+            println!("{} {}: {} differs", actual.method, path, actual.status);
+            let (old, new) = if baseline.is_some() {
+                ("baseline", "replayed")
+            } else {
+                ("upstream", "replayed")
+            };
+            print!(
+                "{}",
+                TextDiff::from_lines(&expected.render(), &actual.render())
+                    .unified_diff()
+                    .context_radius(2)
+                    .header(old, new)
+            );
+        }
+        results.push(actual);
+    }
+
+    println!(
+        "{} requests replayed, {} differ, {} to other hosts skipped",
+        results.len(),
+        differing,
+        total - results.len()
+    );
+
+    if let Some(path) = &args.save {
+        let json = serde_json::to_vec_pretty(&results).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(differing)
+}
+
+/// Path and query of a recorded URL of the upstream, `None` for other hosts.
+fn path_query(url: &str, variants: &[String]) -> Option<String> {
+    variants.iter().find_map(|variant| {
+        let rest = url.strip_prefix(variant.as_str())?;
+        let rest = rest.split('#').next().unwrap_or_default();
+        match rest.chars().next() {
+            None => Some("/".to_string()),
+            Some('/') => Some(rest.to_string()),
+            Some('?') => Some(format!("/{}", rest)),
+            Some(_) => None,
+        }
+    })
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, String> {
+    let data = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_slice(&data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn recorded(response: &HarResponse) -> Result<Recorded, String> {
+    let text = response.content.text.as_deref().unwrap_or_default();
+    let body = if response.content.encoding.as_deref() == Some("base64") {
+        base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .map_err(|e| format!("invalid base64 body in the recording: {}", e))?
+    } else {
+        text.as_bytes().to_vec()
+    };
+    Ok(Recorded {
+        status: response.status,
+        headers: kept_headers(&response.headers),
+        body,
+    })
+}
+
+/// Recorded headers without HTTP/2 pseudo-headers and those of [`SKIPPED_HEADERS`].
+fn kept_headers(headers: &[HarHeader]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|h| {
+            !h.name.starts_with(':')
+                && !SKIPPED_HEADERS
+                    .iter()
+                    .any(|skipped| h.name.eq_ignore_ascii_case(skipped))
+        })
+        .map(|h| (h.name.to_lowercase(), h.value.clone()))
+        .collect()
+}
+
+/// Serves the recorded responses in order, repeating the last one of each
+/// request.
+async fn start_mock(
+    responses: HashMap<(String, String), VecDeque<Recorded>>,
+) -> std::io::Result<SocketAddr> {
+    let responses = Arc::new(Mutex::new(responses));
+    let mock = Router::new().fallback(move |req: Request| {
+        let responses = responses.clone();
+        async move {
+            let path = req
+                .uri()
+                .path_and_query()
+                .map_or("/".to_string(), |pq| pq.to_string());
+            let mut responses = responses.lock().expect("mock lock poisoned");
+            let recorded = match responses.get_mut(&(req.method().to_string(), path)) {
+                Some(queue) if queue.len() > 1 => queue.pop_front(),
+                Some(queue) => queue.front().cloned(),
+                None => None,
+            };
+            match recorded {
+                Some(recorded) => recorded.into_response(),
+                None => (StatusCode::NOT_FOUND, "Not recorded").into_response(),
+            }
+        }
+    });
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, mock).await {
+            tracing::error!("Mock upstream failed: {}", e);
+        }
+    });
+    Ok(addr)
+}
+
+/// Sends a recorded request through the proxy's routes.
+async fn send(
+    app: &Router,
+    path: &str,
+    request: &HarRequest,
+    host: &str,
+    mock_base: &str,
+    upstream_base: &str,
+) -> Result<Exchange, String> {
+    let mut builder = Request::builder()
+        .method(request.method.as_str())
+        .uri(path)
+        .header("host", host);
+    for (name, value) in kept_headers(&request.headers) {
+        builder = builder.header(name, value);
+    }
+    let body = request
+        .post_data
+        .as_ref()
+        .map(|data| data.text.clone())
+        .unwrap_or_default();
+    let mut req = builder
+        .body(Body::from(body))
+        .map_err(|e| format!("invalid recorded request {}: {}", path, e))?;
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))));
+
+    let response = match app.clone().oneshot(req).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
+    };
+    let status = response.status().as_u16();
+    // The mock's random address only leaks where the upstream URL is used as is.
+    let unmock = |value: &str| value.replace(mock_base, upstream_base);
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                unmock(&String::from_utf8_lossy(value.as_bytes())),
+            )
+        })
+        .collect();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|e| format!("failed to read the response to {}: {}", path, e))?;
+    let body = match std::str::from_utf8(&body) {
+        Ok(text) => unmock(text).into_bytes(),
+        Err(_) => body.to_vec(),
+    };
+    Ok(Exchange::new(
+        &request.method.to_uppercase(),
+        path,
+        status,
+        headers,
+        &body,
+    ))
+}
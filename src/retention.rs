@@ -0,0 +1,149 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Retention of persisted data.
+//!
+//! A scheduled job deletes change history, audit entries and API tokens older
+//! than their configured retention, expired bans and snapshot files no crawl
+//! has refreshed, then vacuums the database. Deleted rows, files and the
+//! reclaimed bytes are counted in the metrics.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::{config, db::Db, effective_config, snapshot, state::AppState};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Retention settings. A `None` age keeps the data forever.
+#[derive(Debug, Clone, Serialize)]
+pub struct RetentionConfig {
+    /// Interval of the cleanup job.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub interval: Duration,
+    /// Age after which changes of monitored pages are deleted.
+    #[serde(serialize_with = "effective_config::opt_secs")]
+    pub changes: Option<Duration>,
+    /// Age after which audit entries are deleted.
+    #[serde(serialize_with = "effective_config::opt_secs")]
+    pub audit: Option<Duration>,
+    /// Age after which API tokens expire.
+    #[serde(serialize_with = "effective_config::opt_secs")]
+    pub tokens: Option<Duration>,
+    /// Age after which files in `OFFLINE_DIR` not rewritten by a snapshot are deleted.
+    #[serde(serialize_with = "effective_config::opt_secs")]
+    pub snapshots: Option<Duration>,
+}
+
+impl RetentionConfig {
+    /// # Environment Variables
+    /// * `RETENTION_INTERVAL_SECS` - Interval of the cleanup job (default: 86400).
+    /// * `RETENTION_CHANGES_DAYS` - Days the change history is kept, 0 for ever (default: 90).
+    /// * `RETENTION_AUDIT_DAYS` - Days audit entries are kept, 0 for ever (default: 0).
+    /// * `RETENTION_TOKEN_DAYS` - Days after which API tokens expire, 0 for never (default: 0).
+    /// * `RETENTION_SNAPSHOT_DAYS` - Days after which snapshot files no crawl refreshed are deleted, 0 for never (default: 0).
+    pub fn from_env() -> Self {
+        Self {
+            interval: Duration::from_secs(
+                config::env_parse("RETENTION_INTERVAL_SECS").unwrap_or(86400),
+            ),
+            changes: days("RETENTION_CHANGES_DAYS", 90),
+            audit: days("RETENTION_AUDIT_DAYS", 0),
+            tokens: days("RETENTION_TOKEN_DAYS", 0),
+            snapshots: days("RETENTION_SNAPSHOT_DAYS", 0),
+        }
+    }
+}
+
+fn days(name: &str, default: u32) -> Option<Duration> {
+    let days = config::env_parse(name).unwrap_or(default);
+    (days > 0).then(|| DAY * days)
+}
+
+/// Runs the cleanup once.
+pub async fn run(state: &AppState) {
+    let config = &state.config.retention;
+
+    if let Some(db) = &state.db
+        && let Err(e) = clean_database(state, db, config).await
+    {
+        tracing::error!("Retention cleanup of the database failed: {}", e);
+    }
+
+    if let (Some(age), Some(dir)) = (config.snapshots, &state.config.offline_dir) {
+        match snapshot::delete_stale(dir, SystemTime::now() - age).await {
+            Ok((files, bytes)) => {
+                if files > 0 {
+                    tracing::info!("Retention: deleted {} stale snapshot files", files);
+                }
+                state
+                    .metrics
+                    .retention_files
+                    .fetch_add(files, std::sync::atomic::Ordering::Relaxed);
+                state.metrics.retention_reclaimed("snapshots", bytes);
+            }
+            Err(e) => tracing::error!("Retention cleanup of {} failed: {}", dir.display(), e),
+        }
+    }
+}
+
+async fn clean_database(
+    state: &AppState,
+    db: &Db,
+    config: &RetentionConfig,
+) -> Result<(), sqlx::Error> {
+    let mut deleted = 0;
+
+    if let Some(age) = config.changes {
+        let before = cutoff(age);
+        let rows = db.delete_changes_before(before).await?;
+        state.watch.prune(before);
+        state.metrics.retention_deleted("changes", rows);
+        deleted += rows;
+    }
+    if let Some(age) = config.audit {
+        let rows = db.delete_audit_before(cutoff(age)).await?;
+        state.metrics.retention_deleted("audit_log", rows);
+        deleted += rows;
+    }
+    if let Some(age) = config.tokens {
+        let rows = db.delete_tokens_before(cutoff(age)).await?;
+        state.metrics.retention_deleted("api_tokens", rows);
+        deleted += rows;
+    }
+    let rows = db.delete_expired_bans().await?;
+    state.metrics.retention_deleted("bans", rows);
+    deleted += rows;
+
+    if deleted > 0 {
+        let reclaimed = db.vacuum().await?;
+        state.metrics.retention_reclaimed("database", reclaimed);
+        tracing::info!(
+            "Retention: deleted {} rows, vacuum reclaimed {} bytes",
+            deleted,
+            reclaimed
+        );
+    }
+    Ok(())
+}
+
+/// Unix timestamp `age` ago.
+fn cutoff(age: Duration) -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .saturating_sub(age)
+        .as_secs()
+}
@@ -0,0 +1,153 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! `GET /_admin/diff`: what the proxy changes on a real upstream page.
+//!
+//! The page is fetched once and the upstream response is run through the same
+//! rewriting as proxied responses, so the diff shows exactly what clients get.
+
+use axum::{
+    Extension, Json,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use reqwest::{ResponseBuilderExt, Url};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+
+use crate::{handlers, state::AppState, tls::Https, utils};
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RewriteDiff {
+    /// Final upstream URL.
+    url: String,
+    status: u16,
+    /// Headers as `name: value` lines, before and after rewriting.
+    original_headers: String,
+    rewritten_headers: String,
+    original: String,
+    rewritten: String,
+    /// Unified diff of the headers and the body.
+    diff: String,
+}
+
+/// Handler for `GET /_admin/diff?path=...`.
+///
+/// The client's cookies are forwarded, so pages behind the upstream login can
+/// be compared too.
+pub async fn handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    https: Option<Extension<Https>>,
+    Query(query): Query<DiffQuery>,
+) -> Response {
+    if !query.path.starts_with('/') || query.path.starts_with("//") {
+        return (StatusCode::BAD_REQUEST, "Path must start with a single '/'").into_response();
+    }
+    let upstream = state.upstream();
+    let url = match Url::parse(&format!("{}{}", upstream.base, query.path)) {
+        Ok(url) => url,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Invalid path: {}", e)).into_response(),
+    };
+
+    let mut upstream_headers = HeaderMap::new();
+    for cookie in headers.get_all("cookie") {
+        upstream_headers.append("cookie", cookie.clone());
+    }
+    utils::prepare_request_headers(&mut upstream_headers, &state);
+    let fetched = match state.client.get(url).headers(upstream_headers).send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            tracing::error!("Upstream request failed: {}", e);
+            return (StatusCode::BAD_GATEWAY, format!("Proxy Error: {}", e)).into_response();
+        }
+    };
+    let status = fetched.status();
+    let url = fetched.url().clone();
+    let original_headers = fetched.headers().clone();
+    let original = match fetched.bytes().await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("Failed to read response body: {}", e);
+            return (StatusCode::BAD_GATEWAY, "Failed to read body").into_response();
+        }
+    };
+
+    // Replay the response through the proxy's rewriting.
+    let mut replay = axum::http::Response::builder()
+        .status(status)
+        .url(url.clone());
+    if let Some(replay_headers) = replay.headers_mut() {
+        *replay_headers = original_headers.clone();
+    }
+    let replay = match replay.body(original.clone()) {
+        Ok(replay) => reqwest::Response::from(replay),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let proxy_origin =
+        utils::determine_proxy_origin(state.config.base_url.as_deref(), &headers, https.is_some())
+            + &upstream.prefix;
+    let rewritten = handlers::process_response(
+        replay,
+        &proxy_origin,
+        &state,
+        &headers,
+        Vec::new(),
+        Vec::new(),
+        None,
+    )
+    .await;
+    let rewritten_headers = rewritten.headers().clone();
+    let rewritten = match axum::body::to_bytes(rewritten.into_body(), usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+
+    let original_headers = header_lines(&original_headers);
+    let rewritten_headers = header_lines(&rewritten_headers);
+    let original = String::from_utf8_lossy(&original).into_owned();
+    let rewritten = String::from_utf8_lossy(&rewritten).into_owned();
+    let diff = TextDiff::from_lines(
+        &format!("{}\n{}", original_headers, original),
+        &format!("{}\n{}", rewritten_headers, rewritten),
+    )
+    .unified_diff()
+    .context_radius(3)
+    .header("upstream", "rewritten")
+    .to_string();
+
+    Json(RewriteDiff {
+        url: url.to_string(),
+        status: status.as_u16(),
+        original_headers,
+        rewritten_headers,
+        original,
+        rewritten,
+        diff,
+    })
+    .into_response()
+}
+
+fn header_lines(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}\n", name, String::from_utf8_lossy(value.as_bytes())))
+        .collect()
+}
@@ -0,0 +1,105 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Sizing of the Tokio runtime.
+//!
+//! By default there is one worker thread per CPU core and up to 512 threads
+//! for blocking work (file access, compression). On a small ARM board a single
+//! thread serves a class just fine with less memory, while a busy server
+//! may want to pin the number of workers below the core count.
+
+use std::io;
+
+use serde::Serialize;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::config;
+
+/// Which scheduler the runtime uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Flavor {
+    /// Tasks are spread over a pool of worker threads.
+    MultiThread,
+    /// All tasks run on the main thread.
+    CurrentThread,
+}
+
+/// Runtime settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuntimeConfig {
+    pub flavor: Flavor,
+    /// Worker threads of the multi-threaded runtime, the number of CPU cores if not set.
+    pub worker_threads: Option<usize>,
+    /// Maximum number of threads for blocking operations.
+    pub max_blocking_threads: usize,
+}
+
+impl RuntimeConfig {
+    /// # Environment Variables
+    /// * `RUNTIME_FLAVOR` - `multi-thread` or `current-thread` (default: "multi-thread").
+    /// * `RUNTIME_WORKER_THREADS` - Worker threads (default: number of CPU cores).
+    /// * `RUNTIME_MAX_BLOCKING_THREADS` - Blocking thread pool limit (default: 512).
+    pub fn from_env() -> Self {
+        let flavor = match std::env::var("RUNTIME_FLAVOR")
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase()
+            .as_str()
+        {
+            "" | "multi-thread" => Flavor::MultiThread,
+            "current-thread" => Flavor::CurrentThread,
+            other => {
+                tracing::warn!("Unknown RUNTIME_FLAVOR value: {}", other);
+                Flavor::MultiThread
+            }
+        };
+        let worker_threads = config::env_parse("RUNTIME_WORKER_THREADS").filter(|&n: &usize| {
+            if n == 0 {
+                tracing::warn!("Ignoring RUNTIME_WORKER_THREADS=0");
+            }
+            n > 0
+        });
+        if worker_threads.is_some() && flavor == Flavor::CurrentThread {
+            tracing::warn!(
+                "RUNTIME_WORKER_THREADS has no effect with RUNTIME_FLAVOR=current-thread"
+            );
+        }
+
+        Self {
+            flavor,
+            worker_threads,
+            max_blocking_threads: config::env_parse("RUNTIME_MAX_BLOCKING_THREADS")
+                .unwrap_or(512)
+                .max(1),
+        }
+    }
+
+    /// Builds the runtime the proxy runs on.
+    pub fn build(&self) -> io::Result<Runtime> {
+        let mut builder = match self.flavor {
+            Flavor::MultiThread => Builder::new_multi_thread(),
+            Flavor::CurrentThread => Builder::new_current_thread(),
+        };
+        if let Some(threads) = self.worker_threads
+            && self.flavor == Flavor::MultiThread
+        {
+            builder.worker_threads(threads);
+        }
+        builder
+            .max_blocking_threads(self.max_blocking_threads)
+            .enable_all()
+            .build()
+    }
+}
@@ -0,0 +1,89 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Detailed logging of sampled requests, for debugging rewriting in production.
+//!
+//! A sampled request logs its headers, the upstream response headers, the
+//! rewritten headers and optionally the start of the body before and after
+//! rewriting, all tagged with the same sample id. Everything still goes
+//! through [`LogPrivacy`](crate::privacy::LogPrivacy), so secrets stay masked.
+
+use std::{borrow::Cow, fmt};
+
+use serde::Serialize;
+
+use crate::config;
+
+/// Which requests are logged in detail.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SamplingConfig {
+    /// Log one in `rate` requests, 0 disables random sampling.
+    pub rate: u32,
+    /// Path prefixes of requests that are always sampled.
+    pub paths: Vec<String>,
+    /// How much of each body is logged, 0 logs no bodies.
+    pub body_bytes: usize,
+}
+
+impl SamplingConfig {
+    /// # Environment Variables
+    /// * `LOG_SAMPLE_RATE` - Log one in N requests in detail (default: 0, disabled).
+    /// * `LOG_SAMPLE_PATHS` - Comma-separated path prefixes always logged in detail.
+    /// * `LOG_SAMPLE_BODY_BYTES` - Bytes of each body logged for sampled requests
+    ///   (default: 0, bodies aren't logged). Bodies may contain personal data.
+    pub fn from_env() -> Self {
+        Self {
+            rate: config::env_parse("LOG_SAMPLE_RATE").unwrap_or(0),
+            paths: config::env_list("LOG_SAMPLE_PATHS"),
+            body_bytes: config::env_parse("LOG_SAMPLE_BODY_BYTES").unwrap_or(0),
+        }
+    }
+
+    /// Decides whether the request for `path` is logged in detail.
+    pub fn sample(&self, path: &str) -> Option<Sample> {
+        let sampled = self
+            .paths
+            .iter()
+            .any(|prefix| path.starts_with(prefix.as_str()))
+            || (self.rate > 0 && rand::random_range(0..self.rate) == 0);
+        sampled.then(|| Sample(rand::random()))
+    }
+
+    /// The logged start of a body, `None` if bodies aren't logged.
+    pub fn excerpt<'a>(&self, body: &'a [u8]) -> Option<Cow<'a, str>> {
+        if self.body_bytes == 0 {
+            return None;
+        }
+        let excerpt = String::from_utf8_lossy(&body[..body.len().min(self.body_bytes)]);
+        if body.len() > self.body_bytes {
+            Some(Cow::Owned(format!(
+                "{}... ({} bytes total)",
+                excerpt,
+                body.len()
+            )))
+        } else {
+            Some(excerpt)
+        }
+    }
+}
+
+/// Id of a sampled request, shared by all of its log lines.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample(u32);
+
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}", self.0)
+    }
+}
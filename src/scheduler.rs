@@ -0,0 +1,219 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Periodic background jobs.
+//!
+//! Every job runs once at startup and then every `interval` plus a random
+//! jitter (so replicas and jobs don't hit the upstream at the same moment).
+//! A run is skipped if the previous one is still in progress.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{config, crawler, retention, search, snapshot, state::AppState, vault, watcher};
+
+type JobFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+type JobFn = Arc<dyn Fn(AppState) -> JobFuture + Send + Sync>;
+
+/// Run statistics of a job, as reported by the admin API.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobStatus {
+    pub name: &'static str,
+    pub interval_secs: u64,
+    pub running: bool,
+    pub runs: u64,
+    /// Runs skipped because the previous one was still in progress.
+    pub skipped: u64,
+    /// Unix timestamp of the last start.
+    pub last_started_at: Option<u64>,
+    pub last_duration_ms: Option<u64>,
+}
+
+struct Job {
+    name: &'static str,
+    interval: Duration,
+    run: JobFn,
+    running: AtomicBool,
+    status: Mutex<JobStatus>,
+}
+
+/// Registry of periodic jobs.
+#[derive(Default)]
+pub struct Scheduler {
+    jobs: Mutex<Vec<Arc<Job>>>,
+}
+
+impl Scheduler {
+    /// Registers a job. Jobs only start running after [`Scheduler::start`].
+    pub fn add<F, Fut>(&self, name: &'static str, interval: Duration, run: F)
+    where
+        F: Fn(AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let job = Job {
+            name,
+            interval,
+            run: Arc::new(move |state| Box::pin(run(state))),
+            running: AtomicBool::new(false),
+            status: Mutex::new(JobStatus {
+                name,
+                interval_secs: interval.as_secs(),
+                ..JobStatus::default()
+            }),
+        };
+        self.jobs.lock().unwrap().push(Arc::new(job));
+    }
+
+    /// Returns the status of all jobs.
+    pub fn status(&self) -> Vec<JobStatus> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|job| {
+                let mut status = job.status.lock().unwrap().clone();
+                status.running = job.running.load(Ordering::SeqCst);
+                status
+            })
+            .collect()
+    }
+
+    /// Spawns the timer loop of every registered job.
+    pub fn start(&self, state: &AppState) {
+        let jitter = state.config.scheduler_jitter;
+
+        for job in self.jobs.lock().unwrap().iter() {
+            tracing::info!("Scheduling job {} every {:?}", job.name, job.interval);
+
+            let job = job.clone();
+            let state = state.clone();
+            tokio::spawn(async move {
+                loop {
+                    trigger(&job, &state);
+                    tokio::time::sleep(job.interval + random_jitter(jitter)).await;
+                }
+            });
+        }
+    }
+}
+
+fn trigger(job: &Arc<Job>, state: &AppState) {
+    if job.running.swap(true, Ordering::SeqCst) {
+        tracing::warn!("Job {} is still running, skipping this run", job.name);
+        job.status.lock().unwrap().skipped += 1;
+        return;
+    }
+
+    let job = job.clone();
+    let state = state.clone();
+    tokio::spawn(async move {
+        let started = Instant::now();
+        {
+            let mut status = job.status.lock().unwrap();
+            status.runs += 1;
+            status.last_started_at = Some(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            );
+        }
+
+        (job.run)(state).await;
+
+        job.status.lock().unwrap().last_duration_ms = Some(started.elapsed().as_millis() as u64);
+        job.running.store(false, Ordering::SeqCst);
+    });
+}
+
+fn random_jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(rand::random_range(0..=max.as_millis() as u64))
+}
+
+/// Registers all jobs enabled in the configuration.
+pub fn register_jobs(state: &AppState) {
+    let config = &state.config;
+    let scheduler = &state.scheduler;
+
+    if let Some(search) = &config.search {
+        scheduler.add(
+            "search-index",
+            search.refresh_interval,
+            |state| async move {
+                search::rebuild(&state).await;
+            },
+        );
+    }
+
+    if let Some(watch) = &config.watch {
+        let watch = watch.clone();
+        scheduler.add("change-watcher", watch.interval, move |state| {
+            let watch = watch.clone();
+            async move { watcher::check(&state, &watch).await }
+        });
+    }
+
+    if let (Some(vault), Some(_)) = (&config.vault, &state.db) {
+        scheduler.add("grade-check", vault.check_interval, |state| async move {
+            vault::check_grades(&state).await;
+        });
+        if vault.weekly_digest {
+            scheduler.add(
+                "weekly-digest",
+                Duration::from_secs(60 * 60),
+                |state| async move {
+                    vault::send_digests(&state).await;
+                },
+            );
+        }
+    }
+
+    if let (Some(interval), Some(dir)) = (config.snapshot_interval, &config.offline_dir) {
+        let dir = dir.clone();
+        scheduler.add("snapshot", interval, move |state| {
+            let dir = dir.clone();
+            async move {
+                let pages = crawler::crawl(&state, &state.config.crawl).await;
+                match snapshot::write(&state, &pages, &dir).await {
+                    Ok(written) => tracing::info!("Snapshot job wrote {} files", written),
+                    Err(e) => tracing::error!("Snapshot job failed: {}", e),
+                }
+            }
+        });
+    }
+
+    let retention = &config.retention;
+    if state.db.is_some() || (retention.snapshots.is_some() && config.offline_dir.is_some()) {
+        scheduler.add("retention", retention.interval, |state| async move {
+            retention::run(&state).await;
+        });
+    }
+}
+
+/// Reads `SCHEDULER_JITTER_SECS` (default: 30).
+pub fn jitter_from_env() -> Duration {
+    Duration::from_secs(config::env_parse("SCHEDULER_JITTER_SECS").unwrap_or(30))
+}
@@ -0,0 +1,229 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Full-text search over crawled upstream pages.
+//!
+//! The index lives in memory and is rebuilt from a fresh crawl periodically.
+//! Czech text is folded to ASCII so "suplovani" finds "suplování".
+
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::Serialize;
+use tantivy::{
+    Index, IndexReader, IndexWriter, TantivyDocument,
+    collector::TopDocs,
+    doc,
+    query::QueryParser,
+    schema::{
+        Field, IndexRecordOption, STORED, STRING, Schema, TextFieldIndexing, TextOptions, Value,
+    },
+    snippet::SnippetGenerator,
+    tokenizer::{AsciiFoldingFilter, LowerCaser, RemoveLongFilter, SimpleTokenizer, TextAnalyzer},
+};
+
+use crate::{
+    config,
+    crawler::{self, CrawledPage},
+    db, effective_config,
+    extract::{self, Format},
+    state::AppState,
+};
+
+const TOKENIZER: &str = "cs";
+
+/// Search settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchConfig {
+    /// How often the index is rebuilt from a fresh crawl.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub refresh_interval: Duration,
+}
+
+impl SearchConfig {
+    /// # Environment Variables
+    /// * `SEARCH_ENABLED` - Set to "true" or "1" to crawl and index the upstream.
+    /// * `SEARCH_REFRESH_SECS` - Rebuild interval (default: 86400).
+    pub fn from_env() -> Option<Self> {
+        if !config::env_flag("SEARCH_ENABLED") {
+            return None;
+        }
+
+        Some(Self {
+            refresh_interval: Duration::from_secs(
+                config::env_parse("SEARCH_REFRESH_SECS").unwrap_or(86400),
+            ),
+        })
+    }
+}
+
+/// Holder of the current index, swapped atomically after each rebuild.
+#[derive(Default)]
+pub struct SearchState {
+    current: RwLock<Option<Arc<SearchIndex>>>,
+}
+
+impl SearchState {
+    pub fn get(&self) -> Option<Arc<SearchIndex>> {
+        self.current.read().unwrap().clone()
+    }
+
+    fn replace(&self, index: SearchIndex) {
+        *self.current.write().unwrap() = Some(Arc::new(index));
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    /// Path of the page on the upstream (and the proxy).
+    pub path: String,
+    pub title: String,
+    pub snippet: String,
+    /// The snippet with matched terms wrapped in `<b>`.
+    pub highlighted: String,
+    pub score: f32,
+}
+
+/// An in-memory index of crawled pages.
+pub struct SearchIndex {
+    index: Index,
+    reader: IndexReader,
+    path: Field,
+    title: Field,
+    body: Field,
+}
+
+impl SearchIndex {
+    /// Indexes the HTML pages among `pages`.
+    pub fn build(pages: &[CrawledPage], base: &Url) -> tantivy::Result<Self> {
+        let text = TextOptions::default()
+            .set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(TOKENIZER)
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            )
+            .set_stored();
+
+        let mut schema = Schema::builder();
+        let path = schema.add_text_field("path", STRING | STORED);
+        let title = schema.add_text_field("title", text.clone());
+        let body = schema.add_text_field("body", text);
+
+        let index = Index::create_in_ram(schema.build());
+        index.tokenizers().register(
+            TOKENIZER,
+            TextAnalyzer::builder(SimpleTokenizer::default())
+                .filter(RemoveLongFilter::limit(40))
+                .filter(LowerCaser)
+                .filter(AsciiFoldingFilter)
+                .build(),
+        );
+
+        let mut writer: IndexWriter = index.writer(15_000_000)?;
+        for page in pages.iter().filter(|p| p.is_html()) {
+            let html = String::from_utf8_lossy(&page.body);
+            let Ok(url) = base.join(&page.path) else {
+                continue;
+            };
+            writer.add_document(doc!(
+                path => page.path.clone(),
+                title => page_title(&html),
+                body => extract::render_page(&html, &url, Format::Text),
+            ))?;
+        }
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        Ok(Self {
+            index,
+            reader,
+            path,
+            title,
+            body,
+        })
+    }
+
+    /// Returns the best matching pages for a user query.
+    pub fn search(&self, query: &str, limit: usize) -> tantivy::Result<Vec<SearchHit>> {
+        let searcher = self.reader.searcher();
+
+        let mut parser = QueryParser::for_index(&self.index, vec![self.title, self.body]);
+        parser.set_field_boost(self.title, 2.0);
+        parser.set_conjunction_by_default();
+        let (query, _) = parser.parse_query_lenient(query);
+
+        let snippets = SnippetGenerator::create(&searcher, &query, self.body)?;
+        let top = searcher.search(&query, &TopDocs::with_limit(limit))?;
+
+        let mut hits = Vec::with_capacity(top.len());
+        for (score, address) in top {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let field = |f: Field| {
+                doc.get_first(f)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let snippet = snippets.snippet_from_doc(&doc);
+
+            hits.push(SearchHit {
+                path: field(self.path),
+                title: field(self.title),
+                snippet: snippet.fragment().to_string(),
+                highlighted: snippet.to_html(),
+                score,
+            });
+        }
+
+        Ok(hits)
+    }
+}
+
+fn page_title(html: &str) -> String {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse("title, h1").expect("valid selector");
+    document
+        .select(&selector)
+        .next()
+        .map(|el| el.text().collect::<Vec<_>>().join(" ").trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Crawls the upstream and swaps in a fresh index.
+pub async fn rebuild(state: &AppState) {
+    let pages = crawler::crawl(state, &state.config.crawl).await;
+    if let Some(db) = &state.db {
+        db::log_error(db.save_pages(&pages).await, "crawled pages");
+    }
+    index_pages(state, pages).await;
+}
+
+/// Builds an index from `pages` and swaps it in.
+pub async fn index_pages(state: &AppState, pages: Vec<CrawledPage>) {
+    let base = state.upstream().url.clone();
+    let count = pages.len();
+
+    match tokio::task::spawn_blocking(move || SearchIndex::build(&pages, &base)).await {
+        Ok(Ok(index)) => {
+            state.search.replace(index);
+            tracing::info!("Search index rebuilt from {} resources", count);
+        }
+        Ok(Err(e)) => tracing::error!("Failed to build search index: {}", e),
+        Err(e) => tracing::error!("Search indexing task failed: {}", e),
+    }
+}
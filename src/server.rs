@@ -0,0 +1,357 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! TCP accept loop.
+//!
+//! Replaces `axum::serve` so client connections can be given timeouts: slow-drip
+//! clients (Slowloris) must send their request headers within
+//! `CLIENT_HEADER_TIMEOUT_SECS` and may not pause a request body for longer than
+//! `CLIENT_BODY_IDLE_TIMEOUT_SECS`. Connections from a client IP beyond
+//! `MAX_CONNECTIONS_PER_IP` are closed right after being accepted.
+//!
+//! With `HTTPS_PORT` a second listener terminates TLS itself, sharing the
+//! connection limits with the plain one.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex, atomic::Ordering},
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Request},
+};
+use http_body::{Frame, SizeHint};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto,
+};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    time::Sleep,
+};
+use tokio_rustls::TlsAcceptor;
+use tower::Service;
+
+use crate::{
+    bandwidth::{Bucket, ConnectionBucket},
+    config, effective_config,
+    metrics::Metrics,
+    state::AppState,
+    tls::Https,
+};
+
+/// Client and upstream timeouts.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimeoutConfig {
+    /// Time a client has to send the request headers.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub client_header: Duration,
+    /// Longest pause allowed while a client sends the request body.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub client_body_idle: Duration,
+    /// Time to establish a connection to the upstream.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub upstream_connect: Duration,
+    /// Longest pause allowed while reading an upstream response.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub upstream_read: Duration,
+    /// Overall deadline of a proxied request, see [`crate::deadline`].
+    #[serde(serialize_with = "effective_config::opt_secs")]
+    pub request: Option<Duration>,
+}
+
+impl TimeoutConfig {
+    /// # Environment Variables
+    /// * `CLIENT_HEADER_TIMEOUT_SECS` - Time to receive request headers (default: 10).
+    /// * `CLIENT_BODY_IDLE_TIMEOUT_SECS` - Request body idle timeout (default: 30).
+    /// * `UPSTREAM_CONNECT_TIMEOUT_SECS` - Upstream connect timeout (default: 10).
+    /// * `UPSTREAM_READ_TIMEOUT_SECS` - Upstream read idle timeout (default: 30).
+    /// * `REQUEST_TIMEOUT_SECS` - Overall deadline of proxied requests, 0 for none (default: 0).
+    pub fn from_env() -> Self {
+        let secs = |name, default| Duration::from_secs(config::env_parse(name).unwrap_or(default));
+        Self {
+            client_header: secs("CLIENT_HEADER_TIMEOUT_SECS", 10),
+            client_body_idle: secs("CLIENT_BODY_IDLE_TIMEOUT_SECS", 30),
+            upstream_connect: secs("UPSTREAM_CONNECT_TIMEOUT_SECS", 10),
+            upstream_read: secs("UPSTREAM_READ_TIMEOUT_SECS", 30),
+            request: Some(secs("REQUEST_TIMEOUT_SECS", 0)).filter(|d| !d.is_zero()),
+        }
+    }
+}
+
+/// Serves `app` on `listener`, and over TLS on `https` if given, until the process exits.
+pub async fn serve(
+    listener: TcpListener,
+    https: Option<(TcpListener, TlsAcceptor)>,
+    app: Router,
+    state: &AppState,
+) {
+    let connections = Arc::new(Connections {
+        // Behind a reverse proxy every connection comes from the proxy's address.
+        limit: if state.config.trust_forwarded_for {
+            0
+        } else {
+            state.config.max_connections_per_ip
+        },
+        open: Mutex::new(HashMap::new()),
+        metrics: state.metrics.clone(),
+    });
+
+    // hyper rejects requests over these limits with 431 before they reach `limits::enforce`.
+    let header_limits = &state.config.header_limits;
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .header_read_timeout(state.config.timeouts.client_header)
+        .max_headers(header_limits.request_count);
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_header_list_size(header_limits.request_bytes.try_into().unwrap_or(u32::MAX));
+
+    let server = Server {
+        app,
+        state,
+        builder,
+        connections,
+    };
+    match https {
+        Some((tls_listener, acceptor)) => {
+            tokio::join!(
+                server.accept(listener, None),
+                server.accept(tls_listener, Some(acceptor))
+            );
+        }
+        None => server.accept(listener, None).await,
+    }
+}
+
+struct Server<'a> {
+    app: Router,
+    state: &'a AppState,
+    builder: auto::Builder<TokioExecutor>,
+    connections: Arc<Connections>,
+}
+
+impl Server<'_> {
+    async fn accept(&self, listener: TcpListener, tls: Option<TlsAcceptor>) {
+        let timeouts = &self.state.config.timeouts;
+        loop {
+            let (stream, remote) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    // Usually running out of file descriptors, back off instead of spinning.
+                    tracing::error!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            let Some(slot) = self.connections.acquire(remote.ip()) else {
+                tracing::debug!(
+                    "Rejecting connection from {}: too many open connections",
+                    self.state.config.privacy.ip(remote.ip())
+                );
+                continue;
+            };
+
+            let builder = self.builder.clone();
+            let app = self.app.clone();
+            let tls = tls.clone();
+            let handshake_timeout = timeouts.client_header;
+            let body_idle = timeouts.client_body_idle;
+            let bandwidth = self
+                .state
+                .config
+                .bandwidth
+                .per_connection
+                .map(|rate| ConnectionBucket(Arc::new(Bucket::new(rate))));
+            tokio::spawn(async move {
+                let result = match tls {
+                    None => {
+                        serve_connection(builder, stream, app, remote, body_idle, bandwidth, false)
+                            .await
+                    }
+                    // The handshake counts towards the header timeout.
+                    Some(acceptor) => {
+                        match tokio::time::timeout(handshake_timeout, acceptor.accept(stream)).await
+                        {
+                            Ok(Ok(stream)) => {
+                                serve_connection(
+                                    builder, stream, app, remote, body_idle, bandwidth, true,
+                                )
+                                .await
+                            }
+                            Ok(Err(e)) => Err(e.into()),
+                            Err(_) => Err("TLS handshake timed out".into()),
+                        }
+                    }
+                };
+                if let Err(e) = result {
+                    tracing::debug!("Connection from {} closed: {}", remote, e);
+                }
+                drop(slot);
+            });
+        }
+    }
+}
+
+async fn serve_connection<I>(
+    builder: auto::Builder<TokioExecutor>,
+    io: I,
+    app: Router,
+    remote: SocketAddr,
+    body_idle: Duration,
+    bandwidth: Option<ConnectionBucket>,
+    https: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = hyper::service::service_fn(move |req: hyper::Request<Incoming>| {
+        let mut req: Request = req.map(|body| Body::new(IdleTimeout::new(body, body_idle)));
+        req.extensions_mut().insert(ConnectInfo(remote));
+        if let Some(bucket) = &bandwidth {
+            req.extensions_mut().insert(bucket.clone());
+        }
+        if https {
+            req.extensions_mut().insert(Https);
+        }
+        app.clone().call(req)
+    });
+
+    builder
+        .serve_connection_with_upgrades(TokioIo::new(io), service)
+        .await
+}
+
+/// Open connections per client IP.
+struct Connections {
+    /// Maximum connections per IP, 0 for no limit.
+    limit: usize,
+    open: Mutex<HashMap<IpAddr, usize>>,
+    metrics: Arc<Metrics>,
+}
+
+impl Connections {
+    /// Takes a connection slot of `ip`, or `None` if it has too many open connections.
+    fn acquire(self: &Arc<Self>, ip: IpAddr) -> Option<Slot> {
+        self.metrics
+            .connections_accepted
+            .fetch_add(1, Ordering::Relaxed);
+        {
+            let mut open = self.open.lock().expect("connections lock poisoned");
+            let count = open.entry(ip).or_default();
+            if self.limit > 0 && *count >= self.limit {
+                self.metrics
+                    .connections_rejected
+                    .fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+            *count += 1;
+        }
+        self.metrics
+            .connections_open
+            .fetch_add(1, Ordering::Relaxed);
+        Some(Slot {
+            connections: self.clone(),
+            ip,
+        })
+    }
+}
+
+/// A connection slot, released when dropped.
+struct Slot {
+    connections: Arc<Connections>,
+    ip: IpAddr,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut open = self
+            .connections
+            .open
+            .lock()
+            .expect("connections lock poisoned");
+        if let Some(count) = open.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&self.ip);
+            }
+        }
+        self.connections
+            .metrics
+            .connections_open
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A request body failing when the client sends nothing for too long.
+struct IdleTimeout {
+    inner: Incoming,
+    timeout: Duration,
+    /// Armed while the body is polled and nothing has arrived, so a handler
+    /// reading the body late doesn't run into the timeout.
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl IdleTimeout {
+    fn new(inner: Incoming, timeout: Duration) -> Self {
+        Self {
+            inner,
+            timeout,
+            sleep: None,
+        }
+    }
+}
+
+impl http_body::Body for IdleTimeout {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        if let Poll::Ready(frame) = Pin::new(&mut self.inner).poll_frame(cx) {
+            self.sleep = None;
+            return Poll::Ready(frame.map(|f| f.map_err(axum::Error::new)));
+        }
+
+        let timeout = self.timeout;
+        let sleep = self
+            .sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+        ready!(sleep.as_mut().poll(cx));
+        Poll::Ready(Some(Err(axum::Error::new("request body idle timeout"))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
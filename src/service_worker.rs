@@ -0,0 +1,74 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Upstream service workers and web app manifests.
+//!
+//! Manifests get their URLs rewritten like any other text response, and the
+//! `Service-Worker-Allowed` header is pointed at the proxy. With
+//! `BLOCK_SERVICE_WORKERS` the upstream's worker scripts are replaced by one
+//! that unregisters itself, so workers installed earlier are removed as well.
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{assets, state::AppState, utils};
+
+/// Whether the request fetches a service worker script (`Service-Worker: script`).
+pub fn is_worker_request(headers: &HeaderMap) -> bool {
+    headers
+        .get("service-worker")
+        .is_some_and(|v| v.as_bytes() == b"script")
+}
+
+/// Whether a response is a web app manifest, by content type or file name.
+pub fn is_manifest(content_type: &str, path: &str) -> bool {
+    content_type.contains("application/manifest+json")
+        || path.ends_with(".webmanifest")
+        || path.ends_with("/manifest.json")
+}
+
+/// Rewrites the `Service-Worker-Allowed` scope of an upstream response to the proxy.
+pub fn rewrite_headers(headers: &mut HeaderMap, proxy_origin: &str, state: &AppState) {
+    if let Some(value) = headers.get("service-worker-allowed")
+        && let Ok(scope) = value.to_str()
+        && let Ok(rewritten) =
+            HeaderValue::from_str(&utils::rewrite_location(scope, proxy_origin, state))
+    {
+        headers.insert("service-worker-allowed", rewritten);
+    }
+}
+
+/// Middleware answering service worker script requests when `BLOCK_SERVICE_WORKERS` is set.
+pub async fn block(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if !state.config.block_service_workers || !is_worker_request(req.headers()) {
+        return next.run(req).await;
+    }
+
+    tracing::debug!(
+        "Replacing service worker {}",
+        state.config.privacy.url(&req.uri().to_string())
+    );
+    (
+        [
+            (header::CONTENT_TYPE, "text/javascript; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        assets::text("unregister-sw.js"),
+    )
+        .into_response()
+}
@@ -0,0 +1,393 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Expiring signed share links.
+//!
+//! `POST /_share` (admin token required) returns a link to a single proxied page
+//! carrying a `_share=<expires>.<hmac>` query parameter. Requests with a valid
+//! signature skip the forward-auth gate, so one page (e.g. this week's substitution
+//! plan) can be shared publicly from an otherwise private mirror. Static assets
+//! under the `SHARE_ASSET_PREFIXES` are public as well, so shared pages keep
+//! their styles and images. Anything the client sends besides the signed URL
+//! (such as the `Referer`) is never trusted.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::{
+    Extension, Json,
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    audit::{self, Actor},
+    config, effective_config,
+    state::AppState,
+    tls::Https,
+    utils,
+};
+
+const PARAM: &str = "_share";
+
+const DEFAULT_ASSET_PREFIXES: &[&str] = &["/css/", "/js/", "/img/", "/fonts/", "/favicon.ico"];
+
+/// Share link settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShareConfig {
+    /// HMAC key used to sign links.
+    #[serde(serialize_with = "effective_config::redacted")]
+    pub secret: Vec<u8>,
+    /// Validity of links created without an explicit TTL.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub default_ttl: Duration,
+    /// Path prefixes of static assets served without a signature.
+    pub asset_prefixes: Vec<String>,
+}
+
+impl ShareConfig {
+    /// # Environment Variables
+    /// * `SHARE_SECRET` - Signing key. Share links are disabled when unset.
+    /// * `SHARE_DEFAULT_TTL_SECS` - Default link validity (default: 86400).
+    /// * `SHARE_ASSET_PREFIXES` - Comma-separated public asset path prefixes
+    ///   (default: "/css/,/js/,/img/,/fonts/,/favicon.ico").
+    pub fn from_env() -> Option<Self> {
+        let secret = std::env::var("SHARE_SECRET")
+            .ok()
+            .filter(|v| !v.is_empty())?;
+
+        Some(Self {
+            secret: secret.into_bytes(),
+            default_ttl: Duration::from_secs(
+                config::env_parse("SHARE_DEFAULT_TTL_SECS").unwrap_or(86400),
+            ),
+            asset_prefixes: match config::env_list("SHARE_ASSET_PREFIXES") {
+                list if list.is_empty() => DEFAULT_ASSET_PREFIXES
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+                list => list,
+            },
+        })
+    }
+}
+
+/// Request extension marking a request authorized by a share link.
+#[derive(Debug, Clone, Copy)]
+pub struct SharedAccess;
+
+#[derive(Debug, Deserialize)]
+pub struct ShareRequest {
+    /// Path (and query) of the page to share.
+    path: String,
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShareResponse {
+    url: String,
+    expires_at: u64,
+}
+
+/// Handler for `POST /_share`.
+pub async fn create(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    headers: HeaderMap,
+    https: Option<Extension<Https>>,
+    Json(share_req): Json<ShareRequest>,
+) -> Response {
+    let Some(share) = &state.config.share else {
+        return (StatusCode::NOT_FOUND, "Share links are not configured").into_response();
+    };
+
+    let origin =
+        utils::determine_proxy_origin(state.config.base_url.as_deref(), &headers, https.is_some());
+
+    if !share_req.path.starts_with('/') {
+        return (StatusCode::BAD_REQUEST, "Path must start with '/'").into_response();
+    }
+
+    let ttl = share_req
+        .ttl_secs
+        .map(Duration::from_secs)
+        .unwrap_or(share.default_ttl);
+    let Some(expires_at) = unix_now().checked_add(ttl.as_secs()) else {
+        return (StatusCode::BAD_REQUEST, "ttl_secs is too large").into_response();
+    };
+    let signature = sign(&share.secret, expires_at, &share_req.path);
+
+    let separator = if share_req.path.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    let url = format!(
+        "{}{}{}{}={}.{}",
+        origin, share_req.path, separator, PARAM, expires_at, signature
+    );
+
+    tracing::info!(
+        "Created share link for {} valid for {:?}",
+        share_req.path,
+        ttl
+    );
+    audit::record(
+        &state,
+        &actor,
+        "share.create",
+        Some(&share_req.path),
+        Some(&format!("ttl_secs={}", ttl.as_secs())),
+    )
+    .await;
+    Json(ShareResponse { url, expires_at }).into_response()
+}
+
+/// Middleware verifying share signatures.
+///
+/// Valid requests get the [`SharedAccess`] extension and have the `_share`
+/// parameter removed before they are proxied.
+pub async fn verify(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let Some(share) = &state.config.share else {
+        return next.run(req).await;
+    };
+
+    if let Some((uri, expires_at, signature)) = split_share_param(req.uri()) {
+        let path = uri.path_and_query().map(|v| v.as_str()).unwrap_or("/");
+        if !is_valid(&share.secret, expires_at, path, &signature) {
+            return (
+                StatusCode::FORBIDDEN,
+                "Share link is invalid or has expired",
+            )
+                .into_response();
+        }
+        *req.uri_mut() = uri;
+        req.extensions_mut().insert(SharedAccess);
+    } else if is_public_asset(req.uri().path(), &share.asset_prefixes) {
+        req.extensions_mut().insert(SharedAccess);
+    }
+
+    next.run(req).await
+}
+
+/// Whether `path` lies under one of the asset `prefixes`.
+///
+/// Paths the upstream could resolve elsewhere are rejected: dot segments,
+/// `;` path parameters (`/css/x;.css` is `/css/x` to a Java server), backslashes
+/// and encoded separators or dots.
+fn is_public_asset(path: &str, prefixes: &[String]) -> bool {
+    let lower = path.to_ascii_lowercase();
+    if lower.contains(';')
+        || lower.contains('\\')
+        || lower.contains("//")
+        || ["%2e", "%2f", "%3b", "%5c"]
+            .iter()
+            .any(|e| lower.contains(e))
+        || path
+            .split('/')
+            .any(|segment| segment == "." || segment == "..")
+    {
+        return false;
+    }
+    prefixes
+        .iter()
+        .any(|prefix| path.starts_with(prefix.as_str()))
+}
+
+/// Removes the share parameter, returning the remaining path-only URI and the parsed parameter.
+fn split_share_param(uri: &Uri) -> Option<(Uri, u64, String)> {
+    let query = uri.query()?;
+    let mut share_value = None;
+    let rest: Vec<&str> = query
+        .split('&')
+        .filter(
+            |pair| match pair.strip_prefix(PARAM).and_then(|v| v.strip_prefix('=')) {
+                Some(value) => {
+                    share_value = Some(value);
+                    false
+                }
+                None => true,
+            },
+        )
+        .collect();
+
+    let (expires_at, signature) = share_value?.split_once('.')?;
+    let expires_at = expires_at.parse().ok()?;
+
+    let path_and_query = if rest.is_empty() {
+        uri.path().to_string()
+    } else {
+        format!("{}?{}", uri.path(), rest.join("&"))
+    };
+
+    Some((
+        Uri::try_from(path_and_query).ok()?,
+        expires_at,
+        signature.to_string(),
+    ))
+}
+
+fn is_valid(secret: &[u8], expires_at: u64, path: &str, signature: &str) -> bool {
+    if expires_at < unix_now() {
+        return false;
+    }
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    mac(secret, expires_at, path)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+fn sign(secret: &[u8], expires_at: u64, path: &str) -> String {
+    hex::encode(mac(secret, expires_at, path).finalize().into_bytes())
+}
+
+fn mac(secret: &[u8], expires_at: u64, path: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}\n{}", expires_at, path).as_bytes());
+    mac
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{Router, body::Body, middleware};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::config::{Config, Mode, Upstream};
+
+    const SECRET: &[u8] = b"test secret";
+
+    /// Sends a request through [`verify`], returning the status and, if it was
+    /// let through, the URI it was proxied with and whether it is shared.
+    async fn request(uri: &str, referer: Option<&str>) -> (StatusCode, String) {
+        let mut config = Config::from_env();
+        config.share = Some(ShareConfig {
+            secret: SECRET.to_vec(),
+            default_ttl: Duration::from_secs(60),
+            asset_prefixes: DEFAULT_ASSET_PREFIXES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        });
+        let upstream = Upstream::new(Mode::SPSEJECNA, &[]).unwrap();
+        let state = AppState::new(Arc::new(config), upstream);
+
+        let app = Router::new()
+            .fallback(|req: Request| async move {
+                let shared = req.extensions().get::<SharedAccess>().is_some();
+                format!("{} shared={}", req.uri(), shared)
+            })
+            .layer(middleware::from_fn_with_state(state, verify));
+        let mut req = Request::builder().uri(uri);
+        if let Some(referer) = referer {
+            req = req.header("referer", referer);
+        }
+
+        let response = app.oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn link(path: &str, expires_at: u64) -> String {
+        let separator = if path.contains('?') { '&' } else { '?' };
+        format!(
+            "{}{}{}={}.{}",
+            path,
+            separator,
+            PARAM,
+            expires_at,
+            sign(SECRET, expires_at, path)
+        )
+    }
+
+    #[tokio::test]
+    async fn valid_links_are_shared() {
+        let (status, body) = request(&link("/suplovani?den=1", unix_now() + 60), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "/suplovani?den=1 shared=true");
+    }
+
+    #[tokio::test]
+    async fn expired_links_are_rejected() {
+        let (status, _) = request(&link("/suplovani", unix_now() - 1), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn tampered_links_are_rejected() {
+        let expires_at = unix_now() + 60;
+        let valid = link("/suplovani", expires_at);
+
+        let other_page = valid.replace("/suplovani", "/score/student");
+        assert_eq!(request(&other_page, None).await.0, StatusCode::FORBIDDEN);
+
+        let later = valid.replace(
+            &format!("={}.", expires_at),
+            &format!("={}.", expires_at + 3600),
+        );
+        assert_eq!(request(&later, None).await.0, StatusCode::FORBIDDEN);
+
+        let mut signature = valid.clone();
+        let last = signature.pop().unwrap();
+        signature.push(if last == '0' { '1' } else { '0' });
+        assert_eq!(request(&signature, None).await.0, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn referer_grants_nothing() {
+        let referer = format!(
+            "https://jecna.example.org{}",
+            link("/suplovani", unix_now() + 60)
+        );
+        for path in [
+            "/score/student",
+            "/score/student;x.css",
+            "/css/../score/student",
+            "/css/%2e%2e/score/student",
+            "/css/main.css;jsessionid=1/../../score/student",
+        ] {
+            let (status, body) = request(path, Some(&referer)).await;
+            assert_eq!(status, StatusCode::OK);
+            assert!(body.ends_with("shared=false"), "{} was shared", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn assets_are_public() {
+        let (_, body) = request("/css/main.css", None).await;
+        assert_eq!(body, "/css/main.css shared=true");
+        let (_, body) = request("/img/akce/1200/foto.jpg", None).await;
+        assert_eq!(body, "/img/akce/1200/foto.jpg shared=true");
+        let (_, body) = request("/cssx/main.css", None).await;
+        assert_eq!(body, "/cssx/main.css shared=false");
+    }
+}
@@ -0,0 +1,317 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Service level objectives: availability (share of non-5xx responses) and
+//! latency (share of responses faster than `SLO_LATENCY_MS`).
+//!
+//! Requests are counted per minute for the last three days, so the metrics
+//! include the availability and burn rates over the windows of multiwindow
+//! burn-rate alerting, and Prometheus needs no recording rules.
+//! `jecnaproxy gen-alerts` prints matching alert rules. Admin requests aren't
+//! counted, metric scrapes would skew small deployments.
+
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{cli::GenAlertsArgs, config, effective_config};
+
+/// Rolling windows the SLO gauges are computed over, with their length in minutes.
+const WINDOWS: [(&str, u64); 7] = [
+    ("5m", 5),
+    ("30m", 30),
+    ("1h", 60),
+    ("2h", 120),
+    ("6h", 360),
+    ("1d", 1440),
+    ("3d", 4320),
+];
+
+/// Gauges computed per window (without the `jecnaproxy_` prefix), with their help.
+const GAUGES: [(&str, &str); 4] = [
+    (
+        "slo_requests",
+        "Requests counted by the SLOs in the window.",
+    ),
+    (
+        "slo_availability",
+        "Share of non-5xx responses in the window (1 without requests).",
+    ),
+    (
+        "slo_availability_burn_rate",
+        "Error budget burn rate of the availability objective (1 spends it exactly).",
+    ),
+    (
+        "slo_latency_burn_rate",
+        "Error budget burn rate of the latency objective (1 spends it exactly).",
+    ),
+];
+
+/// Upper bounds of the request duration histogram, in seconds.
+const DURATION_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Objectives of the proxy.
+#[derive(Debug, Clone, Serialize)]
+pub struct SloConfig {
+    /// Share of requests that must not fail with a 5xx.
+    pub availability: f64,
+    /// Requests taking longer than this are slow.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub latency: Duration,
+    /// Share of requests that must not be slow.
+    pub latency_target: f64,
+}
+
+impl SloConfig {
+    /// # Environment Variables
+    /// * `SLO_AVAILABILITY` - Target share of non-5xx responses (default: 0.995).
+    /// * `SLO_LATENCY_MS` - Responses slower than this count against the latency objective (default: 1000).
+    /// * `SLO_LATENCY_TARGET` - Target share of responses faster than `SLO_LATENCY_MS` (default: 0.99).
+    pub fn from_env() -> Self {
+        Self {
+            availability: target("SLO_AVAILABILITY", 0.995),
+            latency: Duration::from_millis(config::env_parse("SLO_LATENCY_MS").unwrap_or(1000)),
+            latency_target: target("SLO_LATENCY_TARGET", 0.99),
+        }
+    }
+}
+
+fn target(name: &str, default: f64) -> f64 {
+    match config::env_parse::<f64>(name) {
+        Some(target) if target > 0.0 && target < 1.0 => target,
+        Some(_) => {
+            tracing::warn!("{} must be between 0 and 1, using {}", name, default);
+            default
+        }
+        None => default,
+    }
+}
+
+/// Requests of one minute.
+#[derive(Debug, Default, Clone, Copy)]
+struct Minute {
+    /// Minutes since the Unix epoch.
+    at: u64,
+    total: u64,
+    errors: u64,
+    slow: u64,
+}
+
+/// Request outcomes, per minute and as a duration histogram.
+#[derive(Debug, Default)]
+pub struct Slo {
+    minutes: Mutex<VecDeque<Minute>>,
+    /// Requests per histogram bucket, the last one for slower requests.
+    durations: [AtomicU64; DURATION_BUCKETS.len() + 1],
+    duration_sum_micros: AtomicU64,
+}
+
+impl Slo {
+    /// Records a finished request.
+    pub fn record(&self, config: &SloConfig, status: u16, duration: Duration) {
+        let bucket = DURATION_BUCKETS
+            .iter()
+            .position(|&le| duration.as_secs_f64() <= le)
+            .unwrap_or(DURATION_BUCKETS.len());
+        self.durations[bucket].fetch_add(1, Ordering::Relaxed);
+        self.duration_sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+
+        let now = now_minute();
+        let mut minutes = self.minutes.lock().expect("slo lock poisoned");
+        if minutes.back().is_none_or(|m| m.at != now) {
+            minutes.push_back(Minute {
+                at: now,
+                ..Minute::default()
+            });
+        }
+        let longest = WINDOWS[WINDOWS.len() - 1].1;
+        while minutes.front().is_some_and(|m| m.at + longest <= now) {
+            minutes.pop_front();
+        }
+        let minute = minutes.back_mut().expect("pushed above");
+        minute.total += 1;
+        minute.errors += u64::from(status >= 500);
+        minute.slow += u64::from(duration > config.latency);
+    }
+
+    /// The windowed SLO gauges as `(name, window, value)`, see [`GAUGES`].
+    pub fn gauges(&self, config: &SloConfig) -> Vec<(&'static str, &'static str, f64)> {
+        let now = now_minute();
+        let minutes = self.minutes.lock().expect("slo lock poisoned");
+        let ratio = |part: u64, total: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                part as f64 / total as f64
+            }
+        };
+
+        let mut gauges = Vec::with_capacity(WINDOWS.len() * GAUGES.len());
+        for (window, length) in WINDOWS {
+            let mut sum = Minute::default();
+            for minute in minutes.iter().filter(|m| m.at + length > now) {
+                sum.total += minute.total;
+                sum.errors += minute.errors;
+                sum.slow += minute.slow;
+            }
+            let errors = ratio(sum.errors, sum.total);
+            let slow = ratio(sum.slow, sum.total);
+            gauges.extend([
+                ("slo_requests", window, sum.total as f64),
+                ("slo_availability", window, 1.0 - errors),
+                (
+                    "slo_availability_burn_rate",
+                    window,
+                    errors / (1.0 - config.availability),
+                ),
+                (
+                    "slo_latency_burn_rate",
+                    window,
+                    slow / (1.0 - config.latency_target),
+                ),
+            ]);
+        }
+        gauges
+    }
+
+    /// Renders the histogram and the SLO gauges in the Prometheus text format.
+    pub fn render(&self, config: &SloConfig, out: &mut String) {
+        let name = "jecnaproxy_request_duration_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} Time until the response headers, excluding admin requests.",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut count = 0;
+        for (i, bucket) in self.durations.iter().enumerate() {
+            count += bucket.load(Ordering::Relaxed);
+            let le = DURATION_BUCKETS
+                .get(i)
+                .map_or("+Inf".to_string(), |le| le.to_string());
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, count);
+        }
+        let sum = self.duration_sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+
+        let gauges = self.gauges(config);
+        for (name, help) in GAUGES {
+            let _ = writeln!(out, "# HELP jecnaproxy_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE jecnaproxy_{} gauge", name);
+            for (_, window, value) in gauges.iter().filter(|(n, _, _)| *n == name) {
+                let _ = writeln!(
+                    out,
+                    "jecnaproxy_{}{{window=\"{}\"}} {}",
+                    name, window, value
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP jecnaproxy_slo_objective Configured objectives."
+        );
+        let _ = writeln!(out, "# TYPE jecnaproxy_slo_objective gauge");
+        let _ = writeln!(
+            out,
+            "jecnaproxy_slo_objective{{slo=\"availability\"}} {}",
+            config.availability
+        );
+        let _ = writeln!(
+            out,
+            "jecnaproxy_slo_objective{{slo=\"latency\"}} {}",
+            config.latency_target
+        );
+    }
+}
+
+fn now_minute() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 60
+}
+
+/// Burn-rate alerts as in the Google SRE workbook: `(long window, short
+/// window, burn rate, severity)`. A burn rate of 14.4 over an hour spends 2 %
+/// of a 30-day error budget.
+const BURN_ALERTS: [(&str, &str, f64, &str); 4] = [
+    ("1h", "5m", 14.4, "page"),
+    ("6h", "30m", 6.0, "page"),
+    ("1d", "2h", 3.0, "ticket"),
+    ("3d", "6h", 1.0, "ticket"),
+];
+
+/// Entry point of the `gen-alerts` subcommand.
+pub fn gen_alerts(config: &SloConfig, args: &GenAlertsArgs) {
+    let rules = alert_rules(config, &args.job, args.min_requests);
+    match &args.out {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, rules) {
+                tracing::error!("Failed to write {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+        None => print!("{}", rules),
+    }
+}
+
+/// Prometheus alerting rules for the objectives in `config`.
+fn alert_rules(config: &SloConfig, job: &str, min_requests: u64) -> String {
+    let mut out = format!(
+        "# Generated by `jecnaproxy gen-alerts` for availability {} and {} of\n\
+         # requests under {} ms. Load it with `rule_files` in prometheus.yml.\n\
+         groups:\n  - name: jecnaproxy\n    rules:\n",
+        config.availability,
+        config.latency_target,
+        config.latency.as_millis()
+    );
+
+    let _ = write!(
+        out,
+        "      - alert: JecnaproxyDown\n        expr: up{{job=\"{job}\"}} == 0\n        for: 5m\n        labels:\n          severity: page\n        annotations:\n          summary: \"jecnaproxy {{{{ $labels.instance }}}} is not scraped\"\n",
+    );
+
+    for (slo, metric, what) in [
+        (
+            "Availability",
+            "jecnaproxy_slo_availability_burn_rate",
+            "5xx responses",
+        ),
+        (
+            "Latency",
+            "jecnaproxy_slo_latency_burn_rate",
+            "slow responses",
+        ),
+    ] {
+        for (long, short, rate, severity) in BURN_ALERTS {
+            let _ = write!(
+                out,
+                "      - alert: Jecnaproxy{slo}BurnRate{long}\n        expr: |\n          {metric}{{job=\"{job}\",window=\"{long}\"}} > {rate}\n          and on (job, instance) {metric}{{job=\"{job}\",window=\"{short}\"}} > {rate}\n          and on (job, instance) jecnaproxy_slo_requests{{job=\"{job}\",window=\"{long}\"}} >= {min_requests}\n        for: 2m\n        labels:\n          severity: {severity}\n        annotations:\n          summary: \"jecnaproxy {{{{ $labels.instance }}}} burns its {} error budget {rate}x too fast\"\n          description: \"{what} over the last {long} and {short} exceed the objective.\"\n",
+                slo.to_lowercase()
+            );
+        }
+    }
+    out
+}
@@ -0,0 +1,239 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Static snapshots of the upstream site.
+//!
+//! `jecnaproxy snapshot` crawls the upstream and writes every resource to disk
+//! with upstream URLs rewritten to root-relative ones, so the directory works
+//! both as an archival static mirror and as the `OFFLINE_DIR` fallback served
+//! when the upstream is down.
+
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use axum::{
+    body::Body,
+    http::{HeaderValue, StatusCode},
+    response::Response,
+};
+
+use crate::{
+    cli::SnapshotArgs,
+    crawler::{self, CrawledPage},
+    handlers,
+    state::AppState,
+    trackers, utils,
+};
+
+/// Entry point of the `snapshot` subcommand.
+pub async fn run(state: &AppState, args: SnapshotArgs) {
+    let out = args
+        .out
+        .or_else(|| state.config.offline_dir.clone())
+        .unwrap_or_else(|| PathBuf::from("snapshot"));
+
+    let mut limits = state.config.crawl.clone();
+    if let Some(max_pages) = args.max_pages {
+        limits.max_pages = max_pages;
+    }
+    if let Some(max_depth) = args.max_depth {
+        limits.max_depth = max_depth;
+    }
+
+    tracing::info!(
+        "Creating snapshot of {} in {}",
+        state.upstream().base,
+        out.display()
+    );
+    let pages = crawler::crawl(state, &limits).await;
+
+    match write(state, &pages, &out).await {
+        Ok(written) => tracing::info!("Snapshot finished: {} files written", written),
+        Err(e) => {
+            tracing::error!("Failed to write snapshot: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Writes crawled pages below `dir`, returning the number of written files.
+pub async fn write(state: &AppState, pages: &[CrawledPage], dir: &Path) -> std::io::Result<usize> {
+    let mut written = 0;
+
+    for page in pages {
+        let Some(path) = file_path(dir, &page.path, page.is_html()) else {
+            tracing::warn!("Snapshot: skipping unsafe path {}", page.path);
+            continue;
+        };
+
+        let body = if is_text(&page.content_type) {
+            // An empty origin turns absolute upstream URLs into root-relative ones.
+            utils::rewrite_content_bytes(&page.body, "", state)
+        } else {
+            page.body.to_vec()
+        };
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, body).await?;
+        written += 1;
+    }
+
+    Ok(written)
+}
+
+/// Deletes files below `dir` last written before `before`, i.e. pages no
+/// snapshot has contained since. Returns the number of files and their bytes.
+pub async fn delete_stale(dir: &Path, before: SystemTime) -> std::io::Result<(u64, u64)> {
+    let (mut files, mut bytes) = (0, 0);
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            // Symlinks are neither followed nor deleted.
+            let metadata = tokio::fs::symlink_metadata(entry.path()).await?;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else if metadata.is_file() && metadata.modified()? < before {
+                tokio::fs::remove_file(entry.path()).await?;
+                files += 1;
+                bytes += metadata.len();
+            }
+        }
+    }
+    Ok((files, bytes))
+}
+
+/// Serves `path_and_query` from `OFFLINE_DIR`, if configured and present.
+pub async fn serve_offline(state: &AppState, path_and_query: &str) -> Option<Response> {
+    let dir = state.config.offline_dir.as_ref()?;
+
+    for html in [true, false] {
+        let path = file_path(dir, path_and_query, html)?;
+        let Ok(bytes) = tokio::fs::read(&path).await else {
+            continue;
+        };
+
+        tracing::warn!(
+            "Upstream unavailable, serving {} from snapshot",
+            path_and_query
+        );
+
+        let content_type = if html {
+            "text/html; charset=utf-8"
+        } else {
+            content_type_for(&path)
+        };
+
+        let mut bytes = bytes;
+        if html && !state.config.trackers.is_empty() {
+            bytes = trackers::strip(&bytes, &state.config.trackers);
+        }
+        if html && state.upstream().banner {
+            handlers::inject_banner(&mut bytes, state);
+        }
+
+        let mut response = Response::new(Body::from(bytes));
+        *response.status_mut() = StatusCode::OK;
+        let headers = response.headers_mut();
+        headers.insert("content-type", HeaderValue::from_static(content_type));
+        headers.insert("x-jecnaproxy-offline", HeaderValue::from_static("true"));
+        return Some(response);
+    }
+
+    None
+}
+
+/// Maps an upstream path (and query) to a file below `dir`.
+///
+/// HTML pages are stored as `<path>/index.html` so that `/akce` and `/akce/42`
+/// can coexist. Returns `None` for paths that would escape `dir`.
+pub fn file_path(dir: &Path, path_and_query: &str, html: bool) -> Option<PathBuf> {
+    let (path, query) = match path_and_query.split_once('?') {
+        Some((p, q)) => (p, Some(q)),
+        None => (path_and_query, None),
+    };
+
+    let mut segments: Vec<String> = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    if segments
+        .iter()
+        .any(|s| s == "." || s == ".." || s.contains('\\') || s.contains('\0'))
+    {
+        return None;
+    }
+
+    if let Some(query) = query {
+        let encoded = format!("%3F{}", query.replace('/', "%2F"));
+        match segments.last_mut() {
+            Some(last) => last.push_str(&encoded),
+            None => segments.push(encoded),
+        }
+    }
+
+    let mut file = dir.to_path_buf();
+    file.extend(&segments);
+
+    let is_html_file = segments
+        .last()
+        .is_some_and(|s| s.ends_with(".html") || s.ends_with(".htm"));
+    if html && !is_html_file {
+        file.push("index.html");
+    } else if segments.is_empty() {
+        file.push("index");
+    }
+
+    Some(file)
+}
+
+fn is_text(content_type: &str) -> bool {
+    ["text/", "javascript", "json", "xml"]
+        .iter()
+        .any(|t| content_type.contains(t))
+}
+
+/// Guesses the content type of a file from its extension.
+pub fn content_type_for(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css",
+        "js" => "application/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "txt" => "text/plain; charset=utf-8",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
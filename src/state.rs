@@ -12,15 +12,131 @@
  * GNU General Public License for more details.
  */
 
-use crate::config::Config;
+use crate::api::{
+    Cache, SESSION_CACHE_TTL,
+    canteen::Account,
+    exams::Exam,
+    rooms::RoomsState,
+    teachers::{TEACHER_CACHE_TTL, TeacherTimetable},
+};
+use crate::ban::BanList;
+use crate::bandwidth::Bucket;
+use crate::cluster::Cluster;
+use crate::config::{Config, Upstream};
+use crate::db::Db;
+use crate::memory::MemoryBudget;
+use crate::metrics::Metrics;
+use crate::notify;
+use crate::scheduler::Scheduler;
+use crate::search::SearchState;
+use crate::statsd::Statsd;
+use crate::throttle::Throttle;
+use crate::tls::CertResolver;
+use crate::upstream_client;
+use crate::upstreams::Route;
+use crate::users::UserState;
+use crate::watcher::WatchState;
 use reqwest::Client;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 /// Shared application state.
 #[derive(Clone)]
 pub struct AppState {
     /// The HTTP client used to forward requests to the upstream server.
     pub client: Client,
+    /// The HTTP client delivering notifications to user webhooks.
+    pub webhook_client: Client,
     /// The application configuration.
     pub config: Arc<Config>,
+    /// The current upstream, starts as `config.mode` and can be switched by the admin API.
+    pub upstream: Arc<RwLock<Arc<Upstream>>>,
+    /// Named upstreams selected by host or path prefix, checked before `upstream`.
+    pub routes: Arc<Vec<Route>>,
+    /// Abuse counters and active bans.
+    pub bans: Arc<BanList>,
+    /// The current full-text search index, if built.
+    pub search: Arc<SearchState>,
+    /// Monitored page versions and detected changes.
+    pub watch: Arc<WatchState>,
+    /// Politeness limits of background scraping.
+    pub throttle: Arc<Throttle>,
+    /// Periodic background jobs.
+    pub scheduler: Arc<Scheduler>,
+    /// Per-user API rate limit counters.
+    pub users: Arc<UserState>,
+    /// Recently fetched canteen account overviews.
+    pub canteen: Arc<Cache<Account>>,
+    /// Recently fetched exam schedules.
+    pub exams: Arc<Cache<Vec<Exam>>>,
+    /// Teacher timetables by shortcut.
+    pub teachers: Arc<Cache<Arc<TeacherTimetable>>>,
+    /// Scraped room timetables.
+    pub rooms: Arc<RoomsState>,
+    /// Redis shared between replicas, if `REDIS_URL` is set.
+    pub cluster: Option<Arc<Cluster>>,
+    /// Bandwidth shared by all streamed responses, if `BANDWIDTH_LIMIT` is set.
+    pub bandwidth: Option<Arc<Bucket>>,
+    /// Process metrics.
+    pub metrics: Arc<Metrics>,
+    /// Body bytes buffered in memory.
+    pub memory: Arc<MemoryBudget>,
+    /// StatsD client, if `METRICS_BACKEND` selects it.
+    pub statsd: Option<Arc<Statsd>>,
+    /// Persistent storage, if `DATABASE_URL` is set.
+    pub db: Option<Db>,
+    /// TLS certificates, if `TLS_CERT_FILE` is set.
+    pub tls: Option<Arc<CertResolver>>,
+}
+
+impl AppState {
+    pub fn new(config: Arc<Config>, upstream: Upstream) -> Self {
+        let metrics = Arc::new(Metrics::default());
+        let client = upstream_client::build(&config.upstream_http, &config.timeouts, &metrics);
+        let memory = Arc::new(MemoryBudget::new(&config.memory, metrics.clone()));
+
+        Self {
+            client,
+            webhook_client: notify::user_webhook_client(),
+            throttle: Arc::new(Throttle::new(&config.throttle)),
+            bandwidth: config
+                .bandwidth
+                .global
+                .map(|rate| Arc::new(Bucket::new(rate))),
+            upstream: Arc::new(RwLock::new(Arc::new(upstream))),
+            routes: Arc::new(Vec::new()),
+            config,
+            bans: Arc::new(BanList::default()),
+            search: Arc::new(SearchState::default()),
+            watch: Arc::new(WatchState::default()),
+            scheduler: Arc::new(Scheduler::default()),
+            users: Arc::new(UserState::default()),
+            canteen: Arc::new(Cache::new(SESSION_CACHE_TTL)),
+            exams: Arc::new(Cache::new(SESSION_CACHE_TTL)),
+            teachers: Arc::new(Cache::new(TEACHER_CACHE_TTL)),
+            rooms: Arc::new(RoomsState::default()),
+            cluster: None,
+            memory,
+            metrics,
+            statsd: None,
+            db: None,
+            tls: None,
+        }
+    }
+
+    /// Returns the current upstream.
+    pub fn upstream(&self) -> Arc<Upstream> {
+        self.upstream
+            .read()
+            .expect("upstream lock poisoned")
+            .clone()
+    }
+
+    /// A copy of the state using `upstream` instead of the current one, for
+    /// requests served by a named upstream.
+    pub fn with_upstream(&self, upstream: Arc<Upstream>) -> Self {
+        Self {
+            upstream: Arc::new(RwLock::new(upstream)),
+            ..self.clone()
+        }
+    }
 }
@@ -0,0 +1,218 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! StatsD and DogStatsD export of the metrics, for operators without
+//! Prometheus.
+//!
+//! Every `STATSD_FLUSH_SECS` the values of [`Metrics::samples`] are sent over
+//! UDP, counters as their increase since the last flush. Request durations
+//! are sent as timings when the request is answered. DogStatsD gets labels as
+//! tags; plain StatsD has no tags, so label values are appended to the name.
+
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    net::{ToSocketAddrs, UdpSocket},
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::{config, effective_config, metrics::Metrics, slo::SloConfig, state::AppState};
+
+/// Largest datagram sent, safe for common MTUs.
+const MAX_DATAGRAM: usize = 1432;
+
+/// StatsD exporter settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsdConfig {
+    /// `host:port` of the agent.
+    pub addr: String,
+    /// Prefix of all metric names.
+    pub prefix: String,
+    /// Send labels as DogStatsD tags.
+    pub dogstatsd: bool,
+    /// Interval of sending counters and gauges.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub flush_interval: Duration,
+}
+
+impl StatsdConfig {
+    /// # Environment Variables
+    /// * `METRICS_BACKEND` - `prometheus`, `statsd` or `dogstatsd` (default: "prometheus"). `/_admin/metrics` is served either way.
+    /// * `STATSD_ADDR` - `host:port` of the StatsD agent (default: "127.0.0.1:8125").
+    /// * `STATSD_PREFIX` - Prefix of metric names (default: "jecnaproxy.").
+    /// * `STATSD_FLUSH_SECS` - Interval of sending counters and gauges (default: 10).
+    pub fn from_env() -> Option<Self> {
+        let dogstatsd = match std::env::var("METRICS_BACKEND")
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "statsd" => false,
+            "dogstatsd" => true,
+            "" | "prometheus" => return None,
+            other => {
+                tracing::warn!("Unknown METRICS_BACKEND {:?}, using prometheus", other);
+                return None;
+            }
+        };
+
+        Some(Self {
+            addr: std::env::var("STATSD_ADDR").unwrap_or_else(|_| "127.0.0.1:8125".to_string()),
+            prefix: std::env::var("STATSD_PREFIX").unwrap_or_else(|_| "jecnaproxy.".to_string()),
+            dogstatsd,
+            flush_interval: Duration::from_secs(
+                config::env_parse("STATSD_FLUSH_SECS").unwrap_or(10).max(1),
+            ),
+        })
+    }
+}
+
+/// A connected StatsD client.
+pub struct Statsd {
+    socket: UdpSocket,
+    config: StatsdConfig,
+    /// Counter values of the last flush, by rendered name.
+    last: Mutex<HashMap<String, f64>>,
+}
+
+impl Statsd {
+    /// Resolves the agent's address and opens a socket sending to it.
+    pub fn connect(config: &StatsdConfig) -> std::io::Result<Self> {
+        let addr = config
+            .addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| std::io::Error::other("STATSD_ADDR resolves to no address"))?;
+        let socket = UdpSocket::bind(if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        })?;
+        socket.connect(addr)?;
+        // A slow or missing agent must never block request handling.
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            config: config.clone(),
+            last: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Sends the duration of an answered request.
+    pub fn timing(&self, duration: Duration, labels: &[(&'static str, String)]) {
+        let millis = format!("{:.3}", duration.as_secs_f64() * 1000.0);
+        self.send(&[self.line("request_duration", labels, &millis, "ms")]);
+    }
+
+    /// Sends the current counters and gauges.
+    pub fn flush(&self, metrics: &Metrics, slo: &SloConfig) {
+        let mut lines = Vec::new();
+        let mut last = self.last.lock().expect("statsd lock poisoned");
+        for sample in metrics.samples(slo) {
+            if sample.counter {
+                let key = self.line(sample.name, &sample.labels, "", "");
+                let previous = last.insert(key, sample.value).unwrap_or_default();
+                let delta = sample.value - previous;
+                if delta > 0.0 {
+                    lines.push(self.line(sample.name, &sample.labels, &delta.to_string(), "c"));
+                }
+            } else {
+                lines.push(self.line(sample.name, &sample.labels, &sample.value.to_string(), "g"));
+            }
+        }
+        drop(last);
+        self.send(&lines);
+    }
+
+    /// Renders `<prefix><name>:<value>|<kind>`, with the labels as tags or name parts.
+    fn line(
+        &self,
+        name: &str,
+        labels: &[(&'static str, String)],
+        value: &str,
+        kind: &str,
+    ) -> String {
+        let mut line = format!("{}{}", self.config.prefix, name);
+        if !self.config.dogstatsd {
+            for (_, value) in labels {
+                line.push('.');
+                line.push_str(&sanitize(value));
+            }
+        }
+        line.push_str(&format!(":{}|{}", value, kind));
+        if self.config.dogstatsd && !labels.is_empty() {
+            let tags: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{}:{}", key, sanitize(value)))
+                .collect();
+            line.push_str(&format!("|#{}", tags.join(",")));
+        }
+        line
+    }
+
+    /// Sends `lines` in as few datagrams as possible. Drops them if the
+    /// socket's buffer is full.
+    fn send(&self, lines: &[String]) {
+        let mut datagram = String::new();
+        for line in lines {
+            if !datagram.is_empty() && datagram.len() + 1 + line.len() > MAX_DATAGRAM {
+                let _ = self.socket.send(datagram.as_bytes());
+                datagram.clear();
+            }
+            if !datagram.is_empty() {
+                datagram.push('\n');
+            }
+            datagram.push_str(line);
+        }
+        if !datagram.is_empty() {
+            let _ = self.socket.send(datagram.as_bytes());
+        }
+    }
+}
+
+/// Replaces characters with a meaning in the StatsD line format.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Starts flushing the metrics, if a StatsD backend is configured.
+pub fn start(state: &AppState) {
+    let Some(statsd) = state.statsd.clone() else {
+        return;
+    };
+    tracing::info!(
+        "Sending metrics to StatsD at {} every {:?}",
+        statsd.config.addr,
+        statsd.config.flush_interval
+    );
+    let state = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(statsd.config.flush_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            statsd.flush(&state.metrics, &state.config.slo);
+        }
+    });
+}
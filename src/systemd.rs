@@ -0,0 +1,136 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! systemd integration, without linking libsystemd.
+//!
+//! With socket activation (`LISTEN_FDS`) the first passed socket is served
+//! instead of binding `PORT`, the second one instead of `HTTPS_PORT`. Under
+//! `Type=notify` units readiness is reported through `NOTIFY_SOCKET` once the
+//! listeners are open, and with `WatchdogSec=` the watchdog is pinged at half
+//! its interval for as long as the runtime keeps scheduling tasks.
+
+use std::time::Duration;
+
+/// First file descriptor passed by socket activation.
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// TCP listeners passed by systemd socket activation, in the order of the
+/// socket unit's `ListenStream=` lines.
+#[cfg(unix)]
+pub fn activated_listeners() -> Vec<std::net::TcpListener> {
+    use std::os::fd::{FromRawFd, IntoRawFd};
+
+    // The variables are inherited by children, only the intended process may use them.
+    if !for_this_process("LISTEN_PID") {
+        return Vec::new();
+    }
+    let count: i32 = match std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+    {
+        Some(count) if count > 0 => count,
+        _ => return Vec::new(),
+    };
+
+    let mut listeners = Vec::new();
+    for fd in LISTEN_FDS_START..LISTEN_FDS_START + count {
+        // SAFETY: systemd passes these descriptors to this process, nothing else owns them.
+        let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+        match listener
+            .local_addr()
+            .and_then(|_| listener.set_nonblocking(true))
+        {
+            Ok(()) => listeners.push(listener),
+            Err(e) => {
+                tracing::error!(
+                    "Socket {} passed by systemd is not a TCP listener: {}",
+                    fd,
+                    e
+                );
+                // Not ours after all, leave it open.
+                let _ = listener.into_raw_fd();
+            }
+        }
+    }
+    listeners
+}
+
+#[cfg(not(unix))]
+pub fn activated_listeners() -> Vec<std::net::TcpListener> {
+    Vec::new()
+}
+
+/// Whether a `LISTEN_PID`/`WATCHDOG_PID` style variable is unset or names this process.
+#[cfg(unix)]
+fn for_this_process(name: &str) -> bool {
+    match std::env::var(name) {
+        Ok(pid) => pid.parse() == Ok(std::process::id()),
+        Err(_) => name == "WATCHDOG_PID",
+    }
+}
+
+/// Sends `state` (e.g. `READY=1`) to the service manager, if there is one.
+#[cfg(unix)]
+pub fn notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let result = UnixDatagram::unbound().and_then(|socket| {
+        // A leading '@' stands for a socket in the abstract namespace.
+        #[cfg(target_os = "linux")]
+        if let Some(name) = path.as_encoded_bytes().strip_prefix(b"@") {
+            use std::os::linux::net::SocketAddrExt;
+
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            return socket.send_to_addr(state.as_bytes(), &addr);
+        }
+        socket.send_to(state.as_bytes(), &path)
+    });
+    if let Err(e) = result {
+        tracing::warn!("Failed to notify systemd: {}", e);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn notify(_state: &str) {}
+
+/// Reports that the proxy accepts connections and starts pinging the watchdog.
+pub fn ready() {
+    notify("READY=1\nSTATUS=Accepting connections");
+
+    let Some(interval) = watchdog_interval() else {
+        return;
+    };
+    tracing::info!("Pinging the systemd watchdog every {:?}", interval);
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            ticks.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
+
+/// Half of `WATCHDOG_USEC`, if the watchdog is enabled for this process.
+fn watchdog_interval() -> Option<Duration> {
+    #[cfg(unix)]
+    if !for_this_process("WATCHDOG_PID") {
+        return None;
+    }
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
@@ -0,0 +1,91 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Global politeness throttle for background scraping.
+//!
+//! Every upstream request made on behalf of a background job (crawls, the
+//! change watcher, vault grade checks) waits for a slot here, so all jobs
+//! together never exceed the configured request rate. Proxied user traffic is
+//! not throttled.
+
+use std::{sync::Mutex, time::Duration};
+
+use serde::Serialize;
+use tokio::{
+    sync::{Semaphore, SemaphorePermit},
+    time::Instant,
+};
+
+use crate::config;
+
+/// Outbound limits for background scraping.
+#[derive(Debug, Clone, Serialize)]
+pub struct ThrottleConfig {
+    /// Maximum background requests per second.
+    pub max_rps: f64,
+    /// Maximum number of crawls running at the same time.
+    pub max_concurrent_crawls: usize,
+}
+
+impl ThrottleConfig {
+    /// # Environment Variables
+    /// * `SCRAPE_MAX_RPS` - Maximum background requests per second (default: 2).
+    /// * `SCRAPE_MAX_CONCURRENT_CRAWLS` - Maximum simultaneous crawls (default: 1).
+    pub fn from_env() -> Self {
+        Self {
+            max_rps: config::env_parse("SCRAPE_MAX_RPS")
+                .filter(|v: &f64| v.is_finite() && *v > 0.0)
+                .unwrap_or(2.0),
+            max_concurrent_crawls: config::env_parse("SCRAPE_MAX_CONCURRENT_CRAWLS")
+                .filter(|v| *v > 0)
+                .unwrap_or(1),
+        }
+    }
+}
+
+/// Shared request slots and crawl permits.
+pub struct Throttle {
+    interval: Duration,
+    next: Mutex<Instant>,
+    crawls: Semaphore,
+}
+
+impl Throttle {
+    pub fn new(config: &ThrottleConfig) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / config.max_rps),
+            next: Mutex::new(Instant::now()),
+            crawls: Semaphore::new(config.max_concurrent_crawls),
+        }
+    }
+
+    /// Waits until the next background request may be sent.
+    pub async fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().expect("throttle lock poisoned");
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+
+    /// Waits until another crawl may start. The crawl ends when the permit is dropped.
+    pub async fn crawl(&self) -> SemaphorePermit<'_> {
+        self.crawls
+            .acquire()
+            .await
+            .expect("crawl semaphore is never closed")
+    }
+}
@@ -0,0 +1,305 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Certificates of the native HTTPS and HTTP/3 listeners.
+//!
+//! The certificate is picked per handshake from the SNI hostname, falling back
+//! to `TLS_CERT_FILE`. The files are reloaded on `SIGHUP` and when their
+//! modification time changes; open connections keep the certificate they were
+//! established with, so a renewal never drops clients.
+//!
+//! For local development `--dev-tls` serves a generated self-signed certificate
+//! instead.
+
+use std::{
+    collections::HashMap,
+    env, fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use rustls::{
+    SupportedProtocolVersion,
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use serde::Serialize;
+
+use crate::{config, effective_config};
+
+/// TLS settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct TlsConfig {
+    /// Certificate served when no SNI certificate matches.
+    pub default: CertFiles,
+    /// Certificates by lowercase SNI hostname.
+    pub sni: Vec<(String, CertFiles)>,
+    /// How often the files are checked for changes, zero to only reload on `SIGHUP`.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub reload_interval: Duration,
+}
+
+/// A PEM certificate chain and its private key.
+#[derive(Debug, Clone, Serialize)]
+pub struct CertFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsConfig {
+    /// # Environment Variables
+    /// * `TLS_CERT_FILE` - PEM certificate chain. TLS is disabled when unset.
+    /// * `TLS_KEY_FILE` - PEM private key of `TLS_CERT_FILE`.
+    /// * `TLS_SNI_CERTS` - Per-hostname certificates, `host=cert.pem:key.pem,...`.
+    /// * `TLS_RELOAD_INTERVAL_SECS` - Interval of checking the files for changes (default: 60).
+    pub fn from_env() -> Option<Self> {
+        let (Some(cert), Some(key)) = (path_var("TLS_CERT_FILE"), path_var("TLS_KEY_FILE")) else {
+            if path_var("TLS_CERT_FILE").is_some() || path_var("TLS_KEY_FILE").is_some() {
+                tracing::error!(
+                    "TLS_CERT_FILE and TLS_KEY_FILE must be set together, TLS is disabled"
+                );
+            }
+            return None;
+        };
+
+        let sni = env::var("TLS_SNI_CERTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .filter_map(|entry| {
+                let parsed = entry.split_once('=').and_then(|(host, files)| {
+                    let (cert, key) = files.split_once(':')?;
+                    Some((
+                        host.trim().to_ascii_lowercase(),
+                        CertFiles {
+                            cert: PathBuf::from(cert.trim()),
+                            key: PathBuf::from(key.trim()),
+                        },
+                    ))
+                });
+                if parsed.is_none() {
+                    tracing::warn!("Ignoring invalid TLS_SNI_CERTS entry: {}", entry);
+                }
+                parsed
+            })
+            .collect();
+
+        Some(Self {
+            default: CertFiles { cert, key },
+            sni,
+            reload_interval: Duration::from_secs(
+                config::env_parse("TLS_RELOAD_INTERVAL_SECS").unwrap_or(60),
+            ),
+        })
+    }
+
+    fn files(&self) -> impl Iterator<Item = &CertFiles> {
+        std::iter::once(&self.default).chain(self.sni.iter().map(|(_, files)| files))
+    }
+}
+
+fn path_var(name: &str) -> Option<PathBuf> {
+    env::var(name)
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Request extension marking requests received over TLS.
+#[derive(Debug, Clone, Copy)]
+pub struct Https;
+
+/// Picks the certificate of a handshake, reloadable at runtime.
+pub struct CertResolver {
+    /// The certificate files, `None` for a generated certificate.
+    config: Option<TlsConfig>,
+    provider: Arc<CryptoProvider>,
+    certs: RwLock<Certs>,
+    /// Modification times of all files at the last (re)load attempt.
+    modified: Mutex<Vec<Option<SystemTime>>>,
+}
+
+struct Certs {
+    default: Arc<CertifiedKey>,
+    by_host: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl fmt::Debug for CertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertResolver")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CertResolver {
+    /// Loads all certificates of `config`.
+    pub fn load(config: TlsConfig) -> Result<Arc<Self>, String> {
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let modified = modified_times(&config);
+        let certs = load_certs(&config, &provider)?;
+        Ok(Arc::new(Self {
+            config: Some(config),
+            provider,
+            certs: RwLock::new(certs),
+            modified: Mutex::new(modified),
+        }))
+    }
+
+    /// Generates a self-signed certificate for `localhost`, kept in memory only.
+    pub fn self_signed() -> Result<Arc<Self>, String> {
+        let names = ["localhost", "127.0.0.1", "::1"].map(String::from).to_vec();
+        let generated = rcgen::generate_simple_self_signed(names).map_err(|e| e.to_string())?;
+        let key = PrivateKeyDer::Pkcs8(generated.signing_key.serialize_der().into());
+
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let default = CertifiedKey::from_der(vec![generated.cert.der().clone()], key, &provider)
+            .map_err(|e| e.to_string())?;
+        Ok(Arc::new(Self {
+            config: None,
+            provider,
+            certs: RwLock::new(Certs {
+                default: Arc::new(default),
+                by_host: HashMap::new(),
+            }),
+            modified: Mutex::new(Vec::new()),
+        }))
+    }
+
+    /// Reloads the certificates, keeping the current ones if any file is invalid.
+    pub fn reload(&self) {
+        let Some(config) = &self.config else {
+            return;
+        };
+        // Read the times first, a file replaced while loading is then picked up next time.
+        *self.modified.lock().expect("modified lock poisoned") = modified_times(config);
+        match load_certs(config, &self.provider) {
+            Ok(certs) => {
+                *self.certs.write().expect("certs lock poisoned") = certs;
+                tracing::info!("Reloaded TLS certificates");
+            }
+            Err(e) => tracing::error!(
+                "Failed to reload TLS certificates, keeping the old ones: {}",
+                e
+            ),
+        }
+    }
+
+    /// Reloads the certificates on `SIGHUP` and when the files change.
+    pub fn watch(self: &Arc<Self>) {
+        let Some(config) = self.config.clone() else {
+            return;
+        };
+        let resolver = self.clone();
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            let mut hangup =
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok();
+
+            loop {
+                let poll = async {
+                    if config.reload_interval.is_zero() {
+                        std::future::pending::<()>().await;
+                    }
+                    tokio::time::sleep(config.reload_interval).await;
+                };
+                #[cfg(unix)]
+                let signal = async {
+                    match hangup.as_mut() {
+                        Some(hangup) => hangup.recv().await,
+                        None => std::future::pending().await,
+                    }
+                };
+                #[cfg(not(unix))]
+                let signal = std::future::pending::<Option<()>>();
+
+                tokio::select! {
+                    _ = signal => resolver.reload(),
+                    () = poll => {
+                        let modified = modified_times(&config);
+                        if modified != *resolver.modified.lock().expect("modified lock poisoned") {
+                            resolver.reload();
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Builds a server config resolving certificates through `self`.
+    pub fn server_config(
+        self: &Arc<Self>,
+        versions: &[&'static SupportedProtocolVersion],
+        alpn: &[&[u8]],
+    ) -> Result<rustls::ServerConfig, String> {
+        let mut config = rustls::ServerConfig::builder_with_provider(self.provider.clone())
+            .with_protocol_versions(versions)
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone());
+        config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+        Ok(config)
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let certs = self.certs.read().expect("certs lock poisoned");
+        let by_host = hello
+            .server_name()
+            .and_then(|name| certs.by_host.get(&name.to_ascii_lowercase()));
+        Some(by_host.unwrap_or(&certs.default).clone())
+    }
+}
+
+fn load_certs(config: &TlsConfig, provider: &CryptoProvider) -> Result<Certs, String> {
+    let default = load_cert(&config.default, provider)?;
+    let by_host = config
+        .sni
+        .iter()
+        .map(|(host, files)| Ok((host.clone(), load_cert(files, provider)?)))
+        .collect::<Result<_, String>>()?;
+
+    Ok(Certs { default, by_host })
+}
+
+fn load_cert(files: &CertFiles, provider: &CryptoProvider) -> Result<Arc<CertifiedKey>, String> {
+    let certs = CertificateDer::pem_file_iter(&files.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("{}: {}", files.cert.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("{}: no certificates found", files.cert.display()));
+    }
+    let key = PrivateKeyDer::from_pem_file(&files.key)
+        .map_err(|e| format!("{}: {}", files.key.display(), e))?;
+
+    CertifiedKey::from_der(certs, key, provider)
+        .map(Arc::new)
+        .map_err(|e| format!("{}: {}", files.cert.display(), e))
+}
+
+fn modified_times(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+    config
+        .files()
+        .flat_map(|files| [modified(&files.cert), modified(&files.key)])
+        .collect()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
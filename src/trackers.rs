@@ -0,0 +1,120 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Removal of analytics and tracking embeds from proxied HTML.
+//!
+//! Scripts, iframes, `<noscript>` blocks, images (tracking pixels) and
+//! `<link>` hints whose markup mentions one of the patterns are dropped, so
+//! visits to the mirror aren't reported to third parties.
+
+use memchr::memmem;
+
+use crate::config;
+
+/// Patterns used when `STRIP_TRACKERS` is set without `TRACKER_PATTERNS`.
+const DEFAULT_PATTERNS: &[&str] = &[
+    "google-analytics.com",
+    "googletagmanager.com",
+    "gtag(",
+    "doubleclick.net",
+    "connect.facebook.net",
+    "facebook.com/tr",
+    "hotjar.com",
+    "clarity.ms",
+    "matomo",
+    "piwik",
+    "toplist.cz",
+    "hit.gemius.pl",
+];
+
+/// Elements inspected, and whether they have a closing tag.
+const ELEMENTS: &[(&[u8], bool)] = &[
+    (b"script", true),
+    (b"noscript", true),
+    (b"iframe", true),
+    (b"img", false),
+    (b"link", false),
+];
+
+/// Reads the lowercase patterns to strip, empty when stripping is disabled.
+///
+/// # Environment Variables
+/// * `STRIP_TRACKERS` - Set to "true" or "1" to strip tracking embeds.
+/// * `TRACKER_PATTERNS` - Comma-separated substrings marking a tracker (default: built-in list).
+pub fn patterns_from_env() -> Vec<String> {
+    if !config::env_flag("STRIP_TRACKERS") {
+        return Vec::new();
+    }
+
+    let patterns = config::env_list("TRACKER_PATTERNS");
+    if patterns.is_empty() {
+        DEFAULT_PATTERNS.iter().map(|p| p.to_string()).collect()
+    } else {
+        patterns.iter().map(|p| p.to_ascii_lowercase()).collect()
+    }
+}
+
+/// Removes the elements of `body` mentioning any of the lowercase `patterns`.
+pub fn strip(body: &[u8], patterns: &[String]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len());
+    let mut last = 0;
+    let mut pos = 0;
+
+    while let Some(offset) = memchr::memchr(b'<', &body[pos..]) {
+        let start = pos + offset;
+        let Some(&(name, paired)) = ELEMENTS
+            .iter()
+            .find(|(name, _)| is_tag(&body[start + 1..], name))
+        else {
+            pos = start + 1;
+            continue;
+        };
+        let Some(end) = element_end(body, start, name, paired) else {
+            break;
+        };
+
+        let element = body[start..end].to_ascii_lowercase();
+        if patterns
+            .iter()
+            .any(|p| memmem::find(&element, p.as_bytes()).is_some())
+        {
+            out.extend_from_slice(&body[last..start]);
+            last = end;
+        }
+        pos = end;
+    }
+
+    out.extend_from_slice(&body[last..]);
+    out
+}
+
+/// Whether `rest` (the bytes after `<`) starts with the tag `name`.
+fn is_tag(rest: &[u8], name: &[u8]) -> bool {
+    rest.len() > name.len()
+        && rest[..name.len()].eq_ignore_ascii_case(name)
+        && matches!(rest[name.len()], b'>' | b'/' | b' ' | b'\t' | b'\n' | b'\r')
+}
+
+/// End offset of the element starting at `start`, `None` if it isn't closed.
+fn element_end(body: &[u8], start: usize, name: &[u8], paired: bool) -> Option<usize> {
+    let open_end = start + memchr::memchr(b'>', &body[start..])? + 1;
+    if !paired || body[open_end - 2] == b'/' {
+        return Some(open_end);
+    }
+
+    let close = memmem::find_iter(&body[open_end..], b"</")
+        .map(|idx| open_end + idx)
+        .find(|&idx| is_tag(&body[idx + 2..], name))?;
+    Some(close + memchr::memchr(b'>', &body[close..])? + 1)
+}
@@ -0,0 +1,244 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! The HTTP client forwarding requests to the upstreams.
+//!
+//! HTTPS upstreams are offered HTTP/2 during the TLS handshake (ALPN) and
+//! fall back to HTTP/1.1 if they don't pick it. A page view fans out into
+//! dozens of asset requests, which HTTP/2 multiplexes over one connection
+//! instead of opening several. New connections and responses by protocol
+//! version are counted in the metrics, to see how well connections are reused.
+//!
+//! When a host has both IPv4 and IPv6 addresses, the connector tries them in
+//! the order of [`IpFamily`] and starts the other family after 300 ms if the
+//! first hasn't connected yet (Happy Eyeballs). Some hosting providers have
+//! broken IPv6 routes to the school's network, `UPSTREAM_IP_FAMILY=ipv4`
+//! avoids them without touching the OS configuration.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, atomic::Ordering};
+use std::time::Duration;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Client, ClientBuilder};
+use serde::Serialize;
+use tower::util::MapResponseLayer;
+
+use crate::{config, effective_config, metrics::Metrics, server::TimeoutConfig};
+
+/// How HTTP/2 is used toward the upstreams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Http2Mode {
+    /// Negotiated with ALPN over HTTPS, HTTP/1.1 otherwise.
+    Auto,
+    /// HTTP/1.1 only.
+    Off,
+    /// HTTP/2 without negotiation, also over plain HTTP (h2c).
+    Always,
+}
+
+/// Which addresses of an upstream host are connected to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpFamily {
+    /// In the order the resolver returns them.
+    Auto,
+    /// IPv4 addresses first, IPv6 as the Happy Eyeballs fallback.
+    PreferIpv4,
+    /// IPv6 addresses first, IPv4 as the Happy Eyeballs fallback.
+    PreferIpv6,
+    /// Only IPv4 addresses.
+    Ipv4,
+    /// Only IPv6 addresses.
+    Ipv6,
+}
+
+impl IpFamily {
+    /// Orders and filters resolved addresses.
+    fn select(self, mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        match self {
+            IpFamily::Auto => {}
+            // Stable, so each family keeps the resolver's order.
+            IpFamily::PreferIpv4 => addrs.sort_by_key(SocketAddr::is_ipv6),
+            IpFamily::PreferIpv6 => addrs.sort_by_key(SocketAddr::is_ipv4),
+            IpFamily::Ipv4 => addrs.retain(SocketAddr::is_ipv4),
+            IpFamily::Ipv6 => addrs.retain(SocketAddr::is_ipv6),
+        }
+        addrs
+    }
+}
+
+/// Upstream connection settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamHttpConfig {
+    pub http2: Http2Mode,
+    pub ip_family: IpFamily,
+    /// How long an unused connection is kept open for reuse.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub pool_idle_timeout: Duration,
+    /// Maximum number of unused connections kept open per upstream host.
+    pub pool_max_idle_per_host: usize,
+}
+
+impl UpstreamHttpConfig {
+    /// # Environment Variables
+    /// * `UPSTREAM_HTTP2` - `auto`, `off` or `always` (default: "auto").
+    /// * `UPSTREAM_IP_FAMILY` - `auto`, `prefer-ipv4`, `prefer-ipv6`, `ipv4` or `ipv6` (default: "auto").
+    /// * `UPSTREAM_POOL_IDLE_TIMEOUT_SECS` - Idle connection lifetime (default: 90).
+    /// * `UPSTREAM_POOL_MAX_IDLE_PER_HOST` - Idle connections kept per host (default: 32).
+    pub fn from_env() -> Self {
+        let http2 = match std::env::var("UPSTREAM_HTTP2")
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase()
+            .as_str()
+        {
+            "" | "auto" => Http2Mode::Auto,
+            "off" => Http2Mode::Off,
+            "always" => Http2Mode::Always,
+            other => {
+                tracing::warn!("Unknown UPSTREAM_HTTP2 value: {}", other);
+                Http2Mode::Auto
+            }
+        };
+
+        let ip_family = match std::env::var("UPSTREAM_IP_FAMILY")
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase()
+            .as_str()
+        {
+            "" | "auto" => IpFamily::Auto,
+            "prefer-ipv4" => IpFamily::PreferIpv4,
+            "prefer-ipv6" => IpFamily::PreferIpv6,
+            "ipv4" => IpFamily::Ipv4,
+            "ipv6" => IpFamily::Ipv6,
+            other => {
+                tracing::warn!("Unknown UPSTREAM_IP_FAMILY value: {}", other);
+                IpFamily::Auto
+            }
+        };
+
+        Self {
+            http2,
+            ip_family,
+            pool_idle_timeout: Duration::from_secs(
+                config::env_parse("UPSTREAM_POOL_IDLE_TIMEOUT_SECS").unwrap_or(90),
+            ),
+            pool_max_idle_per_host: config::env_parse("UPSTREAM_POOL_MAX_IDLE_PER_HOST")
+                .unwrap_or(32),
+        }
+    }
+}
+
+/// Builds the client used for proxied requests, counting its connections in `metrics`.
+pub fn build(
+    http: &UpstreamHttpConfig,
+    timeouts: &TimeoutConfig,
+    metrics: &Arc<Metrics>,
+) -> Client {
+    let builder = resolving(Client::builder(), http.ip_family)
+        .redirect(reqwest::redirect::Policy::none())
+        .connect_timeout(timeouts.upstream_connect)
+        .read_timeout(timeouts.upstream_read)
+        .pool_idle_timeout(http.pool_idle_timeout)
+        .pool_max_idle_per_host(http.pool_max_idle_per_host)
+        .connector_layer(MapResponseLayer::new(count_connection(metrics.clone())));
+
+    let builder = match http.http2 {
+        Http2Mode::Auto => builder,
+        Http2Mode::Off => builder.http1_only(),
+        Http2Mode::Always => builder.http2_prior_knowledge(),
+    };
+    builder.build().expect("Failed to build reqwest client")
+}
+
+/// Counts each connection the client establishes.
+fn count_connection<C>(metrics: Arc<Metrics>) -> impl Fn(C) -> C + Clone {
+    move |conn| {
+        metrics.upstream_connections.fetch_add(1, Ordering::Relaxed);
+        conn
+    }
+}
+
+/// Makes `builder` connect to the addresses selected by `family`.
+///
+/// Also used for the other clients talking to the upstreams.
+pub fn resolving(builder: ClientBuilder, family: IpFamily) -> ClientBuilder {
+    match family {
+        IpFamily::Auto => builder,
+        family => builder.dns_resolver(FamilyResolver(family)),
+    }
+}
+
+/// The system resolver with its answers passed through [`IpFamily::select`].
+struct FamilyResolver(IpFamily);
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let family = self.0;
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+            let addrs = family.select(addrs);
+            if addrs.is_empty() {
+                return Err(
+                    format!("{} has no address allowed by UPSTREAM_IP_FAMILY", host).into(),
+                );
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn families_are_ordered_and_filtered() {
+        let addrs: Vec<SocketAddr> = [
+            "[2001:db8::1]:0",
+            "192.0.2.1:0",
+            "[2001:db8::2]:0",
+            "192.0.2.2:0",
+        ]
+        .iter()
+        .map(|a| a.parse().unwrap())
+        .collect();
+        let ips = |family: IpFamily| -> Vec<String> {
+            family
+                .select(addrs.clone())
+                .iter()
+                .map(|a| a.ip().to_string())
+                .collect()
+        };
+
+        assert_eq!(
+            ips(IpFamily::Auto),
+            ["2001:db8::1", "192.0.2.1", "2001:db8::2", "192.0.2.2"]
+        );
+        assert_eq!(
+            ips(IpFamily::PreferIpv4),
+            ["192.0.2.1", "192.0.2.2", "2001:db8::1", "2001:db8::2"]
+        );
+        assert_eq!(
+            ips(IpFamily::PreferIpv6),
+            ["2001:db8::1", "2001:db8::2", "192.0.2.1", "192.0.2.2"]
+        );
+        assert_eq!(ips(IpFamily::Ipv4), ["192.0.2.1", "192.0.2.2"]);
+        assert_eq!(ips(IpFamily::Ipv6), ["2001:db8::1", "2001:db8::2"]);
+    }
+}
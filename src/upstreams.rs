@@ -0,0 +1,513 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Named upstreams served next to the `MODE` one.
+//!
+//! Each upstream is selected by the request's host, a path prefix, or both,
+//! and brings its own rewrite variants, banner and cache policy. Requests
+//! matching none of them go to the `MODE` upstream. `spsejecna` and `jidelna`
+//! are presets, so `UPSTREAMS=jidelna` with `UPSTREAM_JIDELNA_PREFIX=/jidelna`
+//! is enough to serve the canteen under `/jidelna`.
+//!
+//! Prefixed upstreams rewrite their absolute URLs and redirects to the prefix,
+//! root-relative links in their pages still point at the proxy root.
+//!
+//! `EXTERNAL_HOSTS` adds one upstream per related host (CDNs, map embeds)
+//! under `/_ext/<host>`, and links to those hosts are rewritten to it.
+//! Wildcard entries like `*.nasejidelna.cz` cover every subdomain, their
+//! upstreams are created per request.
+
+use std::sync::Arc;
+
+use axum::http::HeaderValue;
+use memchr::memmem;
+use serde::Serialize;
+
+use crate::{
+    config::{self, Mode, Upstream},
+    effective_config,
+};
+
+/// A named upstream as configured.
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamSpec {
+    pub name: String,
+    /// Preset or upstream URL.
+    pub mode: Mode,
+    /// Extra hosts or URLs rewritten to the proxy, like `CUSTOM_VARIANTS`.
+    pub variants: Vec<String>,
+    /// Lowercase `Host` header values selecting the upstream.
+    pub hosts: Vec<String>,
+    /// Path prefix selecting the upstream, stripped before forwarding.
+    pub prefix: String,
+    /// Whether pages get the "Not Official" banner.
+    pub banner: bool,
+    /// `Cache-Control` set on the upstream's responses.
+    #[serde(serialize_with = "effective_config::opt_header_value")]
+    pub cache_control: Option<HeaderValue>,
+    /// Whether this is a related host from `EXTERNAL_HOSTS`.
+    pub external: bool,
+}
+
+impl UpstreamSpec {
+    /// Reads the upstreams listed in `UPSTREAMS`. Entries without hosts or a
+    /// prefix could never be selected and are skipped.
+    ///
+    /// `<NAME>` is the upper-cased name with other characters than letters and
+    /// digits replaced by `_`.
+    ///
+    /// # Environment Variables
+    /// * `UPSTREAMS` - Comma-separated names of upstreams.
+    /// * `UPSTREAM_<NAME>_URL` - "spsejecna", "jidelna" or an upstream URL (default: the name).
+    /// * `UPSTREAM_<NAME>_VARIANTS` - Comma-separated hosts or URLs also rewritten to the proxy.
+    /// * `UPSTREAM_<NAME>_HOSTS` - Comma-separated `Host` header values selecting the upstream.
+    /// * `UPSTREAM_<NAME>_PREFIX` - Path prefix selecting the upstream, e.g. "/jidelna".
+    /// * `UPSTREAM_<NAME>_BANNER` - Whether to inject the banner (default: the inverse of `DISABLE_WARNING`).
+    /// * `UPSTREAM_<NAME>_CACHE_CONTROL` - `Cache-Control` value set on responses (optional).
+    pub fn from_env(default_banner: bool) -> Vec<Self> {
+        config::env_list("UPSTREAMS")
+            .into_iter()
+            .filter_map(|name| {
+                let var = |suffix: &str| format!("UPSTREAM_{}_{}", env_name(&name), suffix);
+
+                let url = std::env::var(var("URL"))
+                    .ok()
+                    .filter(|v| !v.trim().is_empty())
+                    .unwrap_or_else(|| name.clone());
+                let prefix = std::env::var(var("PREFIX"))
+                    .map(|p| format!("/{}", p.trim().trim_matches('/')))
+                    .ok()
+                    .filter(|p| p != "/")
+                    .unwrap_or_default();
+                let hosts: Vec<String> = config::env_list(&var("HOSTS"))
+                    .iter()
+                    .map(|h| h.to_ascii_lowercase())
+                    .collect();
+                if hosts.is_empty() && prefix.is_empty() {
+                    tracing::warn!(
+                        "Ignoring upstream {}: neither {} nor {} is set",
+                        name,
+                        var("HOSTS"),
+                        var("PREFIX")
+                    );
+                    return None;
+                }
+
+                let cache_control = std::env::var(var("CACHE_CONTROL"))
+                    .ok()
+                    .filter(|v| !v.is_empty())
+                    .and_then(|v| match HeaderValue::from_str(&v) {
+                        Ok(value) => Some(value),
+                        Err(_) => {
+                            tracing::warn!("Ignoring invalid {}: {:?}", var("CACHE_CONTROL"), v);
+                            None
+                        }
+                    });
+
+                Some(Self {
+                    mode: Mode::parse(url.trim()),
+                    variants: config::env_list(&var("VARIANTS")),
+                    hosts,
+                    prefix,
+                    banner: config::env_parse(&var("BANNER")).unwrap_or(default_banner),
+                    cache_control,
+                    external: false,
+                    name,
+                })
+            })
+            .collect()
+    }
+
+    /// Builds the upstream. Fails like [`Upstream::new`] for invalid URLs.
+    pub fn route(&self) -> Result<Route, String> {
+        let mut upstream = Upstream::new(self.mode.clone(), &self.variants)
+            .map_err(|e| format!("upstream {}: {}", self.name, e))?;
+        upstream.name = self.name.clone();
+        upstream.prefix = self.prefix.clone();
+        upstream.banner = self.banner;
+        upstream.cache_control = self.cache_control.clone();
+        upstream.external = self.external;
+
+        Ok(Route {
+            hosts: self.hosts.clone(),
+            upstream: Arc::new(upstream),
+        })
+    }
+}
+
+/// Path prefix of the related hosts from `EXTERNAL_HOSTS`.
+pub const EXT_PREFIX: &str = "/_ext";
+
+/// Subdomains of an apex proxied under `/_ext/<subdomain>`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Wildcard {
+    /// Lowercase apex domain, without the leading `*.`.
+    pub apex: String,
+    /// Scheme used to reach the subdomains.
+    pub scheme: String,
+    /// The only explicit port accepted in `/_ext/<subdomain>:<port>`.
+    pub port: Option<u16>,
+}
+
+impl Wildcard {
+    /// Whether `host` is a subdomain of the apex.
+    fn matches(&self, host: &str) -> bool {
+        host.len() > self.apex.len() + 1
+            && host.ends_with(&self.apex)
+            && host.as_bytes()[host.len() - self.apex.len() - 1] == b'.'
+    }
+}
+
+/// Reads the related hosts proxied under `/_ext/<host>`, the fixed ones as
+/// upstreams and the wildcards separately.
+///
+/// # Environment Variables
+/// * `EXTERNAL_HOSTS` - Comma-separated hosts or `*.apex` wildcards, prefixed with `http://` for hosts without HTTPS.
+pub fn external_from_env() -> (Vec<UpstreamSpec>, Vec<Wildcard>) {
+    let mut hosts = Vec::new();
+    let mut wildcards = Vec::new();
+
+    for entry in config::env_list("EXTERNAL_HOSTS") {
+        let entry = entry.trim_end_matches('/').to_ascii_lowercase();
+        let (scheme, host) = entry.split_once("://").unwrap_or(("https", &entry));
+
+        if let Some(apex) = host.strip_prefix("*.") {
+            let (apex, port) = match apex.split_once(':') {
+                Some((apex, port)) => match port.parse() {
+                    Ok(port) => (apex, Some(port)),
+                    Err(_) => {
+                        tracing::warn!("Ignoring invalid EXTERNAL_HOSTS entry: {}", entry);
+                        continue;
+                    }
+                },
+                None => (apex, None),
+            };
+            wildcards.push(Wildcard {
+                apex: apex.to_string(),
+                scheme: scheme.to_string(),
+                port,
+            });
+            continue;
+        }
+
+        hosts.push(UpstreamSpec {
+            prefix: format!("{}/{}", EXT_PREFIX, host),
+            mode: Mode::CUSTOM(format!("{}://{}", scheme, host)),
+            variants: Vec::new(),
+            hosts: Vec::new(),
+            banner: false,
+            cache_control: None,
+            external: true,
+            name: host.to_string(),
+        });
+    }
+
+    (hosts, wildcards)
+}
+
+/// Creates the upstream of a wildcard subdomain requested under `/_ext/<host>`,
+/// with the path and query to forward. An explicit port must be the one
+/// configured for the wildcard.
+pub fn select_wildcard(
+    wildcards: &[Wildcard],
+    path_query: &str,
+) -> Option<(Arc<Upstream>, String)> {
+    let rest = path_query.strip_prefix(EXT_PREFIX)?.strip_prefix('/')?;
+    let end = rest.find(['/', '?']).unwrap_or(rest.len());
+    let host = rest[..end].to_ascii_lowercase();
+    let (hostname, port) = host.split_once(':').unwrap_or((&host, ""));
+    if !hostname.bytes().all(is_host_byte) {
+        return None;
+    }
+    let port = match port {
+        "" => None,
+        port => Some(port.parse::<u16>().ok()?),
+    };
+    let wildcard = wildcards
+        .iter()
+        .find(|w| w.matches(hostname) && (port.is_none() || port == w.port))?;
+
+    let mut upstream =
+        Upstream::new(Mode::CUSTOM(format!("{}://{}", wildcard.scheme, host)), &[]).ok()?;
+    upstream.prefix = format!("{}/{}", EXT_PREFIX, host);
+    upstream.banner = false;
+    upstream.external = true;
+    upstream.name = host;
+
+    let rest = match &rest[end..] {
+        "" => "/".to_string(),
+        rest if rest.starts_with('?') => format!("/{}", rest),
+        rest => rest.to_string(),
+    };
+    Some((Arc::new(upstream), rest))
+}
+
+/// Rewrites links to subdomains of the wildcards into root-relative links to
+/// `/_ext/<subdomain>`. `https://`, `http://` and protocol-relative links are
+/// rewritten, other schemes are left alone.
+pub fn rewrite_wildcards(content: &[u8], wildcards: &[Wildcard]) -> Vec<u8> {
+    let mut result = content.to_vec();
+
+    for wildcard in wildcards {
+        let needle = format!(".{}", wildcard.apex);
+        // Host names are case-insensitive, ASCII lowercasing keeps the offsets.
+        let lower = result.to_ascii_lowercase();
+        let mut out = Vec::with_capacity(result.len());
+        let mut last = 0;
+
+        for pos in memmem::find_iter(&lower, needle.as_bytes()) {
+            let end = pos + needle.len();
+            if result
+                .get(end)
+                .is_some_and(|&b| is_host_byte(b.to_ascii_lowercase()))
+            {
+                continue;
+            }
+            let host_start = result[..pos]
+                .iter()
+                .rposition(|&b| !is_host_byte(b.to_ascii_lowercase()))
+                .map_or(0, |idx| idx + 1);
+            if host_start == pos || !result[..host_start].ends_with(b"//") {
+                continue;
+            }
+
+            let before = &result[..host_start - 2];
+            let start = if before.ends_with(b":") {
+                let scheme_start = before[..before.len() - 1]
+                    .iter()
+                    .rposition(|b| !b.is_ascii_alphanumeric())
+                    .map_or(0, |idx| idx + 1);
+                let scheme = &before[scheme_start..before.len() - 1];
+                if !scheme.eq_ignore_ascii_case(b"https") && !scheme.eq_ignore_ascii_case(b"http") {
+                    continue;
+                }
+                scheme_start
+            } else {
+                host_start - 2
+            };
+
+            out.extend_from_slice(&result[last..start]);
+            out.extend_from_slice(EXT_PREFIX.as_bytes());
+            out.push(b'/');
+            out.extend_from_slice(&result[host_start..end].to_ascii_lowercase());
+            last = end;
+        }
+
+        out.extend_from_slice(&result[last..]);
+        result = out;
+    }
+
+    result
+}
+
+fn is_host_byte(b: u8) -> bool {
+    b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'.'
+}
+
+impl UpstreamSpec {
+    /// Rewrites of absolute and protocol-relative links to a related host
+    /// into root-relative links to its prefix, applied by [`rewrite_external`].
+    pub fn external_rewrites(&self) -> Vec<(String, String)> {
+        ["https://", "http://", "//"]
+            .iter()
+            .map(|scheme| (format!("{}{}", scheme, self.name), self.prefix.clone()))
+            .collect()
+    }
+}
+
+/// Replaces links to the related hosts with their prefixes. A link is only
+/// replaced where its host ends, so `//cdn.example.com.evil.net` and
+/// `//cdn.example.com@evil.net` are left alone. Links with an explicit port
+/// point at another server than the configured one and are left alone too.
+pub fn rewrite_external(content: &[u8], rewrites: &[(String, String)]) -> Vec<u8> {
+    let mut result = content.to_vec();
+
+    for (from, to) in rewrites {
+        let mut out = Vec::with_capacity(result.len());
+        let mut last = 0;
+        for pos in memmem::find_iter(&result, from.as_bytes()) {
+            let end = pos + from.len();
+            if !ends_host(result.get(end).copied()) {
+                continue;
+            }
+            out.extend_from_slice(&result[last..pos]);
+            out.extend_from_slice(to.as_bytes());
+            last = end;
+        }
+        out.extend_from_slice(&result[last..]);
+        result = out;
+    }
+
+    result
+}
+
+/// Whether a host is complete before `next`: followed by a path, query,
+/// fragment, quote or the end of the URL.
+fn ends_host(next: Option<u8>) -> bool {
+    next.is_none_or(|b| b"/?#\"'<>()\\".contains(&b) || b.is_ascii_whitespace())
+}
+
+fn env_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// A named upstream ready to serve requests.
+#[derive(Debug, Clone)]
+pub struct Route {
+    /// `Host` header values selecting the upstream, any host if empty.
+    pub hosts: Vec<String>,
+    pub upstream: Arc<Upstream>,
+}
+
+impl Route {
+    /// Returns the path and query to forward if the request belongs to this upstream.
+    fn matches(&self, host: &str, path_query: &str) -> Option<String> {
+        if !self.hosts.is_empty() {
+            let hostname = host.rsplit_once(':').map_or(host, |(name, _)| name);
+            if !self
+                .hosts
+                .iter()
+                .any(|h| h.eq_ignore_ascii_case(host) || h.eq_ignore_ascii_case(hostname))
+            {
+                return None;
+            }
+        }
+
+        let prefix = &self.upstream.prefix;
+        let rest = path_query.strip_prefix(prefix.as_str())?;
+        match rest.as_bytes().first() {
+            None => Some("/".to_string()),
+            Some(b'/') => Some(rest.to_string()),
+            Some(b'?') => Some(format!("/{}", rest)),
+            Some(_) if prefix.is_empty() => Some(rest.to_string()),
+            Some(_) => None,
+        }
+    }
+}
+
+/// Finds the first route serving the request, with the path and query to forward.
+pub fn select<'a>(
+    routes: &'a [Route],
+    host: &str,
+    path_query: &str,
+) -> Option<(&'a Route, String)> {
+    routes
+        .iter()
+        .find_map(|route| route.matches(host, path_query).map(|rest| (route, rest)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cdn() -> Vec<(String, String)> {
+        UpstreamSpec {
+            name: "cdn.example.com".to_string(),
+            mode: Mode::CUSTOM("https://cdn.example.com".to_string()),
+            variants: Vec::new(),
+            hosts: Vec::new(),
+            prefix: format!("{}/cdn.example.com", EXT_PREFIX),
+            banner: false,
+            cache_control: None,
+            external: true,
+        }
+        .external_rewrites()
+    }
+
+    fn rewrite(content: &str) -> String {
+        String::from_utf8(rewrite_external(content.as_bytes(), &cdn())).unwrap()
+    }
+
+    #[test]
+    fn external_links_are_rewritten() {
+        assert_eq!(
+            rewrite(r#"<img src="https://cdn.example.com/a.png">"#),
+            r#"<img src="/_ext/cdn.example.com/a.png">"#
+        );
+        assert_eq!(
+            rewrite("url(//cdn.example.com/b.css?v=2)"),
+            "url(/_ext/cdn.example.com/b.css?v=2)"
+        );
+        assert_eq!(
+            rewrite("'http://cdn.example.com'"),
+            "'/_ext/cdn.example.com'"
+        );
+        assert_eq!(rewrite("https://cdn.example.com"), "/_ext/cdn.example.com");
+    }
+
+    fn wildcards() -> Vec<Wildcard> {
+        vec![
+            Wildcard {
+                apex: "nasejidelna.cz".to_string(),
+                scheme: "https".to_string(),
+                port: None,
+            },
+            Wildcard {
+                apex: "example.org".to_string(),
+                scheme: "http".to_string(),
+                port: Some(8080),
+            },
+        ]
+    }
+
+    #[test]
+    fn wildcard_ports_must_be_configured() {
+        let wildcards = wildcards();
+        let select =
+            |path: &str| select_wildcard(&wildcards, path).map(|(u, rest)| (u.base.clone(), rest));
+
+        assert_eq!(
+            select("/_ext/strav.nasejidelna.cz/login?x=1"),
+            Some((
+                "https://strav.nasejidelna.cz".to_string(),
+                "/login?x=1".to_string()
+            ))
+        );
+        assert_eq!(select("/_ext/strav.nasejidelna.cz:22/"), None);
+        assert_eq!(select("/_ext/strav.nasejidelna.cz:443/"), None);
+        assert_eq!(
+            select("/_ext/a.example.org:8080"),
+            Some(("http://a.example.org:8080".to_string(), "/".to_string()))
+        );
+        assert_eq!(select("/_ext/a.example.org:8081/"), None);
+        assert_eq!(select("/_ext/a.example.org:99999/"), None);
+        assert!(
+            select_wildcard(&wildcards, "/_ext/a.example.org/")
+                .unwrap()
+                .0
+                .external
+        );
+    }
+
+    #[test]
+    fn other_hosts_and_ports_are_left_alone() {
+        for content in [
+            "https://cdn.example.com.evil.net/a.js",
+            "//cdn.example.com@evil.net/a.js",
+            "//cdn.example.community/",
+            "http://cdn.example.com-evil.net",
+            "url(//cdn.example.com:8443/b.css)",
+        ] {
+            assert_eq!(rewrite(content), content);
+        }
+    }
+}
@@ -0,0 +1,694 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Proxy-side user accounts for the API.
+//!
+//! When enabled, everything under `/api` except registration and login needs
+//! `Authorization: Bearer <token>` of a registered user. Accounts are stored in
+//! the database; admins are created with `jecnaproxy add-user --admin`. Users can
+//! subscribe to notifications with their own webhook and are rate limited per
+//! minute (shared across replicas through Redis when `REDIS_URL` is set).
+//! Every token additionally has a daily request quota and an hourly
+//! quota of scrapes (requests that fetch upstream pages); admins are exempt
+//! from all limits.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use axum::{
+    Extension, Json,
+    extract::{Path, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    audit::{self, Actor},
+    cli::{self, AddUserArgs},
+    config,
+    db::Db,
+    notify,
+    state::AppState,
+};
+
+/// User account settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct UsersConfig {
+    /// Whether anyone may register. Otherwise accounts are only created with `add-user`.
+    pub open_registration: bool,
+    /// Default API requests per minute.
+    pub rate_limit: u32,
+    /// API requests per token and day.
+    pub daily_quota: u32,
+    /// Upstream scrapes per token and hour.
+    pub hourly_scrapes: u32,
+}
+
+impl UsersConfig {
+    /// # Environment Variables
+    /// * `USERS_ENABLED` - Set to "true" or "1" to require user accounts for the API.
+    /// * `USERS_OPEN_REGISTRATION` - Set to "true" or "1" to allow anyone to register.
+    /// * `USERS_RATE_LIMIT` - API requests per minute and user (default: 60).
+    /// * `USERS_DAILY_QUOTA` - API requests per token and day (default: 5000).
+    /// * `USERS_HOURLY_SCRAPES` - Upstream scrapes per token and hour (default: 100).
+    pub fn from_env() -> Option<Self> {
+        if !config::env_flag("USERS_ENABLED") {
+            return None;
+        }
+
+        Some(Self {
+            open_registration: config::env_flag("USERS_OPEN_REGISTRATION"),
+            rate_limit: config::env_parse("USERS_RATE_LIMIT").unwrap_or(60),
+            daily_quota: config::env_parse("USERS_DAILY_QUOTA").unwrap_or(5000),
+            hourly_scrapes: config::env_parse("USERS_HOURLY_SCRAPES").unwrap_or(100),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        if s == "admin" {
+            Role::Admin
+        } else {
+            Role::User
+        }
+    }
+}
+
+/// A registered user. Also inserted as a request extension for authenticated API requests.
+#[derive(Debug, Clone, Serialize)]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    pub role: Role,
+    pub webhook_url: Option<String>,
+    /// Notification kinds (e.g. "page_changed") sent to `webhook_url`.
+    pub notify_kinds: Vec<String>,
+    /// Requests per minute, overriding the default.
+    pub rate_limit: Option<u32>,
+    pub created_at: u64,
+}
+
+/// API routes (relative to `/api`) that fetch pages from the upstream.
+const SCRAPING_ROUTES: &[&str] = &[
+    "/page",
+    "/news/",
+    "/grades.",
+    "/timetable.",
+    "/absences",
+    "/exams",
+    "/events",
+    "/dashboard",
+    "/digest/",
+    "/rooms/",
+    "/teachers/",
+    "/canteen/",
+];
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// Quota counters of one token.
+struct TokenUsage {
+    day_start: Instant,
+    requests: u32,
+    hour_start: Instant,
+    scrapes: u32,
+}
+
+impl TokenUsage {
+    fn new(now: Instant) -> Self {
+        Self {
+            day_start: now,
+            requests: 0,
+            hour_start: now,
+            scrapes: 0,
+        }
+    }
+
+    fn roll(&mut self, now: Instant) {
+        if now - self.day_start >= DAY {
+            self.day_start = now;
+            self.requests = 0;
+        }
+        if now - self.hour_start >= HOUR {
+            self.hour_start = now;
+            self.scrapes = 0;
+        }
+    }
+}
+
+/// Quota usage of a token, as reported by `/api/me/usage`.
+#[derive(Debug, Serialize)]
+pub struct Usage {
+    pub requests_today: u32,
+    pub daily_quota: u32,
+    pub daily_reset_secs: u64,
+    pub scrapes_this_hour: u32,
+    pub hourly_scrapes: u32,
+    pub hourly_reset_secs: u64,
+}
+
+/// Per-user request counters and per-token quotas.
+#[derive(Default)]
+pub struct UserState {
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+    quotas: Mutex<HashMap<String, TokenUsage>>,
+}
+
+impl UserState {
+    /// Counts a request, returning the seconds until the window resets if over `limit`.
+    fn hit(&self, user_id: &str, limit: u32) -> Option<u64> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() > 10_000 {
+            windows.retain(|_, (start, _)| now - *start < Duration::from_secs(60));
+        }
+
+        let (start, count) = windows.entry(user_id.to_string()).or_insert((now, 0));
+        if now - *start >= Duration::from_secs(60) {
+            *start = now;
+            *count = 0;
+        }
+        *count += 1;
+        (*count > limit).then(|| 60 - (now - *start).as_secs())
+    }
+
+    /// Charges a request to the quotas of `token_hash`.
+    ///
+    /// Returns the reason and the seconds until the exhausted quota resets if the
+    /// request is over quota; rejected requests aren't counted.
+    fn charge(
+        &self,
+        token_hash: &str,
+        scrape: bool,
+        config: &UsersConfig,
+    ) -> Result<(), (&'static str, u64)> {
+        let now = Instant::now();
+        let mut quotas = self.quotas.lock().unwrap();
+        if quotas.len() > 10_000 {
+            quotas.retain(|_, u| now - u.day_start < DAY);
+        }
+
+        let usage = quotas
+            .entry(token_hash.to_string())
+            .or_insert_with(|| TokenUsage::new(now));
+        usage.roll(now);
+
+        if usage.requests >= config.daily_quota {
+            return Err((
+                "Daily request quota exceeded",
+                (DAY - (now - usage.day_start)).as_secs(),
+            ));
+        }
+        if scrape && usage.scrapes >= config.hourly_scrapes {
+            return Err((
+                "Hourly scrape quota exceeded",
+                (HOUR - (now - usage.hour_start)).as_secs(),
+            ));
+        }
+
+        usage.requests += 1;
+        if scrape {
+            usage.scrapes += 1;
+        }
+        Ok(())
+    }
+
+    fn usage(&self, token_hash: &str, config: &UsersConfig) -> Usage {
+        let now = Instant::now();
+        let mut quotas = self.quotas.lock().unwrap();
+        let usage = quotas
+            .entry(token_hash.to_string())
+            .or_insert_with(|| TokenUsage::new(now));
+        usage.roll(now);
+
+        Usage {
+            requests_today: usage.requests,
+            daily_quota: config.daily_quota,
+            daily_reset_secs: (DAY - (now - usage.day_start)).as_secs(),
+            scrapes_this_hour: usage.scrapes,
+            hourly_scrapes: config.hourly_scrapes,
+            hourly_reset_secs: (HOUR - (now - usage.hour_start)).as_secs(),
+        }
+    }
+}
+
+fn too_many_requests(message: &'static str, retry_after: u64) -> Response {
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, message).into_response();
+    response
+        .headers_mut()
+        .insert("retry-after", HeaderValue::from(retry_after));
+    response
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Returns the user owning the bearer token of a request.
+pub async fn authenticate(state: &AppState, headers: &HeaderMap) -> Option<User> {
+    let db = state.db.as_ref()?;
+    let token = bearer(headers)?;
+    match db.user_by_token(&hash_token(token)).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!("Failed to look up API token: {}", e);
+            None
+        }
+    }
+}
+
+/// Middleware requiring a user token on API routes and applying rate limits and quotas.
+pub async fn require_user(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let Some(config) = &state.config.users else {
+        return next.run(req).await;
+    };
+
+    let Some(user) = authenticate(&state, req.headers()).await else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            "Log in via /api/auth/login and send the token as 'Authorization: Bearer <token>'",
+        )
+            .into_response();
+    };
+
+    if user.role != Role::Admin {
+        let limit = user.rate_limit.unwrap_or(config.rate_limit);
+        let shared = match &state.cluster {
+            Some(cluster) => {
+                cluster
+                    .take_token(
+                        &format!("ratelimit:{}", user.id),
+                        limit,
+                        Duration::from_secs(60),
+                    )
+                    .await
+            }
+            None => None,
+        };
+        if let Some(retry_after) = shared.unwrap_or_else(|| state.users.hit(&user.id, limit)) {
+            return too_many_requests("Rate limit exceeded", retry_after);
+        }
+
+        let path = req.uri().path();
+        let scrape = SCRAPING_ROUTES.iter().any(|r| path.starts_with(r));
+        let token_hash = bearer(req.headers()).map(hash_token).unwrap_or_default();
+        if let Err((message, retry_after)) = state.users.charge(&token_hash, scrape, config) {
+            return too_many_requests(message, retry_after);
+        }
+    }
+
+    req.extensions_mut().insert(user);
+    next.run(req).await
+}
+
+fn db(state: &AppState) -> Option<&Db> {
+    state.db.as_ref().filter(|_| state.config.users.is_some())
+}
+
+fn disabled() -> Response {
+    (StatusCode::NOT_FOUND, "User accounts are not enabled").into_response()
+}
+
+fn internal_error(e: impl std::fmt::Display) -> Response {
+    tracing::error!("User store error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Credentials {
+    username: String,
+    password: String,
+}
+
+/// Handler for `POST /api/auth/register`.
+///
+/// Only creates regular users, admins are created with `jecnaproxy add-user --admin`.
+pub async fn register(State(state): State<AppState>, Json(req): Json<Credentials>) -> Response {
+    let Some(db) = db(&state) else {
+        return disabled();
+    };
+    let open = state
+        .config
+        .users
+        .as_ref()
+        .is_some_and(|u| u.open_registration);
+    if !open {
+        return (StatusCode::FORBIDDEN, "Registration is closed").into_response();
+    }
+
+    let user = match create_user(db, req.username, req.password, Role::User).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return (StatusCode::CONFLICT, "Username is taken").into_response(),
+        Err(CreateError::Invalid(message)) => {
+            return (StatusCode::BAD_REQUEST, message).into_response();
+        }
+        Err(CreateError::Internal(e)) => return internal_error(e),
+    };
+    audit::record(
+        &state,
+        &Actor::user(&user.username),
+        "user.register",
+        Some(&user.id),
+        Some(user.role.as_str()),
+    )
+    .await;
+    (StatusCode::CREATED, Json(user)).into_response()
+}
+
+/// Entry point of the `add-user` subcommand.
+pub async fn add_user(state: &AppState, args: AddUserArgs) {
+    let Some(db) = db(state) else {
+        tracing::error!("add-user needs USERS_ENABLED and DATABASE_URL");
+        std::process::exit(1);
+    };
+    let password = match cli::read_secret("USER_PASSWORD") {
+        Ok(password) => password,
+        Err(e) => {
+            tracing::error!("Failed to read the password: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let role = if args.admin { Role::Admin } else { Role::User };
+
+    match create_user(db, args.username, password, role).await {
+        Ok(Some(user)) => {
+            audit::record(
+                state,
+                &Actor::system(),
+                "user.register",
+                Some(&user.id),
+                Some(user.role.as_str()),
+            )
+            .await;
+        }
+        Ok(None) => {
+            tracing::error!("Username is taken");
+            std::process::exit(1);
+        }
+        Err(CreateError::Invalid(message)) => {
+            tracing::error!("{}", message);
+            std::process::exit(1);
+        }
+        Err(CreateError::Internal(e)) => {
+            tracing::error!("Failed to create the user: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+enum CreateError {
+    Invalid(&'static str),
+    Internal(String),
+}
+
+/// Validates and stores a new account, returning `None` if the username is taken.
+async fn create_user(
+    db: &Db,
+    username: String,
+    password: String,
+    role: Role,
+) -> Result<Option<User>, CreateError> {
+    if username.len() < 3
+        || username.len() > 32
+        || !username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        return Err(CreateError::Invalid(
+            "Username must have 3-32 characters (letters, digits, '.', '_', '-')",
+        ));
+    }
+    if password.len() < 8 {
+        return Err(CreateError::Invalid(
+            "Password must have at least 8 characters",
+        ));
+    }
+
+    let hash = tokio::task::spawn_blocking(move || {
+        Argon2::default()
+            .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
+            .map(|h| h.to_string())
+    })
+    .await;
+    let hash = match hash {
+        Ok(Ok(hash)) => hash,
+        Ok(Err(e)) => return Err(CreateError::Internal(e.to_string())),
+        Err(e) => return Err(CreateError::Internal(e.to_string())),
+    };
+
+    let user = User {
+        id: hex::encode(rand::random::<[u8; 16]>()),
+        username,
+        role,
+        webhook_url: None,
+        notify_kinds: Vec::new(),
+        rate_limit: None,
+        created_at: now(),
+    };
+    match db.insert_user(&user, &hash).await {
+        Ok(true) => {
+            tracing::info!("Registered user {} ({})", user.username, user.role.as_str());
+            Ok(Some(user))
+        }
+        Ok(false) => Ok(None),
+        Err(e) => Err(CreateError::Internal(e.to_string())),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct LoginResponse {
+    token: String,
+    user: User,
+}
+
+/// Handler for `POST /api/auth/login`. Returns a new API token.
+pub async fn login(State(state): State<AppState>, Json(req): Json<Credentials>) -> Response {
+    let Some(db) = db(&state) else {
+        return disabled();
+    };
+
+    let (user, hash) = match db.user_by_name(&req.username).await {
+        Ok(Some(found)) => found,
+        Ok(None) => return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response(),
+        Err(e) => return internal_error(e),
+    };
+
+    let password = req.password;
+    let valid = tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&hash).is_ok_and(|parsed| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok()
+        })
+    })
+    .await
+    .unwrap_or(false);
+    if !valid {
+        audit::record(
+            &state,
+            &Actor::user(&user.username),
+            "user.login_failed",
+            Some(&user.id),
+            None,
+        )
+        .await;
+        return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response();
+    }
+
+    let token = hex::encode(rand::random::<[u8; 32]>());
+    if let Err(e) = db.insert_token(&hash_token(&token), &user.id).await {
+        return internal_error(e);
+    }
+    audit::record(
+        &state,
+        &Actor::user(&user.username),
+        "user.login",
+        Some(&user.id),
+        None,
+    )
+    .await;
+
+    Json(LoginResponse { token, user }).into_response()
+}
+
+/// Handler for `POST /api/auth/logout`. Revokes the token of the request.
+pub async fn logout(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let Some(db) = db(&state) else {
+        return disabled();
+    };
+    let Some(token) = bearer(&headers) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    match db.delete_token(&hash_token(token)).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Handler for `GET /api/me`.
+pub async fn me(user: Option<Extension<User>>) -> Response {
+    match user {
+        Some(Extension(user)) => Json(user).into_response(),
+        None => disabled(),
+    }
+}
+
+/// Handler for `GET /api/me/usage`. Reports the quotas of the token used.
+pub async fn usage(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let (Some(config), Some(token)) = (&state.config.users, bearer(&headers)) else {
+        return disabled();
+    };
+    Json(state.users.usage(&hash_token(token), config)).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationPreferences {
+    webhook_url: Option<String>,
+    kinds: Vec<String>,
+}
+
+/// Handler for `PUT /api/me/notifications`.
+pub async fn update_notifications(
+    State(state): State<AppState>,
+    user: Option<Extension<User>>,
+    Json(req): Json<NotificationPreferences>,
+) -> Response {
+    let (Some(db), Some(Extension(user))) = (db(&state), user) else {
+        return disabled();
+    };
+    if let Some(url) = &req.webhook_url
+        && let Err(message) = notify::validate_user_webhook(url).await
+    {
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    }
+
+    match db
+        .update_notifications(&user.id, req.webhook_url.as_deref(), &req.kinds)
+        .await
+    {
+        Ok(()) => {
+            audit::record(
+                &state,
+                &Actor::user(&user.username),
+                "user.notifications",
+                Some(&user.id),
+                Some(&req.kinds.join(",")),
+            )
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Handler for `GET /_admin/users`.
+pub async fn list(State(state): State<AppState>) -> Response {
+    let Some(db) = db(&state) else {
+        return disabled();
+    };
+    match db.list_users().await {
+        Ok(users) => Json(users).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserUpdate {
+    role: Option<Role>,
+    rate_limit: Option<u32>,
+}
+
+/// Handler for `PATCH /_admin/users/{id}`.
+pub async fn update(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<String>,
+    Json(req): Json<UserUpdate>,
+) -> Response {
+    let Some(db) = db(&state) else {
+        return disabled();
+    };
+    match db.update_user(&id, req.role, req.rate_limit).await {
+        Ok(true) => {
+            let details = format!(
+                "role={} rate_limit={}",
+                req.role.map_or("-", Role::as_str),
+                req.rate_limit.map_or("-".to_string(), |l| l.to_string())
+            );
+            audit::record(&state, &actor, "user.update", Some(&id), Some(&details)).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Handler for `DELETE /_admin/users/{id}`. Also revokes the user's tokens.
+pub async fn remove(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<String>,
+) -> Response {
+    let Some(db) = db(&state) else {
+        return disabled();
+    };
+    match db.delete_user(&id).await {
+        Ok(true) => {
+            audit::record(&state, &actor, "user.remove", Some(&id), None).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => internal_error(e),
+    }
+}
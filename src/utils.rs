@@ -12,10 +12,19 @@
  * GNU General Public License for more details.
  */
 
-use axum::http::{HeaderMap, HeaderValue};
+use std::net::{IpAddr, SocketAddr};
+
+use axum::http::{HeaderMap, HeaderValue, Uri};
+use memchr::memmem;
 use reqwest::Url;
 
-use crate::state::AppState;
+use crate::{
+    config::Upstream,
+    cookies::{self, CookiePolicy},
+    headers,
+    state::AppState,
+    upstreams,
+};
 
 /// Determines the public origin of the proxy for the current request.
 ///
@@ -23,7 +32,7 @@ use crate::state::AppState;
 /// 1. `BASE_URL` from environment configuration.
 /// 2. `Host` header from the incoming request.
 /// 3. Fallback to `http://localhost:3000`.
-pub fn determine_proxy_origin(base_url: Option<&str>, headers: &HeaderMap) -> String {
+pub fn determine_proxy_origin(base_url: Option<&str>, headers: &HeaderMap, https: bool) -> String {
     if let Some(base) = base_url {
         return base.trim_end_matches('/').to_string();
     }
@@ -34,56 +43,240 @@ pub fn determine_proxy_origin(base_url: Option<&str>, headers: &HeaderMap) -> St
         .unwrap_or("localhost:3000");
 
     // If no BASE_URL is set we are probably running locally or behind a simple proxy
-    // that forwards the Host header. We assume HTTP.
-    format!("http://{}", host)
+    // that forwards the Host header. We assume HTTP unless we terminated TLS ourselves.
+    let scheme = if https { "https" } else { "http" };
+    format!("{}://{}", scheme, host)
+}
+
+/// Determines the IP address of the client.
+///
+/// When `trust_forwarded_for` is set, the last address in `X-Forwarded-For` wins,
+/// otherwise the peer address of the connection is used. The last address is
+/// the one appended by the trusted reverse proxy, earlier ones come from the
+/// client and can be anything.
+pub fn client_ip(peer: &SocketAddr, headers: &HeaderMap, trust_forwarded_for: bool) -> IpAddr {
+    if trust_forwarded_for
+        && let Some(ip) = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .next_back()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .and_then(|v| v.trim().parse().ok())
+    {
+        return ip;
+    }
+
+    peer.ip()
 }
 
 /// Rewrites a content string (HTML, JSON, etc.) to point to the proxy instead of the upstream.
+///
+/// `REWRITE_MAP` pairs are replaced first, then links to `EXTERNAL_HOSTS`, then
+/// the spellings of the upstream URL, then links to wildcard `EXTERNAL_HOSTS`.
 pub fn rewrite_content_urls(content: String, proxy_origin: &str, state: &AppState) -> String {
-    let urls = state.config.mode.get_all_variants();
+    let upstream = state.upstream();
     let mut result = content;
-    for url in urls {
-        result = result.replace(&url, proxy_origin);
+    for (from, to) in &state.config.rewrite_map {
+        result = result.replace(from, to);
+    }
+    if !state.config.external_rewrites.is_empty() {
+        let rewritten =
+            upstreams::rewrite_external(result.as_bytes(), &state.config.external_rewrites);
+        result = String::from_utf8(rewritten).expect("only ASCII is replaced");
+    }
+    for url in &upstream.variants {
+        result = result.replace(url, proxy_origin);
+    }
+    if !state.config.external_wildcards.is_empty() {
+        let rewritten =
+            upstreams::rewrite_wildcards(result.as_bytes(), &state.config.external_wildcards);
+        result = String::from_utf8(rewritten).expect("only ASCII is replaced");
     }
     result
 }
 
+/// Rewrites a `Location` header value to point to the proxy.
+///
+/// Root-relative locations of an upstream served under a prefix get the prefix.
+pub fn rewrite_location(location: &str, proxy_origin: &str, state: &AppState) -> String {
+    let rewritten = rewrite_content_urls(location.to_string(), proxy_origin, state);
+    let prefix = &state.upstream().prefix;
+    if rewritten.is_empty() {
+        format!("{}/", prefix)
+    } else if !prefix.is_empty() && location.starts_with('/') && !location.starts_with("//") {
+        format!("{}{}", prefix, rewritten)
+    } else {
+        rewritten
+    }
+}
+
+/// Escapes the characters with a special meaning in XML text and attributes.
+pub fn escape_xml(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Rewrites the URI references of a `Link` header (`<url>; rel=preload, ...`).
+pub fn rewrite_link(link: &str, proxy_origin: &str, state: &AppState) -> String {
+    let mut out = String::with_capacity(link.len());
+    let mut rest = link;
+    while let Some(open) = rest.find('<') {
+        let Some(close) = rest[open..].find('>') else {
+            break;
+        };
+        out.push_str(&rest[..=open]);
+        out.push_str(&rewrite_location(
+            &rest[open + 1..open + close],
+            proxy_origin,
+            state,
+        ));
+        out.push('>');
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Rewrites the target of a `Refresh` header (`5; url=https://...`).
+pub fn rewrite_refresh(refresh: &str, proxy_origin: &str, state: &AppState) -> String {
+    let Some(idx) = refresh.to_ascii_lowercase().find("url=") else {
+        return refresh.to_string();
+    };
+    let (head, url) = refresh.split_at(idx + 4);
+    let quote = url
+        .chars()
+        .next()
+        .filter(|c| *c == '\'' || *c == '"')
+        .map_or("", |c| &url[..c.len_utf8()]);
+    let inner = url[quote.len()..].trim_end_matches(quote);
+    format!(
+        "{}{}{}{}",
+        head,
+        quote,
+        rewrite_location(inner, proxy_origin, state),
+        quote
+    )
+}
+
+/// Byte-level [`rewrite_content_urls`]. Everything except the replaced URLs is kept
+/// byte for byte, so bodies that aren't valid UTF-8 aren't corrupted.
+pub fn rewrite_content_bytes(content: &[u8], proxy_origin: &str, state: &AppState) -> Vec<u8> {
+    let mut result = content.to_vec();
+    for (from, to) in &state.config.rewrite_map {
+        result = replace_bytes(&result, from.as_bytes(), to.as_bytes());
+    }
+    if !state.config.external_rewrites.is_empty() {
+        result = upstreams::rewrite_external(&result, &state.config.external_rewrites);
+    }
+    let result = rewrite_upstream_bytes(&result, proxy_origin, &state.upstream());
+    if state.config.external_wildcards.is_empty() {
+        result
+    } else {
+        upstreams::rewrite_wildcards(&result, &state.config.external_wildcards)
+    }
+}
+
+fn rewrite_upstream_bytes(content: &[u8], proxy_origin: &str, upstream: &Upstream) -> Vec<u8> {
+    let mut result = content.to_vec();
+    for url in &upstream.variants {
+        result = replace_bytes(&result, url.as_bytes(), proxy_origin.as_bytes());
+    }
+    result
+}
+
+fn replace_bytes(haystack: &[u8], needle: &[u8], replacement: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(haystack.len());
+    let mut last = 0;
+    for pos in memmem::find_iter(haystack, needle) {
+        out.extend_from_slice(&haystack[last..pos]);
+        out.extend_from_slice(replacement);
+        last = pos + needle.len();
+    }
+    out.extend_from_slice(&haystack[last..]);
+    out
+}
+
 /// Processes a `Set-Cookie` header value
-pub fn process_cookie(cookie: &str, is_secure_context: bool) -> String {
+///
+/// Every attribute is emitted at most once. Of repeated attributes the last
+/// one is kept, as browsers do.
+pub fn process_cookie(cookie: &str, is_secure_context: bool, policy: &CookiePolicy) -> String {
     let mut has_secure = false;
-    let mut parts: Vec<String> = Vec::new();
+    let mut has_partitioned = false;
+    let mut same_site = None;
+    let mut attributes = Vec::new();
 
-    for raw in cookie.split(';') {
+    let mut segments = cookie.split(';');
+    let name_value = segments.next().unwrap_or_default().trim();
+
+    for raw in segments {
         let part = raw.trim();
-        let lower = part.to_lowercase();
-
-        match lower.as_str() {
-            p if p.starts_with("domain=") => {}
-            p if p.starts_with("path=") => parts.push(part.to_string()),
-            p if p.starts_with("samesite=") => {}
-            "secure" => {
-                has_secure = true;
-                if is_secure_context {
-                    parts.push("Secure".to_string());
-                }
-            }
-            "httponly" => {
-                parts.push("HttpOnly".to_string());
-            }
-            _ => parts.push(part.to_string()),
+        match attribute_name(part).as_str() {
+            "" | "domain" => {}
+            "samesite" => same_site = Some(part.to_string()),
+            "secure" => has_secure = true,
+            "partitioned" => has_partitioned = true,
+            "httponly" => push_attribute(&mut attributes, "HttpOnly".to_string()),
+            _ => push_attribute(&mut attributes, part.to_string()),
         }
     }
 
-    if is_secure_context {
-        if !has_secure {
-            parts.push("Secure".to_string());
+    let secure = (policy.preserve && has_secure) || policy.secure(is_secure_context);
+    let name_value = match cookies::rename_prefixed(name_value, secure) {
+        Some(renamed) => renamed,
+        None => {
+            if cookies::is_host_prefixed(name_value) {
+                push_attribute(&mut attributes, "Path=/".to_string());
+            }
+            name_value.to_string()
         }
-        parts.push("SameSite=None".to_string());
-    } else {
-        parts.push("SameSite=Lax".to_string());
+    };
+    if secure {
+        attributes.push("Secure".to_string());
     }
+    // Browsers reject partitioned cookies without `Secure`.
+    if secure && (has_partitioned || policy.partitioned) {
+        attributes.push("Partitioned".to_string());
+    }
+    match same_site {
+        Some(same_site) if policy.preserve => attributes.push(same_site),
+        _ => attributes.push(policy.same_site(is_secure_context).to_string()),
+    }
+
+    let mut out = name_value;
+    for attribute in attributes {
+        out.push_str("; ");
+        out.push_str(&attribute);
+    }
+    out
+}
+
+/// Lowercase name of a cookie attribute like `Max-Age=60`.
+fn attribute_name(attribute: &str) -> String {
+    attribute
+        .split('=')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
 
-    parts.join("; ")
+/// Appends an attribute, replacing an earlier one of the same name.
+fn push_attribute(attributes: &mut Vec<String>, attribute: String) {
+    let name = attribute_name(&attribute);
+    attributes.retain(|a| attribute_name(a) != name);
+    attributes.push(attribute);
 }
 
 /// Checks if the proxy origin is considered "secure" (HTTPS or localhost).
@@ -95,31 +288,440 @@ pub fn is_secure_origin(origin: &str) -> bool {
 
 /// Rewrites request headers before sending to the upstream server.
 pub fn prepare_request_headers(headers: &mut HeaderMap, state: &AppState) {
+    headers::strip_hop_by_hop(headers);
     headers.remove("host");
     headers.remove("content-length");
     headers.remove("accept-encoding");
+    // The proxy answers the client's `100-continue` itself.
+    headers.remove("expect");
+
+    let upstream = state.upstream();
 
     if headers.contains_key("origin") {
-        headers.insert(
-            "origin",
-            HeaderValue::from_str(&state.config.mode.url()).unwrap(),
+        headers.insert("origin", upstream.origin.clone());
+    }
+
+    cookies::prepare_request(headers);
+    headers::merge_cookies(headers);
+
+    // Related hosts are third parties, the session belongs to the main upstream.
+    if upstream.external {
+        headers.remove("cookie");
+        headers.remove("authorization");
+    }
+
+    if let Some(referer) = headers.get("referer") {
+        // A referer that can't be pointed at the upstream is dropped rather than leaked.
+        let rewritten = referer
+            .to_str()
+            .ok()
+            .and_then(|r| rewrite_referer(r, &upstream))
+            .and_then(|r| HeaderValue::from_str(&r).ok());
+
+        match rewritten {
+            Some(value) => headers.insert("referer", value),
+            None => headers.remove("referer"),
+        };
+    }
+
+    tracing::info!(headers = ?state.config.privacy.headers(headers));
+}
+
+/// Points a `Referer` at the upstream origin.
+///
+/// Only the origin is replaced, the path and query are kept exactly as the
+/// browser sent them instead of being re-serialized.
+fn rewrite_referer(referer: &str, upstream: &Upstream) -> Option<String> {
+    let url = Url::parse(referer).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+
+    let after_scheme = referer.find("://")? + 3;
+    let rest = referer[after_scheme..]
+        .find(['/', '?', '#'])
+        .map_or("/", |idx| &referer[after_scheme + idx..]);
+    let origin = upstream.origin.to_str().ok()?;
+    Some(format!("{}{}", origin, rest))
+}
+
+/// Path and query forwarded upstream for a request URI.
+///
+/// The path is normalized with [`normalize_path`] when `normalize` is set, the
+/// query is always kept byte for byte (`+`, `;`, escapes and their case).
+/// Parsing the upstream URL afterwards only escapes what the URL standard
+/// requires in http(s) queries, i.e. `'` and raw non-ASCII characters.
+pub fn upstream_path_query(uri: &Uri, normalize: bool) -> String {
+    let path = if normalize {
+        normalize_path(uri.path())
+    } else {
+        uri.path().to_string()
+    };
+    match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    }
+}
+
+/// Normalizes a request path before it is forwarded.
+///
+/// Percent-encoded unreserved characters are decoded and other escapes are
+/// uppercased, duplicate slashes are collapsed and `.`/`..` segments resolved
+/// (RFC 3986, sections 6.2.2 and 5.2.4), so equivalent URLs look the same
+/// upstream and never climb above the root.
+pub fn normalize_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = String::with_capacity(path.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) => {
+                decoded.push(byte as char);
+                i += 3;
+            }
+            Some(byte) => {
+                decoded.push_str(&format!("%{:02X}", byte));
+                i += 3;
+            }
+            None => {
+                let ch = path[i..].chars().next().expect("index is a char boundary");
+                decoded.push(ch);
+                i += ch.len_utf8();
+            }
+        }
+    }
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    let trailing_slash = decoded.len() > 1
+        && (decoded.ends_with('/') || decoded.ends_with("/.") || decoded.ends_with("/.."));
+    let mut normalized = format!("/{}", segments.join("/"));
+    if trailing_slash && !segments.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        config::{Config, Mode},
+        cookies::{SameSite, SecureMode},
+    };
+
+    /// URLs seen on the school site, with the path they are forwarded as.
+    const CORPUS: &[(&str, &str)] = &[
+        (
+            "/score/student?schoolYearId=17&schoolYearHalfId=2",
+            "/score/student?schoolYearId=17&schoolYearHalfId=2",
+        ),
+        (
+            "/timetable/class?classId=ABC&timeTableId=",
+            "/timetable/class?classId=ABC&timeTableId=",
+        ),
+        (
+            "/absence/passing-student;jsessionid=1A2B3C?userId=5",
+            "/absence/passing-student;jsessionid=1A2B3C?userId=5",
+        ),
+        (
+            "/vyhledavani?q=Je%C4%8Dn%C3%A1+ulice",
+            "/vyhledavani?q=Je%C4%8Dn%C3%A1+ulice",
+        ),
+        ("/vyhledavani?q=a%2bb+c%2Bd", "/vyhledavani?q=a%2bb+c%2Bd"),
+        ("/aktuality?page=2;sort=date", "/aktuality?page=2;sort=date"),
+        ("/list?a=1&&b=&=c&d", "/list?a=1&&b=&=c&d"),
+        (
+            "/list?redirect=%2Fscore%2Fstudent%3Fid%3D1",
+            "/list?redirect=%2Fscore%2Fstudent%3Fid%3D1",
+        ),
+        ("/list?x=%zz&y=%4", "/list?x=%zz&y=%4"),
+        ("/list?q=a//b/../c", "/list?q=a//b/../c"),
+        ("/list?", "/list?"),
+        (
+            "/dokumenty/%C5%A0koln%C3%AD%20%c5%99%c3%a1d.pdf",
+            "/dokumenty/%C5%A0koln%C3%AD%20%C5%99%C3%A1d.pdf",
+        ),
+        (
+            "/dokumenty//rozvrh/./2025/../2026/",
+            "/dokumenty/rozvrh/2026/",
+        ),
+        ("/%7Eucitel/%2e%2e/index.html", "/index.html"),
+        ("/../../etc/passwd", "/etc/passwd"),
+        ("/a/%2F/b", "/a/%2F/b"),
+    ];
+
+    fn spsejecna() -> Upstream {
+        Upstream::new(Mode::SPSEJECNA, &[]).unwrap()
+    }
+
+    #[test]
+    fn forwarded_path_query() {
+        for (input, expected) in CORPUS {
+            let uri: Uri = input.parse().unwrap();
+            assert_eq!(&upstream_path_query(&uri, true), expected, "{}", input);
+
+            let unnormalized = upstream_path_query(&uri, false);
+            assert_eq!(unnormalized, *input, "{}", input);
+        }
+    }
+
+    #[test]
+    fn query_survives_upstream_url() {
+        let upstream = spsejecna();
+        for (input, _) in CORPUS {
+            let uri: Uri = input.parse().unwrap();
+            let path_query = upstream_path_query(&uri, true);
+            let url = Url::parse(&format!("{}{}", upstream.base, path_query)).unwrap();
+            assert_eq!(url.query(), uri.query(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn normalize_edge_cases() {
+        assert_eq!(normalize_path(""), "/");
+        assert_eq!(normalize_path("/"), "/");
+        assert_eq!(normalize_path("//"), "/");
+        assert_eq!(normalize_path("/dir/."), "/dir/");
+        assert_eq!(normalize_path("/dir/.."), "/");
+        assert_eq!(normalize_path("/a/b/.."), "/a/");
+        assert_eq!(normalize_path("/%41%62%2D"), "/Ab-");
+        assert_eq!(normalize_path("/%3a%3F"), "/%3A%3F");
+        assert_eq!(normalize_path("/č"), "/č");
+    }
+
+    #[test]
+    fn referer_keeps_encoding() {
+        let upstream = spsejecna();
+        for (input, _) in CORPUS {
+            let referer = format!("https://jecna.example.org{}", input);
+            assert_eq!(
+                rewrite_referer(&referer, &upstream).unwrap(),
+                format!("https://www.spsejecna.cz{}", input)
+            );
+        }
+        assert_eq!(
+            rewrite_referer("http://localhost:3000", &upstream).unwrap(),
+            "https://www.spsejecna.cz/"
         );
+        assert_eq!(
+            rewrite_referer("http://localhost:3000?q='a'", &upstream).unwrap(),
+            "https://www.spsejecna.cz?q='a'"
+        );
+        assert!(rewrite_referer("about:blank", &upstream).is_none());
+        assert!(rewrite_referer("/relative", &upstream).is_none());
+    }
+
+    #[test]
+    fn rewritten_links_keep_encoding() {
+        let upstream = spsejecna();
+        for (input, _) in CORPUS {
+            for variant in &upstream.variants {
+                let html = format!(r#"<a href="{}{}">odkaz</a>"#, variant, input);
+                let rewritten =
+                    rewrite_upstream_bytes(html.as_bytes(), "https://jecna.example.org", &upstream);
+                assert_eq!(
+                    String::from_utf8(rewritten).unwrap(),
+                    format!(r#"<a href="https://jecna.example.org{}">odkaz</a>"#, input)
+                );
+            }
+        }
+    }
+
+    fn cookie_name() -> impl Strategy<Value = String> {
+        (
+            prop_oneof![Just(""), Just("__Host-"), Just("__Secure-")],
+            "[A-Za-z0-9_]{1,8}",
+        )
+            .prop_map(|(prefix, name)| format!("{}{}", prefix, name))
+    }
+
+    fn cookie_value() -> impl Strategy<Value = String> {
+        prop_oneof!["[A-Za-z0-9%=._-]{0,12}", "\"[A-Za-z0-9 =]{0,8}\""]
+    }
+
+    fn cookie_attribute() -> impl Strategy<Value = String> {
+        prop_oneof![
+            "(Path|path|PATH)=/[a-z/]{0,8}",
+            "(Domain|domain)=\\.?(www\\.)?spsejecna\\.cz",
+            "(Max-Age|max-age)=-?[0-9]{1,6}",
+            Just("Expires=Fri, 31-Dec-2027 23:59:59 GMT".to_string()),
+            "Secure|secure|SECURE",
+            "HttpOnly|httponly",
+            "(SameSite|samesite)=(Lax|Strict|None|lax)",
+            "Partitioned|partitioned",
+            "Priority=(High|Low)",
+            Just(String::new()),
+        ]
+    }
+
+    fn set_cookie() -> impl Strategy<Value = String> {
+        (
+            cookie_name(),
+            cookie_value(),
+            prop::collection::vec(cookie_attribute(), 0..8),
+            prop_oneof![Just(";"), Just("; "), Just(" ; ")],
+        )
+            .prop_map(|(name, value, attributes, separator)| {
+                let mut cookie = format!("{}={}", name, value);
+                for attribute in attributes {
+                    cookie.push_str(separator);
+                    cookie.push_str(&attribute);
+                }
+                cookie
+            })
+    }
+
+    fn cookie_policy() -> impl Strategy<Value = CookiePolicy> {
+        (
+            prop_oneof![
+                Just(SameSite::Auto),
+                Just(SameSite::None),
+                Just(SameSite::Lax),
+                Just(SameSite::Strict),
+            ],
+            prop_oneof![
+                Just(SecureMode::Auto),
+                Just(SecureMode::Always),
+                Just(SecureMode::Never),
+            ],
+            any::<bool>(),
+            any::<bool>(),
+        )
+            .prop_map(|(same_site, secure, preserve, partitioned)| CookiePolicy {
+                same_site,
+                secure,
+                preserve,
+                partitioned,
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn cookie_keeps_name_value(
+            cookie in set_cookie(),
+            secure_context in any::<bool>(),
+            policy in cookie_policy(),
+        ) {
+            let processed = process_cookie(&cookie, secure_context, &policy);
+            let name_value = cookie.split(';').next().unwrap().trim();
+            let processed_name_value = processed.split(';').next().unwrap();
+            prop_assert!(
+                processed_name_value == name_value
+                    || Some(processed_name_value.to_string())
+                        == cookies::rename_prefixed(name_value, false),
+                "{:?} became {:?}",
+                cookie,
+                processed
+            );
+        }
+
+        #[test]
+        fn cookie_attributes_are_unique(
+            cookie in set_cookie(),
+            secure_context in any::<bool>(),
+            policy in cookie_policy(),
+        ) {
+            let processed = process_cookie(&cookie, secure_context, &policy);
+            let mut names: Vec<String> = processed.split(';').skip(1).map(attribute_name).collect();
+            prop_assert!(!names.contains(&String::new()), "empty attribute in {:?}", processed);
+            prop_assert!(!names.contains(&"domain".to_string()), "domain kept in {:?}", processed);
+            if names.contains(&"partitioned".to_string()) {
+                prop_assert!(names.contains(&"secure".to_string()), "{:?}", processed);
+            }
+            names.sort();
+            let count = names.len();
+            names.dedup();
+            prop_assert_eq!(names.len(), count, "duplicate attribute in {:?}", processed);
+        }
+
+        #[test]
+        fn cookie_processing_is_idempotent(
+            cookie in set_cookie(),
+            secure_context in any::<bool>(),
+            policy in cookie_policy(),
+        ) {
+            let once = process_cookie(&cookie, secure_context, &policy);
+            let twice = process_cookie(&once, secure_context, &policy);
+            prop_assert_eq!(once, twice);
+        }
     }
 
-    if headers.contains_key("referer") {
-        let base_url = Url::parse(&state.config.mode.url()).unwrap();
+    #[test]
+    fn location_rewriting() {
+        let state = AppState::new(Arc::new(Config::from_env()), spsejecna());
+        let origin = "https://jecna.example.org";
+        let path = "/[a-z0-9._~%-]{0,8}(/[a-z0-9._~%-]{0,8}){0,3}(\\?[a-z0-9=&]{0,10})?";
+
+        proptest!(|(path in path, variant in 0..4usize)| {
+            let upstream = &state.upstream().variants[variant];
+            let absolute = rewrite_location(&format!("{}{}", upstream, path), origin, &state);
+            prop_assert_eq!(&absolute, &format!("{}{}", origin, path));
+            prop_assert_eq!(rewrite_location(&absolute, origin, &state), absolute);
+            prop_assert_eq!(rewrite_location(&path, origin, &state), path.clone());
+            let foreign = format!("https://example.com{}", path);
+            prop_assert_eq!(rewrite_location(&foreign, origin, &state), foreign);
+        });
+    }
 
-        let mut referer_url = Url::parse(headers["referer"].to_str().unwrap()).unwrap();
+    #[test]
+    fn external_upstreams_get_no_credentials() {
+        let mut upstream =
+            Upstream::new(Mode::CUSTOM("https://cdn.example.com".into()), &[]).unwrap();
+        upstream.external = true;
+        let state = AppState::new(Arc::new(Config::from_env()), upstream);
 
-        referer_url.set_scheme(base_url.scheme()).unwrap();
-        referer_url.set_host(base_url.host_str()).unwrap();
-        referer_url.set_port(base_url.port()).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("cookie", HeaderValue::from_static("JSESSIONID=1"));
+        headers.insert("authorization", HeaderValue::from_static("Basic YTpi"));
+        headers.insert("accept", HeaderValue::from_static("image/png"));
+        prepare_request_headers(&mut headers, &state);
+
+        assert!(headers.get("cookie").is_none());
+        assert!(headers.get("authorization").is_none());
+        assert_eq!(headers["accept"], "image/png");
+    }
+
+    #[test]
+    fn forwarded_for_takes_the_proxy_entry() {
+        let peer: SocketAddr = "10.0.0.2:4000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.append(
+            "x-forwarded-for",
+            HeaderValue::from_static("203.0.113.9, 198.51.100.1"),
+        );
+        headers.append(
+            "x-forwarded-for",
+            HeaderValue::from_static("1.2.3.4, 192.0.2.7"),
+        );
+
+        assert_eq!(
+            client_ip(&peer, &headers, true),
+            "192.0.2.7".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(client_ip(&peer, &headers, false), peer.ip());
 
         headers.insert(
-            "referer",
-            HeaderValue::from_str(referer_url.as_str()).unwrap(),
+            "x-forwarded-for",
+            HeaderValue::from_static("1.2.3.4, garbage"),
         );
+        assert_eq!(client_ip(&peer, &headers, true), peer.ip());
     }
-
-    tracing::info!(?headers);
 }
@@ -0,0 +1,216 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Strict request validation against request smuggling.
+//!
+//! hyper parses requests leniently where the RFCs allow it, but the upstream
+//! or a proxy in front of this one may frame or route the same bytes
+//! differently. Requests with ambiguous framing, control characters in
+//! headers or a target that disagrees with `Host` are therefore answered with
+//! `400 Bad Request` before anything is forwarded.
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, Method, StatusCode, Version, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// Middleware rejecting malformed requests.
+pub async fn validate(req: Request, next: Next) -> Response {
+    if let Err(reason) = check(&req) {
+        tracing::debug!("Rejecting malformed request: {}", reason);
+        return (StatusCode::BAD_REQUEST, format!("Bad Request: {}", reason)).into_response();
+    }
+    next.run(req).await
+}
+
+/// Returns why `req` is rejected, if it is.
+fn check(req: &Request) -> Result<(), &'static str> {
+    let headers = req.headers();
+    check_header_values(headers)?;
+    check_framing(headers, req.version())?;
+    check_target(req)
+}
+
+/// Header values may not contain control characters other than tab.
+///
+/// The HTTP parsers refuse them already, but hyper builds header values
+/// without validating them again, so this doesn't depend on the parser version.
+fn check_header_values(headers: &HeaderMap) -> Result<(), &'static str> {
+    let control = |b: &u8| (*b < b' ' && *b != b'\t') || *b == 0x7f;
+    if headers.values().any(|v| v.as_bytes().iter().any(control)) {
+        return Err("control character in a header value");
+    }
+    Ok(())
+}
+
+/// The body length must be determined by exactly one, unambiguous header.
+fn check_framing(headers: &HeaderMap, version: Version) -> Result<(), &'static str> {
+    let mut lengths = headers.get_all(header::CONTENT_LENGTH).iter().peekable();
+    let has_length = lengths.peek().is_some();
+    let mut length = None;
+    for value in lengths {
+        // Also covers comma-separated lists, which some servers accept.
+        let value = value.to_str().map_err(|_| "invalid Content-Length")?;
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err("invalid Content-Length");
+        }
+        if length.is_some_and(|length| length != value) {
+            return Err("conflicting Content-Length headers");
+        }
+        length = Some(value);
+    }
+
+    let mut encodings = headers.get_all(header::TRANSFER_ENCODING).iter();
+    let Some(encoding) = encodings.next() else {
+        return Ok(());
+    };
+    if has_length {
+        return Err("both Content-Length and Transfer-Encoding");
+    }
+    if version != Version::HTTP_11 {
+        return Err("Transfer-Encoding is only allowed in HTTP/1.1");
+    }
+    // Clients send nothing but a single `chunked`, anything else is an attempt
+    // to have the upstream disagree about where the body ends.
+    if encodings.next().is_some() || !encoding.as_bytes().eq_ignore_ascii_case(b"chunked") {
+        return Err("unsupported Transfer-Encoding");
+    }
+    Ok(())
+}
+
+/// HTTP/1 requests must use the origin form, or an absolute form naming the `Host`.
+fn check_target(req: &Request) -> Result<(), &'static str> {
+    let mut hosts = req.headers().get_all(header::HOST).iter();
+    let host = hosts.next();
+    if hosts.next().is_some() {
+        return Err("multiple Host headers");
+    }
+    // HTTP/2 and HTTP/3 carry the target in pseudo-headers, hyper builds an absolute URI of them.
+    if !matches!(req.version(), Version::HTTP_10 | Version::HTTP_11) {
+        return Ok(());
+    }
+    if req.version() == Version::HTTP_11 && host.is_none() {
+        return Err("missing Host header");
+    }
+
+    let uri = req.uri();
+    if uri.path() == "*" {
+        return if req.method() == Method::OPTIONS {
+            Ok(())
+        } else {
+            Err("asterisk-form target")
+        };
+    }
+    let Some(authority) = uri.authority() else {
+        return Ok(());
+    };
+    if req.method() == Method::CONNECT || !matches!(uri.scheme_str(), Some("http" | "https")) {
+        return Err("not a proxy for other hosts");
+    }
+    if !host.is_some_and(|host| {
+        host.as_bytes()
+            .eq_ignore_ascii_case(authority.as_str().as_bytes())
+    }) {
+        return Err("absolute-form target differs from Host");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    fn request(method: &str, target: &str, headers: &[(&str, &[u8])]) -> Request {
+        let mut builder = Request::builder().method(method).uri(target);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn accepts_ordinary_requests() {
+        let host: (&str, &[u8]) = ("host", b"localhost:3000");
+        for req in [
+            request("GET", "/score/student?x=1", &[host]),
+            request("POST", "/user/login", &[host, ("content-length", b"12")]),
+            request(
+                "POST",
+                "/upload",
+                &[host, ("transfer-encoding", b"Chunked")],
+            ),
+            request(
+                "POST",
+                "/",
+                &[host, ("content-length", b"5"), ("content-length", b"5")],
+            ),
+            request("GET", "http://localhost:3000/", &[host]),
+            request("OPTIONS", "*", &[host]),
+            request("GET", "/", &[host, ("user-agent", b"Mozilla/5.0\t(X11)")]),
+        ] {
+            assert_eq!(check(&req), Ok(()), "{:?}", req);
+        }
+    }
+
+    #[test]
+    fn rejects_ambiguous_framing() {
+        let host: (&str, &[u8]) = ("host", b"localhost:3000");
+        for headers in [
+            &[
+                host,
+                ("content-length", b"5"),
+                ("transfer-encoding", b"chunked"),
+            ][..],
+            &[host, ("content-length", b"5"), ("content-length", b"6")],
+            &[host, ("content-length", b"5, 5")],
+            &[host, ("content-length", b"+5")],
+            &[host, ("content-length", b"")],
+            &[host, ("transfer-encoding", b"chunked, identity")],
+            &[host, ("transfer-encoding", b"xchunked")],
+            &[
+                host,
+                ("transfer-encoding", b"chunked"),
+                ("transfer-encoding", b"chunked"),
+            ],
+        ] {
+            let req = request("POST", "/", headers);
+            assert!(check(&req).is_err(), "{:?}", req);
+        }
+    }
+
+    #[test]
+    fn rejects_foreign_targets() {
+        let host: (&str, &[u8]) = ("host", b"localhost:3000");
+        for req in [
+            request("GET", "http://evil.example/", &[host]),
+            request("GET", "ftp://localhost:3000/", &[host]),
+            request("CONNECT", "localhost:3000", &[host]),
+            request("GET", "*", &[host]),
+            request("GET", "/", &[]),
+            request("GET", "/", &[host, ("host", b"evil.example")]),
+        ] {
+            assert!(check(&req).is_err(), "{:?}", req);
+        }
+    }
+
+    #[test]
+    fn trusts_http2_targets() {
+        let mut req = request("GET", "https://localhost:3000/", &[]);
+        *req.version_mut() = Version::HTTP_2;
+        assert_eq!(check(&req), Ok(()));
+    }
+}
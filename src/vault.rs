@@ -0,0 +1,670 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Encrypted store of upstream credentials.
+//!
+//! Registered accounts are only used by the scheduled grade check, which logs
+//! in on their behalf and notifies the account's own webhook about new grades
+//! and new entries of the exam schedule, and by the optional weekly digest.
+//! Passwords are encrypted with XChaCha20-Poly1305 under `VAULT_KEY` and never
+//! leave the server again. What was already notified is kept in the database,
+//! so restarts don't repeat notifications.
+
+use std::{
+    collections::HashSet,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chacha20poly1305::{
+    AeadCore, KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, OsRng, Payload},
+};
+use reqwest::{Client, Url};
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+
+use crate::{
+    api::{
+        digest::{self, Digest},
+        events,
+        exams::{self, Exam},
+        export, ical,
+    },
+    audit::{self, Actor},
+    config, db, effective_config,
+    notify::{self, Notification},
+    state::AppState,
+    upstream_client,
+};
+
+/// Credential vault settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct VaultConfig {
+    /// 256-bit encryption key.
+    #[serde(serialize_with = "effective_config::redacted")]
+    pub key: [u8; 32],
+    /// Interval of the grade check job.
+    #[serde(serialize_with = "effective_config::secs")]
+    pub check_interval: Duration,
+    /// Send the weekly digest to the accounts' webhooks on Sundays.
+    pub weekly_digest: bool,
+    /// Hour (Prague time) on Sunday from which the digest is sent.
+    pub digest_hour: u32,
+}
+
+impl VaultConfig {
+    /// # Environment Variables
+    /// * `VAULT_KEY` - 64 hex characters (e.g. `openssl rand -hex 32`). The vault is disabled when unset.
+    /// * `VAULT_CHECK_INTERVAL_SECS` - Grade check interval (default: 1800).
+    /// * `VAULT_WEEKLY_DIGEST` - Set to "true" or "1" to send the weekly digest every Sunday.
+    /// * `VAULT_DIGEST_HOUR` - Hour on Sunday, Prague time, from which the digest is sent (default: 18).
+    pub fn from_env() -> Option<Self> {
+        let key = std::env::var("VAULT_KEY").ok().filter(|v| !v.is_empty())?;
+        let Some(key) = hex::decode(key.trim())
+            .ok()
+            .and_then(|k| <[u8; 32]>::try_from(k).ok())
+        else {
+            tracing::error!("VAULT_KEY must be 64 hex characters, the vault is disabled");
+            return None;
+        };
+
+        Some(Self {
+            key,
+            check_interval: Duration::from_secs(
+                config::env_parse("VAULT_CHECK_INTERVAL_SECS").unwrap_or(1800),
+            ),
+            weekly_digest: config::env_flag("VAULT_WEEKLY_DIGEST"),
+            digest_hour: config::env_parse::<u32>("VAULT_DIGEST_HOUR")
+                .unwrap_or(18)
+                .min(23),
+        })
+    }
+}
+
+/// A stored credential, as reported by the admin API.
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialInfo {
+    pub id: String,
+    pub username: String,
+    /// Webhook receiving this account's notifications.
+    pub notify_url: Option<String>,
+    /// Unix timestamp of the registration.
+    pub created_at: u64,
+}
+
+/// A stored credential with its encrypted password.
+pub struct StoredCredential {
+    pub info: CredentialInfo,
+    pub nonce: Vec<u8>,
+    pub password: Vec<u8>,
+}
+
+fn cipher(config: &VaultConfig) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(&config.key.into())
+}
+
+/// Encrypts `password`, bound to the credential `id`. Returns `(nonce, ciphertext)`.
+fn encrypt(config: &VaultConfig, id: &str, password: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(config)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: password.as_bytes(),
+                aad: id.as_bytes(),
+            },
+        )
+        .ok()?;
+    Some((nonce.to_vec(), ciphertext))
+}
+
+fn decrypt(config: &VaultConfig, credential: &StoredCredential) -> Option<String> {
+    if credential.nonce.len() != 24 {
+        return None;
+    }
+    let plaintext = cipher(config)
+        .decrypt(
+            XNonce::from_slice(&credential.nonce),
+            Payload {
+                msg: &credential.password,
+                aad: credential.info.id.as_bytes(),
+            },
+        )
+        .ok()?;
+    String::from_utf8(plaintext).ok()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddCredential {
+    username: String,
+    password: String,
+    notify_url: Option<String>,
+}
+
+/// Handler for `POST /_admin/vault`.
+pub async fn add(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Json(req): Json<AddCredential>,
+) -> Response {
+    let (Some(config), Some(db)) = (&state.config.vault, &state.db) else {
+        return (StatusCode::NOT_FOUND, "The vault is not configured").into_response();
+    };
+    if req.username.is_empty() || req.password.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Username and password are required",
+        )
+            .into_response();
+    }
+    if let Some(url) = &req.notify_url
+        && Url::parse(url).is_err()
+    {
+        return (StatusCode::BAD_REQUEST, "Invalid notify_url").into_response();
+    }
+
+    let id = hex::encode(rand::random::<[u8; 16]>());
+    let Some((nonce, password)) = encrypt(config, &id, &req.password) else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Encryption failed").into_response();
+    };
+
+    let credential = StoredCredential {
+        info: CredentialInfo {
+            id,
+            username: req.username,
+            notify_url: req.notify_url,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        },
+        nonce,
+        password,
+    };
+
+    match db.insert_credential(&credential).await {
+        Ok(()) => {
+            tracing::info!("Stored credential {}", credential.info.id);
+            audit::record(
+                &state,
+                &actor,
+                "credential.add",
+                Some(&credential.info.id),
+                Some(&credential.info.username),
+            )
+            .await;
+            (StatusCode::CREATED, Json(credential.info)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to store credential: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to store credential",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Handler for `GET /_admin/vault`.
+pub async fn list(State(state): State<AppState>) -> Response {
+    let Some(db) = state.db.as_ref().filter(|_| state.config.vault.is_some()) else {
+        return (StatusCode::NOT_FOUND, "The vault is not configured").into_response();
+    };
+    match db.load_credentials().await {
+        Ok(credentials) => {
+            let infos: Vec<CredentialInfo> = credentials.into_iter().map(|c| c.info).collect();
+            Json(infos).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to load credentials: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Handler for `DELETE /_admin/vault/{id}`.
+pub async fn remove(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<String>,
+) -> StatusCode {
+    let Some(db) = state.db.as_ref().filter(|_| state.config.vault.is_some()) else {
+        return StatusCode::NOT_FOUND;
+    };
+    match db.delete_credential(&id).await {
+        Ok(true) => {
+            tracing::info!("Removed credential {}", id);
+            audit::record(&state, &actor, "credential.remove", Some(&id), None).await;
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            tracing::error!("Failed to remove credential: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Logs in to the upstream, returning a client holding the session cookies.
+///
+/// Submits the first form with a password field on the start page, keeping its
+/// hidden fields (e.g. CSRF tokens).
+pub async fn login(state: &AppState, username: &str, password: &str) -> Result<Client, String> {
+    let client =
+        upstream_client::resolving(Client::builder(), state.config.upstream_http.ip_family)
+            .cookie_store(true)
+            .connect_timeout(state.config.timeouts.upstream_connect)
+            .read_timeout(state.config.timeouts.upstream_read)
+            .build()
+            .map_err(|e| e.to_string())?;
+    let base = state.upstream().url.clone();
+
+    state.throttle.wait().await;
+    let html = client
+        .get(base.clone())
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.without_url().to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let (action, fields) = login_form(&html, username, password).ok_or("login form not found")?;
+    let action = form_target(&base, &action)?;
+
+    state.throttle.wait().await;
+    client
+        .post(action)
+        .form(&fields)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.without_url().to_string())?;
+
+    Ok(client)
+}
+
+/// Resolves the login form's action. The passwords are only posted to the
+/// upstream's own origin, never over another scheme or port.
+fn form_target(base: &Url, action: &str) -> Result<Url, String> {
+    let action = base.join(action).map_err(|e| e.to_string())?;
+    if action.origin() != base.origin() {
+        return Err("login form points to a foreign origin".to_string());
+    }
+    Ok(action)
+}
+
+/// Finds the login form, returning its action and the filled in fields.
+fn login_form(
+    html: &str,
+    username: &str,
+    password: &str,
+) -> Option<(String, Vec<(String, String)>)> {
+    let document = Html::parse_document(html);
+    let form_selector = Selector::parse("form").expect("valid selector");
+    let input_selector = Selector::parse("input[name]").expect("valid selector");
+    let password_selector = Selector::parse("input[type=password]").expect("valid selector");
+
+    let form = document
+        .select(&form_selector)
+        .find(|f| f.select(&password_selector).next().is_some())?;
+
+    let mut fields = Vec::new();
+    for input in form.select(&input_selector) {
+        let el = input.value();
+        let name = el.attr("name").unwrap_or_default().to_string();
+        let value = match el.attr("type").unwrap_or("text").to_lowercase().as_str() {
+            "password" => password.to_string(),
+            "text" | "email" => username.to_string(),
+            "hidden" => el.attr("value").unwrap_or_default().to_string(),
+            _ => continue,
+        };
+        fields.push((name, value));
+    }
+
+    let action = form.value().attr("action").unwrap_or("").to_string();
+    Some((action, fields))
+}
+
+/// Checks the grades and exams of every stored account and notifies about new ones.
+pub async fn check_grades(state: &AppState) {
+    let (Some(config), Some(db)) = (&state.config.vault, &state.db) else {
+        return;
+    };
+    let credentials = match db.load_credentials().await {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("Failed to load credentials: {}", e);
+            return;
+        }
+    };
+
+    for credential in credentials {
+        let id = &credential.info.id;
+        let Some(password) = decrypt(config, &credential) else {
+            tracing::error!("Failed to decrypt credential {}, wrong VAULT_KEY?", id);
+            continue;
+        };
+
+        let (grades, exams) = match fetch(state, &credential.info.username, &password).await {
+            Ok(pages) => pages,
+            Err(e) => {
+                tracing::warn!("Grade check for credential {} failed: {}", id, e);
+                continue;
+            }
+        };
+
+        // The first check only establishes the baseline.
+        let (first_check, mut seen_grades, mut seen_exams) = match db.load_vault_seen(id).await {
+            Ok(Some((grades, exams))) => (false, grades, exams),
+            Ok(None) => (true, HashSet::new(), HashSet::new()),
+            Err(e) => {
+                tracing::error!("Failed to load the grade check state of {}: {}", id, e);
+                continue;
+            }
+        };
+        let new: Vec<Vec<String>> = grades
+            .into_iter()
+            .filter(|g| seen_grades.insert(entry_hash(g)))
+            .collect();
+        let new_exams: Vec<Exam> = exams
+            .into_iter()
+            .filter(|e| seen_exams.insert(entry_hash(e)))
+            .collect();
+        db::log_error(
+            db.save_vault_seen(id, &seen_grades, &seen_exams).await,
+            "grade check state",
+        );
+        if first_check {
+            continue;
+        }
+
+        let Some(url) = &credential.info.notify_url else {
+            continue;
+        };
+        for grade in new {
+            let [subject, value, _, description, date] = grade.as_slice() else {
+                continue;
+            };
+            notify::send_to(
+                state,
+                std::slice::from_ref(url),
+                &Notification {
+                    kind: "new_grade",
+                    title: format!("Nová známka z předmětu {}: {}", subject, value),
+                    message: format!("{} ({})", description, date),
+                    url: None,
+                },
+            )
+            .await;
+        }
+        for exam in new_exams {
+            let title = match exam.kind {
+                "homework" => format!("Nový úkol z předmětu {}", exam.subject),
+                _ => format!("Nový test z předmětu {}", exam.subject),
+            };
+            notify::send_to(
+                state,
+                std::slice::from_ref(url),
+                &Notification {
+                    kind: "new_exam",
+                    title,
+                    message: format!("{} ({})", exam.description, exam.date),
+                    url: None,
+                },
+            )
+            .await;
+        }
+    }
+}
+
+/// Identifies a grade or exam in the grade check state.
+fn entry_hash(entry: &impl Serialize) -> String {
+    let json = serde_json::to_string(entry).unwrap_or_default();
+    hex::encode(Sha256::digest(json.as_bytes()))
+}
+
+/// Fetches the grades and the exam schedule of an account.
+async fn fetch(
+    state: &AppState,
+    username: &str,
+    password: &str,
+) -> Result<(Vec<Vec<String>>, Vec<Exam>), String> {
+    let client = login(state, username, password).await?;
+    let grades = fetch_page(state, &client, &state.config.grades_path, |document| {
+        export::grades(document).rows
+    })
+    .await?;
+    let exams = fetch_page(state, &client, &state.config.exams_path, exams::exams).await?;
+    Ok((grades, exams))
+}
+
+/// Fetches a page with the account's session and extracts data with `parse`.
+async fn fetch_page<T>(
+    state: &AppState,
+    client: &Client,
+    path: &str,
+    parse: impl FnOnce(&Html) -> T,
+) -> Result<T, String> {
+    let url = format!("{}{}", state.upstream().base, path);
+    state.throttle.wait().await;
+    let html = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.without_url().to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let document = Html::parse_document(&html);
+    if document
+        .select(&Selector::parse("input[type=password]").expect("valid selector"))
+        .next()
+        .is_some()
+    {
+        return Err("login failed".to_string());
+    }
+    Ok(parse(&document))
+}
+
+/// Sends the weekly digest to every stored account with a webhook.
+///
+/// Runs hourly; the digest goes out once, on Sunday from `VAULT_DIGEST_HOUR`.
+pub async fn send_digests(state: &AppState) {
+    let (Some(config), Some(db)) = (&state.config.vault, &state.db) else {
+        return;
+    };
+    let (today, minute) = ical::prague_now();
+    if today.weekday() != 6 || minute / 60 < config.digest_hour {
+        return;
+    }
+    match db.load_digest_sent().await {
+        Ok(sent) if sent == Some(today.days()) => return,
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!("Failed to load the weekly digest state: {}", e);
+            return;
+        }
+    }
+    db::log_error(
+        db.save_digest_sent(today.days()).await,
+        "weekly digest state",
+    );
+
+    let credentials = match db.load_credentials().await {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("Failed to load credentials: {}", e);
+            return;
+        }
+    };
+    for credential in credentials {
+        let id = &credential.info.id;
+        let Some(url) = &credential.info.notify_url else {
+            continue;
+        };
+        let Some(password) = decrypt(config, &credential) else {
+            tracing::error!("Failed to decrypt credential {}, wrong VAULT_KEY?", id);
+            continue;
+        };
+
+        let digest = match fetch_digest(state, &credential.info.username, &password).await {
+            Ok(digest) => digest,
+            Err(e) => {
+                tracing::warn!("Weekly digest for credential {} failed: {}", id, e);
+                continue;
+            }
+        };
+        notify::send_to(
+            state,
+            std::slice::from_ref(url),
+            &Notification {
+                kind: "weekly_digest",
+                title: digest.title(),
+                message: digest.markdown(),
+                url: None,
+            },
+        )
+        .await;
+    }
+}
+
+/// Builds the weekly digest of an account.
+async fn fetch_digest(state: &AppState, username: &str, password: &str) -> Result<Digest, String> {
+    let client = login(state, username, password).await?;
+    let substitutions = fetch_page(
+        state,
+        &client,
+        &state.config.substitutions_path,
+        digest::substitution_rows,
+    )
+    .await?;
+    let exams = fetch_page(state, &client, &state.config.exams_path, exams::exams).await?;
+
+    let base = Url::parse(&format!(
+        "{}{}",
+        state.upstream().base,
+        state.config.events_path
+    ))
+    .map_err(|e| e.to_string())?;
+    let prefix = events::article_prefix(&state.config.news_path);
+    let mut events = fetch_page(state, &client, &state.config.events_path, |document| {
+        events::parse_events(document, &base, prefix)
+    })
+    .await?;
+    // Without BASE_URL there's no known address of the proxy, links stay upstream.
+    if let Some(base_url) = &state.config.base_url {
+        let origin = base_url.trim_end_matches('/');
+        for event in &mut events {
+            event.url =
+                crate::utils::rewrite_content_urls(std::mem::take(&mut event.url), origin, state);
+        }
+    }
+
+    Ok(Digest::next_week(substitutions, exams, events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(key: u8) -> VaultConfig {
+        VaultConfig {
+            key: [key; 32],
+            check_interval: Duration::from_secs(1800),
+            weekly_digest: false,
+            digest_hour: 18,
+        }
+    }
+
+    fn stored(config: &VaultConfig, id: &str, password: &str) -> StoredCredential {
+        let (nonce, password) = encrypt(config, id, password).unwrap();
+        StoredCredential {
+            info: CredentialInfo {
+                id: id.to_string(),
+                username: "novak".to_string(),
+                notify_url: None,
+                created_at: 0,
+            },
+            nonce,
+            password,
+        }
+    }
+
+    #[test]
+    fn passwords_round_trip() {
+        let config = config(1);
+        let credential = stored(&config, "a1", "heslo123");
+        assert_ne!(credential.password, b"heslo123");
+        assert_eq!(decrypt(&config, &credential).as_deref(), Some("heslo123"));
+    }
+
+    #[test]
+    fn wrong_key_fails() {
+        let credential = stored(&config(1), "a1", "heslo123");
+        assert_eq!(decrypt(&config(2), &credential), None);
+    }
+
+    #[test]
+    fn tampering_fails() {
+        let config = config(1);
+
+        let mut credential = stored(&config, "a1", "heslo123");
+        credential.password[0] ^= 1;
+        assert_eq!(decrypt(&config, &credential), None);
+
+        let mut credential = stored(&config, "a1", "heslo123");
+        credential.nonce[0] ^= 1;
+        assert_eq!(decrypt(&config, &credential), None);
+
+        // The ciphertext is bound to its credential id.
+        let mut credential = stored(&config, "a1", "heslo123");
+        credential.info.id = "b2".to_string();
+        assert_eq!(decrypt(&config, &credential), None);
+
+        let mut credential = stored(&config, "a1", "heslo123");
+        credential.nonce.truncate(12);
+        assert_eq!(decrypt(&config, &credential), None);
+    }
+
+    #[test]
+    fn login_form_stays_on_the_origin() {
+        let base = Url::parse("https://www.spsejecna.cz").unwrap();
+        assert_eq!(
+            form_target(&base, "/user/login").unwrap().as_str(),
+            "https://www.spsejecna.cz/user/login"
+        );
+        assert!(form_target(&base, "https://www.spsejecna.cz:443/login").is_ok());
+
+        for action in [
+            "http://www.spsejecna.cz/user/login",
+            "https://www.spsejecna.cz:8443/user/login",
+            "//evil.example.com/user/login",
+            "https://spsejecna.cz/user/login",
+        ] {
+            assert!(form_target(&base, action).is_err(), "{}", action);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Build information, served by `GET /_jecnaproxy/version`.
+//!
+//! The commit and build time are embedded by `build.rs`; users reporting an
+//! issue can paste the endpoint's output.
+
+use axum::Json;
+use serde::Serialize;
+
+/// Crate version.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Abbreviated git commit, or "unknown".
+pub const GIT_COMMIT: &str = env!("JECNAPROXY_GIT_COMMIT");
+
+/// Build time as RFC 3339 UTC timestamp.
+pub const BUILD_TIMESTAMP: &str = env!("JECNAPROXY_BUILD_TIMESTAMP");
+
+/// `--version --long` output of the CLI.
+pub const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("JECNAPROXY_GIT_COMMIT"),
+    ", built ",
+    env!("JECNAPROXY_BUILD_TIMESTAMP"),
+    ")"
+);
+
+/// Cargo features the binary was built with.
+pub const FEATURES: &[&str] = &[
+    #[cfg(feature = "http3")]
+    "http3",
+];
+
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub build_timestamp: &'static str,
+    pub features: &'static [&'static str],
+}
+
+/// Handler for `GET /_jecnaproxy/version`.
+pub async fn handler() -> Json<BuildInfo> {
+    Json(BuildInfo {
+        version: VERSION,
+        git_commit: GIT_COMMIT,
+        build_timestamp: BUILD_TIMESTAMP,
+        features: FEATURES,
+    })
+}
@@ -0,0 +1,123 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! `Via` header handling and proxy loop detection.
+//!
+//! A `MODE` pointing back at the proxy itself (easy to do by accident in CUSTOM
+//! mode) would otherwise make every request recurse until sockets run out.
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, Version},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::{config, state::AppState, version};
+
+/// `Via` settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct ViaConfig {
+    /// Pseudonym this proxy uses in `Via` headers.
+    pub name: String,
+    /// Maximum number of proxies a request may have passed through.
+    pub max_hops: usize,
+}
+
+impl ViaConfig {
+    /// # Environment Variables
+    /// * `VIA_NAME` - Identifier used in `Via` headers (default: "jecnaproxy").
+    /// * `MAX_HOPS` - Maximum `Via` entries on incoming requests (default: 10).
+    pub fn from_env() -> Self {
+        Self {
+            name: std::env::var("VIA_NAME")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| "jecnaproxy".to_string()),
+            max_hops: config::env_parse("MAX_HOPS").unwrap_or(10),
+        }
+    }
+}
+
+/// Appends this proxy to the `Via` header, with the proxy version as comment.
+pub fn append(headers: &mut HeaderMap, version: Version, name: &str) {
+    let protocol = match version {
+        Version::HTTP_09 => "0.9",
+        Version::HTTP_10 => "1.0",
+        Version::HTTP_2 => "2",
+        Version::HTTP_3 => "3",
+        _ => "1.1",
+    };
+
+    if let Ok(v) = HeaderValue::from_str(&format!(
+        "{} {} (jecnaproxy/{})",
+        protocol,
+        name,
+        version::VERSION
+    )) {
+        headers.append("via", v);
+    }
+}
+
+/// Middleware rejecting requests that already passed through this proxy.
+pub async fn detect_loop(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let via = &state.config.via;
+
+    // Each entry is "<protocol> <received-by> [comment]".
+    let hops: Vec<String> = req
+        .headers()
+        .get_all("via")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|entry| entry.split_whitespace().nth(1).map(str::to_string))
+        .collect();
+
+    if hops.iter().any(|by| by.eq_ignore_ascii_case(&via.name)) {
+        tracing::error!(
+            "Proxy loop detected: request already passed through {}",
+            via.name
+        );
+        return loop_detected();
+    }
+
+    if hops.len() >= via.max_hops {
+        tracing::error!("Request exceeded hop limit ({} hops)", hops.len());
+        return loop_detected();
+    }
+
+    if targets_self(&req, &state) {
+        tracing::error!("Proxy loop detected: upstream is this proxy's own host");
+        return loop_detected();
+    }
+
+    next.run(req).await
+}
+
+/// Whether the `Host` of the request is the upstream host itself.
+fn targets_self(req: &Request, state: &AppState) -> bool {
+    let Some(host) = req.headers().get("host").and_then(|h| h.to_str().ok()) else {
+        return false;
+    };
+    host.eq_ignore_ascii_case(&state.upstream().authority)
+}
+
+fn loop_detected() -> Response {
+    (
+        StatusCode::LOOP_DETECTED,
+        "Proxy Error: request loop detected",
+    )
+        .into_response()
+}
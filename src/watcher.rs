@@ -0,0 +1,216 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Page change monitoring.
+//!
+//! Periodically fetches the configured paths, normalizes them to their main
+//! text content (so rotating tokens or scripts don't count as changes) and
+//! records a unified diff whenever the content hash changes.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::http::HeaderMap;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+
+use crate::{
+    api, config, db, effective_config,
+    extract::{self, Format},
+    notify::{self, Notification},
+    state::AppState,
+    utils,
+};
+
+/// Change monitoring settings.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchConfig {
+    /// Upstream paths to monitor.
+    pub paths: Vec<String>,
+    #[serde(serialize_with = "effective_config::secs")]
+    pub interval: Duration,
+    /// Maximum number of remembered changes.
+    pub history: usize,
+}
+
+impl WatchConfig {
+    /// # Environment Variables
+    /// * `WATCH_PATHS` - Comma-separated paths to monitor. Disabled when empty.
+    /// * `WATCH_INTERVAL_SECS` - Check interval (default: 900).
+    /// * `WATCH_HISTORY` - Number of remembered changes (default: 100).
+    pub fn from_env() -> Option<Self> {
+        let paths = config::env_list("WATCH_PATHS");
+        if paths.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            paths,
+            interval: Duration::from_secs(config::env_parse("WATCH_INTERVAL_SECS").unwrap_or(900)),
+            history: config::env_parse("WATCH_HISTORY").unwrap_or(100),
+        })
+    }
+}
+
+/// A detected change of a monitored page.
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub path: String,
+    /// Unix timestamp of the detection.
+    pub detected_at: u64,
+    pub old_hash: String,
+    pub new_hash: String,
+    /// Unified diff of the normalized page text.
+    pub diff: String,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Last seen hash and normalized text per path.
+    latest: HashMap<String, (String, String)>,
+    changes: VecDeque<Change>,
+}
+
+/// Last seen page versions and the change history.
+#[derive(Default)]
+pub struct WatchState {
+    inner: Mutex<Inner>,
+}
+
+impl WatchState {
+    /// Returns recorded changes, newest first, optionally only for `path`.
+    pub fn changes(&self, path: Option<&str>) -> Vec<Change> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .changes
+            .iter()
+            .rev()
+            .filter(|c| path.is_none_or(|p| c.path == p))
+            .cloned()
+            .collect()
+    }
+
+    /// Replaces the in-memory state with persisted versions and changes.
+    pub fn restore(&self, latest: Vec<(String, String, String)>, changes: Vec<Change>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.latest = latest
+            .into_iter()
+            .map(|(path, hash, text)| (path, (hash, text)))
+            .collect();
+        inner.changes = changes.into();
+    }
+
+    /// Forgets changes detected before the Unix timestamp `before`.
+    pub fn prune(&self, before: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.changes.retain(|c| c.detected_at >= before);
+    }
+
+    /// Stores the new version of `path`, returning the change if it differs.
+    fn observe(&self, path: &str, text: String, history: usize) -> Option<Change> {
+        let hash = hex::encode(Sha256::digest(text.as_bytes()));
+        let mut inner = self.inner.lock().unwrap();
+
+        let previous = inner
+            .latest
+            .insert(path.to_string(), (hash.clone(), text.clone()));
+        let (old_hash, old_text) = previous?;
+        if old_hash == hash {
+            return None;
+        }
+
+        let diff = TextDiff::from_lines(&old_text, &text)
+            .unified_diff()
+            .context_radius(2)
+            .header("before", "after")
+            .to_string();
+
+        let change = Change {
+            path: path.to_string(),
+            detected_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            old_hash,
+            new_hash: hash,
+            diff,
+        };
+
+        inner.changes.push_back(change.clone());
+        while inner.changes.len() > history {
+            inner.changes.pop_front();
+        }
+
+        Some(change)
+    }
+}
+
+/// Checks all monitored paths once.
+pub async fn check(state: &AppState, config: &WatchConfig) {
+    for path in &config.paths {
+        state.throttle.wait().await;
+        let page = match api::fetch_html(state, path, &HeaderMap::new()).await {
+            Ok(p) => p,
+            Err(_) => {
+                tracing::warn!("Watcher: failed to fetch {}", path);
+                continue;
+            }
+        };
+
+        let text = extract::render_page(&page.html, &page.url, Format::Text);
+        let change = state.watch.observe(path, text.clone(), config.history);
+
+        if let Some(db) = &state.db {
+            let hash = change
+                .as_ref()
+                .map(|c| c.new_hash.clone())
+                .unwrap_or_else(|| hex::encode(Sha256::digest(text.as_bytes())));
+            db::log_error(db.save_watched(path, &hash, &text).await, "watched page");
+            if let Some(change) = &change {
+                db::log_error(db.save_change(change).await, "change");
+            }
+        }
+
+        let Some(change) = change else {
+            continue;
+        };
+
+        tracing::info!("Watcher: {} changed", path);
+
+        let url = state
+            .config
+            .base_url
+            .as_ref()
+            .map(|base| format!("{}{}", base.trim_end_matches('/'), path));
+        let message = utils::rewrite_content_urls(
+            change.diff,
+            state.config.base_url.as_deref().unwrap_or(""),
+            state,
+        );
+        notify::send(
+            state,
+            &Notification {
+                kind: "page_changed",
+                title: format!("Stránka {} se změnila", path),
+                message,
+                url,
+            },
+        )
+        .await;
+    }
+}
@@ -0,0 +1,24 @@
+(function ($) {
+  "use strict";
+
+  var BASE = "https://jecna.example.org";
+  var endpoints = {
+    login: "https://jecna.example.org/user/login",
+    timetable: BASE + "/timetable/class",
+    canteen: "https://strav.nasejidelna.cz/0341/"
+  };
+
+  function load(path) {
+    return $.getJSON(BASE + path);
+  }
+
+  $(function () {
+    $("a.external").attr("target", "_blank");
+    if (window.location.origin !== "https://jecna.example.org") {
+      console.warn("Served from " + window.location.origin);
+    }
+    load("/api/menu").done(function (menu) {
+      $("#menu").data("items", menu);
+    });
+  });
+})(jQuery);
@@ -0,0 +1,122 @@
+/*
+ * Copyright (C) 2025 Jakub Žitník
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ */
+
+//! Golden-file tests of the response rewriting.
+//!
+//! Every file in `fixtures/upstream` is served as an upstream response, with
+//! the content type given by its extension, and the rewritten body must match
+//! the file of the same name in `fixtures/expected`. After an intended change,
+//! regenerate the expected files with `UPDATE_GOLDEN=1 cargo test --test golden`
+//! and review their diff.
+
+use std::{fs, path::Path, sync::Arc};
+
+use axum::http::HeaderMap;
+use jecnaproxy::{
+    config::{Config, Mode, Upstream},
+    handlers,
+    state::AppState,
+};
+use reqwest::{ResponseBuilderExt, Url};
+use similar::TextDiff;
+
+const PROXY_ORIGIN: &str = "https://jecna.example.org";
+
+fn state() -> AppState {
+    let upstream = Upstream::new(Mode::SPSEJECNA, &[]).expect("built-in mode is valid");
+    AppState::new(Arc::new(Config::from_env()), upstream)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css",
+        Some("js") => "application/javascript",
+        Some("json") => "application/json",
+        Some("rss") => "application/rss+xml",
+        Some("xml") => "application/xml",
+        other => panic!("no content type for fixture extension {:?}", other),
+    }
+}
+
+/// Rewrites a fixture like a proxied upstream response.
+async fn rewrite(state: &AppState, path: &Path) -> String {
+    let name = path.file_name().unwrap().to_str().unwrap();
+    let url = Url::parse(&format!("https://www.spsejecna.cz/{}", name)).unwrap();
+    let upstream = axum::http::Response::builder()
+        .status(200)
+        .url(url)
+        .header("content-type", content_type(path))
+        .body(fs::read(path).unwrap())
+        .unwrap();
+    let response = handlers::process_response(
+        reqwest::Response::from(upstream),
+        PROXY_ORIGIN,
+        state,
+        &HeaderMap::new(),
+        Vec::new(),
+        Vec::new(),
+        None,
+    )
+    .await;
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn rewritten_fixtures_match_expected() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let state = state();
+
+    let mut inputs: Vec<_> = fs::read_dir(fixtures.join("upstream"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty(), "no fixtures in tests/fixtures/upstream");
+
+    let mut failures = Vec::new();
+    for input in &inputs {
+        let name = input.file_name().unwrap();
+        let expected_path = fixtures.join("expected").join(name);
+        let actual = rewrite(&state, input).await;
+        if update {
+            fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+        if actual != expected {
+            let diff = TextDiff::from_lines(&expected, &actual)
+                .unified_diff()
+                .header(
+                    &format!("expected/{}", name.display()),
+                    &format!("actual/{}", name.display()),
+                )
+                .to_string();
+            failures.push(diff);
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} fixtures differ, rerun with UPDATE_GOLDEN=1 if intended:\n{}",
+        failures.len(),
+        inputs.len(),
+        failures.join("\n")
+    );
+}